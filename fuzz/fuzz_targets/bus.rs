@@ -0,0 +1,6 @@
+#![no_main]
+
+use gameboy_emulator_fuzz::{BusInput, bus};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: BusInput| bus(input));
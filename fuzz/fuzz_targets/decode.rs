@@ -0,0 +1,6 @@
+#![no_main]
+
+use gameboy_emulator_fuzz::{DecodeInput, decode};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: DecodeInput| decode(input));
@@ -0,0 +1,88 @@
+use arbitrary::Arbitrary;
+use gameboy_emulator::Emulator;
+use gameboy_emulator::disassembler;
+
+// Random instructions at PC, run from random registers
+#[derive(Arbitrary, Debug)]
+pub struct DecodeInput {
+    registers: [u16; 6],
+    program: Vec<u8>,
+}
+
+pub fn decode(input: DecodeInput) {
+    let mut emulator = Emulator::new();
+    let cpu = emulator.cpu_mut();
+    cpu.set_registers(input.registers);
+    let [.., pc] = input.registers;
+    for (offset, &byte) in input.program.iter().enumerate() {
+        cpu.poke(pc.wrapping_add(offset as u16), byte);
+    }
+    run(&mut emulator, input.program.len());
+}
+
+// Random writes anywhere on the bus, IO registers and VRAM as much as RAM, then the instructions
+// they leave at PC
+#[derive(Arbitrary, Debug)]
+pub struct BusInput {
+    registers: [u16; 6],
+    writes: Vec<(u16, u8)>,
+    instructions: u8,
+}
+
+pub fn bus(input: BusInput) {
+    let mut emulator = Emulator::new();
+    let cpu = emulator.cpu_mut();
+    cpu.set_registers(input.registers);
+    for (address, value) in input.writes {
+        cpu.poke(address, value);
+    }
+    run(&mut emulator, input.instructions as usize);
+}
+
+// Runs up to count instructions from the state the target set up, checking that each one moves PC
+// and SP only the way its opcode allows, as the disassembler reads it. Anything out of bounds or
+// overflowing panics on its own, cargo fuzz builds with debug assertions. Stops at the first
+// opcode the CPU doesn't implement yet, those panic on purpose
+fn run(emulator: &mut Emulator, count: usize) {
+    let cpu = emulator.cpu_mut();
+    for _ in 0..count {
+        let [.., (_, hl), (_, sp), (_, pc)] = cpu.registers();
+        let line = disassembler::disassemble(pc, |address| cpu.peek(address));
+        let opcode = line.bytes[0];
+        let popped = u16::from_le_bytes([cpu.peek(sp), cpu.peek(sp.wrapping_add(1))]);
+        if cpu.try_step_instruction().is_err() {
+            return;
+        }
+        let [.., (_, new_sp), (_, new_pc)] = cpu.registers();
+
+        // Conditional ones may not be taken
+        let pcs = if disassembler::is_return(opcode) {
+            vec![line.next(), popped]
+        } else if opcode == 0xE9 {
+            vec![hl]
+        } else if let Some(target) = line.target {
+            vec![line.next(), target]
+        } else {
+            vec![line.next()]
+        };
+        assert!(
+            pcs.contains(&new_pc),
+            "{line}: PC went from {pc:04X} to {new_pc:04X}"
+        );
+
+        let sps = match opcode {
+            // LD SP, n16, LD SP, HL and ADD SP, e8 can leave it anywhere
+            0x31 | 0xF9 | 0xE8 => continue,
+            _ if disassembler::is_call(opcode) => vec![sp, sp.wrapping_sub(2)],
+            _ if disassembler::is_return(opcode) => vec![sp, sp.wrapping_add(2)],
+            // PUSH and POP
+            _ if opcode & 0xCF == 0xC5 => vec![sp.wrapping_sub(2)],
+            _ if opcode & 0xCF == 0xC1 => vec![sp.wrapping_add(2)],
+            _ => vec![sp],
+        };
+        assert!(
+            sps.contains(&new_sp),
+            "{line}: SP went from {sp:04X} to {new_sp:04X}"
+        );
+    }
+}
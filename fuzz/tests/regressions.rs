@@ -0,0 +1,15 @@
+use arbitrary::{Arbitrary, Unstructured};
+use gameboy_emulator_fuzz::{BusInput, DecodeInput, bus, decode};
+use std::fs;
+
+// Inputs the fuzzers once crashed on, replayed through both targets the way libfuzzer hands them
+// over. A new one goes in as is, under the name cargo fuzz gave it
+#[test]
+fn regressions() {
+    let directory = concat!(env!("CARGO_MANIFEST_DIR"), "/regressions");
+    for entry in fs::read_dir(directory).unwrap() {
+        let data = fs::read(entry.unwrap().path()).unwrap();
+        decode(DecodeInput::arbitrary_take_rest(Unstructured::new(&data)).unwrap());
+        bus(BusInput::arbitrary_take_rest(Unstructured::new(&data)).unwrap());
+    }
+}
@@ -0,0 +1,64 @@
+#ifndef GAMEBOY_EMULATOR_H
+#define GAMEBOY_EMULATOR_H
+
+/* Generated by cbindgen from src/ffi.rs, don't edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define GB_FRAME_WIDTH 160
+
+#define GB_FRAME_HEIGHT 144
+
+#define GB_BYTES_PER_PIXEL 3
+
+#define GB_BUTTON_RIGHT (1 << 0)
+
+#define GB_BUTTON_LEFT (1 << 1)
+
+#define GB_BUTTON_UP (1 << 2)
+
+#define GB_BUTTON_DOWN (1 << 3)
+
+#define GB_BUTTON_A (1 << 4)
+
+#define GB_BUTTON_B (1 << 5)
+
+#define GB_BUTTON_SELECT (1 << 6)
+
+#define GB_BUTTON_START (1 << 7)
+
+typedef struct GbEmulator GbEmulator;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+struct GbEmulator *gb_create(void);
+
+void gb_destroy(struct GbEmulator *emulator);
+
+bool gb_load_rom(struct GbEmulator *emulator, const uint8_t *rom, size_t length);
+
+void gb_run_frame(struct GbEmulator *emulator);
+
+const uint8_t *gb_get_framebuffer(const struct GbEmulator *emulator);
+
+void gb_set_buttons(struct GbEmulator *emulator, uint8_t buttons);
+
+uint8_t gb_read(const struct GbEmulator *emulator, uint16_t address);
+
+void gb_write(struct GbEmulator *emulator, uint16_t address, uint8_t value);
+
+size_t gb_save_state(const struct GbEmulator *emulator, uint8_t *buffer, size_t capacity);
+
+bool gb_load_state(struct GbEmulator *emulator, const uint8_t *state, size_t length);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* GAMEBOY_EMULATOR_H */
@@ -0,0 +1,779 @@
+mod envelope;
+mod filter;
+mod noise;
+mod square;
+mod wave;
+
+use crate::cpu::FREQUENCY;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+use filter::OutputFilter;
+use noise::Noise;
+use square::Square;
+use wave::Wave;
+
+// Sound registers followed by wave RAM, the whole range is handled by the APU
+pub const START: u16 = 0xFF10;
+pub const END: u16 = 0xFF3F;
+
+const NR10: u16 = 0xFF10;
+const NR11: u16 = 0xFF11;
+const NR12: u16 = 0xFF12;
+const NR14: u16 = 0xFF14;
+const NR21: u16 = 0xFF16;
+const NR22: u16 = 0xFF17;
+const NR24: u16 = 0xFF19;
+const NR30: u16 = 0xFF1A;
+const NR31: u16 = 0xFF1B;
+const NR34: u16 = 0xFF1E;
+const NR41: u16 = 0xFF20;
+const NR42: u16 = 0xFF21;
+const NR44: u16 = 0xFF23;
+const NR50: u16 = 0xFF24;
+const NR51: u16 = 0xFF25;
+const NR52: u16 = 0xFF26;
+const WAVE_RAM: u16 = 0xFF30;
+
+// Every NRxx register in address order, for inspecting them
+pub const REGISTERS: [(&str, u16); 21] = [
+    ("NR10", 0xFF10),
+    ("NR11", 0xFF11),
+    ("NR12", 0xFF12),
+    ("NR13", 0xFF13),
+    ("NR14", 0xFF14),
+    ("NR21", 0xFF16),
+    ("NR22", 0xFF17),
+    ("NR23", 0xFF18),
+    ("NR24", 0xFF19),
+    ("NR30", 0xFF1A),
+    ("NR31", 0xFF1B),
+    ("NR32", 0xFF1C),
+    ("NR33", 0xFF1D),
+    ("NR34", 0xFF1E),
+    ("NR41", 0xFF20),
+    ("NR42", 0xFF21),
+    ("NR43", 0xFF22),
+    ("NR44", 0xFF23),
+    ("NR50", 0xFF24),
+    ("NR51", 0xFF25),
+    ("NR52", 0xFF26),
+];
+
+// The frame sequencer runs at 512 Hz and drives length counters, sweep and envelopes
+const FRAME_SEQUENCER_PERIOD: u32 = FREQUENCY / 512;
+
+// Bits that read back as 1 no matter what was written, indexed from NR10. Write-only registers
+// and the unused gap before wave RAM read as 0xFF
+const READ_MASKS: [u8; (WAVE_RAM - START) as usize] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // NR20-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // NR40-NR44
+    0x00, 0x00, 0x70, // NR50-NR52
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+struct LengthCounter {
+    value: u16,
+    max: u16,
+    enabled: bool,
+}
+
+impl LengthCounter {
+    fn new(max: u16) -> Self {
+        Self {
+            value: 0,
+            max,
+            enabled: false,
+        }
+    }
+
+    fn load(&mut self, length: u8) {
+        self.value = self.max - length as u16;
+    }
+
+    fn trigger(&mut self) {
+        if self.value == 0 {
+            self.value = self.max;
+        }
+    }
+
+    // Returns whether the counter just ran out, which silences its channel
+    fn clock(&mut self) -> bool {
+        if self.enabled && self.value > 0 {
+            self.value -= 1;
+            return self.value == 0;
+        }
+        false
+    }
+}
+
+pub struct Apu {
+    registers: [u8; (END - START + 1) as usize],
+    powered: bool,
+    // Channel status as reported by the low bits of NR52
+    channels: [bool; 4],
+    // Muted channels keep running but are left out of the mix
+    muted: [bool; 4],
+    lengths: [LengthCounter; 4],
+    square1: Square,
+    square2: Square,
+    wave: Wave,
+    noise: Noise,
+    sequencer_step: u8,
+    sequencer_cycles: u32,
+    // Output rate in Hz, no samples are produced without one
+    sample_rate: Option<u32>,
+    // Advances by the sample rate every cycle, a sample is due each time it reaches FREQUENCY
+    sample_clock: u32,
+    // Sum of the mixed output over the cycles since the last sample, weighted by their length
+    mix_sum: (f32, f32),
+    stem_sums: [(f32, f32); 4],
+    mix_cycles: u32,
+    low_pass_cutoff: Option<u32>,
+    // The mix's filter followed by each stem's, set up along with the sample rate
+    filters: Option<[OutputFilter; 5]>,
+    samples: Vec<f32>,
+    // Every channel's own output, only produced on request
+    stems: Option<[Vec<f32>; 4]>,
+}
+
+// A channel's state at a glance, for debugging
+pub struct ChannelStatus {
+    pub enabled: bool,
+    pub dac_enabled: bool,
+    // In Hz, of the waveform for tone channels and of the LFSR shifts for noise
+    pub frequency: f64,
+    // 0 to 15
+    pub volume: u8,
+    // Percent of the waveform spent high, square channels only
+    pub duty: Option<f32>,
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Self {
+            registers: [0; (END - START + 1) as usize],
+            powered: false,
+            channels: [false; 4],
+            muted: [false; 4],
+            lengths: [
+                LengthCounter::new(64),
+                LengthCounter::new(64),
+                LengthCounter::new(256),
+                LengthCounter::new(64),
+            ],
+            square1: Square::new(true),
+            square2: Square::new(false),
+            wave: Wave::new(),
+            noise: Noise::new(),
+            sequencer_step: 0,
+            sequencer_cycles: 0,
+            sample_rate: None,
+            sample_clock: 0,
+            mix_sum: (0.0, 0.0),
+            stem_sums: [(0.0, 0.0); 4],
+            mix_cycles: 0,
+            low_pass_cutoff: None,
+            filters: None,
+            samples: Vec::new(),
+            stems: None,
+        }
+    }
+
+    // Back to power-on, output settings and muted channels stay
+    pub fn reset(&mut self) {
+        *self = Self {
+            muted: self.muted,
+            sample_rate: self.sample_rate,
+            low_pass_cutoff: self.low_pass_cutoff,
+            stems: self.stems.take(),
+            ..Self::new()
+        };
+        self.reset_filters();
+        self.clear_samples();
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        let index = (address - START) as usize;
+        match address {
+            NR52 => {
+                let status = self
+                    .channels
+                    .iter()
+                    .enumerate()
+                    .fold(0, |status, (i, on)| status | (*on as u8) << i);
+                READ_MASKS[index] | (self.powered as u8) << 7 | status
+            }
+            WAVE_RAM..=END => match self.wave_ram_index(index) {
+                Some(index) => self.registers[index],
+                None => 0xFF,
+            },
+            _ => self.registers[index] | READ_MASKS[index],
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        let index = (address - START) as usize;
+        match address {
+            NR52 => self.set_power(value & 0b1000_0000 != 0),
+            WAVE_RAM..=END => {
+                if let Some(index) = self.wave_ram_index(index) {
+                    self.registers[index] = value;
+                }
+            }
+            // Length counters can still be loaded while the APU is off on the DMG
+            NR11 | NR21 | NR31 | NR41 if !self.powered => {
+                self.load_length(Self::channel(address), value)
+            }
+            _ if !self.powered => {}
+            _ => {
+                self.registers[index] = value;
+                self.write_channel_register(address, value);
+            }
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: Option<u32>) {
+        self.sample_rate = sample_rate;
+        self.reset_filters();
+    }
+
+    // Cutoff in Hz of the low-pass applied to the output, none leaves it unfiltered
+    pub fn set_low_pass(&mut self, cutoff: Option<u32>) {
+        self.low_pass_cutoff = cutoff;
+        self.reset_filters();
+    }
+
+    // Interleaved stereo samples produced since the last clear_samples, between -1.0 and 1.0
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    // Per-channel samples in the same format as samples(), muting doesn't apply to them
+    pub fn stems(&self) -> Option<&[Vec<f32>; 4]> {
+        self.stems.as_ref()
+    }
+
+    pub fn set_stems(&mut self, enabled: bool) {
+        self.stems = enabled.then(Default::default);
+    }
+
+    pub fn clear_samples(&mut self) {
+        self.samples.clear();
+        if let Some(stems) = &mut self.stems {
+            stems.iter_mut().for_each(Vec::clear);
+        }
+    }
+
+    // Advances the APU by the given number of CPU cycles. The output is downsampled to the sample
+    // rate by averaging it over each sample's duration
+    pub fn step(&mut self, mut cycles: u32) {
+        let Some(sample_rate) = self.sample_rate else {
+            self.clock(cycles);
+            return;
+        };
+        while cycles > 0 {
+            let until_sample = (FREQUENCY - self.sample_clock).div_ceil(sample_rate);
+            let chunk = cycles.min(until_sample);
+            self.clock(chunk);
+            let channels = self.mix_channels();
+            let (left, right) = self.mix(&channels);
+            self.mix_sum.0 += left * chunk as f32;
+            self.mix_sum.1 += right * chunk as f32;
+            for (sum, (left, right)) in self.stem_sums.iter_mut().zip(channels) {
+                sum.0 += left * chunk as f32;
+                sum.1 += right * chunk as f32;
+            }
+            self.mix_cycles += chunk;
+            self.sample_clock += chunk * sample_rate;
+            if self.sample_clock >= FREQUENCY {
+                self.sample_clock -= FREQUENCY;
+                let mix_cycles = self.mix_cycles as f32;
+                let dacs: [bool; 4] =
+                    std::array::from_fn(|channel| self.powered && self.dac_enabled(channel));
+                let filters = self
+                    .filters
+                    .as_mut()
+                    .expect("filters follow the sample rate");
+                let (left, right) = filters[0].apply(
+                    (self.mix_sum.0 / mix_cycles, self.mix_sum.1 / mix_cycles),
+                    dacs.contains(&true),
+                );
+                self.samples.push(left);
+                self.samples.push(right);
+                if let Some(stems) = &mut self.stems {
+                    for (channel, (stem, sum)) in stems.iter_mut().zip(self.stem_sums).enumerate() {
+                        let (left, right) = filters[channel + 1]
+                            .apply((sum.0 / mix_cycles, sum.1 / mix_cycles), dacs[channel]);
+                        stem.push(left);
+                        stem.push(right);
+                    }
+                }
+                self.mix_sum = (0.0, 0.0);
+                self.stem_sums = [(0.0, 0.0); 4];
+                self.mix_cycles = 0;
+            }
+            cycles -= chunk;
+        }
+    }
+
+    // Amplitude of every channel, 0 to 15, silent channels output 0
+    pub fn channel_outputs(&self) -> [u8; 4] {
+        let outputs = [
+            self.square1.output(),
+            self.square2.output(),
+            self.wave.output(),
+            self.noise.output(),
+        ];
+        std::array::from_fn(|i| if self.channels[i] { outputs[i] } else { 0 })
+    }
+
+    pub fn status(&self, channel: usize) -> ChannelStatus {
+        let (frequency, volume, duty) = match channel {
+            0 => (
+                self.square1.frequency(),
+                self.square1.volume(),
+                Some(self.square1.duty()),
+            ),
+            1 => (
+                self.square2.frequency(),
+                self.square2.volume(),
+                Some(self.square2.duty()),
+            ),
+            2 => (self.wave.frequency(), self.wave.volume(), None),
+            _ => (self.noise.frequency(), self.noise.volume(), None),
+        };
+        ChannelStatus {
+            enabled: self.channels[channel],
+            dac_enabled: self.dac_enabled(channel),
+            frequency,
+            volume,
+            duty,
+        }
+    }
+
+    pub fn muted(&self, channel: usize) -> bool {
+        self.muted[channel]
+    }
+
+    pub fn toggle_mute(&mut self, channel: usize) {
+        self.muted[channel] = !self.muted[channel];
+    }
+
+    // Mutes every other channel, soloing the channel that is already soloed unmutes them all
+    pub fn solo(&mut self, channel: usize) {
+        let soloed = std::array::from_fn(|i| i != channel);
+        self.muted = if self.muted == soloed {
+            [false; 4]
+        } else {
+            soloed
+        };
+    }
+
+    // Master volume for the left and right outputs, 0 to 7
+    pub fn master_volume(&self) -> (u8, u8) {
+        let nr50 = self.registers[(NR50 - START) as usize];
+        ((nr50 >> 4) & 0b111, nr50 & 0b111)
+    }
+
+    // Whether the channel (0 to 3) is panned to the left and right outputs
+    pub fn panning(&self, channel: usize) -> (bool, bool) {
+        let nr51 = self.registers[(NR51 - START) as usize];
+        (nr51 & (0x10 << channel) != 0, nr51 & (0x01 << channel) != 0)
+    }
+
+    // Where a wave RAM access lands, the CPU and channel 3 can't both use it freely
+    fn wave_ram_index(&self, index: usize) -> Option<usize> {
+        if !self.channels[2] {
+            return Some(index);
+        }
+        self.wave
+            .accessible_byte()
+            .map(|byte| (WAVE_RAM - START) as usize + byte)
+    }
+
+    // Channels and frame sequencer
+    fn clock(&mut self, cycles: u32) {
+        if !self.powered {
+            return;
+        }
+        self.square1.step(cycles);
+        self.square2.step(cycles);
+        self.wave
+            .step(cycles, &self.registers[(WAVE_RAM - START) as usize..]);
+        self.noise.step(cycles);
+        self.sequencer_cycles += cycles;
+        while self.sequencer_cycles >= FRAME_SEQUENCER_PERIOD {
+            self.sequencer_cycles -= FRAME_SEQUENCER_PERIOD;
+            if self.sequencer_step.is_multiple_of(2) {
+                self.clock_lengths();
+            }
+            if self.sequencer_step == 2 || self.sequencer_step == 6 {
+                self.channels[0] &= self.square1.clock_sweep();
+            }
+            if self.sequencer_step == 7 {
+                self.square1.clock_envelope();
+                self.square2.clock_envelope();
+                self.noise.clock_envelope();
+            }
+            self.sequencer_step = (self.sequencer_step + 1) % 8;
+        }
+    }
+
+    // Each DAC turns its channel's 0 to 15 into -1.0 to 1.0, which goes to the sides the channel
+    // is panned to, scaled by the master volume. Dividing by 4 leaves room for all four channels
+    fn mix_channels(&self) -> [(f32, f32); 4] {
+        if !self.powered {
+            return [(0.0, 0.0); 4];
+        }
+        let (left_volume, right_volume) = self.master_volume();
+        let left_scale = (left_volume + 1) as f32 / 8.0 / 4.0;
+        let right_scale = (right_volume + 1) as f32 / 8.0 / 4.0;
+        let outputs = self.channel_outputs();
+        std::array::from_fn(|channel| {
+            if !self.dac_enabled(channel) {
+                return (0.0, 0.0);
+            }
+            let analog = outputs[channel] as f32 / 7.5 - 1.0;
+            let (to_left, to_right) = self.panning(channel);
+            (
+                if to_left { analog * left_scale } else { 0.0 },
+                if to_right { analog * right_scale } else { 0.0 },
+            )
+        })
+    }
+
+    fn mix(&self, channels: &[(f32, f32); 4]) -> (f32, f32) {
+        channels
+            .iter()
+            .zip(self.muted)
+            .filter(|(_, muted)| !muted)
+            .fold(
+                (0.0, 0.0),
+                |(left, right), ((channel_left, channel_right), _)| {
+                    (left + channel_left, right + channel_right)
+                },
+            )
+    }
+
+    // Every channel owns five registers starting at NR10
+    fn channel(address: u16) -> usize {
+        ((address - NR10) / 5) as usize
+    }
+
+    fn load_length(&mut self, channel: usize, value: u8) {
+        let mask = if channel == 2 { 0xFF } else { 0x3F };
+        self.lengths[channel].load(value & mask);
+    }
+
+    fn write_channel_register(&mut self, address: u16, value: u8) {
+        let register = (address - NR10) % 5;
+        match Self::channel(address) {
+            0 => self.square1.write(register, value),
+            1 => self.square2.write(register, value),
+            2 => self.wave.write(register, value),
+            _ => self.noise.write(register, value),
+        }
+        match address {
+            NR11 | NR21 | NR31 | NR41 => self.load_length(Self::channel(address), value),
+            // Turning a channel's DAC off also silences the channel
+            NR12 | NR22 | NR42 | NR30 if !self.dac_enabled(Self::channel(address)) => {
+                self.channels[Self::channel(address)] = false;
+            }
+            NR14 | NR24 | NR34 | NR44 => {
+                let channel = Self::channel(address);
+                self.lengths[channel].enabled = value & 0b0100_0000 != 0;
+                if value & 0b1000_0000 != 0 {
+                    self.lengths[channel].trigger();
+                    let sweep_ok = match channel {
+                        0 => self.square1.trigger(),
+                        1 => self.square2.trigger(),
+                        2 => {
+                            self.wave.trigger();
+                            true
+                        }
+                        _ => {
+                            self.noise.trigger();
+                            true
+                        }
+                    };
+                    self.channels[channel] = self.dac_enabled(channel) && sweep_ok;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn dac_enabled(&self, channel: usize) -> bool {
+        match channel {
+            2 => self.registers[(NR30 - START) as usize] & 0b1000_0000 != 0,
+            _ => self.registers[(NR12 - START) as usize + channel * 5] & 0b1111_1000 != 0,
+        }
+    }
+
+    fn reset_filters(&mut self) {
+        self.filters = self.sample_rate.map(|sample_rate| {
+            std::array::from_fn(|_| OutputFilter::new(sample_rate, self.low_pass_cutoff))
+        });
+    }
+
+    // Powering off clears every register but wave RAM, and the frame sequencer restarts from
+    // its first step once powered back on
+    fn set_power(&mut self, on: bool) {
+        if self.powered && !on {
+            self.registers[..(NR52 - START) as usize].fill(0);
+            self.channels = [false; 4];
+            self.square1 = Square::new(true);
+            self.square2 = Square::new(false);
+            self.wave = Wave::new();
+            self.noise = Noise::new();
+        }
+        if !self.powered && on {
+            self.sequencer_step = 0;
+            self.sequencer_cycles = 0;
+        }
+        self.powered = on;
+    }
+
+    fn clock_lengths(&mut self) {
+        for (length, channel) in self.lengths.iter_mut().zip(&mut self.channels) {
+            if length.clock() {
+                *channel = false;
+            }
+        }
+    }
+}
+
+// Sound output is rebuilt from here, only the channels and the sequencer driving them are saved
+impl Snapshot for Apu {
+    fn save(&self, state: &mut StateWriter) {
+        state.bytes(&self.registers);
+        state.bool(self.powered);
+        for on in self.channels {
+            state.bool(on);
+        }
+        for length in &self.lengths {
+            state.u16(length.value);
+            state.bool(length.enabled);
+        }
+        self.square1.save(state);
+        self.square2.save(state);
+        self.wave.save(state);
+        self.noise.save(state);
+        state.u8(self.sequencer_step);
+        state.u32(self.sequencer_cycles);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), String> {
+        state.bytes(&mut self.registers)?;
+        self.powered = state.bool()?;
+        for on in &mut self.channels {
+            *on = state.bool()?;
+        }
+        for length in &mut self.lengths {
+            length.value = state.u16()?.min(length.max);
+            length.enabled = state.bool()?;
+        }
+        self.square1.load(state)?;
+        self.square2.load(state)?;
+        self.wave.load(state)?;
+        self.noise.load(state)?;
+        self.sequencer_step = state.u8()? % 8;
+        self.sequencer_cycles = state.u32()?;
+        Ok(())
+    }
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn powered_apu() -> Apu {
+        let mut apu = Apu::new();
+        apu.write(NR52, 0x80);
+        apu
+    }
+
+    #[test]
+    fn should_report_power_and_channel_status_in_nr52() {
+        let mut apu = powered_apu();
+        assert_eq!(apu.read(NR52), 0xF0);
+        apu.write(NR12, 0xF0);
+        apu.write(NR14, 0x80);
+        assert_eq!(apu.read(NR52), 0xF1);
+    }
+
+    #[test]
+    fn should_read_unused_bits_as_ones() {
+        let mut apu = powered_apu();
+        apu.write(NR11, 0b1000_0001);
+        assert_eq!(apu.read(NR11), 0b1011_1111);
+        assert_eq!(apu.read(0xFF15), 0xFF);
+        apu.write(WAVE_RAM, 0x12);
+        assert_eq!(apu.read(WAVE_RAM), 0x12);
+    }
+
+    #[test]
+    fn should_clear_registers_and_ignore_writes_while_powered_off() {
+        let mut apu = powered_apu();
+        apu.write(NR50, 0x77);
+        apu.write(WAVE_RAM, 0x12);
+        apu.write(NR52, 0x00);
+        assert_eq!(apu.read(NR50), 0x00);
+        apu.write(NR50, 0x77);
+        assert_eq!(apu.read(NR50), 0x00);
+        assert_eq!(apu.read(WAVE_RAM), 0x12);
+        assert_eq!(apu.read(NR52), 0x70);
+    }
+
+    #[test]
+    fn should_silence_channel_when_its_length_runs_out() {
+        let mut apu = powered_apu();
+        apu.write(NR22, 0xF0);
+        // Length 62 leaves two clocks
+        apu.write(NR21, 62);
+        apu.write(NR24, 0b1100_0000);
+        assert_eq!(apu.read(NR52) & 0b10, 0b10);
+        // Lengths are clocked on every other step of the frame sequencer
+        apu.step(FRAME_SEQUENCER_PERIOD);
+        assert_eq!(apu.read(NR52) & 0b10, 0b10);
+        apu.step(FRAME_SEQUENCER_PERIOD * 2);
+        assert_eq!(apu.read(NR52) & 0b10, 0);
+    }
+
+    #[test]
+    fn should_disable_channel_when_its_dac_is_turned_off() {
+        let mut apu = powered_apu();
+        apu.write(NR42, 0xF0);
+        apu.write(NR44, 0x80);
+        assert_eq!(apu.read(NR52) & 0b1000, 0b1000);
+        apu.write(NR42, 0x00);
+        assert_eq!(apu.read(NR52) & 0b1000, 0);
+    }
+
+    #[test]
+    fn should_decode_master_volume_and_panning() {
+        let mut apu = powered_apu();
+        apu.write(NR50, 0b0101_0011);
+        apu.write(NR51, 0b0010_0100);
+        assert_eq!(apu.master_volume(), (5, 3));
+        assert_eq!(apu.panning(1), (true, false));
+        assert_eq!(apu.panning(2), (false, true));
+    }
+
+    #[test]
+    fn should_output_triggered_square_channels_only() {
+        let mut apu = powered_apu();
+        apu.write(NR11, 0b1100_0000);
+        apu.write(NR12, 0xA0);
+        apu.write(NR14, 0x80);
+        // 75% duty starts high on its second step
+        apu.step(8192);
+        assert_eq!(apu.channel_outputs(), [10, 0, 0, 0]);
+    }
+
+    #[test]
+    fn should_block_wave_ram_while_channel_3_plays() {
+        let mut apu = powered_apu();
+        apu.write(WAVE_RAM + 1, 0x12);
+        apu.write(NR30, 0x80);
+        apu.write(NR34, 0x80);
+        assert_eq!(apu.read(WAVE_RAM + 1), 0xFF);
+        apu.write(WAVE_RAM + 1, 0x34);
+        apu.write(NR30, 0x00);
+        assert_eq!(apu.read(WAVE_RAM + 1), 0x12);
+    }
+
+    #[test]
+    fn should_produce_samples_at_the_sample_rate() {
+        let mut apu = Apu::new();
+        apu.set_sample_rate(Some(48_000));
+        apu.step(FREQUENCY / 2);
+        assert_eq!(apu.samples().len(), 48_000);
+        // Powered off, the output is silence
+        assert!(apu.samples().iter().all(|sample| *sample == 0.0));
+        apu.clear_samples();
+        assert!(apu.samples().is_empty());
+    }
+
+    #[test]
+    fn should_mix_panned_channels_at_master_volume() {
+        let mut apu = powered_apu();
+        apu.set_sample_rate(Some(48_000));
+        // Channel 2 at full volume on the left only, its DAC outputs 1.0 while the duty is high.
+        // The other DACs are off
+        apu.write(NR50, 0x70);
+        apu.write(NR51, 0b0010_0000);
+        apu.write(NR21, 0b1100_0000);
+        apu.write(NR22, 0xF0);
+        apu.write(NR24, 0x80);
+        assert_eq!(apu.mix(&apu.mix_channels()).1, 0.0);
+        // 75% duty starts low, frequency 0 spends 8192 cycles on each step
+        apu.step(8192);
+        let (left, _) = apu.mix(&apu.mix_channels());
+        assert_eq!(left, 1.0 / 4.0);
+    }
+
+    #[test]
+    fn should_toggle_solo() {
+        let mut apu = Apu::new();
+        apu.toggle_mute(0);
+        apu.solo(2);
+        assert_eq!(apu.muted, [true, true, false, true]);
+        apu.solo(2);
+        assert_eq!(apu.muted, [false; 4]);
+    }
+
+    #[test]
+    fn should_leave_muted_channels_out_of_the_mix() {
+        let mut apu = powered_apu();
+        apu.write(NR50, 0x77);
+        apu.write(NR51, 0xFF);
+        apu.write(NR12, 0xF0);
+        apu.write(NR14, 0x80);
+        assert_ne!(apu.mix(&apu.mix_channels()), (0.0, 0.0));
+        apu.toggle_mute(0);
+        assert_eq!(apu.mix(&apu.mix_channels()), (0.0, 0.0));
+    }
+
+    #[test]
+    fn should_produce_stems_alongside_the_mix() {
+        let mut apu = powered_apu();
+        apu.set_sample_rate(Some(48_000));
+        apu.set_stems(true);
+        apu.write(NR51, 0xFF);
+        apu.write(NR12, 0xF0);
+        apu.write(NR14, 0x80);
+        apu.toggle_mute(0);
+        apu.step(FREQUENCY / 100);
+        let stems = apu.stems().unwrap();
+        assert!(stems.iter().all(|stem| stem.len() == apu.samples().len()));
+        assert!(stems[0].iter().any(|sample| *sample != 0.0));
+        assert!(apu.samples().iter().all(|sample| *sample == 0.0));
+        apu.clear_samples();
+        assert!(apu.stems().unwrap()[0].is_empty());
+    }
+
+    #[test]
+    fn should_report_channel_status() {
+        let mut apu = powered_apu();
+        // 25% duty, volume 12, frequency 1750 which plays at 131072 / (2048 - 1750) Hz
+        apu.write(NR21, 0b0100_0000);
+        apu.write(NR22, 0xC0);
+        apu.write(NR21 + 2, 0xD6);
+        apu.write(NR24, 0x86);
+        let status = apu.status(1);
+        assert!(status.enabled && status.dac_enabled);
+        assert_eq!(status.volume, 12);
+        assert_eq!(status.duty, Some(25.0));
+        assert!((status.frequency - 131_072.0 / 298.0).abs() < 0.001);
+        assert!(!apu.status(2).enabled);
+        assert_eq!(apu.status(2).duty, None);
+    }
+}
@@ -0,0 +1,101 @@
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+// Volume envelope shared by the square and noise channels, configured through NRx2
+pub struct Envelope {
+    initial_volume: u8,
+    increase: bool,
+    period: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    pub fn new() -> Self {
+        Self {
+            initial_volume: 0,
+            increase: false,
+            period: 0,
+            volume: 0,
+            timer: 0,
+        }
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.initial_volume = value >> 4;
+        self.increase = value & 0b0000_1000 != 0;
+        self.period = value & 0b0000_0111;
+    }
+
+    pub fn trigger(&mut self) {
+        self.volume = self.initial_volume;
+        self.timer = self.period;
+    }
+
+    // Clocked at 64 Hz by the frame sequencer, a period of 0 freezes the volume
+    pub fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+        // The timer may still be 0 when the period was set without retriggering
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.increase && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+}
+
+impl Snapshot for Envelope {
+    fn save(&self, state: &mut StateWriter) {
+        state.u8(self.initial_volume);
+        state.bool(self.increase);
+        state.u8(self.period);
+        state.u8(self.volume);
+        state.u8(self.timer);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.initial_volume = state.u8()?;
+        self.increase = state.bool()?;
+        self.period = state.u8()?;
+        self.volume = state.u8()?;
+        self.timer = state.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_change_volume_once_per_period() {
+        let mut envelope = Envelope::new();
+        // Volume 2, decreasing every 2 clocks
+        envelope.write(0b0010_0010);
+        envelope.trigger();
+        envelope.clock();
+        assert_eq!(envelope.volume(), 2);
+        envelope.clock();
+        assert_eq!(envelope.volume(), 1);
+        (0..4).for_each(|_| envelope.clock());
+        assert_eq!(envelope.volume(), 0);
+    }
+
+    #[test]
+    fn should_stop_increasing_at_full_volume() {
+        let mut envelope = Envelope::new();
+        envelope.write(0b1110_1001);
+        envelope.trigger();
+        (0..5).for_each(|_| envelope.clock());
+        assert_eq!(envelope.volume(), 15);
+    }
+}
@@ -0,0 +1,75 @@
+use crate::cpu::FREQUENCY;
+use std::f32::consts::PI;
+
+// Charge kept by the DMG's output capacitor after every CPU cycle
+const CAPACITOR_CHARGE: f32 = 0.999958;
+
+// Output stage applied to each stereo sample: the capacitor the DMG uses to block the DACs' DC
+// offset, followed by an optional low-pass approximating the small speaker's muffled sound
+pub struct OutputFilter {
+    // Capacitor charge left after one sample's worth of cycles
+    charge: f32,
+    capacitor: (f32, f32),
+    // Share of the distance to the new input covered by each sample, none without a cutoff
+    low_pass: Option<f32>,
+    smoothed: (f32, f32),
+}
+
+impl OutputFilter {
+    pub fn new(sample_rate: u32, low_pass_cutoff: Option<u32>) -> Self {
+        Self {
+            charge: CAPACITOR_CHARGE.powf(FREQUENCY as f32 / sample_rate as f32),
+            capacitor: (0.0, 0.0),
+            low_pass: low_pass_cutoff
+                .map(|cutoff| 1.0 - (-2.0 * PI * cutoff as f32 / sample_rate as f32).exp()),
+            smoothed: (0.0, 0.0),
+        }
+    }
+
+    // The capacitor only charges while some DAC drives it
+    pub fn apply(&mut self, (left, right): (f32, f32), dacs_enabled: bool) -> (f32, f32) {
+        let (mut left, mut right) = if dacs_enabled {
+            let output = (left - self.capacitor.0, right - self.capacitor.1);
+            self.capacitor = (
+                left - output.0 * self.charge,
+                right - output.1 * self.charge,
+            );
+            output
+        } else {
+            (0.0, 0.0)
+        };
+        if let Some(alpha) = self.low_pass {
+            self.smoothed.0 += alpha * (left - self.smoothed.0);
+            self.smoothed.1 += alpha * (right - self.smoothed.1);
+            (left, right) = self.smoothed;
+        }
+        (left, right)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_remove_dc_offset() {
+        let mut filter = OutputFilter::new(48_000, None);
+        let first = filter.apply((0.5, -0.5), true);
+        assert_eq!(first, (0.5, -0.5));
+        for _ in 0..48_000 {
+            filter.apply((0.5, -0.5), true);
+        }
+        let (left, right) = filter.apply((0.5, -0.5), true);
+        assert!(left.abs() < 0.001 && right.abs() < 0.001);
+        assert_eq!(filter.apply((0.5, 0.5), false), (0.0, 0.0));
+    }
+
+    #[test]
+    fn should_smooth_steps_with_a_low_pass() {
+        let mut filter = OutputFilter::new(48_000, Some(4_000));
+        let (left, _) = filter.apply((0.5, 0.0), true);
+        assert!(left > 0.0 && left < 0.5);
+        let (next, _) = filter.apply((0.5, 0.0), true);
+        assert!(next > left);
+    }
+}
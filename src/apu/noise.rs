@@ -0,0 +1,156 @@
+use super::envelope::Envelope;
+use crate::cpu::FREQUENCY;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+// Base periods for the NR43 divisor codes, in CPU cycles
+const DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+// Channel 4, pseudo-random noise from a linear feedback shift register
+pub struct Noise {
+    clock_shift: u8,
+    // 7-bit mode gives a short, metallic sounding sequence instead of white noise
+    short_mode: bool,
+    divisor: u8,
+    lfsr: u16,
+    timer: u32,
+    envelope: Envelope,
+}
+
+impl Noise {
+    pub fn new() -> Self {
+        let mut noise = Self {
+            clock_shift: 0,
+            short_mode: false,
+            divisor: 0,
+            lfsr: 0x7FFF,
+            timer: 0,
+            envelope: Envelope::new(),
+        };
+        noise.timer = noise.period();
+        noise
+    }
+
+    // Register is the offset from the unused NR40
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            2 => self.envelope.write(value),
+            3 => {
+                self.clock_shift = value >> 4;
+                self.short_mode = value & 0b0000_1000 != 0;
+                self.divisor = value & 0b0000_0111;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn trigger(&mut self) {
+        self.timer = self.period();
+        self.lfsr = 0x7FFF;
+        self.envelope.trigger();
+    }
+
+    pub fn step(&mut self, mut cycles: u32) {
+        // Shifts of 14 and 15 leave the LFSR without clocks
+        if self.clock_shift >= 14 {
+            return;
+        }
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.shift();
+        }
+        self.timer -= cycles;
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    // Current amplitude, 0 to 15. The output is high while bit 0 of the LFSR is clear
+    pub fn output(&self) -> u8 {
+        (!self.lfsr & 1) as u8 * self.envelope.volume()
+    }
+
+    // LFSR shifts per second
+    pub fn frequency(&self) -> f64 {
+        FREQUENCY as f64 / self.period() as f64
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.envelope.volume()
+    }
+
+    fn shift(&mut self) {
+        let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+        self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+        if self.short_mode {
+            self.lfsr = (self.lfsr & !(1 << 6)) | (feedback << 6);
+        }
+    }
+
+    // CPU cycles per LFSR shift
+    fn period(&self) -> u32 {
+        DIVISORS[self.divisor as usize] << self.clock_shift
+    }
+}
+
+impl Snapshot for Noise {
+    fn save(&self, state: &mut StateWriter) {
+        state.u8(self.clock_shift);
+        state.bool(self.short_mode);
+        state.u8(self.divisor);
+        state.u16(self.lfsr);
+        state.u32(self.timer);
+        self.envelope.save(state);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.clock_shift = state.u8()? & 0xF;
+        self.short_mode = state.bool()?;
+        self.divisor = state.u8()? & 0b111;
+        self.lfsr = state.u16()? & 0x7FFF;
+        self.timer = state.u32()?;
+        self.envelope.load(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Number of shifts until the LFSR comes back to a value, after letting it run for a while
+    fn sequence_length(short_mode: bool) -> usize {
+        let mut noise = Noise::new();
+        noise.write(3, if short_mode { 0b0000_1000 } else { 0 });
+        noise.trigger();
+        // The short sequence isn't entered right from 0x7FFF, it needs a few shifts to settle
+        noise.step(8 * 200);
+        let start = noise.lfsr;
+        (1..=1 << 15)
+            .find(|_| {
+                noise.step(8);
+                noise.lfsr == start
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn should_repeat_after_full_lfsr_period() {
+        assert_eq!(sequence_length(false), 32767);
+    }
+
+    #[test]
+    fn should_repeat_after_127_shifts_in_short_mode() {
+        assert_eq!(sequence_length(true), 127);
+    }
+
+    #[test]
+    fn should_output_envelope_volume_while_bit_0_is_clear() {
+        let mut noise = Noise::new();
+        noise.write(2, 0xC0);
+        noise.trigger();
+        assert_eq!(noise.output(), 0);
+        noise.lfsr = 0x7FFE;
+        assert_eq!(noise.output(), 12);
+    }
+}
@@ -0,0 +1,266 @@
+use super::envelope::Envelope;
+use crate::cpu::FREQUENCY;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+// Waveforms for the four NRx1 duty settings (12.5%, 25%, 50% and 75%), one bit per step
+const DUTY_PATTERNS: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+const MAX_FREQUENCY: u16 = 2047;
+
+// Channel 1's frequency sweep, configured through NR10
+struct Sweep {
+    period: u8,
+    negate: bool,
+    shift: u8,
+    enabled: bool,
+    shadow: u16,
+    timer: u8,
+}
+
+impl Sweep {
+    fn new() -> Self {
+        Self {
+            period: 0,
+            negate: false,
+            shift: 0,
+            enabled: false,
+            shadow: 0,
+            timer: 0,
+        }
+    }
+
+    fn write(&mut self, value: u8) {
+        self.period = (value >> 4) & 0b111;
+        self.negate = value & 0b0000_1000 != 0;
+        self.shift = value & 0b0000_0111;
+    }
+
+    // A period of 0 still reloads the timer with 8
+    fn reload_timer(&mut self) {
+        self.timer = if self.period == 0 { 8 } else { self.period };
+    }
+
+    fn next_frequency(&self) -> u16 {
+        let delta = self.shadow >> self.shift;
+        if self.negate {
+            self.shadow - delta
+        } else {
+            self.shadow + delta
+        }
+    }
+}
+
+// Pulse channel, channel 1 is the one with a sweep unit
+pub struct Square {
+    duty: u8,
+    duty_step: u8,
+    frequency: u16,
+    timer: u32,
+    envelope: Envelope,
+    sweep: Option<Sweep>,
+}
+
+impl Square {
+    pub fn new(with_sweep: bool) -> Self {
+        let mut square = Self {
+            duty: 0,
+            duty_step: 0,
+            frequency: 0,
+            timer: 0,
+            envelope: Envelope::new(),
+            sweep: with_sweep.then(Sweep::new),
+        };
+        square.timer = square.period();
+        square
+    }
+
+    // Register is the offset from NRx0
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                if let Some(sweep) = &mut self.sweep {
+                    sweep.write(value);
+                }
+            }
+            1 => self.duty = value >> 6,
+            2 => self.envelope.write(value),
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            4 => self.frequency = (self.frequency & 0xFF) | ((value as u16 & 0b111) << 8),
+            _ => {}
+        }
+    }
+
+    // Returns false when the sweep's overflow check disables the channel right away
+    pub fn trigger(&mut self) -> bool {
+        self.timer = self.period();
+        self.envelope.trigger();
+        match &mut self.sweep {
+            Some(sweep) => {
+                sweep.shadow = self.frequency;
+                sweep.reload_timer();
+                sweep.enabled = sweep.period != 0 || sweep.shift != 0;
+                sweep.shift == 0 || sweep.next_frequency() <= MAX_FREQUENCY
+            }
+            None => true,
+        }
+    }
+
+    pub fn step(&mut self, mut cycles: u32) {
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+        self.timer -= cycles;
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    // Clocked at 128 Hz by the frame sequencer. Returns false when the new frequency overflows,
+    // which disables the channel
+    pub fn clock_sweep(&mut self) -> bool {
+        let Some(sweep) = &mut self.sweep else {
+            return true;
+        };
+        sweep.timer = sweep.timer.saturating_sub(1);
+        if sweep.timer > 0 {
+            return true;
+        }
+        sweep.reload_timer();
+        if !sweep.enabled || sweep.period == 0 {
+            return true;
+        }
+        let frequency = sweep.next_frequency();
+        if frequency > MAX_FREQUENCY {
+            return false;
+        }
+        if sweep.shift != 0 {
+            sweep.shadow = frequency;
+            self.frequency = frequency;
+        }
+        // The new frequency is checked for overflow again right away, without being applied
+        sweep.next_frequency() <= MAX_FREQUENCY
+    }
+
+    // Current amplitude, 0 to 15
+    pub fn output(&self) -> u8 {
+        let high = (DUTY_PATTERNS[self.duty as usize] >> (7 - self.duty_step)) & 1;
+        high * self.envelope.volume()
+    }
+
+    // Of the duty waveform, in Hz
+    pub fn frequency(&self) -> f64 {
+        FREQUENCY as f64 / (self.period() * 8) as f64
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.envelope.volume()
+    }
+
+    // Share of each cycle spent high, in percent
+    pub fn duty(&self) -> f32 {
+        [12.5, 25.0, 50.0, 75.0][self.duty as usize]
+    }
+
+    // CPU cycles per duty step
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 4
+    }
+}
+
+impl Snapshot for Sweep {
+    fn save(&self, state: &mut StateWriter) {
+        state.u8(self.period);
+        state.bool(self.negate);
+        state.u8(self.shift);
+        state.bool(self.enabled);
+        state.u16(self.shadow);
+        state.u8(self.timer);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.period = state.u8()?;
+        self.negate = state.bool()?;
+        self.shift = state.u8()?;
+        self.enabled = state.bool()?;
+        self.shadow = state.u16()?;
+        self.timer = state.u8()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Square {
+    fn save(&self, state: &mut StateWriter) {
+        state.u8(self.duty);
+        state.u8(self.duty_step);
+        state.u16(self.frequency);
+        state.u32(self.timer);
+        self.envelope.save(state);
+        // Whether there's a sweep depends on the channel, not on the state
+        if let Some(sweep) = &self.sweep {
+            sweep.save(state);
+        }
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.duty = state.u8()? & 0b11;
+        self.duty_step = state.u8()? & 0b111;
+        self.frequency = state.u16()? & MAX_FREQUENCY;
+        self.timer = state.u32()?;
+        self.envelope.load(state)?;
+        if let Some(sweep) = &mut self.sweep {
+            sweep.load(state)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_step_through_duty_pattern_at_channel_frequency() {
+        let mut square = Square::new(false);
+        // 50% duty, full volume, frequency 2047 which is 4 cycles per step
+        square.write(1, 0b1000_0000);
+        square.write(2, 0xF0);
+        square.write(3, 0xFF);
+        square.write(4, 0x07);
+        assert!(square.trigger());
+        let outputs: Vec<u8> = (0..8)
+            .map(|_| {
+                square.step(4);
+                square.output()
+            })
+            .collect();
+        assert_eq!(outputs, vec![0, 0, 0, 0, 15, 15, 15, 15]);
+    }
+
+    #[test]
+    fn should_sweep_frequency_up_until_it_overflows() {
+        let mut square = Square::new(true);
+        // Period 1, increasing, shift 1
+        square.write(0, 0b0001_0001);
+        square.write(3, 0x00);
+        square.write(4, 0x02);
+        assert!(square.trigger());
+        assert!(square.clock_sweep());
+        assert_eq!(square.frequency, 0x300);
+        assert!(square.clock_sweep());
+        assert_eq!(square.frequency, 0x480);
+        // 0x6C0 still fits, but the check that follows the update sees 0x6C0 + 0x360 overflow
+        assert!(!square.clock_sweep());
+        assert_eq!(square.frequency, 0x6C0);
+    }
+
+    #[test]
+    fn should_disable_on_trigger_when_first_sweep_would_overflow() {
+        let mut square = Square::new(true);
+        square.write(0, 0b0000_0001);
+        square.write(3, 0xFF);
+        square.write(4, 0x07);
+        assert!(!square.trigger());
+    }
+}
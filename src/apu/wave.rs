@@ -0,0 +1,162 @@
+use crate::cpu::FREQUENCY;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+// Right shifts for the NR32 output levels: mute, 100%, 50% and 25%
+const VOLUME_SHIFTS: [u8; 4] = [4, 0, 1, 2];
+const SAMPLES: u8 = 32;
+
+// Channel 3, plays the 32 4-bit samples stored in wave RAM
+pub struct Wave {
+    volume: u8,
+    frequency: u16,
+    timer: u32,
+    position: u8,
+    // The channel plays from a copy of the byte it last read, not from wave RAM directly
+    sample_buffer: u8,
+    cycles_since_read: u32,
+}
+
+impl Wave {
+    pub fn new() -> Self {
+        let mut wave = Self {
+            volume: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            sample_buffer: 0,
+            cycles_since_read: u32::MAX,
+        };
+        wave.timer = wave.period();
+        wave
+    }
+
+    // Register is the offset from NR30
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            2 => self.volume = (value >> 5) & 0b11,
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            4 => self.frequency = (self.frequency & 0xFF) | ((value as u16 & 0b111) << 8),
+            _ => {}
+        }
+    }
+
+    // Playback restarts from the first sample, the buffer keeps its old byte until the next read
+    pub fn trigger(&mut self) {
+        self.timer = self.period();
+        self.position = 0;
+    }
+
+    pub fn step(&mut self, mut cycles: u32, wave_ram: &[u8]) {
+        let mut read = false;
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.position = (self.position + 1) % SAMPLES;
+            self.sample_buffer = wave_ram[self.position as usize / 2];
+            read = true;
+        }
+        self.timer -= cycles;
+        self.cycles_since_read = if read {
+            cycles
+        } else {
+            self.cycles_since_read.saturating_add(cycles)
+        };
+    }
+
+    // Current amplitude, 0 to 15
+    pub fn output(&self) -> u8 {
+        let sample = if self.position.is_multiple_of(2) {
+            self.sample_buffer >> 4
+        } else {
+            self.sample_buffer & 0x0F
+        };
+        sample >> VOLUME_SHIFTS[self.volume as usize]
+    }
+
+    // While the channel plays, the CPU only reaches the wave RAM byte being played, and on the
+    // DMG only right as the channel reads it. Any other time reads give 0xFF and writes are lost
+    pub fn accessible_byte(&self) -> Option<usize> {
+        (self.cycles_since_read < 2).then_some(self.position as usize / 2)
+    }
+
+    // Of a full pass over the 32 samples, in Hz
+    pub fn frequency(&self) -> f64 {
+        FREQUENCY as f64 / (self.period() * SAMPLES as u32) as f64
+    }
+
+    // The output level on the envelope channels' 0 to 15 scale
+    pub fn volume(&self) -> u8 {
+        15 >> VOLUME_SHIFTS[self.volume as usize]
+    }
+
+    // CPU cycles per sample
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 2
+    }
+}
+
+impl Snapshot for Wave {
+    fn save(&self, state: &mut StateWriter) {
+        state.u8(self.volume);
+        state.u16(self.frequency);
+        state.u32(self.timer);
+        state.u8(self.position);
+        state.u8(self.sample_buffer);
+        state.u32(self.cycles_since_read);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.volume = state.u8()? & 0b11;
+        self.frequency = state.u16()? & 0x7FF;
+        self.timer = state.u32()?;
+        self.position = state.u8()? % SAMPLES;
+        self.sample_buffer = state.u8()?;
+        self.cycles_since_read = state.u32()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_play_samples_in_order_with_volume_shift() {
+        let mut wave = Wave::new();
+        let mut wave_ram = [0u8; 16];
+        wave_ram[0] = 0x8F;
+        wave_ram[1] = 0x42;
+        // 50% volume, frequency 2047 which is 2 cycles per sample
+        wave.write(2, 0b0100_0000);
+        wave.write(3, 0xFF);
+        wave.write(4, 0x07);
+        wave.trigger();
+        let outputs: Vec<u8> = (0..4)
+            .map(|_| {
+                wave.step(2, &wave_ram);
+                wave.output()
+            })
+            .collect();
+        // The first step moves to the second sample, the first one is only played after wrapping
+        assert_eq!(outputs, vec![7, 2, 1, 0]);
+    }
+
+    #[test]
+    fn should_only_expose_wave_ram_right_after_a_read() {
+        let mut wave = Wave::new();
+        let wave_ram = [0u8; 16];
+        // Frequency 2046, 4 cycles per sample
+        wave.write(3, 0xFE);
+        wave.write(4, 0x07);
+        wave.trigger();
+        assert_eq!(wave.accessible_byte(), None);
+        wave.step(4, &wave_ram);
+        assert_eq!(wave.accessible_byte(), Some(0));
+        wave.step(1, &wave_ram);
+        assert_eq!(wave.accessible_byte(), Some(0));
+        wave.step(1, &wave_ram);
+        assert_eq!(wave.accessible_byte(), None);
+        wave.step(2, &wave_ram);
+        assert_eq!(wave.accessible_byte(), Some(1));
+    }
+}
@@ -0,0 +1,10 @@
+use std::time::Duration;
+
+// Sound backend. The APU produces interleaved stereo f32 samples, an AudioSink plays them
+pub trait AudioSink {
+    // Rate the sink plays at, None when there's no audio output at all
+    fn sample_rate(&self) -> Option<u32>;
+    fn queue_audio(&mut self, samples: &[f32]);
+    // How long the samples queued so far take to play
+    fn queued_audio(&self) -> Option<Duration>;
+}
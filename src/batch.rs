@@ -0,0 +1,80 @@
+use crate::audio::AudioSink;
+use crate::cpu::CPU;
+use crate::event::EventSource;
+use crate::gpu::Renderer;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+// What a batch run ends with, for comparing runs across builds or collecting rollouts
+pub struct Report {
+    pub frames: u64,
+    // CRC-32 of the last frame, equal frames hash the same on every machine
+    pub framebuffer_hash: u32,
+    // Everything the game sent over the link port
+    pub serial: Vec<u8>,
+    pub state: Vec<u8>,
+}
+
+// Runs frames frames with whatever input the CPU was set up with, like a movie, and reports how
+// the run ended
+pub fn run<T: Renderer + EventSource + AudioSink>(cpu: &mut CPU<T>, frames: u64) -> Report {
+    let serial = Rc::new(RefCell::new(Vec::new()));
+    let sent = Rc::clone(&serial);
+    cpu.on_serial(move |bytes| sent.borrow_mut().extend_from_slice(bytes));
+    cpu.run_frames(frames);
+    let mut hash = flate2::Crc::new();
+    hash.update(cpu.gpu().frame());
+    Report {
+        frames,
+        framebuffer_hash: hash.sum(),
+        serial: serial.take(),
+        state: cpu.save_state(),
+    }
+}
+
+// Lines of "name: value" that are easy to diff or pick apart in a script
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "frames: {}", self.frames)?;
+        writeln!(f, "framebuffer: {:08x}", self.framebuffer_hash)?;
+        write!(f, "serial: {:?}", String::from_utf8_lossy(&self.serial))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::emulator::test_rom;
+    use crate::headless::NullRenderer;
+    use crate::memory::Memory;
+
+    // Sends "ok" over the link port, then spins in place
+    fn rom() -> Vec<u8> {
+        let mut rom = test_rom();
+        let program = [
+            0x3E, b'o', 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02, // SB = 'o', start a transfer
+            0x3E, b'k', 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02, // SB = 'k', start a transfer
+        ];
+        rom[..program.len()].copy_from_slice(&program);
+        rom
+    }
+
+    fn run_rom() -> Report {
+        let mut memory = Memory::new();
+        memory.insert_rom(rom());
+        let mut cpu = CPU::new(memory, NullRenderer, Config::default());
+        run(&mut cpu, 10)
+    }
+
+    #[test]
+    fn should_end_every_run_the_same_way() {
+        let report = run_rom();
+        assert_eq!(report.serial, b"ok");
+        let again = run_rom();
+        assert_eq!(report.framebuffer_hash, again.framebuffer_hash);
+        assert_eq!(report.state, again.state);
+        assert!(report.to_string().ends_with("serial: \"ok\""));
+    }
+}
@@ -0,0 +1,23 @@
+use gameboy_emulator::egui_frontend::EguiApp;
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+fn main() {
+    let rom = env::args().nth(1).map(PathBuf::from);
+    let options = eframe::NativeOptions {
+        viewport: eframe::egui::ViewportBuilder::default()
+            .with_title("Game Boy Emulator")
+            .with_inner_size([960.0, 640.0]),
+        ..Default::default()
+    };
+    let result = eframe::run_native(
+        "Game Boy Emulator",
+        options,
+        Box::new(|_| Ok(Box::new(EguiApp::new(rom)))),
+    );
+    if let Err(error) = result {
+        eprintln!("Couldn't open the window: {error}");
+        process::exit(1);
+    }
+}
@@ -0,0 +1,369 @@
+use crate::event::Action;
+use crate::joypad::Button;
+use crate::viewers::View;
+use std::error::Error;
+use std::fs;
+
+// What a key or controller button is bound to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Button(Button),
+    Action(Action),
+}
+
+// Names used in bindings files and by --print-bindings
+const TARGETS: [(&str, Target); 54] = [
+    ("up", Target::Button(Button::Up)),
+    ("down", Target::Button(Button::Down)),
+    ("left", Target::Button(Button::Left)),
+    ("right", Target::Button(Button::Right)),
+    ("a", Target::Button(Button::A)),
+    ("b", Target::Button(Button::B)),
+    ("select", Target::Button(Button::Select)),
+    ("start", Target::Button(Button::Start)),
+    ("cycle-scaling", Target::Action(Action::CycleScaling)),
+    ("scale-1", Target::Action(Action::SetScale(1))),
+    ("scale-2", Target::Action(Action::SetScale(2))),
+    ("scale-3", Target::Action(Action::SetScale(3))),
+    ("scale-4", Target::Action(Action::SetScale(4))),
+    ("scale-5", Target::Action(Action::SetScale(5))),
+    ("scale-6", Target::Action(Action::SetScale(6))),
+    ("cycle-palette", Target::Action(Action::CyclePalette)),
+    ("cycle-filter", Target::Action(Action::CycleFilter)),
+    ("toggle-ghosting", Target::Action(Action::ToggleGhosting)),
+    (
+        "tiles-view",
+        Target::Action(Action::ToggleView(View::Tiles)),
+    ),
+    (
+        "background-map-view",
+        Target::Action(Action::ToggleView(View::BackgroundMap)),
+    ),
+    ("oam-view", Target::Action(Action::ToggleView(View::Oam))),
+    ("capture", Target::Action(Action::ToggleCapture)),
+    ("record-audio", Target::Action(Action::ToggleAudioRecording)),
+    ("fullscreen", Target::Action(Action::ToggleFullscreen)),
+    ("screenshot", Target::Action(Action::Screenshot)),
+    ("mute-1", Target::Action(Action::ToggleMute(0))),
+    ("mute-2", Target::Action(Action::ToggleMute(1))),
+    ("mute-3", Target::Action(Action::ToggleMute(2))),
+    ("mute-4", Target::Action(Action::ToggleMute(3))),
+    ("solo-1", Target::Action(Action::SoloChannel(0))),
+    ("solo-2", Target::Action(Action::SoloChannel(1))),
+    ("solo-3", Target::Action(Action::SoloChannel(2))),
+    ("solo-4", Target::Action(Action::SoloChannel(3))),
+    ("pause", Target::Action(Action::TogglePause)),
+    ("fast-forward", Target::Action(Action::FastForward)),
+    ("rewind", Target::Action(Action::Rewind)),
+    ("slow-motion", Target::Action(Action::CycleSlowMotion)),
+    ("frame-advance", Target::Action(Action::FrameAdvance)),
+    ("save-state", Target::Action(Action::SaveState)),
+    ("load-state", Target::Action(Action::LoadState)),
+    ("slot-0", Target::Action(Action::SelectSlot(0))),
+    ("slot-1", Target::Action(Action::SelectSlot(1))),
+    ("slot-2", Target::Action(Action::SelectSlot(2))),
+    ("slot-3", Target::Action(Action::SelectSlot(3))),
+    ("slot-4", Target::Action(Action::SelectSlot(4))),
+    ("slot-5", Target::Action(Action::SelectSlot(5))),
+    ("slot-6", Target::Action(Action::SelectSlot(6))),
+    ("slot-7", Target::Action(Action::SelectSlot(7))),
+    ("slot-8", Target::Action(Action::SelectSlot(8))),
+    ("slot-9", Target::Action(Action::SelectSlot(9))),
+    ("reset", Target::Action(Action::Reset)),
+    ("hard-reset", Target::Action(Action::HardReset)),
+    ("recent-roms", Target::Action(Action::RecentRoms)),
+    ("quit", Target::Action(Action::Quit)),
+];
+
+impl Target {
+    pub fn name(&self) -> &'static str {
+        TARGETS
+            .iter()
+            .find(|(_, target)| target == self)
+            .map(|(name, _)| *name)
+            .unwrap_or("unknown")
+    }
+
+    pub fn from_name(name: &str) -> Option<Target> {
+        TARGETS
+            .iter()
+            .find(|(known, _)| *known == name)
+            .map(|(_, target)| *target)
+    }
+}
+
+// A key plus the modifiers that have to be held with it, written like "Ctrl+Shift+1". Key names
+// are the frontend's, SDL's for the SDL frontend
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chord {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub key: String,
+}
+
+impl Chord {
+    pub fn parse(text: &str) -> Chord {
+        let mut chord = Chord {
+            ctrl: false,
+            shift: false,
+            alt: false,
+            key: String::new(),
+        };
+        let mut rest = text;
+        loop {
+            if let Some(key) = rest.strip_prefix("Ctrl+") {
+                chord.ctrl = true;
+                rest = key;
+            } else if let Some(key) = rest.strip_prefix("Shift+") {
+                chord.shift = true;
+                rest = key;
+            } else if let Some(key) = rest.strip_prefix("Alt+") {
+                chord.alt = true;
+                rest = key;
+            } else {
+                break;
+            }
+        }
+        chord.key = rest.to_string();
+        chord
+    }
+
+    pub fn modifiers(&self) -> usize {
+        [self.ctrl, self.shift, self.alt]
+            .iter()
+            .filter(|held| **held)
+            .count()
+    }
+}
+
+// Keyboard chords and controller buttons for every Game Boy button and hotkey. A file only needs
+// to list what it changes, each entry replaces all the default inputs of its target
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bindings {
+    pub keys: Vec<(Target, Vec<String>)>,
+    // SDL game controller button names, like "a" or "dpup"
+    pub controller: Vec<(Button, Vec<String>)>,
+}
+
+impl Bindings {
+    // A JSON object with optional "keys" and "controller" objects, mapping target names to an
+    // input or a list of them:
+    // {"keys": {"a": "S", "fullscreen": ["F11", "Alt+Return"]}, "controller": {"a": "a"}}
+    pub fn apply_file(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        self.apply(&fs::read_to_string(path)?)
+    }
+
+    pub fn describe(&self) -> Vec<String> {
+        let list = |inputs: &Vec<String>| {
+            if inputs.is_empty() {
+                "unbound".to_string()
+            } else {
+                inputs.join(", ")
+            }
+        };
+        let keys = self
+            .keys
+            .iter()
+            .map(|(target, chords)| format!("{}: {}", target.name(), list(chords)));
+        let controller = self.controller.iter().map(|(button, names)| {
+            format!(
+                "{} (controller): {}",
+                Target::Button(*button).name(),
+                list(names)
+            )
+        });
+        keys.chain(controller).collect()
+    }
+
+    fn apply(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        self.apply_value(&serde_json::from_str(text)?)
+    }
+
+    // The same object as a file, already parsed
+    pub fn apply_value(&mut self, value: &serde_json::Value) -> Result<(), Box<dyn Error>> {
+        if let Some(keys) = value.get("keys") {
+            for (name, inputs) in Self::entries(keys)? {
+                let target = Target::from_name(&name).ok_or(format!("Unknown binding: {name}"))?;
+                Self::rebind(&mut self.keys, target, inputs);
+            }
+        }
+        if let Some(controller) = value.get("controller") {
+            for (name, inputs) in Self::entries(controller)? {
+                match Target::from_name(&name) {
+                    Some(Target::Button(button)) => {
+                        Self::rebind(&mut self.controller, button, inputs)
+                    }
+                    _ => return Err(format!("Unknown controller binding: {name}").into()),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn entries(value: &serde_json::Value) -> Result<Vec<(String, Vec<String>)>, String> {
+        let object = value.as_object().ok_or("Bindings must be an object")?;
+        object
+            .iter()
+            .map(|(name, inputs)| {
+                let inputs = match inputs {
+                    serde_json::Value::String(input) => vec![input.clone()],
+                    serde_json::Value::Array(inputs) => inputs
+                        .iter()
+                        .map(|input| input.as_str().map(str::to_string))
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or(format!("Inputs for {name} must be strings"))?,
+                    _ => return Err(format!("Inputs for {name} must be strings")),
+                };
+                Ok((name.clone(), inputs))
+            })
+            .collect()
+    }
+
+    // Inputs taken by the target are freed from whatever they were bound to before
+    fn rebind<T: PartialEq>(bindings: &mut Vec<(T, Vec<String>)>, target: T, inputs: Vec<String>) {
+        for (_, bound) in bindings.iter_mut() {
+            bound.retain(|input| !inputs.contains(input));
+        }
+        match bindings.iter_mut().find(|(bound, _)| *bound == target) {
+            Some((_, bound)) => *bound = inputs,
+            None => bindings.push((target, inputs)),
+        }
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let keys = [
+            ("up", &["Up"][..]),
+            ("down", &["Down"]),
+            ("left", &["Left"]),
+            ("right", &["Right"]),
+            ("a", &["X"]),
+            ("b", &["Z"]),
+            ("select", &["Left Shift", "Right Shift"]),
+            ("start", &["Return"]),
+            ("cycle-scaling", &["F1"]),
+            // Plain digits pick save state slots
+            ("scale-1", &["Alt+1"]),
+            ("scale-2", &["Alt+2"]),
+            ("scale-3", &["Alt+3"]),
+            ("scale-4", &["Alt+4"]),
+            ("scale-5", &["Alt+5"]),
+            ("scale-6", &["Alt+6"]),
+            ("cycle-palette", &["F2"]),
+            ("cycle-filter", &["F3"]),
+            ("toggle-ghosting", &["F4"]),
+            ("tiles-view", &["F6"]),
+            ("background-map-view", &["F8"]),
+            ("oam-view", &["F10"]),
+            ("capture", &["F9"]),
+            ("record-audio", &["Shift+F9"]),
+            ("fullscreen", &["F11", "Alt+Return"]),
+            ("screenshot", &["F12"]),
+            ("mute-1", &["Ctrl+1"]),
+            ("mute-2", &["Ctrl+2"]),
+            ("mute-3", &["Ctrl+3"]),
+            ("mute-4", &["Ctrl+4"]),
+            ("solo-1", &["Ctrl+Shift+1"]),
+            ("solo-2", &["Ctrl+Shift+2"]),
+            ("solo-3", &["Ctrl+Shift+3"]),
+            ("solo-4", &["Ctrl+Shift+4"]),
+            ("pause", &["P"]),
+            ("fast-forward", &["Tab"]),
+            ("rewind", &["Backspace"]),
+            ("slow-motion", &["`"]),
+            ("frame-advance", &["N"]),
+            ("save-state", &["F5"]),
+            ("load-state", &["F7"]),
+            ("slot-0", &["0"]),
+            ("slot-1", &["1"]),
+            ("slot-2", &["2"]),
+            ("slot-3", &["3"]),
+            ("slot-4", &["4"]),
+            ("slot-5", &["5"]),
+            ("slot-6", &["6"]),
+            ("slot-7", &["7"]),
+            ("slot-8", &["8"]),
+            ("slot-9", &["9"]),
+            ("reset", &["Ctrl+R"]),
+            ("hard-reset", &["Ctrl+Shift+R"]),
+            ("recent-roms", &["Ctrl+O"]),
+            ("quit", &["Escape"]),
+        ];
+        // Face buttons go by position, the Game Boy's B sits lower left of A like a pad's south
+        // and east buttons
+        let controller = [
+            (Button::Up, "dpup"),
+            (Button::Down, "dpdown"),
+            (Button::Left, "dpleft"),
+            (Button::Right, "dpright"),
+            (Button::A, "b"),
+            (Button::B, "a"),
+            (Button::Select, "back"),
+            (Button::Start, "start"),
+        ];
+        Self {
+            keys: keys
+                .iter()
+                .map(|(name, chords)| {
+                    (
+                        Target::from_name(name).unwrap(),
+                        chords.iter().map(|chord| chord.to_string()).collect(),
+                    )
+                })
+                .collect(),
+            controller: controller
+                .iter()
+                .map(|(button, name)| (*button, vec![name.to_string()]))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_chords() {
+        let chord = Chord::parse("Ctrl+Shift+1");
+        assert!(chord.ctrl && chord.shift && !chord.alt);
+        assert_eq!(chord.key, "1");
+        assert_eq!(chord.modifiers(), 2);
+        assert_eq!(Chord::parse("Left Shift").key, "Left Shift");
+    }
+
+    #[test]
+    fn should_override_defaults_and_free_taken_inputs() {
+        let mut bindings = Bindings::default();
+        bindings
+            .apply(r#"{"keys": {"b": "X", "screenshot": ["F12", "P"]}, "controller": {"a": ["a", "y"]}}"#)
+            .unwrap();
+        let keys = |name| {
+            let target = Target::from_name(name).unwrap();
+            bindings
+                .keys
+                .iter()
+                .find(|(bound, _)| *bound == target)
+                .unwrap()
+                .1
+                .clone()
+        };
+        assert_eq!(keys("b"), vec!["X"]);
+        assert!(keys("a").is_empty());
+        assert_eq!(keys("screenshot"), vec!["F12", "P"]);
+        assert!(
+            bindings
+                .controller
+                .contains(&(Button::A, vec!["a".to_string(), "y".to_string()]))
+        );
+        assert!(bindings.controller.contains(&(Button::B, Vec::new())));
+        assert!(bindings.apply(r#"{"keys": {"jump": "Space"}}"#).is_err());
+        assert!(
+            bindings
+                .apply(r#"{"controller": {"screenshot": "x"}}"#)
+                .is_err()
+        );
+    }
+}
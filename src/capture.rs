@@ -0,0 +1,201 @@
+use crate::frame_limiter::FRAME_RATE;
+use crate::gpu::{BYTES_PER_PIXEL, FRAME_HEIGHT, FRAME_WIDTH};
+use crate::paths;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+// ffmpeg output is upscaled, most players and sites blur a 160x144 video into mush otherwise
+const FFMPEG_SCALE: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaptureFormat {
+    Gif,
+    // Raw frames piped to an ffmpeg process encoding an H.264 MP4
+    Ffmpeg,
+}
+
+impl CaptureFormat {
+    pub fn from_name(name: &str) -> Option<CaptureFormat> {
+        match name {
+            "gif" => Some(CaptureFormat::Gif),
+            "ffmpeg" => Some(CaptureFormat::Ffmpeg),
+            _ => None,
+        }
+    }
+}
+
+enum Encoder {
+    Gif {
+        encoder: gif::Encoder<BufWriter<File>>,
+        // GIF delays are in whole centiseconds, the error is carried over to the next frame so
+        // the average speed matches the Game Boy's frame rate
+        elapsed: f64,
+        written: u64,
+    },
+    Ffmpeg(Child),
+}
+
+pub struct Capture {
+    encoder: Encoder,
+    path: PathBuf,
+}
+
+impl Capture {
+    pub fn start(format: CaptureFormat, dir: &Path) -> Result<Self, Box<dyn Error>> {
+        match format {
+            CaptureFormat::Gif => {
+                let path = paths::timestamped_file(dir, "capture", "gif")?;
+                let file = BufWriter::new(File::create(&path)?);
+                let mut encoder =
+                    gif::Encoder::new(file, FRAME_WIDTH as u16, FRAME_HEIGHT as u16, &[])?;
+                encoder.set_repeat(gif::Repeat::Infinite)?;
+                Ok(Self {
+                    encoder: Encoder::Gif {
+                        encoder,
+                        elapsed: 0.0,
+                        written: 0,
+                    },
+                    path,
+                })
+            }
+            CaptureFormat::Ffmpeg => {
+                let path = paths::timestamped_file(dir, "capture", "mp4")?;
+                let child = Command::new("ffmpeg")
+                    .args(["-loglevel", "error", "-y", "-f", "rawvideo"])
+                    .args(["-pixel_format", "rgb24"])
+                    .args(["-video_size", &format!("{FRAME_WIDTH}x{FRAME_HEIGHT}")])
+                    .args(["-framerate", &FRAME_RATE.to_string(), "-i", "-"])
+                    .args([
+                        "-vf",
+                        &format!(
+                            "scale={}:{}:flags=neighbor",
+                            FRAME_WIDTH * FFMPEG_SCALE,
+                            FRAME_HEIGHT * FFMPEG_SCALE
+                        ),
+                    ])
+                    .args(["-c:v", "libx264", "-pix_fmt", "yuv420p"])
+                    .arg(&path)
+                    .stdin(Stdio::piped())
+                    .spawn()
+                    .map_err(|error| format!("Couldn't start ffmpeg: {error}"))?;
+                Ok(Self {
+                    encoder: Encoder::Ffmpeg(child),
+                    path,
+                })
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn push_frame(&mut self, frame: &[u8]) -> Result<(), Box<dyn Error>> {
+        match &mut self.encoder {
+            Encoder::Gif {
+                encoder,
+                elapsed,
+                written,
+            } => {
+                *elapsed += 100.0 / FRAME_RATE;
+                let delay = (elapsed.round() as u64 - *written) as u16;
+                *written += delay as u64;
+                let mut gif_frame = Self::indexed_frame(frame);
+                gif_frame.delay = delay;
+                encoder.write_frame(&gif_frame)?;
+            }
+            Encoder::Ffmpeg(child) => {
+                let stdin = child.stdin.as_mut().ok_or("ffmpeg's input was closed")?;
+                stdin.write_all(frame)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Flushes and closes the file, waiting for ffmpeg to finish encoding
+    pub fn finish(self) -> Result<PathBuf, Box<dyn Error>> {
+        match self.encoder {
+            Encoder::Gif { encoder, .. } => {
+                encoder.into_inner()?.flush()?;
+            }
+            Encoder::Ffmpeg(mut child) => {
+                drop(child.stdin.take());
+                let status = child.wait()?;
+                if !status.success() {
+                    return Err(format!("ffmpeg exited with {status}").into());
+                }
+            }
+        }
+        Ok(self.path)
+    }
+
+    // DMG frames hold at most a handful of colors, so they map to a local palette exactly instead
+    // of going through gif's much slower color quantization
+    fn indexed_frame(frame: &[u8]) -> gif::Frame<'static> {
+        let mut palette: Vec<[u8; 3]> = Vec::new();
+        let mut indices = Vec::with_capacity(FRAME_WIDTH * FRAME_HEIGHT);
+        for pixel in frame.chunks(BYTES_PER_PIXEL) {
+            let color = [pixel[0], pixel[1], pixel[2]];
+            let index = match palette.iter().position(|known| *known == color) {
+                Some(index) => index,
+                None if palette.len() < 256 => {
+                    palette.push(color);
+                    palette.len() - 1
+                }
+                None => {
+                    return gif::Frame::from_rgb_speed(
+                        FRAME_WIDTH as u16,
+                        FRAME_HEIGHT as u16,
+                        frame,
+                        10,
+                    );
+                }
+            };
+            indices.push(index as u8);
+        }
+        gif::Frame::from_palette_pixels(
+            FRAME_WIDTH as u16,
+            FRAME_HEIGHT as u16,
+            indices,
+            palette.as_flattened(),
+            None,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpu::FRAME_SIZE;
+    use std::fs;
+
+    #[test]
+    fn should_write_gif_capture_at_game_boy_speed() {
+        let dir = std::env::temp_dir().join("gameboy-emulator-capture-test");
+        let mut capture = Capture::start(CaptureFormat::Gif, &dir).unwrap();
+        // 60 frames at ~59.73 fps last just over a second
+        for frame in 0..60 {
+            capture.push_frame(&[frame as u8; FRAME_SIZE]).unwrap();
+        }
+        match &capture.encoder {
+            Encoder::Gif { written, .. } => assert_eq!(*written, 100),
+            Encoder::Ffmpeg(_) => unreachable!(),
+        }
+        let path = capture.finish().unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        assert!(bytes.starts_with(b"GIF89a"));
+        assert_eq!(bytes.last(), Some(&0x3B));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_index_frames_with_an_exact_palette() {
+        let frame = Capture::indexed_frame(&[0x12; FRAME_SIZE]);
+        assert_eq!(frame.palette.as_deref(), Some(&[0x12, 0x12, 0x12][..]));
+        assert!(frame.buffer.iter().all(|index| *index == 0));
+    }
+}
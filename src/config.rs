@@ -0,0 +1,492 @@
+use crate::capture::CaptureFormat;
+use crate::display::Scaling;
+use crate::filter::Filter;
+use crate::layout::Layout;
+use crate::paths;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+const CONFIG_FILE: &str = "config.toml";
+
+#[derive(Clone)]
+pub struct Config {
+    // The game to play, the only argument without dashes
+    pub rom: Option<PathBuf>,
+    // Play the ROM played last
+    pub last: bool,
+    // Run without opening a window or reading input
+    pub headless: bool,
+    // Let the display's vertical sync pace frames instead of the built-in frame limiter
+    pub vsync: bool,
+    // Let the audio device's consumption pace emulation, for drift-free sound
+    pub audio_sync: bool,
+    // Number of frames emulated without being rendered after each rendered frame
+    pub frame_skip: u32,
+    // Speed while fast-forwarding as a multiple of normal, 0 for as fast as possible
+    pub fast_forward_speed: u32,
+    // Speed to start at in percent, below 100 for slow motion
+    pub speed: u32,
+    // Silence slow motion instead of stretching the sound to match
+    pub mute_slow_motion: bool,
+    // How far back rewinding goes, 0 turns it off
+    pub rewind_seconds: u32,
+    // Pause while none of the emulator's windows has focus
+    pub pause_in_background: bool,
+    pub palette: Option<String>,
+    pub palette_files: Vec<String>,
+    pub filter: Filter,
+    pub ghosting: bool,
+    // Initial window size, in multiples of 160x144
+    pub scale: u32,
+    pub scaling: Scaling,
+    pub sample_rate: u32,
+    // Size of the audio device's buffer, in samples per channel
+    pub audio_buffer: u16,
+    // Name of the playback device, the system default otherwise
+    pub audio_device: Option<String>,
+    // Print the playback devices and exit
+    pub list_audio_devices: bool,
+    // JSON file remapping keys and controller buttons
+    pub bindings_file: Option<String>,
+    // Print the active bindings and exit
+    pub print_bindings: bool,
+    // Bindings from the config file, shaped like a --bindings file. --bindings goes on top
+    pub key_bindings: Option<serde_json::Value>,
+    // Input movie to record from power-on, or to play back instead of live input
+    pub record_movie: Option<PathBuf>,
+    pub play_movie: Option<PathBuf>,
+    // Movie from another emulator to convert to the native format, then exit
+    pub convert_movie: Option<PathBuf>,
+    // Index of the game controller driving the joypad, the first one connected otherwise
+    pub controller: Option<u32>,
+    // Frames the cartridge's rumble is averaged over before it reaches the controller
+    pub rumble_smoothing: u32,
+    // Link cable over TCP: the address to wait for the other emulator on, or the one to connect to
+    pub link_listen: Option<String>,
+    pub link_connect: Option<String>,
+    // Run a second emulator linked to this one, in its own window
+    pub dual: bool,
+    // Let the linked emulator see the infrared LED
+    pub infrared_link: bool,
+    // Where bytes sent over the link port are written, "-" for stdout
+    pub serial_log: Option<PathBuf>,
+    // Cutoff in Hz of a low-pass on the sound output, approximating the DMG speaker
+    pub low_pass: Option<u32>,
+    // Where a game's files go, and overrides for its screenshots and captures
+    pub layout: Layout,
+    pub screenshot_dir: Option<PathBuf>,
+    pub capture_format: CaptureFormat,
+    pub capture_dir: Option<PathBuf>,
+    // Also record every sound channel to its own WAV file
+    pub wav_stems: bool,
+    // Batch run: this many frames as fast as possible without a window, then a report and quit
+    pub frames: Option<u64>,
+    // Where a batch run saves the state it ends in
+    pub final_state: Option<PathBuf>,
+    // Socket the debugger takes its commands from instead of the terminal
+    pub debug_socket: Option<String>,
+}
+
+impl Config {
+    // Settings come from the config file first and the command line overrides them, on/off ones
+    // with their --no- forms. The file's keys are the flags without their dashes, plus [keys] and
+    // [controller] tables for bindings:
+    // scale = 4
+    // palette-file = ["dark.pal", "sepia.pal"]
+    // [keys]
+    // a = "S"
+    pub fn load(args: Vec<String>) -> Result<Self, String> {
+        let path = match args.iter().position(|arg| arg == "--config") {
+            Some(index) => Some(PathBuf::from(
+                args.get(index + 1).ok_or("Missing value for --config")?,
+            )),
+            None if args.iter().any(|arg| arg == "--no-config") => None,
+            None => paths::config_dir()
+                .map(|dir| dir.join(CONFIG_FILE))
+                .filter(|path| path.exists()),
+        };
+        let (file_args, key_bindings) = match &path {
+            Some(path) => fs::read_to_string(path)
+                .map_err(|error| error.to_string())
+                .and_then(|text| Self::file_args(&text))
+                .map_err(|error| format!("Problem reading config {}: {error}", path.display()))?,
+            None => (Vec::new(), None),
+        };
+        let mut config = Self::default();
+        config.apply_args(file_args.into_iter())?;
+        let mut command_line = Self::default();
+        command_line.apply_args(args.iter().cloned())?;
+        config.forget_replaced(&command_line);
+        config.apply_args(args.into_iter())?;
+        config.check()?;
+        config.key_bindings = key_bindings;
+        Ok(config)
+    }
+
+    // The config file as the flags it stands for, and its bindings
+    fn file_args(text: &str) -> Result<(Vec<String>, Option<serde_json::Value>), String> {
+        let table: toml::Table = text
+            .parse()
+            .map_err(|error: toml::de::Error| error.message().to_string())?;
+        let mut args = Vec::new();
+        let mut bindings = serde_json::Map::new();
+        for (key, value) in table {
+            let flag = format!("--{key}");
+            match value {
+                toml::Value::Boolean(true) => args.push(flag),
+                toml::Value::Boolean(false) => args.push(format!("--no-{key}")),
+                toml::Value::Table(table) if key == "keys" || key == "controller" => {
+                    let table = serde_json::to_value(table).map_err(|error| error.to_string())?;
+                    bindings.insert(key, table);
+                }
+                // Flags that can be repeated
+                toml::Value::Array(values) => {
+                    for value in values {
+                        args.push(flag.clone());
+                        args.push(file_value(&key, value)?);
+                    }
+                }
+                value => {
+                    args.push(flag);
+                    args.push(file_value(&key, value)?);
+                }
+            }
+        }
+        Ok((
+            args,
+            (!bindings.is_empty()).then_some(serde_json::Value::Object(bindings)),
+        ))
+    }
+
+    pub fn from_args(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut config = Self::default();
+        config.apply_args(args)?;
+        config.check()?;
+        Ok(config)
+    }
+
+    // Sets what the flags ask for on top of the settings so far. Every on/off option also has a
+    // --no- form turning it off, so the command line can undo the config file
+    fn apply_args(&mut self, mut args: impl Iterator<Item = String>) -> Result<(), String> {
+        while let Some(arg) = args.next() {
+            if let Some(name) = arg.strip_prefix("--no-")
+                && self.set_switch(name, false)
+            {
+                continue;
+            }
+            if let Some(name) = arg.strip_prefix("--")
+                && self.set_switch(name, true)
+            {
+                continue;
+            }
+            match arg.as_str() {
+                // Handled by load
+                "--config" => {
+                    args.next();
+                }
+                "--no-config" => {}
+                "--frame-skip" => self.frame_skip = parse_value(&arg, args.next())?,
+                "--fast-forward-speed" => self.fast_forward_speed = parse_value(&arg, args.next())?,
+                "--speed" => {
+                    self.speed = parse_value(&arg, args.next())?;
+                    if !(1..=100).contains(&self.speed) {
+                        return Err("--speed must be between 1 and 100".to_string());
+                    }
+                }
+                "--rewind-seconds" => self.rewind_seconds = parse_value(&arg, args.next())?,
+                "--palette" => self.palette = Some(parse_value(&arg, args.next())?),
+                "--palette-file" => self.palette_files.push(parse_value(&arg, args.next())?),
+                "--filter" => {
+                    let name: String = parse_value(&arg, args.next())?;
+                    self.filter =
+                        Filter::from_name(&name).ok_or(format!("Unknown filter: {name}"))?;
+                }
+                "--scale" => {
+                    self.scale = parse_value(&arg, args.next())?;
+                    if self.scale == 0 {
+                        return Err("--scale must be at least 1".to_string());
+                    }
+                }
+                "--scaling" => {
+                    let name: String = parse_value(&arg, args.next())?;
+                    self.scaling =
+                        Scaling::from_name(&name).ok_or(format!("Unknown scaling: {name}"))?;
+                }
+                "--sample-rate" => self.sample_rate = parse_value(&arg, args.next())?,
+                "--audio-buffer" => self.audio_buffer = parse_value(&arg, args.next())?,
+                "--audio-device" => self.audio_device = Some(parse_value(&arg, args.next())?),
+                "--bindings" => self.bindings_file = Some(parse_value(&arg, args.next())?),
+                "--record-movie" => self.record_movie = Some(parse_value(&arg, args.next())?),
+                "--play-movie" => self.play_movie = Some(parse_value(&arg, args.next())?),
+                "--convert-movie" => self.convert_movie = Some(parse_value(&arg, args.next())?),
+                "--controller" => self.controller = Some(parse_value(&arg, args.next())?),
+                "--rumble-smoothing" => {
+                    self.rumble_smoothing = parse_value(&arg, args.next())?;
+                    if self.rumble_smoothing == 0 {
+                        return Err("--rumble-smoothing must be at least 1".to_string());
+                    }
+                }
+                "--link-listen" => self.link_listen = Some(parse_value(&arg, args.next())?),
+                "--link-connect" => self.link_connect = Some(parse_value(&arg, args.next())?),
+                "--serial-log" => self.serial_log = Some(parse_value(&arg, args.next())?),
+                "--low-pass" => {
+                    let cutoff = parse_value(&arg, args.next())?;
+                    if cutoff == 0 {
+                        return Err("--low-pass must be at least 1".to_string());
+                    }
+                    self.low_pass = Some(cutoff);
+                }
+                "--layout" => {
+                    let name: String = parse_value(&arg, args.next())?;
+                    self.layout =
+                        Layout::from_name(&name).ok_or(format!("Unknown layout: {name}"))?;
+                }
+                "--screenshot-dir" => self.screenshot_dir = Some(parse_value(&arg, args.next())?),
+                "--capture-format" => {
+                    let name: String = parse_value(&arg, args.next())?;
+                    self.capture_format = CaptureFormat::from_name(&name)
+                        .ok_or(format!("Unknown capture format: {name}"))?;
+                }
+                "--capture-dir" => self.capture_dir = Some(parse_value(&arg, args.next())?),
+                "--frames" => self.frames = Some(parse_value(&arg, args.next())?),
+                "--final-state" => self.final_state = Some(parse_value(&arg, args.next())?),
+                "--debug-socket" => self.debug_socket = Some(parse_value(&arg, args.next())?),
+                _ if !arg.starts_with('-') && self.rom.is_none() => {
+                    self.rom = Some(PathBuf::from(arg))
+                }
+                _ => return Err(format!("Unknown argument: {arg}")),
+            }
+        }
+        Ok(())
+    }
+
+    // Turns an on/off option on or off by its name without dashes, returns whether it is one
+    fn set_switch(&mut self, name: &str, on: bool) -> bool {
+        match name {
+            "last" => self.last = on,
+            "headless" => self.headless = on,
+            "vsync" => self.vsync = on,
+            "audio-sync" => self.audio_sync = on,
+            "mute-slow-motion" => self.mute_slow_motion = on,
+            "run-in-background" => self.pause_in_background = !on,
+            "ghosting" => self.ghosting = on,
+            "dual" => self.dual = on,
+            "infrared-link" => self.infrared_link = on,
+            "wav-stems" => self.wav_stems = on,
+            "list-audio-devices" => self.list_audio_devices = on,
+            "print-bindings" => self.print_bindings = on,
+            _ => return false,
+        }
+        true
+    }
+
+    // Drops the config file's settings that the command line replaces as a whole rather than
+    // adding to: a ROM or --last, a link, and the list of palette files
+    fn forget_replaced(&mut self, command_line: &Config) {
+        if command_line.rom.is_some() || command_line.last {
+            self.rom = None;
+            self.last = false;
+        }
+        if command_line.link_listen.is_some()
+            || command_line.link_connect.is_some()
+            || command_line.dual
+        {
+            self.link_listen = None;
+            self.link_connect = None;
+            self.dual = false;
+        }
+        if !command_line.palette_files.is_empty() {
+            self.palette_files.clear();
+        }
+    }
+
+    // Settings that don't go together, checked once the command line had its say
+    fn check(&mut self) -> Result<(), String> {
+        let links = [
+            self.link_listen.is_some(),
+            self.link_connect.is_some(),
+            self.dual,
+        ];
+        if links.iter().filter(|set| **set).count() > 1 {
+            return Err("Use only one of --link-listen, --link-connect and --dual".to_string());
+        }
+        if self.rom.is_some() && self.last {
+            return Err("Give either a ROM or --last, not both".to_string());
+        }
+        if self.frames.is_some() {
+            if self.dual {
+                return Err("A batch run with --frames can't have a second screen".to_string());
+            }
+            self.headless = true;
+        } else if self.final_state.is_some() {
+            return Err("--final-state only applies to batch runs with --frames".to_string());
+        }
+        if self.dual && self.headless {
+            return Err("--dual opens a second window, it can't run --headless".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            rom: None,
+            last: false,
+            headless: false,
+            vsync: false,
+            audio_sync: false,
+            frame_skip: 0,
+            fast_forward_speed: 0,
+            speed: 100,
+            mute_slow_motion: false,
+            rewind_seconds: 30,
+            pause_in_background: true,
+            palette: None,
+            palette_files: Vec::new(),
+            filter: Filter::Nearest,
+            ghosting: false,
+            scale: 7,
+            scaling: Scaling::Fit,
+            sample_rate: 48_000,
+            audio_buffer: 1024,
+            audio_device: None,
+            list_audio_devices: false,
+            bindings_file: None,
+            print_bindings: false,
+            key_bindings: None,
+            record_movie: None,
+            play_movie: None,
+            convert_movie: None,
+            controller: None,
+            rumble_smoothing: 4,
+            link_listen: None,
+            link_connect: None,
+            dual: false,
+            infrared_link: false,
+            serial_log: None,
+            low_pass: None,
+            layout: Layout::Portable,
+            screenshot_dir: None,
+            capture_format: CaptureFormat::Gif,
+            capture_dir: None,
+            wav_stems: false,
+            frames: None,
+            final_state: None,
+            debug_socket: None,
+        }
+    }
+}
+
+fn file_value(key: &str, value: toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(text) => Ok(text),
+        toml::Value::Integer(number) => Ok(number.to_string()),
+        toml::Value::Float(number) => Ok(number.to_string()),
+        _ => Err(format!("Unsupported value for {key}")),
+    }
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or(format!("Missing value for {flag}"))?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {flag}: {value}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_turn_the_config_file_into_flags() {
+        let text = "scale = 3\nvsync = true\nghosting = false\npalette = \"pocket\"\n\
+                    palette-file = [\"a.pal\", \"b.pal\"]\n[keys]\na = \"S\"\n";
+        let (args, bindings) = Config::file_args(text).unwrap();
+        assert_eq!(
+            args,
+            vec![
+                "--no-ghosting",
+                "--palette",
+                "pocket",
+                "--palette-file",
+                "a.pal",
+                "--palette-file",
+                "b.pal",
+                "--scale",
+                "3",
+                "--vsync"
+            ]
+        );
+        assert_eq!(bindings.unwrap()["keys"]["a"], "S");
+        assert!(Config::file_args("scale = [[1]]").is_err());
+        assert!(Config::file_args("scale = ").is_err());
+    }
+
+    #[test]
+    fn should_let_flags_override_the_config_file() {
+        let path = std::env::temp_dir().join("gameboy-emulator-config-test.toml");
+        fs::write(&path, "scale = 3\nframe-skip = 1\n").unwrap();
+        let args = ["--config", path.to_str().unwrap(), "--scale", "5"];
+        let config = Config::load(args.iter().map(|arg| arg.to_string()).collect()).unwrap();
+        assert_eq!(config.scale, 5);
+        assert_eq!(config.frame_skip, 1);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_let_flags_replace_the_config_files_switches_and_lists() {
+        let path = std::env::temp_dir().join("gameboy-emulator-config-switches-test.toml");
+        fs::write(
+            &path,
+            "vsync = true\nlast = true\ndual = true\npalette-file = [\"a.pal\"]\n\
+             print-bindings = false\n",
+        )
+        .unwrap();
+        let load = |args: &[&str]| {
+            let mut all = vec!["--config", path.to_str().unwrap()];
+            all.extend(args);
+            Config::load(all.iter().map(|arg| arg.to_string()).collect()).unwrap()
+        };
+        let config = load(&[
+            "--no-vsync",
+            "game.gb",
+            "--link-connect",
+            "127.0.0.1:5000",
+            "--palette-file",
+            "b.pal",
+        ]);
+        assert!(!config.vsync);
+        assert!(!config.print_bindings);
+        assert!(!config.last);
+        assert_eq!(config.rom, Some(PathBuf::from("game.gb")));
+        assert!(!config.dual);
+        assert_eq!(config.link_connect.as_deref(), Some("127.0.0.1:5000"));
+        assert_eq!(config.palette_files, vec!["b.pal"]);
+        let config = load(&[]);
+        assert!(config.vsync && config.last && config.dual);
+        assert_eq!(config.palette_files, vec!["a.pal"]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_take_the_rom_without_dashes() {
+        let args = |args: &[&str]| Config::from_args(args.iter().map(|arg| arg.to_string()));
+        let config = args(&["--scale", "2", "game.gb"]).unwrap();
+        assert_eq!(config.rom, Some(PathBuf::from("game.gb")));
+        assert!(args(&["game.gb", "other.gb"]).is_err());
+        assert!(args(&["game.gb", "--last"]).is_err());
+    }
+
+    #[test]
+    fn should_run_batches_headless() {
+        let args = |args: &[&str]| Config::from_args(args.iter().map(|arg| arg.to_string()));
+        let config = args(&["--frames", "600", "--final-state", "end.state"]).unwrap();
+        assert_eq!(config.frames, Some(600));
+        assert!(config.headless);
+        assert!(args(&["--final-state", "end.state"]).is_err());
+        assert!(args(&["--frames", "600", "--dual"]).is_err());
+        assert!(args(&["--headless", "--dual"]).is_err());
+    }
+}
@@ -0,0 +1,91 @@
+use crate::disassembler;
+
+const BANK_SIZE: usize = 0x4000;
+
+// Which ROM bytes have been run as part of an instruction, opcodes and operands alike. What's
+// never run is data, or code nothing has reached yet. There's no bank switching, so an address
+// is the offset into the ROM and banks past 1 can't be reached
+pub struct Coverage {
+    executed: Vec<bool>,
+}
+
+pub struct BankCoverage {
+    pub bank: usize,
+    pub executed: usize,
+    pub size: usize,
+}
+
+impl BankCoverage {
+    pub fn percent(&self) -> f64 {
+        self.executed as f64 * 100.0 / self.size as f64
+    }
+}
+
+impl Coverage {
+    pub fn new(rom_size: usize) -> Self {
+        Self {
+            executed: vec![false; rom_size],
+        }
+    }
+
+    // Marks the instruction at address, given its opcode. Anywhere outside the ROM is ignored
+    pub fn mark(&mut self, address: u16, opcode: u8) {
+        let start = address as usize;
+        let end = (start + disassembler::length(opcode) as usize).min(self.executed.len());
+        if let Some(bytes) = self.executed.get_mut(start..end) {
+            bytes.fill(true);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.executed.fill(false);
+    }
+
+    pub fn banks(&self) -> Vec<BankCoverage> {
+        self.executed
+            .chunks(BANK_SIZE)
+            .enumerate()
+            .map(|(bank, bytes)| BankCoverage {
+                bank,
+                executed: bytes.iter().filter(|&&executed| executed).count(),
+                size: bytes.len(),
+            })
+            .collect()
+    }
+
+    // One byte for every ROM byte, 1 where it was run and 0 elsewhere, so it lines up with the
+    // ROM byte for byte in a hex editor or a script
+    pub fn bitmap(&self) -> Vec<u8> {
+        self.executed
+            .iter()
+            .map(|&executed| executed as u8)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_mark_operands_and_count_per_bank() {
+        let mut coverage = Coverage::new(0x8000);
+        // JP $0150, then CB-prefixed and illegal opcodes in bank 1
+        coverage.mark(0x0100, 0xC3);
+        coverage.mark(0x4000, 0xCB);
+        coverage.mark(0x4002, 0xD3);
+        coverage.mark(0xC000, 0x00);
+        let banks = coverage.banks();
+        assert_eq!((banks[0].executed, banks[1].executed), (3, 3));
+        assert!((banks[1].percent() - 3.0 * 100.0 / 0x4000 as f64).abs() < 1e-9);
+        let bitmap = coverage.bitmap();
+        assert_eq!(bitmap.len(), 0x8000);
+        assert_eq!(&bitmap[0x00FF..0x0104], [0, 1, 1, 1, 0]);
+        // The end of the ROM cuts the last instruction short
+        let mut coverage = Coverage::new(0x10);
+        coverage.mark(0x000F, 0xCD);
+        assert_eq!(coverage.banks()[0].executed, 1);
+        coverage.clear();
+        assert_eq!(coverage.banks()[0].executed, 0);
+    }
+}
@@ -1,16 +1,76 @@
-use crate::gpu::Drawable;
-use crate::memory::Memory;
-use colored::Colorize;
-use std::fmt;
+#[cfg(feature = "debug")]
+use crate::apu;
+use crate::audio::AudioSink;
+use crate::capture::Capture;
+use crate::config::Config;
+#[cfg(feature = "debug")]
+use crate::coverage::Coverage;
+#[cfg(feature = "debug")]
+use crate::debugger::{
+    self, Breakpoints, Checkpoint, Condition, Counters, History, RamSnapshot, Trace,
+};
+#[cfg(feature = "debug")]
+use crate::disassembler;
+use crate::event::{Action, Event, EventSource};
+use crate::expression::Machine;
+#[cfg(feature = "debug")]
+use crate::expression::{Expression, Value};
+use crate::frame_limiter::FrameLimiter;
+#[cfg(feature = "debug")]
+use crate::gpu::{self, LY, OBP1};
+use crate::gpu::{GPU, Renderer};
+#[cfg(feature = "debug")]
+use crate::memory::AccessKind;
+use crate::memory::{self, Interrupt, Memory};
+use crate::movie::{Movie, MovieMode, MovieRecorder};
+use crate::palette::Palette;
+#[cfg(feature = "debug")]
+use crate::profiler::Profile;
+#[cfg(feature = "debug")]
+use crate::prompt::Prompt;
+use crate::recent;
+use crate::rewind::{self, Rewind};
+use crate::savestate::{self, Section, Snapshot, StateReader, StateWriter};
+use crate::screenshot;
+#[cfg(feature = "debug")]
+use crate::script::{Request, Script};
+use crate::stats::FrameStats;
+#[cfg(feature = "debug")]
+use crate::symbols::Symbols;
+use crate::time_stretch::TimeStretch;
+use crate::timer;
+#[cfg(feature = "debug")]
+use crate::tui::{Command, Panes, Tui};
+use crate::viewers::{self, Image, View};
+use crate::wav::WavRecording;
+#[cfg(feature = "debug")]
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File};
+#[cfg(feature = "debug")]
+use std::io::BufWriter;
 use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::Duration;
+// Same as std's outside the browser, where std's panics
+use web_time::Instant;
 
 #[cfg(feature = "debug")]
-use prettytable::{Cell, Row, Table, format};
+use colored::Colorize;
+#[cfg(feature = "debug")]
+use prettytable::Table;
+#[cfg(feature = "debug")]
+use std::fmt;
 
-const FREQUENCY: u32 = 4_194_304;
+pub const FREQUENCY: u32 = 4_194_304;
+// Shortest time between frames shown while fast-forwarding
+const FAST_FORWARD_PRESENT_INTERVAL: Duration = Duration::from_millis(16);
+const INFO: &[u8; 4] = b"INFO";
+// Save state sections of the CPU's registers and the PPU, with the version of each one's layout
+const STATE_SECTIONS: [(&[u8; 4], u8); 2] = [(b"CPU ", 1), (b"PPU ", 1)];
 
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq)]
 enum Instruction {
     ADC_A_n8,
@@ -42,8 +102,14 @@ enum Instruction {
     LD_E_n8,
     CALL_a16,
     LDH_A_a8,
+    HALT,
+    STOP,
+    // An opcode that's illegal or not emulated yet, caught while debugging. Nothing ran
+    #[cfg(feature = "debug")]
+    Fault(u8),
 }
 
+#[cfg_attr(not(feature = "debug"), allow(dead_code))]
 struct InstructionData {
     mnemonic: &'static str,
     opcode: u8,
@@ -198,6 +264,22 @@ impl Instruction {
                 opcode: 0xF0,
                 cycles: 12,
             },
+            Instruction::HALT => InstructionData {
+                mnemonic: "HALT",
+                opcode: 0x76,
+                cycles: 4,
+            },
+            Instruction::STOP => InstructionData {
+                mnemonic: "STOP",
+                opcode: 0x10,
+                cycles: 4,
+            },
+            #[cfg(feature = "debug")]
+            Instruction::Fault(opcode) => InstructionData {
+                mnemonic: "Fault",
+                opcode: *opcode,
+                cycles: 0,
+            },
         }
     }
 }
@@ -219,6 +301,7 @@ enum Flag {
     C = 4,
 }
 
+#[derive(Default)]
 struct Registers {
     af: u16,
     bc: u16,
@@ -227,14 +310,103 @@ struct Registers {
     sp: u16,
     pc: u16,
 }
-pub struct CPU<T: Drawable> {
+pub struct CPU<T: Renderer + EventSource + AudioSink> {
     registers: Registers,
     memory: Memory,
-    gpu: T,
+    gpu: GPU,
+    frontend: T,
+    frames: u64,
+    instructions: u64,
+    config: Config,
+    capture: Option<Capture>,
+    audio_recording: Option<WavRecording>,
+    frame_callbacks: Vec<FrameCallback>,
+    audio_callbacks: Vec<AudioCallback>,
+    serial_callbacks: Vec<SerialCallback>,
+    instruction_callbacks: Vec<InstructionCallback>,
+    interrupt_request_callbacks: Vec<InterruptRequestCallback>,
+    movie: Option<MovieMode>,
+    serial_log: Option<Box<dyn Write>>,
+    // In STOP, waiting for a button press
+    stopped: bool,
+    quit: bool,
+    paused: bool,
+    // Paused by the window losing focus or a menu opening rather than by the player, so it
+    // resumes once that's over
+    auto_paused: bool,
+    // Frame advance asked for while paused, run before idling again
+    advance_frame: bool,
+    fast_forward: bool,
+    rewind: Rewind,
+    rewinding: bool,
+    // Percent of the normal speed, below 100 in slow motion
+    speed: u32,
+    stretch: TimeStretch,
+    // Save state slot the hotkeys use
+    slot: u8,
+    stats: FrameStats,
+    // When a frame was last shown, fast-forwarding shows no more than the display can
+    last_present: Instant,
+    #[cfg(feature = "debug")]
+    breakpoints: Breakpoints,
+    #[cfg(feature = "debug")]
+    symbols: Symbols,
+    #[cfg(feature = "debug")]
+    trace: Option<Trace<BufWriter<File>>>,
+    // Interrupts the last instruction requested, for breaking on them
+    #[cfg(feature = "debug")]
+    requested_interrupts: Vec<Interrupt>,
+    // Counted since the last reset, shown in the prompt
+    #[cfg(feature = "debug")]
+    since_reset: Counters,
+    #[cfg(feature = "debug")]
+    stopwatch: Counters,
+    // Kept after stopping, for the report
+    #[cfg(feature = "debug")]
+    profile: Profile,
+    #[cfg(feature = "debug")]
+    profiling: bool,
+    // Every ROM byte run since the game was put in
+    #[cfg(feature = "debug")]
+    coverage: Coverage,
+    // Where rstep goes back to
+    #[cfg(feature = "debug")]
+    history: History,
+    // Set while the debugger runs, so a bad opcode stops at its prompt instead of panicking
+    #[cfg(feature = "debug")]
+    catch_faults: bool,
+    // RAM taken by name, to compare with later
+    #[cfg(feature = "debug")]
+    snapshots: BTreeMap<String, RamSnapshot>,
 }
 
-impl<T: Drawable> CPU<T> {
-    pub fn new(memory: Memory, gpu: T) -> Self {
+// Called with the framebuffer and the frame number every time the PPU enters VBlank
+pub type FrameCallback = Box<dyn FnMut(&[u8], u64)>;
+
+// Called with the interleaved stereo samples the APU produced over each frame
+pub type AudioCallback = Box<dyn FnMut(&[f32])>;
+
+// Called with the bytes the game sent over the link port during each frame
+pub type SerialCallback = Box<dyn FnMut(&[u8])>;
+
+// Called with the PC and opcode before each instruction runs
+pub type InstructionCallback = Box<dyn FnMut(u16, u8)>;
+
+// Called with every interrupt a device requests by setting its IF bit, whether or not IME and IE
+// would let it through
+pub type InterruptRequestCallback = Box<dyn FnMut(Interrupt)>;
+
+impl<T: Renderer + EventSource + AudioSink> CPU<T> {
+    pub fn new(mut memory: Memory, mut frontend: T, config: Config) -> Self {
+        #[cfg(feature = "debug")]
+        let coverage = Coverage::new(memory.rom().len());
+        frontend.set_title(&game_name(&memory));
+        memory.apu.set_low_pass(config.low_pass);
+        memory.apu.set_sample_rate(frontend.sample_rate());
+        memory.rumble.set_smoothing(config.rumble_smoothing);
+        memory.serial.set_share_infrared(config.infrared_link);
+        let speed = config.speed;
+        let rewind = Rewind::new(config.rewind_seconds);
         Self {
             registers: Registers {
                 af: 0,
@@ -245,68 +417,1291 @@ impl<T: Drawable> CPU<T> {
                 pc: 0,
             },
             memory,
-            gpu,
+            gpu: GPU::new(),
+            frontend,
+            frames: 0,
+            instructions: 0,
+            config,
+            capture: None,
+            audio_recording: None,
+            frame_callbacks: Vec::new(),
+            audio_callbacks: Vec::new(),
+            serial_callbacks: Vec::new(),
+            instruction_callbacks: Vec::new(),
+            interrupt_request_callbacks: Vec::new(),
+            movie: None,
+            serial_log: None,
+            stopped: false,
+            quit: false,
+            paused: false,
+            auto_paused: false,
+            advance_frame: false,
+            fast_forward: false,
+            rewind,
+            rewinding: false,
+            speed,
+            stretch: TimeStretch::new(),
+            slot: 0,
+            stats: FrameStats::new(),
+            last_present: Instant::now(),
+            #[cfg(feature = "debug")]
+            breakpoints: Breakpoints::default(),
+            #[cfg(feature = "debug")]
+            symbols: Symbols::default(),
+            #[cfg(feature = "debug")]
+            trace: None,
+            #[cfg(feature = "debug")]
+            requested_interrupts: Vec::new(),
+            #[cfg(feature = "debug")]
+            since_reset: Counters::default(),
+            #[cfg(feature = "debug")]
+            stopwatch: Counters::default(),
+            #[cfg(feature = "debug")]
+            profile: Profile::default(),
+            #[cfg(feature = "debug")]
+            profiling: false,
+            #[cfg(feature = "debug")]
+            coverage,
+            #[cfg(feature = "debug")]
+            history: History::default(),
+            #[cfg(feature = "debug")]
+            catch_faults: false,
+            #[cfg(feature = "debug")]
+            snapshots: BTreeMap::new(),
+        }
+    }
+
+    pub fn gpu(&self) -> &GPU {
+        &self.gpu
+    }
+
+    pub fn gpu_mut(&mut self) -> &mut GPU {
+        &mut self.gpu
+    }
+
+    // Adds the config's palette files and selects its palette. Without an explicit --palette, the
+    // first palette file is the one to start with
+    pub fn apply_palettes(&mut self) -> Result<(), String> {
+        let mut palette = self.config.palette.clone();
+        for path in &self.config.palette_files {
+            let custom = Palette::from_file(path)
+                .map_err(|error| format!("Problem reading palette {path}: {error}"))?;
+            palette.get_or_insert(custom.name.clone());
+            self.gpu.add_palette(custom);
+        }
+        match palette {
+            Some(palette) => self.gpu.select_palette(&palette),
+            None => Ok(()),
+        }
+    }
+
+    // The byte at address as instructions see it, through the bus. Memory::peek only borrows and
+    // doesn't count as an access, so looking can't disturb the machine or set off a watchpoint
+    pub fn peek(&self, address: u16) -> u8 {
+        self.memory.peek(address)
+    }
+
+    // Writes like an instruction would, IO registers react as usual: poking DIV resets it
+    pub fn poke(&mut self, address: u16, value: u8) {
+        self.memory.write(address, value);
+    }
+
+    // Register pairs by name, for frontends that show them
+    pub fn registers(&self) -> [(&'static str, u16); 6] {
+        let registers = &self.registers;
+        [
+            ("AF", registers.af),
+            ("BC", registers.bc),
+            ("DE", registers.de),
+            ("HL", registers.hl),
+            ("SP", registers.sp),
+            ("PC", registers.pc),
+        ]
+    }
+
+    // The pairs in the same order as registers, for harnesses that start from a state of their own
+    pub fn set_registers(&mut self, [af, bc, de, hl, sp, pc]: [u16; 6]) {
+        // The low nibble of F is always 0 on the hardware
+        self.registers = Registers {
+            af: af & 0xFFF0,
+            bc,
+            de,
+            hl,
+            sp,
+            pc,
+        };
+    }
+
+    pub fn render_view(&self, view: View) -> Image {
+        viewers::render(view, &self.gpu, &self.memory)
+    }
+
+    pub fn frontend_mut(&mut self) -> &mut T {
+        &mut self.frontend
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn screenshot(&mut self) {
+        let dir = self.output_dir(&self.config.screenshot_dir, "screenshots");
+        match screenshot::save(self.gpu.frame(), &dir) {
+            Ok(path) => {
+                println!("Screenshot saved to {}", path.display());
+                self.frontend.show_message("Screenshot saved");
+            }
+            Err(error) => self.warn(&format!("Couldn't save screenshot: {error}")),
+        }
+    }
+
+    // Tells the player about something a hotkey did, on screen as well since the terminal is
+    // usually out of sight
+    fn notify(&mut self, message: &str) {
+        println!("{message}");
+        self.frontend.show_message(message);
+    }
+
+    fn warn(&mut self, message: &str) {
+        eprintln!("{message}");
+        self.frontend.show_message(message);
+    }
+
+    fn print_mutes(&mut self) {
+        let channels: Vec<String> = (0..4)
+            .map(|channel| {
+                let state = if self.memory.apu.muted(channel) {
+                    "muted"
+                } else {
+                    "on"
+                };
+                format!("{} {state}", channel + 1)
+            })
+            .collect();
+        self.notify(&format!("Sound channels: {}", channels.join(", ")));
+    }
+
+    // Frames are rendered in full while any callback is registered, regardless of frame skip
+    pub fn on_frame(&mut self, callback: impl FnMut(&[u8], u64) + 'static) {
+        self.frame_callbacks.push(Box::new(callback));
+    }
+
+    // Samples come at audio_sample_rate, which falls back to the configured rate when the
+    // frontend doesn't play sound itself
+    pub fn on_audio(&mut self, callback: impl FnMut(&[f32]) + 'static) {
+        self.audio_callbacks.push(Box::new(callback));
+        self.update_sample_rate();
+    }
+
+    // Test ROMs print their results over the link port, this is how harnesses read them
+    pub fn on_serial(&mut self, callback: impl FnMut(&[u8]) + 'static) {
+        self.serial_callbacks.push(Box::new(callback));
+    }
+
+    // For tracers and profilers. Runs on every instruction, so it slows emulation down
+    pub fn on_instruction(&mut self, callback: impl FnMut(u16, u8) + 'static) {
+        self.instruction_callbacks.push(Box::new(callback));
+    }
+
+    // When a device requests an interrupt: the PPU entering VBlank, the timer overflowing, a serial
+    // transfer completing and so on. Nothing dispatches interrupts to their handlers yet, so this
+    // is as far as one gets
+    pub fn on_interrupt_request(&mut self, callback: impl FnMut(Interrupt) + 'static) {
+        self.interrupt_request_callbacks.push(Box::new(callback));
+    }
+
+    pub fn audio_sample_rate(&self) -> Option<u32> {
+        self.frontend
+            .sample_rate()
+            .or(
+                (self.audio_recording.is_some() || !self.audio_callbacks.is_empty())
+                    .then_some(self.config.sample_rate),
+            )
+    }
+
+    fn update_sample_rate(&mut self) {
+        let sample_rate = self.audio_sample_rate();
+        self.memory.apu.set_sample_rate(sample_rate);
+    }
+
+    // Movies start from power-on, so these are meant to be called before running
+    pub fn record_movie(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let recorder = MovieRecorder::create(path, self.memory.rom_hash())?;
+        println!("Recording input to {}", recorder.path().display());
+        self.movie = Some(MovieMode::Recording(recorder));
+        Ok(())
+    }
+
+    // Live input is ignored until the movie ends
+    pub fn play_movie(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let movie = Movie::load(path)?;
+        if movie
+            .rom_hash
+            .is_some_and(|hash| hash != self.memory.rom_hash())
+        {
+            eprintln!("Warning: the movie was recorded on a different ROM");
+        }
+        self.movie = Some(MovieMode::Playing(movie, 0));
+        Ok(())
+    }
+
+    // Input changes only take effect at frame boundaries, which is what makes a frame's worth of
+    // joypad state enough to reproduce a run
+    fn step_movie(&mut self) {
+        let finished = match &mut self.movie {
+            Some(MovieMode::Recording(recorder)) => {
+                let result = recorder.push(self.memory.joypad.state());
+                if let Err(error) = &result {
+                    eprintln!("Movie recording stopped: {error}");
+                }
+                result.is_err()
+            }
+            Some(MovieMode::Playing(movie, frame)) => match movie.frames.get(*frame) {
+                Some(state) => {
+                    self.memory.set_joypad_state(*state);
+                    *frame += 1;
+                    false
+                }
+                None => {
+                    println!("Movie finished after {frame} frames");
+                    self.memory.set_joypad_state(0);
+                    true
+                }
+            },
+            None => false,
+        };
+        if finished {
+            self.movie = None;
+        }
+    }
+
+    fn playing_movie(&self) -> bool {
+        matches!(self.movie, Some(MovieMode::Playing(..)))
+    }
+
+    // Copies every byte sent over the link port, which is how test ROMs report their results
+    pub fn log_serial(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        self.serial_log = Some(if path == Path::new("-") {
+            Box::new(io::stdout())
+        } else {
+            Box::new(File::create(path)?)
+        });
+        Ok(())
+    }
+
+    // An INFO section with the hash of the ROM it belongs to and the frame number, then one for
+    // every part of the machine. How the emulator is being run, recordings, speed and the like,
+    // isn't part of the machine
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.bytes(savestate::MAGIC);
+        state.u8(savestate::VERSION);
+        state.section(INFO, 1, |info| {
+            info.u64(self.memory.rom_hash());
+            info.u64(self.frames);
+        });
+        let parts: [&dyn Snapshot; 2] = [&self.registers, &self.gpu];
+        let parts = parts.into_iter().chain(self.memory.state_parts());
+        for ((id, version), part) in state_sections().zip(parts) {
+            state.section(id, *version, |state| part.save(state));
+        }
+        state.into_bytes()
+    }
+
+    // Loads all of the state or, when it turns out to be broken, none of it. Sections from newer
+    // versions of the emulator are skipped, parts older states have no section for stay as they
+    // are
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), String> {
+        if let Some(state) = savestate::parse_v1(bytes) {
+            return self.load_state_v1(state);
+        }
+        let sections = savestate::parse(bytes)?;
+        let info = sections
+            .iter()
+            .find(|section| &section.id == INFO)
+            .ok_or("Save state has no INFO section")?;
+        let mut state = StateReader::new(info.data);
+        if state.u64()? != self.memory.rom_hash() {
+            return Err("The state was saved on a different ROM".to_string());
+        }
+        let frames = state.u64()?;
+        let backup = self.save_state();
+        if let Err(error) = self.load_sections(&sections) {
+            self.restore_state(&backup);
+            return Err(error);
+        }
+        self.loaded_state(frames);
+        for section in &sections {
+            if &section.id != INFO && !state_sections().any(|(id, _)| **id == section.id) {
+                eprintln!("Skipped unknown save state section {}", section.name());
+            }
+        }
+        Ok(())
+    }
+
+    // States from before sections: the ROM hash, the registers, the frame number, everything on
+    // the bus and the PPU. None of those parts has changed its layout since
+    fn load_state_v1(&mut self, mut state: StateReader) -> Result<(), String> {
+        if state.u64()? != self.memory.rom_hash() {
+            return Err("The state was saved on a different ROM".to_string());
+        }
+        let backup = self.save_state();
+        let result = self
+            .load_v1(&mut state)
+            .and_then(|frames| match state.is_empty() {
+                true => Ok(frames),
+                false => Err("Save state is longer than expected".to_string()),
+            });
+        match result {
+            Ok(frames) => self.loaded_state(frames),
+            Err(_) => self.restore_state(&backup),
+        }
+        result.map(|_| ())
+    }
+
+    fn load_v1(&mut self, state: &mut StateReader) -> Result<u64, String> {
+        self.registers.load(state)?;
+        let frames = state.u64()?;
+        for part in self.memory.state_parts_mut() {
+            part.load(state)?;
+        }
+        self.gpu.load(state)?;
+        Ok(frames)
+    }
+
+    fn restore_state(&mut self, backup: &[u8]) {
+        let backup = savestate::parse(backup).expect("The machine's own state is valid");
+        self.load_sections(&backup)
+            .expect("Restoring the machine's own state can't fail");
+    }
+
+    fn loaded_state(&mut self, frames: u64) {
+        self.frames = frames;
+        // Running forward from before it wouldn't end up at the loaded state
+        #[cfg(feature = "debug")]
+        self.history.clear();
+    }
+
+    fn load_sections(&mut self, sections: &[Section]) -> Result<(), String> {
+        let parts: [&mut dyn Snapshot; 2] = [&mut self.registers, &mut self.gpu];
+        let parts = parts.into_iter().chain(self.memory.state_parts_mut());
+        for ((id, version), part) in state_sections().zip(parts) {
+            match sections.iter().find(|section| &section.id == *id) {
+                Some(section) if section.version > *version => {
+                    return Err(format!(
+                        "The {} section is from a newer version of the emulator",
+                        section.name()
+                    ));
+                }
+                Some(section) => section.load(part)?,
+                None => eprintln!(
+                    "Save state has no {} section, it's left as it is",
+                    savestate::section_name(id)
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    // Power cycles the machine with another game in. There are no battery saves yet, so nothing
+    // of the previous game needs writing out first
+    pub fn load_rom(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if self.movie.is_some() {
+            return Err("Can't change games while a movie is recorded or played".into());
+        }
+        self.memory.load_rom(path)?;
+        self.power_on();
+        #[cfg(feature = "debug")]
+        self.load_rom_symbols();
+        Ok(())
+    }
+
+    pub fn insert_rom(&mut self, rom: Vec<u8>) -> Result<(), Box<dyn Error>> {
+        if self.movie.is_some() {
+            return Err("Can't change games while a movie is recorded or played".into());
+        }
+        self.memory.insert_rom(rom);
+        self.power_on();
+        Ok(())
+    }
+
+    // Everything from the previous game goes, even its rewind snapshots
+    fn power_on(&mut self) {
+        self.reset(true);
+        self.frames = 0;
+        self.instructions = 0;
+        #[cfg(feature = "debug")]
+        {
+            self.coverage = Coverage::new(self.memory.rom().len());
+        }
+        self.rewind.clear();
+        self.rewinding = false;
+        self.frontend.set_title(&game_name(&self.memory));
+        self.stats.restart();
+    }
+
+    // Starts the game over, a hard reset also clears RAM. The ROM stays in and rewinding can
+    // still go back to before the reset
+    pub fn reset(&mut self, hard: bool) {
+        self.memory.reset(hard);
+        self.registers = Registers::default();
+        self.stopped = false;
+        self.gpu.reset();
+        self.stretch.reset();
+        #[cfg(feature = "debug")]
+        {
+            self.since_reset.clear();
+            // Running forward again wouldn't reset
+            self.history.clear();
         }
     }
 
+    // Resetting in the middle of a movie would leave its input out of step with the frames
+    fn request_reset(&mut self, hard: bool) {
+        if self.movie.is_some() {
+            self.warn("Can't reset while a movie is recorded or played");
+            return;
+        }
+        self.reset(hard);
+        self.notify(if hard { "Hard reset" } else { "Reset" });
+    }
+
+    fn open_rom(&mut self, path: &Path) {
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        if !extension.is_some_and(|extension| memory::ROM_EXTENSIONS.contains(&extension)) {
+            self.warn(&format!("Not a Game Boy ROM: {}", path.display()));
+            return;
+        }
+        match self.load_rom(path) {
+            Ok(()) => {
+                recent::remember(path);
+                println!("Loaded {}", path.display());
+                self.frontend
+                    .show_message(&format!("Loaded {}", game_name(&self.memory)));
+            }
+            Err(error) => self.warn(&format!("Couldn't load {}: {error}", path.display())),
+        }
+    }
+
+    pub fn select_slot(&mut self, slot: u8) {
+        self.slot = slot;
+        self.notify(&format!("Save state slot {slot}"));
+    }
+
+    pub fn save_state_slot(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let path = self.slot_path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, self.save_state())?;
+        Ok(path)
+    }
+
+    // A movie's input only lines up with the frames it was recorded on, so states can't be
+    // loaded in the middle of one
+    pub fn load_state_slot(&mut self) -> Result<PathBuf, Box<dyn Error>> {
+        if self.movie.is_some() {
+            return Err("States can't be loaded while a movie is recorded or played".into());
+        }
+        let path = self.slot_path()?;
+        self.load_state(&fs::read(&path)?)?;
+        // Shows the state right away, even when paused
+        self.frontend.present(self.gpu.frame());
+        Ok(path)
+    }
+
+    fn slot_path(&self) -> Result<PathBuf, String> {
+        let rom = self.memory.rom_path().ok_or("No ROM loaded")?;
+        let dir = self
+            .game_dir()
+            .ok_or("No data directory to keep save states in")?;
+        Ok(savestate::slot_path(
+            &dir.join(rom.file_name().unwrap_or_default()),
+            self.slot,
+        ))
+    }
+
+    // Where the game's files go under the chosen layout, None without a ROM file
+    fn game_dir(&self) -> Option<PathBuf> {
+        let rom = self.memory.rom_path()?;
+        let title = self.memory.title();
+        self.config
+            .layout
+            .game_dir(rom, title.as_deref(), self.memory.rom_hash())
+    }
+
+    // The directory given for screenshots or captures, or one with this name in the game's
+    fn output_dir(&self, given: &Option<PathBuf>, name: &str) -> PathBuf {
+        match (given, self.game_dir()) {
+            (Some(dir), _) => dir.clone(),
+            (None, Some(game)) => game.join(name),
+            (None, None) => PathBuf::from(name),
+        }
+    }
+
+    fn save_slot(&mut self) {
+        match self.save_state_slot() {
+            Ok(path) => {
+                println!("State saved to slot {} ({})", self.slot, path.display());
+                self.frontend
+                    .show_message(&format!("State saved to slot {}", self.slot));
+            }
+            Err(error) => self.warn(&format!(
+                "Couldn't save state to slot {}: {error}",
+                self.slot
+            )),
+        }
+    }
+
+    fn load_slot(&mut self) {
+        match self.load_state_slot() {
+            Ok(path) => {
+                println!("State loaded from slot {} ({})", self.slot, path.display());
+                self.frontend
+                    .show_message(&format!("State loaded from slot {}", self.slot));
+            }
+            Err(error) => self.warn(&format!(
+                "Couldn't load state from slot {}: {error}",
+                self.slot
+            )),
+        }
+    }
+
+    fn flush_serial_log(&mut self) {
+        let sent = self.memory.serial.take_sent();
+        if !sent.is_empty() {
+            for callback in &mut self.serial_callbacks {
+                callback(&sent);
+            }
+        }
+        if let Some(log) = &mut self.serial_log
+            && let Err(error) = log.write_all(&sent).and_then(|_| log.flush())
+        {
+            eprintln!("Serial log stopped: {error}");
+            self.serial_log = None;
+        }
+    }
+
+    pub fn toggle_capture(&mut self) {
+        match self.capture.take() {
+            Some(capture) => match capture.finish() {
+                Ok(path) => {
+                    println!("Capture saved to {}", path.display());
+                    self.frontend.show_message("Capture saved");
+                }
+                Err(error) => self.warn(&format!("Couldn't finish capture: {error}")),
+            },
+            None => match Capture::start(
+                self.config.capture_format,
+                &self.output_dir(&self.config.capture_dir, "captures"),
+            ) {
+                Ok(capture) => {
+                    println!("Capturing to {}", capture.path().display());
+                    self.frontend.show_message("Capturing");
+                    self.capture = Some(capture);
+                }
+                Err(error) => self.warn(&format!("Couldn't start capture: {error}")),
+            },
+        }
+    }
+
+    pub fn toggle_audio_recording(&mut self) {
+        match self.audio_recording.take() {
+            Some(recording) => {
+                match recording.finish() {
+                    Ok(path) => {
+                        println!("Audio saved to {}", path.display());
+                        self.frontend.show_message("Audio saved");
+                    }
+                    Err(error) => self.warn(&format!("Couldn't finish audio recording: {error}")),
+                }
+                self.memory.apu.set_stems(false);
+            }
+            None => {
+                let sample_rate = self
+                    .frontend
+                    .sample_rate()
+                    .unwrap_or(self.config.sample_rate);
+                match WavRecording::start(
+                    &self.output_dir(&self.config.capture_dir, "captures"),
+                    sample_rate,
+                    self.config.wav_stems,
+                ) {
+                    Ok(recording) => {
+                        println!("Recording audio to {}", recording.path().display());
+                        self.frontend.show_message("Recording audio");
+                        self.memory.apu.set_stems(recording.has_stems());
+                        self.audio_recording = Some(recording);
+                    }
+                    Err(error) => self.warn(&format!("Couldn't start audio recording: {error}")),
+                }
+            }
+        }
+        self.update_sample_rate();
+    }
+
+    // Runs until the frontend asks to quit
     #[cfg(not(feature = "debug"))]
     pub fn run(&mut self) {
-        let mut cycles = 0;
-        let one_sec = Duration::from_secs(1);
-        let mut next_cycle = 0;
-        loop {
-            let timer = Instant::now();
-            while cycles < FREQUENCY {
-                if next_cycle == cycles {
-                    let instruction = self.cycle();
-                    next_cycle = cycles + instruction.data().cycles as u32;
+        let mut limiter = FrameLimiter::new();
+        while !self.quit {
+            if self.paused {
+                if self.advance_frame {
+                    self.advance_frame();
+                } else {
+                    self.idle();
                 }
-                cycles += 1;
+                continue;
+            }
+            if self.rewinding {
+                self.step_back(&mut limiter);
+                continue;
+            }
+            self.run_frame();
+            self.sync(&mut limiter);
+        }
+        self.shutdown();
+    }
+
+    // Runs exactly that many frames as fast as possible, then wraps up like a quit. Nothing here
+    // goes by the clock, so the same ROM and input always end up in the same state
+    pub fn run_frames(&mut self, frames: u64) {
+        for _ in 0..frames {
+            self.run_frame();
+        }
+        self.shutdown();
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.auto_paused = false;
+        self.frontend.set_paused(paused);
+        self.stats.restart();
+        println!("{}", if paused { "Paused" } else { "Resumed" });
+    }
+
+    // Pauses on the frontend's behalf, unless the player already did. Resuming only undoes such a
+    // pause. Pausing hands over to the debugger's prompt, which is no place to wait for focus or a
+    // menu, so debug builds keep running
+    fn auto_pause(&mut self, pause: bool) {
+        if cfg!(feature = "debug") {
+            return;
+        }
+        if pause && !self.paused {
+            self.set_paused(true);
+            self.auto_paused = true;
+        } else if !pause && self.auto_paused {
+            self.set_paused(false);
+        }
+    }
+
+    // Runs exactly one frame, with whatever buttons are held down
+    pub fn advance_frame(&mut self) {
+        self.advance_frame = false;
+        // The pause overlay would hide the very frame being looked at
+        self.frontend.set_paused(false);
+        self.run_frame();
+        println!("Frame {}", self.frames);
+    }
+
+    // Goes back to the previous snapshot and shows it, at the normal frame rate. Nothing is
+    // emulated, so events are polled here
+    fn step_back(&mut self, limiter: &mut FrameLimiter) {
+        if let Some(state) = self.rewind.pop()
+            && let Err(error) = self.load_state(&state)
+        {
+            eprintln!("Couldn't rewind: {error}");
+        }
+        self.frontend.present(self.gpu.frame());
+        for event in self.frontend.poll_events() {
+            self.handle_event(event);
+        }
+        limiter.set_speed(1.0);
+        limiter.wait();
+    }
+
+    // Going back in the middle of a movie would leave its input out of step with the frames
+    fn set_rewinding(&mut self, rewinding: bool) {
+        if rewinding && !self.rewind.is_enabled() {
+            self.notify("Rewinding is turned off, see --rewind-seconds");
+        } else if rewinding && self.movie.is_some() {
+            self.warn("Can't rewind while a movie is recorded or played");
+        } else {
+            self.rewinding = rewinding;
+        }
+    }
+
+    pub fn cycle_slow_motion(&mut self) {
+        self.speed = match self.speed {
+            speed if speed > 50 => 50,
+            speed if speed > 25 => 25,
+            _ => 100,
+        };
+        self.stretch.reset();
+        self.notify(&format!("Speed: {}%", self.speed));
+    }
+
+    // With nothing queued, audio sync has nothing to pace by
+    fn slow_motion_muted(&self) -> bool {
+        self.speed < 100 && self.config.mute_slow_motion
+    }
+
+    // Keeps the frontend responsive while no frames are being emulated
+    #[cfg(not(feature = "debug"))]
+    fn idle(&mut self) {
+        for event in self.frontend.poll_events() {
+            self.handle_event(event);
+        }
+        thread::sleep(Duration::from_millis(16));
+    }
+
+    // Finishes whatever is being written so nothing is left truncated
+    fn shutdown(&mut self) {
+        if self.capture.is_some() {
+            self.toggle_capture();
+        }
+        if self.audio_recording.is_some() {
+            self.toggle_audio_recording();
+        }
+        if let Some(MovieMode::Recording(recorder)) = self.movie.take() {
+            println!("Movie saved to {}", recorder.path().display());
+        }
+        self.flush_serial_log();
+        #[cfg(feature = "debug")]
+        self.stop_trace();
+    }
+
+    // Paces emulation after each frame. With audio sync the sound card's consumption of samples
+    // sets the speed, otherwise the frame limiter does unless vsync already blocks on present
+    fn sync(&self, limiter: &mut FrameLimiter) {
+        if self.fast_forward {
+            // Sound is dropped while fast-forwarding, so there's nothing to sync with
+            if self.config.fast_forward_speed != 0 {
+                limiter.set_speed(self.config.fast_forward_speed as f64);
+                limiter.wait();
             }
-            let elapsed = timer.elapsed();
-            if elapsed < one_sec {
-                thread::sleep(one_sec - elapsed);
+            return;
+        }
+        limiter.set_speed(self.speed as f64 / 100.0);
+        if self.config.audio_sync
+            && !self.slow_motion_muted()
+            && let Some(sample_rate) = self.frontend.sample_rate()
+        {
+            let target =
+                Duration::from_secs_f64(self.config.audio_buffer as f64 * 2.0 / sample_rate as f64);
+            while self
+                .frontend
+                .queued_audio()
+                .is_some_and(|queued| queued > target)
+            {
+                thread::sleep(Duration::from_millis(1));
             }
-            cycles = 0;
+        } else if !self.config.vsync {
+            limiter.wait();
+        }
+    }
+
+    // Executes instructions until the PPU completes and presents the next frame
+    pub fn run_frame(&mut self) {
+        let frame = self.frames;
+        while self.frames == frame {
+            self.cycle();
+        }
+    }
+
+    // Executes the instruction at PC, for harnesses that have to stop between two of them
+    pub fn step_instruction(&mut self) {
+        self.cycle();
+    }
+
+    // Like step_instruction, but an opcode that isn't implemented yet is handed back instead of
+    // panicking, with nothing run. Fuzzing has to tell those apart from real crashes
+    #[cfg(feature = "debug")]
+    pub fn try_step_instruction(&mut self) -> Result<(), u8> {
+        let catch_faults = std::mem::replace(&mut self.catch_faults, true);
+        let instruction = self.cycle();
+        self.catch_faults = catch_faults;
+        match instruction {
+            Instruction::Fault(opcode) => Err(opcode),
+            _ => Ok(()),
         }
     }
 
     fn cycle(&mut self) -> Instruction {
-        let opcode: u8 = self.memory.memory[self.registers.pc as usize];
+        let opcode: u8 = self.memory.read(self.registers.pc);
+        for callback in &mut self.instruction_callbacks {
+            callback(self.registers.pc, opcode);
+        }
+        // Disassembled before running, while the registers are still as the instruction found them
+        #[cfg(feature = "debug")]
+        let traced = self.trace.is_some().then(|| {
+            let line = disassembler::disassemble(self.registers.pc, |address| self.peek(address));
+            (line, self.registers())
+        });
+        #[cfg(feature = "debug")]
+        let pc = self.registers.pc;
+        #[cfg(feature = "debug")]
+        {
+            self.coverage.mark(pc, opcode);
+            if self.history.due(self.instructions) {
+                let state = self.save_state();
+                self.history.push(Checkpoint {
+                    instruction: self.instructions,
+                    state,
+                    since_reset: self.since_reset,
+                    stopwatch: self.stopwatch,
+                });
+            }
+        }
         let instruction = self.decode(opcode);
-        self.gpu.draw();
+        let cycles = instruction.data().cycles as u32;
+        #[cfg(feature = "debug")]
+        if self.profiling {
+            self.profile.record(pc, cycles);
+        }
+        #[cfg(feature = "debug")]
+        if let Some((line, registers)) = traced
+            && let Some(trace) = &mut self.trace
+            && let Err(error) = trace.log(&line, &registers, cycles)
+        {
+            println!("Trace stopped: {error}");
+            self.trace = None;
+        }
+        self.gpu.step(&mut self.memory, cycles);
+        self.memory.step(cycles);
+        #[cfg(feature = "debug")]
+        {
+            self.since_reset.count(cycles);
+            self.stopwatch.count(cycles);
+            self.requested_interrupts.clear();
+        }
+        for interrupt in self.memory.take_requested() {
+            // A button press ends STOP, which goes on past the byte after it
+            if interrupt == Interrupt::Joypad && self.stopped {
+                self.stopped = false;
+                self.registers.pc = self.registers.pc.wrapping_add(2);
+            }
+            for callback in &mut self.interrupt_request_callbacks {
+                callback(interrupt);
+            }
+            #[cfg(feature = "debug")]
+            self.requested_interrupts.push(interrupt);
+        }
+        if self.gpu.frame_ready() {
+            // Recordings and callbacks get every frame, skipping only applies to what's shown on
+            // screen
+            let rendered_every = if self.capture.is_some() || !self.frame_callbacks.is_empty() {
+                1
+            } else {
+                self.config.frame_skip as u64 + 1
+            };
+            let showing =
+                !self.fast_forward || self.last_present.elapsed() >= FAST_FORWARD_PRESENT_INTERVAL;
+            let presenting = self.frames.is_multiple_of(rendered_every) && showing;
+            if presenting {
+                self.last_present = Instant::now();
+                self.frontend.present(self.gpu.frame());
+                for view in self.frontend.open_views() {
+                    let image = self.render_view(view);
+                    self.frontend.present_view(view, &image);
+                }
+            }
+            if let Some(capture) = &mut self.capture
+                && let Err(error) = capture.push_frame(self.gpu.frame())
+            {
+                eprintln!("Capture stopped: {error}");
+                self.capture = None;
+            }
+            for callback in &mut self.frame_callbacks {
+                callback(self.gpu.frame(), self.frames);
+            }
+            if self.memory.rumble.is_present() {
+                self.frontend.set_rumble(self.memory.rumble.end_frame());
+            }
+            if self.speed < 100 && !self.fast_forward {
+                if !self.config.mute_slow_motion {
+                    let speed = self.speed as f64 / 100.0;
+                    let stretched = self.stretch.process(self.memory.apu.samples(), speed);
+                    self.frontend.queue_audio(&stretched);
+                }
+            } else if !self.fast_forward {
+                self.frontend.queue_audio(self.memory.apu.samples());
+            }
+            if let Some(recording) = &mut self.audio_recording
+                && let Err(error) =
+                    recording.push(self.memory.apu.samples(), self.memory.apu.stems())
+            {
+                eprintln!("Audio recording stopped: {error}");
+                self.audio_recording = None;
+                self.memory.apu.set_stems(false);
+                self.update_sample_rate();
+            }
+            for callback in &mut self.audio_callbacks {
+                callback(self.memory.apu.samples());
+            }
+            self.memory.apu.clear_samples();
+            self.flush_serial_log();
+            self.gpu.clear_frame_ready();
+            self.frames += 1;
+            #[cfg(feature = "debug")]
+            {
+                self.since_reset.frames += 1;
+                self.stopwatch.frames += 1;
+            }
+            if let Some((speed, fps)) = self.stats.frame(presenting) {
+                let name = game_name(&self.memory);
+                self.frontend
+                    .set_title(&format!("{name} - {speed}% - {fps} FPS"));
+            }
+            self.gpu
+                .set_skip_rendering(!self.frames.is_multiple_of(rendered_every));
+            if self.rewind.is_enabled() && self.frames.is_multiple_of(rewind::INTERVAL) {
+                let state = self.save_state();
+                self.rewind.push(&state);
+            }
+            for event in self.frontend.poll_events() {
+                self.handle_event(event);
+            }
+            self.step_movie();
+        }
         instruction
     }
 
+    // What the frontend's events do, also how input gets in when embedded
+    pub fn handle_event(&mut self, event: Event) {
+        match event {
+            Event::Action(Action::CyclePalette) => {
+                self.gpu.cycle_palette();
+            }
+            Event::Action(Action::Screenshot) => self.screenshot(),
+            Event::Action(Action::ToggleCapture) => self.toggle_capture(),
+            Event::Action(Action::ToggleAudioRecording) => self.toggle_audio_recording(),
+            Event::Action(Action::ToggleMute(channel)) => {
+                self.memory.apu.toggle_mute(channel);
+                self.print_mutes();
+            }
+            Event::Action(Action::SoloChannel(channel)) => {
+                self.memory.apu.solo(channel);
+                self.print_mutes();
+            }
+            Event::Action(Action::TogglePause) => self.set_paused(!self.paused),
+            Event::Action(Action::FastForward) => {
+                if !self.fast_forward {
+                    let message = match self.config.fast_forward_speed {
+                        0 => "Fast forward".to_string(),
+                        speed => format!("Fast forward {speed}x"),
+                    };
+                    self.frontend.show_message(&message);
+                }
+                self.fast_forward = true;
+            }
+            Event::ActionReleased(Action::FastForward) => self.fast_forward = false,
+            Event::Action(Action::Rewind) => self.set_rewinding(true),
+            Event::ActionReleased(Action::Rewind) => self.set_rewinding(false),
+            Event::Action(Action::CycleSlowMotion) => self.cycle_slow_motion(),
+            Event::Action(Action::FrameAdvance) if self.paused => self.advance_frame = true,
+            Event::Action(Action::SaveState) => self.save_slot(),
+            Event::Action(Action::LoadState) => self.load_slot(),
+            Event::Action(Action::SelectSlot(slot)) => self.select_slot(slot),
+            Event::Action(Action::Reset) => self.request_reset(false),
+            Event::Action(Action::HardReset) => self.request_reset(true),
+            Event::Action(Action::Quit) => self.quit = true,
+            Event::Action(_) | Event::ActionReleased(_) => {}
+            Event::ButtonDown(_) | Event::ButtonUp(_) if self.playing_movie() => {}
+            Event::ButtonDown(button) => self.memory.press_button(button),
+            Event::OpenRom(path) => self.open_rom(&path),
+            Event::Focus(focused) if self.config.pause_in_background => self.auto_pause(!focused),
+            Event::Focus(_) => {}
+            Event::Menu(open) => self.auto_pause(open),
+            Event::ButtonUp(button) => self.memory.joypad.release(button),
+        }
+    }
+
+    #[cfg(feature = "debug")]
+    fn run_until_stopped(&mut self) -> bool {
+        self.run_until(|_, _| false)
+    }
+
+    // Runs until the pause hotkey, a breakpoint, a watchpoint, done or a quit, returns whether it
+    // was a quit. done is called after every instruction with the opcode that ran. The instruction
+    // at PC runs before anything is checked, so running again from a breakpoint moves on
+    #[cfg(feature = "debug")]
+    fn run_until(&mut self, mut done: impl FnMut(&Self, u8) -> bool) -> bool {
+        let mut limiter = FrameLimiter::new();
+        self.memory.take_accesses();
+        while !self.quit && !self.paused {
+            if self.rewinding {
+                self.step_back(&mut limiter);
+                continue;
+            }
+            let frame = self.frames;
+            while self.frames == frame {
+                let pc = self.registers.pc;
+                let opcode = self.peek(pc);
+                let instruction = self.cycle();
+                println!("{instruction}");
+                if let Instruction::Fault(opcode) = instruction {
+                    self.print_fault(opcode);
+                    self.set_paused(true);
+                    return false;
+                }
+                if let Some((watchpoint, access)) =
+                    self.breakpoints.watched(&self.memory.take_accesses())
+                {
+                    let kind = match access.kind {
+                        AccessKind::Read => "read",
+                        AccessKind::Write => "write",
+                    };
+                    println!(
+                        "Watchpoint {}: {kind} of 0x{:04X} at PC 0x{pc:04X}, 0x{:02X} -> 0x{:02X}",
+                        watchpoint.number, access.address, access.old, access.new
+                    );
+                    self.set_paused(true);
+                    return false;
+                }
+                if let Some((number, interrupt)) =
+                    self.breakpoints.interrupted(&self.requested_interrupts)
+                {
+                    println!("Breakpoint {number}: {interrupt:?} requested at PC 0x{pc:04X}");
+                    self.set_paused(true);
+                    return false;
+                }
+                if let Some(breakpoint) = self.breakpoints.hit(self) {
+                    println!(
+                        "Breakpoint {} at {}",
+                        breakpoint.number,
+                        self.describe_address(breakpoint.address)
+                    );
+                    self.set_paused(true);
+                    return false;
+                }
+                if done(self, opcode) {
+                    let line =
+                        disassembler::disassemble(self.registers.pc, |address| self.peek(address));
+                    println!("Stopped at {line}");
+                    self.set_paused(true);
+                    return false;
+                }
+            }
+            self.sync(&mut limiter);
+        }
+        self.quit
+    }
+
+    // Runs over a CALL or RST as if it were one instruction, steps once otherwise. Only stopping
+    // with SP back where it was keeps a recursive call to the same return address from counting
+    #[cfg(feature = "debug")]
+    fn step_over(&mut self) -> bool {
+        let pc = self.registers.pc;
+        let opcode = self.peek(pc);
+        if !disassembler::is_call(opcode) {
+            println!("{}", self.cycle());
+            return false;
+        }
+        let return_address = disassembler::disassemble(pc, |address| self.peek(address)).next();
+        let sp = self.registers.sp;
+        self.resume_debugging();
+        self.run_until(|cpu, _| cpu.registers.pc == return_address && cpu.registers.sp >= sp)
+    }
+
+    // Runs until the subroutine PC is in returns. Calls push and returns pop, so that's the first
+    // return that leaves SP above where it is now, however deep the calls in between go
+    #[cfg(feature = "debug")]
+    fn step_out(&mut self) -> bool {
+        let sp = self.registers.sp;
+        self.resume_debugging();
+        self.run_until(|cpu, opcode| disassembler::is_return(opcode) && cpu.registers.sp > sp)
+    }
+
+    // Shows the frame again and handles the window's events while the prompt waits on a socket,
+    // returns false once the game was quit
+    #[cfg(feature = "debug")]
+    fn keep_window_going(&mut self) -> bool {
+        self.frontend.present(self.gpu.frame());
+        for event in self.frontend.poll_events() {
+            self.handle_event(event);
+        }
+        !self.quit
+    }
+
+    #[cfg(feature = "debug")]
+    fn resume_debugging(&mut self) {
+        if self.paused {
+            self.set_paused(false);
+        }
+    }
+
     #[cfg(feature = "debug")]
     pub fn run(&mut self) {
         let debug_mode_msg = "Running in Debug Mode".bright_yellow();
         let help = "help".bold();
         let guide_msg = format!("Type {help} to see the list of commands!");
-        println!("");
+        println!();
         println!(" {debug_mode_msg}");
         println!(" {guide_msg}");
-        println!("");
-        let mut action = String::new();
+        println!();
+        self.load_rom_symbols();
+        self.catch_faults = true;
+        let mut prompt = match &self.config.debug_socket {
+            Some(address) => match Prompt::serve(address) {
+                Ok(prompt) => prompt,
+                Err(error) => {
+                    eprintln!("Couldn't serve the debugger on {address}: {error}");
+                    return;
+                }
+            },
+            None => Prompt::new().expect("Failed to open the prompt"),
+        };
         loop {
-            let debugger_prefix = "(gb-debugger) ".bright_green();
-            println!("");
-            print!("{debugger_prefix}");
-            io::stdout().flush().expect("Failed to flush stdout");
-            action.clear();
-            io::stdin()
-                .read_line(&mut action)
-                .expect("Failed to read line");
-            println!("");
+            println!();
+            // Input ending, like Ctrl-D, quits
+            let counters = format!(
+                "[frame {}, cycle {}] ",
+                self.since_reset.frames, self.since_reset.cycles
+            );
+            let Some(action) = prompt.read(&counters, &mut || self.keep_window_going()) else {
+                break;
+            };
+            println!();
             match action.trim() {
                 "help" => {
                     let mut table = Table::new();
                     table.add_row(row!["Command", "Description"]);
                     table.add_row(row!["run", "Start the emulator and run the loaded ROM."]);
                     table.add_row(row!["quit, q", "Exit the debugger"]);
-                    table.add_row(row!["step", "Execute one cycle of the emulator."]);
+                    table.add_row(row!["pause", "Freeze the emulator, the pause hotkey also brings you back here while it runs."]);
+                    table.add_row(row!["resume", "Continue running after a pause."]);
+                    table.add_row(row!["frame", "Run a single frame and stop again."]);
+                    table.add_row(row!["step [N]", "Execute one instruction, or N of them."]);
+                    table.add_row(row![
+                        "rstep [N]",
+                        "Go back one instruction, or N of them, as far as a few frames back."
+                    ]);
+                    table.add_row(row![
+                        "next",
+                        "Like step, but run a whole CALL or RST as one instruction."
+                    ]);
+                    table.add_row(row!["finish", "Run until the current subroutine returns."]);
+                    table.add_row(row![
+                        "until <ADDR>",
+                        "Run until PC reaches the address, like a breakpoint that's only used once."
+                    ]);
+                    table.add_row(row!["until ret", "Run until the next return instruction."]);
+                    table.add_row(row![
+                        "until vblank",
+                        "Run until the next frame is complete."
+                    ]);
+                    table.add_row(row![
+                        "stopwatch",
+                        "Show the cycles, frames and time run since the stopwatch was started."
+                    ]);
+                    table.add_row(row![
+                        "stopwatch start",
+                        "Start the stopwatch over from 0, e.g. at the beginning of a routine."
+                    ]);
+                    table.add_row(row!["break <ADDR> [if <COND>]", "Stop running when PC reaches the address, and the condition holds\n(e.g., a == 0x3C && [0xC0A0] > 5, with zf, nf, hf and cf for flags)."]);
+                    table.add_row(row!["break irq <NAME>", "Stop running when the interrupt is requested (vblank, stat, timer, serial\nor joypad). They aren't dispatched yet, so that's as far as they go."]);
+                    table.add_row(row!["break io read|write <REG>", "Stop running when an instruction reads or writes the IO register,\nby name (e.g., LCDC, TAC or NR52) or address."]);
+                    table.add_row(row![
+                        "watch read|write <ADDR>[-<END>]",
+                        "Stop running when an instruction reads or writes the address range."
+                    ]);
+                    table.add_row(row![
+                        "delete <N>",
+                        "Remove breakpoint or watchpoint number N."
+                    ]);
+                    table.add_row(row!["breakpoints", "List the breakpoints and watchpoints."]);
+                    table.add_row(row![
+                        "disasm [ADDR] [COUNT]",
+                        "Disassemble COUNT instructions from the address, or the ones around PC."
+                    ]);
+                    table.add_row(row![
+                        "disasm rom <FILE>",
+                        "Write the whole ROM's disassembly to FILE, for RGBDS."
+                    ]);
+                    table.add_row(row!["trace start <FILE> [<START>-<END>]", "Log every instruction run to FILE with the registers and cycles,\nonly the ones with PC in the range if one is given."]);
+                    table.add_row(row!["trace stop", "Stop logging instructions."]);
+                    table.add_row(row![
+                        "profile start",
+                        "Count the cycles spent at every address from now on, starting from 0."
+                    ]);
+                    table.add_row(row![
+                        "profile stop",
+                        "Stop counting, what was counted stays for the report."
+                    ]);
+                    table.add_row(row!["profile report [N]", "List the N addresses, and symbols if loaded, that took the most cycles,\n20 by default."]);
+                    table.add_row(row![
+                        "coverage",
+                        "Show how much of each ROM bank has been run since the game was put in."
+                    ]);
+                    table.add_row(row![
+                        "coverage export <FILE>",
+                        "Write a byte for every ROM byte to FILE, 1 if it was run and 0 if not."
+                    ]);
+                    table.add_row(row!["coverage clear", "Forget what has been run so far."]);
+                    table.add_row(row!["tui", "Switch to a full screen view of the code, registers, stack, memory\nand breakpoints, driven by single keys."]);
+                    table.add_row(row!["script <FILE>", "Run a Rhai script, which can use reg, set_reg, peek, poke, step, run,\nbreak_at, on_break(<ADDR>, <FN>) and hexdump(<ADDR>, <COUNT>)."]);
+                    table.add_row(row!["sym <FILE>", "Load an RGBDS .sym file to show names for addresses,\n<rom>.sym is loaded on its own."]);
+                    table.add_row(row!["reset", "Start the game over, keeping what's in RAM."]);
+                    table.add_row(row!["reset hard", "Start the game over with RAM cleared."]);
                     table.add_row(row!["display rom", "Display the current ROM contents."]);
+                    table.add_row(row!["screenshot", "Save the current frame as a PNG."]);
+                    table.add_row(row![
+                        "save state",
+                        "Save the machine's state to the current slot."
+                    ]);
+                    table.add_row(row![
+                        "load state",
+                        "Restore the machine's state from the current slot."
+                    ]);
+                    table.add_row(row!["slot <0-9>", "Choose the save state slot."]);
+                    table.add_row(row!["capture", "Start or stop recording gameplay."]);
+                    table.add_row(row![
+                        "record audio",
+                        "Start or stop recording sound to a WAV file."
+                    ]);
+                    table.add_row(row!["mute <1-4>", "Mute or unmute a sound channel."]);
+                    table.add_row(row![
+                        "solo <1-4>",
+                        "Play only this sound channel, again to hear them all."
+                    ]);
+                    table.add_row(row!["show ppu", "Show the LCD registers, the PPU's mode and position and the STAT sources\nholding."]);
+                    table.add_row(row![
+                        "show irq",
+                        "Show IE and IF, and which interrupts are enabled, requested or both."
+                    ]);
+                    table.add_row(row![
+                        "show timer",
+                        "Show DIV's counter, TIMA, TMA, TAC and the cycles until TIMA overflows."
+                    ]);
+                    table.add_row(row![
+                        "show apu",
+                        "Show each sound channel's state and the NRxx registers."
+                    ]);
+                    table.add_row(row![
+                        "oam",
+                        "List the 40 sprites, * marks the ones on the current scanline."
+                    ]);
                     table.add_row(row!["show register <REG>", "Show the value of a specific register\n(e.g., af, bc, de, hl, sp, pc or all)."]);
+                    table.add_row(row!["set register <REG> <VALUE>", "Change a register, half or flag\n(e.g., a 0, hl 0xC000, zf 1, or an expression like a + 1)."]);
+                    table.add_row(row![
+                        "set memory <ADDR> <BYTE...>",
+                        "Write the bytes from the address on, as the CPU would."
+                    ]);
+                    table.add_row(row![
+                        "snapshot take <NAME>",
+                        "Keep a copy of cartridge RAM, WRAM and HRAM under the name."
+                    ]);
+                    table.add_row(row!["snapshot diff <NAME>", "List the RAM addresses that changed since the snapshot, to find\nvariables like lives or the score."]);
+                    table.add_row(row![
+                        "fill <START> <END> <BYTE>",
+                        "Write the byte from START to END, both included."
+                    ]);
+                    table.add_row(row!["x[/<COUNT>] <ADDR> [less]", "Hexdump COUNT bytes from the address, 16 by default, through less if asked."]);
                     table.add_row(row![
                         "show memory <ADDR>",
                         "Display memory content at a given address."
@@ -314,31 +1709,425 @@ impl<T: Drawable> CPU<T> {
                     table.printstd();
                 }
                 "run" => {
-                    let mut cycles = 0;
-                    let one_sec = Duration::from_secs(1);
-                    loop {
-                        let timer = Instant::now();
-                        while cycles < FREQUENCY {
-                            println!("{}", self.cycle());
-                            cycles += 1;
-                        }
-                        let elapsed = timer.elapsed();
-                        if elapsed < one_sec {
-                            thread::sleep(one_sec - elapsed);
+                    self.resume_debugging();
+                    if self.run_until_stopped() {
+                        break;
+                    }
+                }
+                "pause" => {
+                    if !self.paused {
+                        self.set_paused(true);
+                    }
+                }
+                "resume" => {
+                    if !self.paused {
+                        println!("Not paused");
+                    } else {
+                        self.set_paused(false);
+                        if self.run_until_stopped() {
+                            break;
                         }
-                        cycles = 0;
                     }
                 }
                 "quit" | "q" => {
                     break;
                 }
-                "step" => {
-                    println!("{}", self.cycle());
+                "step" => {
+                    println!("{}", self.cycle());
+                }
+                cmd if cmd.starts_with("step ") => match cmd[5..].trim().parse::<u32>() {
+                    Ok(count) => {
+                        for _ in 0..count {
+                            println!("{}", self.cycle());
+                        }
+                    }
+                    Err(_) => println!("Usage: step [N]"),
+                },
+                cmd if cmd == "rstep" || cmd.starts_with("rstep ") => match cmd[5..].trim() {
+                    "" => self.rstep(1),
+                    count => match count.parse() {
+                        Ok(count) => self.rstep(count),
+                        Err(_) => println!("Usage: rstep [N]"),
+                    },
+                },
+                "next" => {
+                    if self.step_over() {
+                        break;
+                    }
+                }
+                "finish" => {
+                    if self.step_out() {
+                        break;
+                    }
+                }
+                cmd if cmd.starts_with("until ") => {
+                    let frame = self.frames;
+                    let quit = match cmd[6..].trim() {
+                        "ret" => {
+                            self.resume_debugging();
+                            self.run_until(|_, opcode| disassembler::is_return(opcode))
+                        }
+                        "vblank" => {
+                            self.resume_debugging();
+                            self.run_until(|cpu, _| cpu.frames != frame)
+                        }
+                        address => match debugger::parse_address(address) {
+                            Some(address) => {
+                                self.resume_debugging();
+                                self.run_until(|cpu, _| cpu.registers.pc == address)
+                            }
+                            None => {
+                                println!("Usage: until <ADDR>|ret|vblank, use 0x for hex.");
+                                false
+                            }
+                        },
+                    };
+                    if quit {
+                        break;
+                    }
+                }
+                "frame" => self.advance_frame(),
+                "stopwatch" => println!("{}", self.stopwatch),
+                "coverage" => {
+                    let banks = self.coverage.banks();
+                    let mut table = Table::new();
+                    table.add_row(row!["Bank", "Executed", "Size", "%"]);
+                    for bank in &banks {
+                        table.add_row(row![
+                            bank.bank,
+                            bank.executed,
+                            bank.size,
+                            format!("{:.2}", bank.percent())
+                        ]);
+                    }
+                    let executed: usize = banks.iter().map(|bank| bank.executed).sum();
+                    let size: usize = banks.iter().map(|bank| bank.size).sum();
+                    table.add_row(row![
+                        "All",
+                        executed,
+                        size,
+                        format!("{:.2}", executed as f64 * 100.0 / size.max(1) as f64)
+                    ]);
+                    table.printstd();
+                }
+                "coverage clear" => {
+                    self.coverage.clear();
+                    println!("Coverage cleared");
+                }
+                cmd if cmd.starts_with("coverage export ") => {
+                    let path = cmd[16..].trim();
+                    match fs::write(path, self.coverage.bitmap()) {
+                        Ok(()) => println!("Coverage written to {path}"),
+                        Err(error) => println!("Couldn't write {path}: {error}"),
+                    }
+                }
+                cmd if cmd.starts_with("snapshot take ") => {
+                    let name = cmd[14..].trim().to_string();
+                    let snapshot = RamSnapshot::take(|address| self.peek(address));
+                    self.snapshots.insert(name.clone(), snapshot);
+                    println!("Snapshot {name} taken");
+                }
+                cmd if cmd.starts_with("snapshot diff ") => {
+                    self.print_snapshot_diff(cmd[14..].trim())
+                }
+                "profile start" => {
+                    self.profile = Profile::default();
+                    self.profiling = true;
+                    println!("Profiling");
+                }
+                "profile stop" => {
+                    if self.profiling {
+                        self.profiling = false;
+                        println!("Profiled {} cycles", self.profile.total());
+                    } else {
+                        println!("Not profiling");
+                    }
+                }
+                cmd if cmd.starts_with("profile report") => match cmd[14..].trim() {
+                    "" => self.print_profile(20),
+                    count => match count.parse() {
+                        Ok(count) => self.print_profile(count),
+                        Err(_) => println!("Usage: profile report [N]"),
+                    },
+                },
+                "stopwatch start" => {
+                    self.stopwatch.clear();
+                    println!("Stopwatch started");
+                }
+                cmd if cmd.starts_with("break irq ") => {
+                    match debugger::parse_interrupt(cmd[10..].trim()) {
+                        Some(interrupt) => {
+                            let number = self.breakpoints.break_on_interrupt(interrupt);
+                            println!("Breakpoint {number} on {interrupt:?}");
+                        }
+                        None => println!("Interrupts are vblank, stat, timer, serial and joypad."),
+                    }
+                }
+                cmd if cmd.starts_with("break io ") => {
+                    let parts: Vec<&str> = cmd.split_whitespace().collect();
+                    let kind = match parts.get(2) {
+                        Some(&"read") => Some(AccessKind::Read),
+                        Some(&"write") => Some(AccessKind::Write),
+                        _ => None,
+                    };
+                    let address = parts.get(3).and_then(|address| debugger::parse_io(address));
+                    match (kind, address) {
+                        (Some(kind), Some(address)) => {
+                            let number = self.breakpoints.watch(kind, address, address);
+                            self.memory.set_logging_accesses(true);
+                            println!("Watchpoint {number} on 0x{address:04X}");
+                        }
+                        _ => println!(
+                            "Usage: break io read|write <REG>, a name like LCDC or an address \
+                             from 0xFF00 to 0xFF7F, or 0xFFFF."
+                        ),
+                    }
+                }
+                cmd if cmd.starts_with("break ") => {
+                    let (address, condition) = match cmd[6..].split_once(" if ") {
+                        Some((address, condition)) => (address, Some(condition)),
+                        None => (&cmd[6..], None),
+                    };
+                    match (
+                        debugger::parse_address(address.trim()),
+                        condition.map(Condition::parse).transpose(),
+                    ) {
+                        (Some(address), Ok(condition)) => {
+                            let number = self.breakpoints.add(address, condition);
+                            println!("Breakpoint {number} at 0x{address:04X}");
+                        }
+                        (None, _) => println!("Invalid address, use 0x for hex."),
+                        (_, Err(error)) => println!("Invalid condition: {error}"),
+                    }
+                }
+                cmd if cmd.starts_with("watch ") => {
+                    let parts: Vec<&str> = cmd.split_whitespace().collect();
+                    let kind = match parts.get(1) {
+                        Some(&"read") => Some(AccessKind::Read),
+                        Some(&"write") => Some(AccessKind::Write),
+                        _ => None,
+                    };
+                    let range = parts.get(2).and_then(|range| debugger::parse_range(range));
+                    match (kind, range) {
+                        (Some(kind), Some((start, end))) => {
+                            let number = self.breakpoints.watch(kind, start, end);
+                            self.memory.set_logging_accesses(true);
+                            println!("Watchpoint {number} on 0x{start:04X}-0x{end:04X}");
+                        }
+                        _ => println!("Usage: watch read|write <ADDR>[-<END>], use 0x for hex."),
+                    }
+                }
+                cmd if cmd.starts_with("delete ") => match cmd[7..].trim().parse::<u32>() {
+                    Ok(number) if self.breakpoints.delete(number) => {
+                        self.memory
+                            .set_logging_accesses(self.breakpoints.is_watching());
+                        println!("Deleted {number}");
+                    }
+                    _ => println!("No breakpoint or watchpoint number {}.", cmd[7..].trim()),
+                },
+                cmd if cmd.starts_with("trace start ") => self.start_trace(&cmd[12..]),
+                "trace stop" => self.stop_trace(),
+                "tui" => match self.run_tui() {
+                    Ok(true) => break,
+                    Ok(false) => {}
+                    Err(error) => println!("The TUI failed: {error}"),
+                },
+                cmd if cmd.starts_with("script ") => {
+                    if self.run_script(Path::new(cmd[7..].trim())) {
+                        break;
+                    }
+                }
+                cmd if cmd.starts_with("sym ") => self.load_symbols(Path::new(cmd[4..].trim())),
+                cmd if cmd.starts_with("disasm rom ") => {
+                    let path = cmd["disasm rom ".len()..].trim();
+                    let title = self.memory.title().unwrap_or_default();
+                    let listing = disassembler::disassemble_rom(self.memory.rom(), &title);
+                    match fs::write(path, listing) {
+                        Ok(()) => println!("Disassembly saved to {path}"),
+                        Err(error) => println!("Couldn't write {path}: {error}"),
+                    }
+                }
+                cmd if cmd == "disasm" || cmd.starts_with("disasm ") => {
+                    self.print_disassembly(&cmd[6..])
+                }
+                "breakpoints" => {
+                    if self.breakpoints.is_empty() {
+                        println!("No breakpoints.");
+                    } else {
+                        let mut table = Table::new();
+                        table.add_row(row!["#", "Type", "Address", "Condition"]);
+                        for breakpoint in self.breakpoints.iter() {
+                            let condition = breakpoint.condition.as_ref();
+                            table.add_row(row![
+                                breakpoint.number,
+                                "break",
+                                self.describe_address(breakpoint.address),
+                                condition.map_or("", |condition| condition.text.as_str())
+                            ]);
+                        }
+                        for (number, interrupt) in self.breakpoints.interrupts() {
+                            table.add_row(row![number, "break irq", format!("{interrupt:?}"), ""]);
+                        }
+                        for watchpoint in self.breakpoints.watchpoints() {
+                            let kind = match watchpoint.kind {
+                                AccessKind::Read => "watch read",
+                                AccessKind::Write => "watch write",
+                            };
+                            table.add_row(row![
+                                watchpoint.number,
+                                kind,
+                                format!("0x{:04X}-0x{:04X}", watchpoint.start, watchpoint.end),
+                                ""
+                            ]);
+                        }
+                        table.printstd();
+                    }
+                }
+                "reset" => self.request_reset(false),
+                "reset hard" => self.request_reset(true),
+                "screenshot" => self.screenshot(),
+                "save state" => self.save_slot(),
+                "load state" => self.load_slot(),
+                cmd if cmd.starts_with("slot ") => match cmd[5..].trim().parse::<u8>() {
+                    Ok(slot) if slot < savestate::SLOTS => self.select_slot(slot),
+                    _ => println!("Slots are 0 to {}.", savestate::SLOTS - 1),
+                },
+                "capture" => self.toggle_capture(),
+                "record audio" => self.toggle_audio_recording(),
+                "oam" => {
+                    let height = viewers::sprite_height(&self.memory);
+                    let ly = self.memory.memory[LY as usize];
+                    let mut table = Table::new();
+                    table.add_row(row!["#", "X", "Y", "Tile", "Flags", ""]);
+                    for sprite in viewers::sprites(&self.memory) {
+                        let flags = [
+                            (sprite.priority(), "behind-bg"),
+                            (sprite.y_flip(), "y-flip"),
+                            (sprite.x_flip(), "x-flip"),
+                            (sprite.palette() == OBP1, "obp1"),
+                        ]
+                        .iter()
+                        .filter(|(set, _)| *set)
+                        .map(|(_, name)| *name)
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                        let on_line = if sprite.on_line(ly, height) { "*" } else { "" };
+                        table.add_row(row![
+                            sprite.index,
+                            sprite.x,
+                            sprite.y,
+                            format!("0x{:02X}", sprite.tile),
+                            flags,
+                            on_line
+                        ]);
+                    }
+                    table.printstd();
+                }
+                "show apu" => {
+                    let mut table = Table::new();
+                    table.add_row(row![
+                        "Channel",
+                        "Enabled",
+                        "DAC",
+                        "Frequency",
+                        "Volume",
+                        "Duty",
+                        "Muted"
+                    ]);
+                    for channel in 0..4 {
+                        let status = self.memory.apu.status(channel);
+                        table.add_row(row![
+                            channel + 1,
+                            status.enabled,
+                            status.dac_enabled,
+                            format!("{:.1} Hz", status.frequency),
+                            status.volume,
+                            status
+                                .duty
+                                .map_or("-".to_string(), |duty| format!("{duty}%")),
+                            self.memory.apu.muted(channel)
+                        ]);
+                    }
+                    table.printstd();
+                    let mut table = Table::new();
+                    for (name, address) in apu::REGISTERS {
+                        table.add_row(row![name, format!("0x{:02X}", self.memory.read(address))]);
+                    }
+                    table.printstd();
+                }
+                "show ppu" => {
+                    let mut table = Table::new();
+                    for (name, address) in gpu::REGISTERS {
+                        table.add_row(row![name, format!("0x{:02X}", self.peek(address))]);
+                    }
+                    table.printstd();
+                    let sources = self.gpu.stat_sources(&self.memory);
+                    let mut table = Table::new();
+                    table.add_row(row!["Mode", format!("{:?}", self.gpu.mode())]);
+                    table.add_row(row!["Scanline", self.peek(LY)]);
+                    table.add_row(row!["Dot", self.gpu.dot()]);
+                    table.add_row(row![
+                        "STAT sources",
+                        if sources.is_empty() {
+                            "-".to_string()
+                        } else {
+                            sources.join(", ")
+                        }
+                    ]);
+                    table.printstd();
+                }
+                "show irq" => {
+                    let enabled = self.peek(memory::INTERRUPT_ENABLE);
+                    let requested = self.peek(memory::INTERRUPT_FLAG);
+                    // There's no EI, DI or dispatch yet, so requested interrupts just stay in IF
+                    println!("IME: not emulated, interrupts aren't serviced yet");
+                    println!("IE: 0x{enabled:02X}  IF: 0x{requested:02X}");
+                    let mut table = Table::new();
+                    table.add_row(row!["Interrupt", "Enabled", "Requested", "Pending"]);
+                    for interrupt in Interrupt::ALL {
+                        let bit = 1 << interrupt as u8;
+                        table.add_row(row![
+                            format!("{interrupt:?}"),
+                            enabled & bit != 0,
+                            requested & bit != 0,
+                            enabled & requested & bit != 0
+                        ]);
+                    }
+                    table.printstd();
+                }
+                "show timer" => {
+                    let timer = &self.memory.timer;
+                    let mut table = Table::new();
+                    for (name, address) in timer::REGISTERS {
+                        table.add_row(row![name, format!("0x{:02X}", self.peek(address))]);
+                    }
+                    table.add_row(row!["Counter", format!("0x{:04X}", timer.counter())]);
+                    table.add_row(row![
+                        "Overflow in",
+                        timer
+                            .cycles_to_overflow()
+                            .map_or("stopped".to_string(), |cycles| format!("{cycles} cycles"))
+                    ]);
+                    table.printstd();
                 }
                 "display rom" => match self.memory.display_rom() {
                     Ok(_) => {}
                     Err(_) => continue,
                 },
+                cmd if cmd.starts_with("mute ") || cmd.starts_with("solo ") => {
+                    let (command, channel) = cmd.split_once(' ').unwrap();
+                    match channel.trim().parse::<usize>() {
+                        Ok(channel @ 1..=4) if command == "mute" => {
+                            self.memory.apu.toggle_mute(channel - 1)
+                        }
+                        Ok(channel @ 1..=4) => self.memory.apu.solo(channel - 1),
+                        _ => {
+                            println!("Sound channels are 1 to 4.");
+                            continue;
+                        }
+                    }
+                    self.print_mutes();
+                }
                 cmd if cmd.starts_with("show register ") => {
                     match cmd.trim_start_matches("show register ") {
                         "af" => println!("0x{:02X?}", self.registers.af),
@@ -360,23 +2149,35 @@ impl<T: Drawable> CPU<T> {
                         _ => println!("Unknown register."),
                     }
                 }
+                cmd if cmd.starts_with("set register ") => match cmd[13..].trim().split_once(' ') {
+                    Some((name, value)) => {
+                        if let Err(error) = self.set_register(name, value) {
+                            println!("{error}");
+                        }
+                    }
+                    None => println!("Usage: set register <REG> <VALUE>"),
+                },
+                cmd if cmd.starts_with("set memory ") => {
+                    if let Err(error) = self.set_memory(&cmd[11..]) {
+                        println!("{error}");
+                    }
+                }
+                cmd if cmd.starts_with("fill ") => {
+                    if let Err(error) = self.fill_memory(&cmd[5..]) {
+                        println!("{error}");
+                    }
+                }
+                cmd if cmd.starts_with("x ") || cmd.starts_with("x/") => {
+                    self.print_hexdump(&cmd[1..])
+                }
                 cmd if cmd.starts_with("show memory ") => {
                     let parts: Vec<&str> = cmd.split_whitespace().collect();
                     if let Some(addr_str) = parts.get(2) {
-                        let address = if addr_str.starts_with("0x") {
-                            u16::from_str_radix(&addr_str[2..], 16)
-                        } else {
-                            addr_str.parse::<u16>()
-                        };
-
-                        match address {
-                            Ok(address) => {
-                                println!(
-                                    "Memory at address {}: {}",
-                                    addr_str, self.memory.memory[address as usize]
-                                );
+                        match debugger::parse_address(addr_str) {
+                            Some(address) => {
+                                println!("Memory at address {}: {}", addr_str, self.peek(address));
                             }
-                            Err(_) => {
+                            None => {
                                 println!("Invalid memory address format: {}", addr_str);
                             }
                         }
@@ -390,23 +2191,525 @@ impl<T: Drawable> CPU<T> {
                 }
             }
         }
+        self.shutdown();
+    }
+
+    // Writes the value, which can be an expression, to the register, half or flag with that name
+    #[cfg(feature = "debug")]
+    fn set_register(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let register = Value::register(name).ok_or_else(|| format!("Unknown register {name}"))?;
+        let number = Expression::parse(value)?.evaluate(self);
+        let (index, pair) = register.written(&self.registers(), number)?;
+        let registers = &mut self.registers;
+        match index {
+            // The low nibble of F is always 0 on the hardware
+            0 => registers.af = pair & 0xFFF0,
+            1 => registers.bc = pair,
+            2 => registers.de = pair,
+            3 => registers.hl = pair,
+            4 => registers.sp = pair,
+            _ => registers.pc = pair,
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "debug")]
+    fn print_hexdump(&self, arguments: &str) {
+        const USAGE: &str = "Usage: x[/<COUNT>] <ADDR> [less], use 0x for hex.";
+        let (count, arguments) = match arguments.strip_prefix('/') {
+            Some(arguments) => match arguments.split_once(' ') {
+                Some((count, arguments)) => (count.parse::<usize>().ok(), arguments),
+                None => (None, arguments),
+            },
+            None => (Some(16), arguments),
+        };
+        let arguments: Vec<&str> = arguments.split_whitespace().collect();
+        let (Some(count), Some(address), paged) = (
+            count,
+            arguments
+                .first()
+                .and_then(|address| debugger::parse_address(address)),
+            arguments.get(1) == Some(&"less"),
+        ) else {
+            println!("{USAGE}");
+            return;
+        };
+        let rows = debugger::hexdump(address, count, |address| self.peek(address));
+        if paged {
+            if let Err(error) = debugger::page((rows.join("\n") + "\n").as_bytes()) {
+                println!("Couldn't run less: {error}");
+            }
+        } else {
+            for row in rows {
+                println!("{row}");
+            }
+        }
+    }
+
+    // Writes go through the bus like the CPU's, so writing DIV resets it and so on
+    #[cfg(feature = "debug")]
+    fn set_memory(&mut self, arguments: &str) -> Result<(), String> {
+        const USAGE: &str = "Usage: set memory <ADDR> <BYTE...>, use 0x for hex.";
+        let arguments: Vec<&str> = arguments.split_whitespace().collect();
+        let Some((address, bytes)) = arguments.split_first() else {
+            return Err(USAGE.to_string());
+        };
+        let address = debugger::parse_address(address).ok_or(USAGE)?;
+        let bytes = bytes
+            .iter()
+            .map(|byte| debugger::parse_byte(byte).ok_or(format!("{byte} isn't a byte.")))
+            .collect::<Result<Vec<u8>, String>>()?;
+        if bytes.is_empty() {
+            return Err(USAGE.to_string());
+        }
+        if address as usize + bytes.len() > 0x10000 {
+            return Err("The bytes run past 0xFFFF.".to_string());
+        }
+        for (address, byte) in (address..=0xFFFF).zip(bytes) {
+            self.memory.write(address, byte);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "debug")]
+    fn fill_memory(&mut self, arguments: &str) -> Result<(), String> {
+        const USAGE: &str = "Usage: fill <START> <END> <BYTE>, use 0x for hex.";
+        let [start, end, byte] = arguments.split_whitespace().collect::<Vec<&str>>()[..] else {
+            return Err(USAGE.to_string());
+        };
+        let (Some(start), Some(end), Some(byte)) = (
+            debugger::parse_address(start),
+            debugger::parse_address(end),
+            debugger::parse_byte(byte),
+        ) else {
+            return Err(USAGE.to_string());
+        };
+        if start > end {
+            return Err("START comes after END.".to_string());
+        }
+        for address in start..=end {
+            self.memory.write(address, byte);
+        }
+        Ok(())
+    }
+
+    // Does what the script asks until it ends, returns whether the game was quit meanwhile
+    #[cfg(feature = "debug")]
+    fn run_script(&mut self, path: &Path) -> bool {
+        let script = Script::start(path.to_path_buf());
+        let mut quit = false;
+        while let Some(request) = script.next() {
+            let reply = match request {
+                Request::Register(name) => Value::register(&name)
+                    .map(|register| Expression::Value(register).evaluate(self) as i64)
+                    .ok_or(format!("Unknown register {name}")),
+                Request::SetRegister(name, value) => {
+                    self.set_register(&name, &value.to_string()).map(|_| 0)
+                }
+                Request::Peek(address) => Ok(self.peek(address) as i64),
+                Request::Poke(address, value) => {
+                    self.memory.write(address, value);
+                    Ok(0)
+                }
+                Request::Step(count) => {
+                    for _ in 0..count {
+                        println!("{}", self.cycle());
+                    }
+                    Ok(0)
+                }
+                Request::Run => {
+                    self.resume_debugging();
+                    quit = self.run_until_stopped();
+                    if quit {
+                        Err("The game was quit".to_string())
+                    } else {
+                        Ok(self.registers.pc as i64)
+                    }
+                }
+                Request::Break(address) => Ok(self.breakpoints.add(address, None) as i64),
+            };
+            script.reply(reply);
+            if quit {
+                break;
+            }
+        }
+        if let Err(error) = script.finish() {
+            println!("Script {} failed: {error}", path.display());
+        }
+        quit
+    }
+
+    // Runs the full screen debugger until it's left, returns whether the game was quit meanwhile
+    #[cfg(feature = "debug")]
+    fn run_tui(&mut self) -> io::Result<bool> {
+        let mut tui = Tui::start()?;
+        loop {
+            tui.draw(&self.panes(tui.memory_start))?;
+            let command = tui.next_command()?;
+            // Anything that can run for a while prints as it goes, which is left to the terminal
+            let quit = match command {
+                Command::Step => {
+                    tui.status = self.cycle().to_string();
+                    false
+                }
+                Command::Next | Command::Finish | Command::Run => {
+                    tui.suspend()?;
+                    let quit = match command {
+                        Command::Next => self.step_over(),
+                        Command::Finish => self.step_out(),
+                        _ => {
+                            self.resume_debugging();
+                            self.run_until_stopped()
+                        }
+                    };
+                    tui.resume()?;
+                    tui.status = format!("Stopped at 0x{:04X}", self.registers.pc);
+                    quit
+                }
+                Command::ToggleBreakpoint => {
+                    let pc = self.registers.pc;
+                    let existing = self
+                        .breakpoints
+                        .iter()
+                        .find(|breakpoint| breakpoint.address == pc)
+                        .map(|breakpoint| breakpoint.number);
+                    match existing {
+                        Some(number) => {
+                            self.breakpoints.delete(number);
+                            tui.status = format!("Deleted breakpoint {number}");
+                        }
+                        None => {
+                            let number = self.breakpoints.add(pc, None);
+                            tui.status = format!("Breakpoint {number} at 0x{pc:04X}");
+                        }
+                    }
+                    false
+                }
+                Command::Refresh => false,
+                Command::Quit => return Ok(false),
+            };
+            if quit {
+                return Ok(true);
+            }
+        }
+    }
+
+    #[cfg(feature = "debug")]
+    fn panes(&self, memory_start: u16) -> Panes {
+        const BEFORE_PC: usize = 8;
+        const INSTRUCTIONS: usize = 48;
+        const STACK_WORDS: usize = 8;
+        const MEMORY_BYTES: usize = 16 * 16;
+        let read = |address| self.peek(address);
+        let start = disassembler::lead_in(self.registers.pc, BEFORE_PC, read);
+        let stack = (0..STACK_WORDS)
+            .map_while(|word| self.registers.sp.checked_add(word as u16 * 2))
+            .map(|address| {
+                let high = self.peek(address.wrapping_add(1)) as u16;
+                (address, high << 8 | self.peek(address) as u16)
+            })
+            .collect();
+        let mut breakpoints: Vec<String> = self
+            .breakpoints
+            .iter()
+            .map(|breakpoint| {
+                let condition = breakpoint.condition.as_ref();
+                format!(
+                    "{} {} {}",
+                    breakpoint.number,
+                    self.describe_address(breakpoint.address),
+                    condition.map_or(String::new(), |condition| format!("if {}", condition.text))
+                )
+            })
+            .collect();
+        breakpoints.extend(
+            self.breakpoints
+                .interrupts()
+                .map(|(number, interrupt)| format!("{number} irq {interrupt:?}")),
+        );
+        breakpoints.extend(self.breakpoints.watchpoints().map(|watchpoint| {
+            let kind = match watchpoint.kind {
+                AccessKind::Read => "read",
+                AccessKind::Write => "write",
+            };
+            format!(
+                "{} {kind} 0x{:04X}-0x{:04X}",
+                watchpoint.number, watchpoint.start, watchpoint.end
+            )
+        }));
+        Panes {
+            disassembly: self.disassembly(start, INSTRUCTIONS),
+            registers: self.registers(),
+            stack,
+            memory: debugger::hexdump(memory_start, MEMORY_BYTES, read),
+            breakpoints,
+        }
+    }
+
+    #[cfg(feature = "debug")]
+    fn start_trace(&mut self, arguments: &str) {
+        const USAGE: &str = "Usage: trace start <FILE> [<START>-<END>], use 0x for hex.";
+        let arguments: Vec<&str> = arguments.split_whitespace().collect();
+        let (path, range) = match arguments[..] {
+            [path] => (path, None),
+            [path, range] if let Some(range) = debugger::parse_range(range) => (path, Some(range)),
+            _ => {
+                println!("{USAGE}");
+                return;
+            }
+        };
+        // Starting again replaces the trace that's running, which is finished first
+        self.stop_trace();
+        match File::create(path) {
+            Ok(file) => {
+                self.trace = Some(Trace::new(BufWriter::new(file), range));
+                println!("Tracing to {path}");
+            }
+            Err(error) => println!("Couldn't create {path}: {error}"),
+        }
+    }
+
+    #[cfg(feature = "debug")]
+    fn stop_trace(&mut self) {
+        match self.trace.take().map(Trace::finish) {
+            Some(Ok(lines)) => println!("Traced {lines} instructions."),
+            Some(Err(error)) => println!("Couldn't finish the trace: {error}"),
+            None => {}
+        }
+    }
+
+    // Picks up <rom>.sym next to the game if there's one, dropping the previous game's names
+    #[cfg(feature = "debug")]
+    fn load_rom_symbols(&mut self) {
+        self.symbols = Symbols::default();
+        let Some(path) = self.memory.rom_path().map(|rom| rom.with_extension("sym")) else {
+            return;
+        };
+        if path.exists() {
+            self.load_symbols(&path);
+        }
+    }
+
+    #[cfg(feature = "debug")]
+    fn load_symbols(&mut self, path: &Path) {
+        match Symbols::load(path) {
+            Ok(symbols) => {
+                println!("Loaded {} symbols from {}", symbols.len(), path.display());
+                self.symbols = symbols;
+            }
+            Err(error) => println!("Couldn't load {}: {error}", path.display()),
+        }
+    }
+
+    // 0x0150, followed by the symbol it's in if there's one
+    #[cfg(feature = "debug")]
+    fn describe_address(&self, address: u16) -> String {
+        match self.symbols.locate(address) {
+            Some(symbol) => format!("0x{address:04X} ({symbol})"),
+            None => format!("0x{address:04X}"),
+        }
+    }
+
+    // Changed addresses from the lowest, only the first few when there are many as that means the
+    // snapshot was too long ago to narrow anything down
+    #[cfg(feature = "debug")]
+    fn print_snapshot_diff(&self, name: &str) {
+        const ROWS: usize = 64;
+        let Some(snapshot) = self.snapshots.get(name) else {
+            let names: Vec<&str> = self.snapshots.keys().map(String::as_str).collect();
+            if names.is_empty() {
+                println!("No snapshots, take one with snapshot take <NAME>");
+            } else {
+                println!("No snapshot {name}, there's {}", names.join(", "));
+            }
+            return;
+        };
+        let changes = snapshot.diff(&RamSnapshot::take(|address| self.peek(address)));
+        let mut table = Table::new();
+        table.add_row(row!["Address", "Was", "Now"]);
+        for change in changes.iter().take(ROWS) {
+            table.add_row(row![
+                self.describe_address(change.address),
+                format!("0x{:02X}", change.old),
+                format!("0x{:02X}", change.new)
+            ]);
+        }
+        table.printstd();
+        match changes.len() {
+            0 => println!("Nothing changed since {name}"),
+            count if count > ROWS => println!("{count} addresses changed, the first {ROWS} shown"),
+            count => println!("{count} addresses changed"),
+        }
+    }
+
+    // What's wrong with the opcode at PC, and the code around it
+    #[cfg(feature = "debug")]
+    fn print_fault(&self, opcode: u8) {
+        let pc = self.registers.pc;
+        let line = disassembler::disassemble(pc, |address| self.peek(address));
+        let kind = if line.illegal {
+            "Illegal"
+        } else {
+            "Unimplemented"
+        };
+        let message = format!(
+            "{kind} opcode 0x{opcode:02X} at {}",
+            self.describe_address(pc)
+        );
+        println!("{}", message.bright_red());
+        self.print_disassembly("");
+    }
+
+    #[cfg(feature = "debug")]
+    fn rstep(&mut self, count: u64) {
+        match self.step_backwards(count) {
+            Ok(()) => {
+                let pc = self.registers.pc;
+                let line = disassembler::disassemble(pc, |address| self.peek(address));
+                println!("Back at {line}");
+            }
+            Err(error) => println!("{error}"),
+        }
+    }
+
+    // Goes back count instructions by loading the checkpoint before and running forward to there.
+    // Running again isn't traced or profiled twice
+    #[cfg(feature = "debug")]
+    fn step_backwards(&mut self, count: u64) -> Result<(), String> {
+        let target = self.instructions.saturating_sub(count);
+        let mut history = std::mem::take(&mut self.history);
+        let Some(checkpoint) = history.rewind_to(target) else {
+            let oldest = history.oldest();
+            self.history = history;
+            return Err(match oldest {
+                Some(oldest) => format!(
+                    "Can only go back {} instructions",
+                    self.instructions - oldest
+                ),
+                None => "Nothing to go back to".to_string(),
+            });
+        };
+        self.load_state(&checkpoint.state)?;
+        self.instructions = checkpoint.instruction;
+        self.since_reset = checkpoint.since_reset;
+        self.stopwatch = checkpoint.stopwatch;
+        self.history = history;
+        let profiling = std::mem::replace(&mut self.profiling, false);
+        let trace = self.trace.take();
+        // A fault can only come up again if the debugger changed something since
+        while self.instructions < target {
+            if let Instruction::Fault(_) = self.cycle() {
+                break;
+            }
+        }
+        self.profiling = profiling;
+        self.trace = trace;
+        // What ran again isn't something for watchpoints to stop on later
+        self.memory.take_accesses();
+        Ok(())
+    }
+
+    // The hot spots, per symbol too when there are symbols to add the addresses up under
+    #[cfg(feature = "debug")]
+    fn print_profile(&self, count: usize) {
+        if self.profile.total() == 0 {
+            println!("Nothing profiled, use profile start and run");
+            return;
+        }
+        let mut table = Table::new();
+        table.add_row(row!["Address", "Cycles", "%"]);
+        for spot in self.profile.by_address(count) {
+            table.add_row(row![
+                self.describe_address(spot.address),
+                spot.cycles,
+                format!("{:.2}", spot.share)
+            ]);
+        }
+        table.printstd();
+        if self.symbols.is_empty() {
+            return;
+        }
+        let mut table = Table::new();
+        table.add_row(row!["Symbol", "Cycles", "%"]);
+        for spot in self.profile.by_symbol(&self.symbols, count) {
+            let name = match spot.symbol {
+                Some(symbol) => format!("0x{:04X} ({symbol})", spot.address),
+                None => format!("0x{:04X}", spot.address),
+            };
+            table.add_row(row![name, spot.cycles, format!("{:.2}", spot.share)]);
+        }
+        table.printstd();
+    }
+
+    // Instructions from address, or the ones around PC. => marks PC and * a breakpoint
+    #[cfg(feature = "debug")]
+    fn print_disassembly(&self, arguments: &str) {
+        const BEFORE_PC: usize = 3;
+        const COUNT: usize = 10;
+        let arguments: Vec<&str> = arguments.split_whitespace().collect();
+        let pc = self.registers.pc;
+        let read = |address| self.peek(address);
+        let start = match arguments.first() {
+            Some(address) => debugger::parse_address(address),
+            None => Some(disassembler::lead_in(pc, BEFORE_PC, read)),
+        };
+        let count = match arguments.get(1) {
+            Some(count) => count.parse().ok(),
+            None => Some(COUNT),
+        };
+        let (Some(start), Some(count)) = (start, count) else {
+            println!("Usage: disasm [ADDR] [COUNT], use 0x for hex.");
+            return;
+        };
+        for (at_pc, text) in self.disassembly(start, count) {
+            if at_pc {
+                println!("{}", text.bright_yellow());
+            } else {
+                println!("{text}");
+            }
+        }
     }
+
+    // count instructions from start with labels for symbols, and true for the one at PC
+    #[cfg(feature = "debug")]
+    fn disassembly(&self, start: u16, count: usize) -> Vec<(bool, String)> {
+        let pc = self.registers.pc;
+        let mut lines = Vec::new();
+        for mut line in disassembler::disassemble_from(start, count, |address| self.peek(address)) {
+            if let Some(name) = self.symbols.name(line.address) {
+                lines.push((false, format!("{name}:")));
+            }
+            if let Some(target) = line.target
+                && let Some(name) = self.symbols.name(target)
+            {
+                line.text = line.text.replace(&format!("${target:04X}"), name);
+            }
+            let marker = if line.address == pc { "=>" } else { "  " };
+            let breakpoint = self
+                .breakpoints
+                .iter()
+                .any(|breakpoint| breakpoint.address == line.address);
+            let text = format!("{marker}{} {line}", if breakpoint { "*" } else { " " });
+            lines.push((line.address == pc, text));
+        }
+        lines
+    }
+
     fn decode(&mut self, opcode: u8) -> Instruction {
-        static mut COUNT: u8 = 0;
-        unsafe { COUNT += 1; }
+        self.instructions += 1;
         match opcode {
             0x00 => {
-                self.registers.pc += 1;
+                self.registers.pc = self.registers.pc.wrapping_add(1);
                 Instruction::NOP
             }
             0x03 => {
                 self.registers.bc = self.registers.bc.wrapping_add(1);
-                self.registers.pc += 1;
+                self.registers.pc = self.registers.pc.wrapping_add(1);
                 Instruction::INC_BC
             }
             0x0B => {
                 self.registers.bc = self.registers.bc.wrapping_sub(1);
-                self.registers.pc += 1;
+                self.registers.pc = self.registers.pc.wrapping_add(1);
                 Instruction::DEC_BC
             }
             0x0C => {
@@ -422,94 +2725,111 @@ impl<T: Drawable> CPU<T> {
                 } else {
                     self.clear_flag(Flag::H);
                 }
-                self.registers.pc += 1;
+                self.registers.pc = self.registers.pc.wrapping_add(1);
                 Instruction::INC_C
             }
             0x0E => {
-                self.registers.bc = self.replace_low_byte(self.registers.bc, self.memory.memory[(self.registers.pc + 1) as usize]);
-                self.registers.pc += 2;
+                self.registers.bc = self.replace_low_byte(self.registers.bc, self.memory.read(self.registers.pc.wrapping_add(1)));
+                self.registers.pc = self.registers.pc.wrapping_add(2);
                 Instruction::LD_C_n8
             }
+            // PC stays on STOP until a button press moves it on, see cycle. The PPU keeps going, or
+            // no frame would end for the press to be polled. DIV is reset on the way in
+            0x10 => {
+                if !self.stopped {
+                    self.stopped = true;
+                    self.memory.write(timer::DIV, 0);
+                }
+                Instruction::STOP
+            }
             0x11 => {
-                let low_byte = self.memory.memory[(self.registers.pc + 1) as usize];
-                let high_byte = self.memory.memory[(self.registers.pc + 2) as usize];
+                let low_byte = self.memory.read(self.registers.pc.wrapping_add(1));
+                let high_byte = self.memory.read(self.registers.pc.wrapping_add(2));
                 self.registers.de = Self::concat_bytes(high_byte, low_byte);
-                self.registers.pc += 1;
+                self.registers.pc = self.registers.pc.wrapping_add(3);
                 Instruction::LD_DE_n16
             }
             0x13 => {
                 self.registers.de = self.registers.de.wrapping_add(1);
-                self.registers.pc += 1;
+                self.registers.pc = self.registers.pc.wrapping_add(1);
                 Instruction::INC_DE
             }
             0x1A => {
-                self.registers.af = self.replace_high_byte(self.registers.af, self.memory.memory[self.registers.de as usize]);
-                self.registers.pc += 1; 
+                self.registers.af = self.replace_high_byte(self.registers.af, self.memory.read(self.registers.de));
+                self.registers.pc = self.registers.pc.wrapping_add(1); 
                 Instruction::LD_A_DE  
             }
             0x1E => {
-                self.registers.de = self.replace_low_byte(self.registers.de, self.memory.memory[(self.registers.pc + 1) as usize]);
-                self.registers.pc += 2;
+                self.registers.de = self.replace_low_byte(self.registers.de, self.memory.read(self.registers.pc.wrapping_add(1)));
+                self.registers.pc = self.registers.pc.wrapping_add(2);
                 Instruction::LD_E_n8
             }
             0x20 => {
                 let mut jump: bool = false;
                 if self.get_flag(Flag::Z) == 0 {
-                    let e8 = self.memory.memory[(self.registers.pc + 1) as usize] as i8;
-                    self.registers.pc = (self.registers.pc as i16 + e8 as i16) as u16;
+                    let e8 = self.memory.read(self.registers.pc.wrapping_add(1)) as i8;
+                    self.registers.pc = self.registers.pc.wrapping_add_signed(e8 as i16);
                     jump = true;
                 }
-                self.registers.pc += 2;
+                self.registers.pc = self.registers.pc.wrapping_add(2);
                 Instruction::JR_NZ_e8(jump)
             }
             0x21 => {
-                let low = self.memory.memory[(self.registers.pc + 1) as usize];
-                let high = self.memory.memory[(self.registers.pc + 2) as usize];
+                let low = self.memory.read(self.registers.pc.wrapping_add(1));
+                let high = self.memory.read(self.registers.pc.wrapping_add(2));
                 self.registers.hl = Self::concat_bytes(high, low);
-                self.registers.pc += 3;
+                self.registers.pc = self.registers.pc.wrapping_add(3);
                 Instruction::LD_HL_n16
             }
             0x31 => {
-                let low = self.memory.memory[(self.registers.pc + 1) as usize];
-                let high = self.memory.memory[(self.registers.pc + 2) as usize];
+                let low = self.memory.read(self.registers.pc.wrapping_add(1));
+                let high = self.memory.read(self.registers.pc.wrapping_add(2));
                 self.registers.sp = Self::concat_bytes(high, low);
-                self.registers.pc += 3;
+                self.registers.pc = self.registers.pc.wrapping_add(3);
                 Instruction::LD_SP_n16
             }
             0x32 => {
-                self.memory.memory[self.registers.hl as usize] = self.get_high_byte(self.registers.af);
-                self.registers.hl -= 1;
-                self.registers.pc += 1;
+                self.memory.write(self.registers.hl, self.get_high_byte(self.registers.af));
+                self.registers.hl = self.registers.hl.wrapping_sub(1);
+                self.registers.pc = self.registers.pc.wrapping_add(1);
                 Instruction::LD_HL_DEC_A
             }
             0x3E => {
-                self.registers.af = self.replace_high_byte(self.registers.af, self.memory.memory[(self.registers.pc + 1) as usize]);
-                self.registers.pc += 2;
+                self.registers.af = self.replace_high_byte(self.registers.af, self.memory.read(self.registers.pc.wrapping_add(1)));
+                self.registers.pc = self.registers.pc.wrapping_add(2);
                 Instruction::LD_A_n8
             }
             0x40 => {
-                self.registers.pc += 1;
+                self.registers.pc = self.registers.pc.wrapping_add(1);
                 Instruction::LD_B_B
             }
             0x47 => {
                 let a = self.get_high_byte(self.registers.af);
                 self.registers.bc = self.replace_high_byte(self.registers.bc, a);
-                self.registers.pc += 1;
+                self.registers.pc = self.registers.pc.wrapping_add(1);
                 Instruction::LD_B_A
             }
             0x73 => {
-                self.memory.memory[self.registers.hl as usize] = self.get_low_byte(self.registers.de);
-                self.registers.pc += 1;
+                self.memory.write(self.registers.hl, self.get_low_byte(self.registers.de));
+                self.registers.pc = self.registers.pc.wrapping_add(1);
                 Instruction::LD_HL_E
             }
+            // PC stays on HALT until an enabled interrupt is requested, whether or not IME is set.
+            // Nothing is dispatched yet, so it then goes on with the next instruction
+            0x76 => {
+                if self.memory.interrupt_pending() {
+                    self.registers.pc = self.registers.pc.wrapping_add(1);
+                }
+                Instruction::HALT
+            }
             0x77 => {
-                self.memory.memory[self.registers.hl as usize] = self.get_high_byte(self.registers.af);
-                self.registers.pc += 1;
+                self.memory.write(self.registers.hl, self.get_high_byte(self.registers.af));
+                self.registers.pc = self.registers.pc.wrapping_add(1);
                 Instruction::LD_HL_A
             }
             0xBE => {
                 let a = self.get_high_byte(self.registers.af);
-                let value = self.memory.memory[self.registers.hl as usize];
+                let value = self.memory.read(self.registers.hl);
                 let result = a.wrapping_sub(value);
 
                 if result == 0 {
@@ -531,11 +2851,19 @@ impl<T: Drawable> CPU<T> {
                 } else {
                     self.clear_flag(Flag::C);
                 }
-                self.registers.pc += 1;
+                self.registers.pc = self.registers.pc.wrapping_add(1);
                 Instruction::CP_A_HL
             }
+            // Of the CB-prefixed instructions only RLC, RRC, RL, RR and BIT are there so far
+            #[cfg(feature = "debug")]
+            0xCB if self.catch_faults
+                && !matches!(self.peek(self.registers.pc.wrapping_add(1)), 0x00..=0x1F | 0x40..=0x7F) =>
+            {
+                self.instructions -= 1;
+                Instruction::Fault(opcode)
+            }
             0xCB => {
-                let instruction = self.memory.memory[(self.registers.pc + 1) as usize];
+                let instruction = self.memory.read(self.registers.pc.wrapping_add(1));
                 let prefix_opcode = (instruction & 0b1100_0000) >> 6;
                 if prefix_opcode == 0 {
                     let cb_opcode = (instruction & 0b0011_1000) >> 3;
@@ -630,22 +2958,22 @@ impl<T: Drawable> CPU<T> {
                         }
                     }
                 }
-                self.registers.pc += 2;
+                self.registers.pc = self.registers.pc.wrapping_add(2);
                 Instruction::PREFIX
             }
             0xCD => {
                  // Fetch 16-bit immediate address (little endian)
-                let low = self.memory.memory[(self.registers.pc + 1) as usize];
-                let high = self.memory.memory[(self.registers.pc + 2) as usize];
+                let low = self.memory.read(self.registers.pc.wrapping_add(1));
+                let high = self.memory.read(self.registers.pc.wrapping_add(2));
                 let addr = Self::concat_bytes(high, low);
 
                 // Calculate return address (the instruction *after* CALL)
-                let ret_addr = self.registers.pc + 3;
+                let ret_addr = self.registers.pc.wrapping_add(3);
 
                 // Push return address onto stack (high byte first)
-                self.memory.memory[(self.registers.sp - 1) as usize] = self.get_high_byte(ret_addr); // high byte
-                self.memory.memory[(self.registers.sp - 2) as usize] = self.get_low_byte(ret_addr); // low byte
-                self.registers.sp -= 2;
+                self.memory.write(self.registers.sp.wrapping_sub(1), self.get_high_byte(ret_addr)); // high byte
+                self.memory.write(self.registers.sp.wrapping_sub(2), self.get_low_byte(ret_addr)); // low byte
+                self.registers.sp = self.registers.sp.wrapping_sub(2);
 
                 // Jump to target address
                 self.registers.pc = addr;
@@ -653,10 +2981,9 @@ impl<T: Drawable> CPU<T> {
             }
             0x66 => {
                 let hl = self.registers.hl;
-                let h = self.get_high_byte(hl);
                 self.registers.hl =
-                self.replace_high_byte(hl, self.memory.memory[hl as usize] as u8);
-                self.registers.pc += 1;
+                self.replace_high_byte(hl, self.memory.read(hl));
+                self.registers.pc = self.registers.pc.wrapping_add(1);
                 Instruction::LD_H_HL
             }
             0xAF => {
@@ -666,25 +2993,25 @@ impl<T: Drawable> CPU<T> {
                 self.clear_flag(Flag::N);
                 self.clear_flag(Flag::H);
                 self.clear_flag(Flag::C);
-                self.registers.pc += 1;
+                self.registers.pc = self.registers.pc.wrapping_add(1);
                 Instruction::XOR_A_A
             }
             0xCC => {
                 if self.get_flag(Flag::Z) != 0 {
-                    let low = self.memory.memory[(self.registers.pc + 1) as usize];
-                    let high = self.memory.memory[(self.registers.pc + 2) as usize];
+                    let low = self.memory.read(self.registers.pc.wrapping_add(1));
+                    let high = self.memory.read(self.registers.pc.wrapping_add(2));
                     let addr = Self::concat_bytes(high, low);
                     self.registers.pc = addr;
-                    return Instruction::Call_Z_a16(true);
+                    Instruction::Call_Z_a16(true)
                 } else {
-                    self.registers.pc += 3;
-                    return Instruction::Call_Z_a16(false);
+                    self.registers.pc = self.registers.pc.wrapping_add(3);
+                    Instruction::Call_Z_a16(false)
                 }
             }
             0xCE => {
                 let af = self.registers.af;
                 let a = self.get_high_byte(af);
-                let n8 = self.memory.memory[(self.registers.pc + 1) as usize];
+                let n8 = self.memory.read(self.registers.pc.wrapping_add(1));
                 let result = a.wrapping_add(n8).wrapping_add(self.get_flag(Flag::C));
                 self.registers.af = self.replace_high_byte(af, result);
 
@@ -698,43 +3025,49 @@ impl<T: Drawable> CPU<T> {
                 }
 
                 let a: u16 = a as u16;
-                let n8: u16 = a as u16;
-                let result: u16 = (a.wrapping_add(n8).wrapping_add(self.get_flag(Flag::C) as u16)) as u16;
+                let n8: u16 = n8 as u16;
+                let result: u16 = a.wrapping_add(n8).wrapping_add(self.get_flag(Flag::C) as u16);
                 if result > 0xFF {
                     self.set_flag(Flag::C);
                 }
-                self.registers.pc += 2;
+                self.registers.pc = self.registers.pc.wrapping_add(2);
                 Instruction::ADC_A_n8
             }
             0xE0 => {
-                let n8 = self.memory.memory[(self.registers.pc + 1) as usize];
+                let n8 = self.memory.read(self.registers.pc.wrapping_add(1));
                 let a8 = 0xFF00 + n8 as u16;
                 let a = self.get_high_byte(self.registers.af);
-                self.memory.memory[a8 as usize] = a;
-                self.registers.pc += 1;
+                self.memory.write(a8, a);
+                self.registers.pc = self.registers.pc.wrapping_add(2);
                 Instruction::LDH_a8_A
             }
             0xE2 => {
                 let c = self.get_low_byte(self.registers.bc);
-                self.memory.memory[(self.memory.map.h_ram.start + c as u16) as usize] = self.get_high_byte(self.registers.af);
-                self.registers.pc += 1;
+                self.memory.write(0xFF00 + c as u16, self.get_high_byte(self.registers.af));
+                self.registers.pc = self.registers.pc.wrapping_add(1);
                 Instruction::LDH_C_A
             }
             0xE5 => {
-                self.memory.memory[(self.registers.sp - 1) as usize] = self.get_low_byte(self.registers.hl);
-                self.memory.memory[(self.registers.sp - 2) as usize] = self.get_high_byte(self.registers.hl);
-                self.registers.sp -= 2;
-                self.registers.pc += 1;
+                self.memory.write(self.registers.sp.wrapping_sub(1), self.get_low_byte(self.registers.hl));
+                self.memory.write(self.registers.sp.wrapping_sub(2), self.get_high_byte(self.registers.hl));
+                self.registers.sp = self.registers.sp.wrapping_sub(2);
+                self.registers.pc = self.registers.pc.wrapping_add(1);
                 Instruction::PUSH_HL
             }
             0xF0 => {
-                self.registers.af = self.replace_high_byte(self.registers.af, self.memory.memory[(self.registers.pc + 1) as usize]);
-                self.registers.pc += 2;
+                self.registers.af = self.replace_high_byte(self.registers.af, self.memory.read(self.registers.pc.wrapping_add(1)));
+                self.registers.pc = self.registers.pc.wrapping_add(2);
                 Instruction::LDH_A_a8
             }
+            // PC stays on it and it doesn't count as run
+            #[cfg(feature = "debug")]
+            _ if self.catch_faults => {
+                self.instructions -= 1;
+                Instruction::Fault(opcode)
+            }
             _ => todo!(
                 "{}",
-                format!("Unimplemented opcode: 0x{:02X?} at address 0x{:02X?}. {} instructions executed", opcode, self.registers.pc, unsafe {COUNT}).as_str()
+                format!("Unimplemented opcode: 0x{:02X?} at address 0x{:02X?}. {} instructions executed", opcode, self.registers.pc, self.instructions).as_str()
             ),
         }
     }
@@ -755,11 +3088,11 @@ impl<T: Drawable> CPU<T> {
     }
     fn set_flag(&mut self, flag: Flag) {
         let mut flags = (self.registers.af & 0x00FF) as u8;
-        flags = flags | (1 << flag.clone() as u8);
+        flags |= 1 << flag.clone() as u8;
         self.registers.af |= flags as u16;
     }
     fn clear_flag(&mut self, flag: Flag) {
-        let mut flags = (self.registers.af & 0x00FF) as u8;
+        let flags = (self.registers.af & 0x00FF) as u8;
         let mask = 1 << flag.clone() as u8;
         let a = (self.get_high_byte(self.registers.af) as u16) << 8;
         self.registers.af = a | ((flags | mask) ^ mask) as u16;
@@ -767,6 +3100,7 @@ impl<T: Drawable> CPU<T> {
     fn concat_bytes(high: u8, low: u8) -> u16 {
         ((high as u16) << 8) | low as u16
     }
+    #[allow(dead_code)]
     fn get_leftmost_five_bits(instruction: u8) -> u8 {
         (instruction & 0b1111_1000) >> 3
     }
@@ -830,23 +3164,72 @@ impl<T: Drawable> CPU<T> {
     }
 }
 
+impl<T: Renderer + EventSource + AudioSink> Machine for CPU<T> {
+    fn registers(&self) -> [(&'static str, u16); 6] {
+        CPU::registers(self)
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        CPU::peek(self, address)
+    }
+}
+
+impl Snapshot for Registers {
+    fn save(&self, state: &mut StateWriter) {
+        state.u16(self.af);
+        state.u16(self.bc);
+        state.u16(self.de);
+        state.u16(self.hl);
+        state.u16(self.sp);
+        state.u16(self.pc);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.af = state.u16()?;
+        self.bc = state.u16()?;
+        self.de = state.u16()?;
+        self.hl = state.u16()?;
+        self.sp = state.u16()?;
+        self.pc = state.u16()?;
+        Ok(())
+    }
+}
+
+// Every save state section after INFO, the CPU's own parts and then the bus
+fn state_sections() -> impl Iterator<Item = &'static (&'static [u8; 4], u8)> {
+    STATE_SECTIONS.iter().chain(&memory::STATE_SECTIONS)
+}
+
+// What the window is called, the title from the cartridge header when there is one
+fn game_name(memory: &Memory) -> String {
+    memory
+        .title()
+        .unwrap_or_else(|| "GameBoy Emulator".to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::gpu::GPU;
-
-    struct FakeGPU {}
-    impl Drawable for FakeGPU {
-        fn draw(&mut self) {}
+    use crate::emulator::test_rom;
+    use crate::gpu::FRAME_SIZE;
+    use crate::headless::NullRenderer;
+    use crate::joypad::Button;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn cpu() -> CPU<NullRenderer> {
+        let mem = Memory::new();
+        CPU::new(mem, NullRenderer, Config::default())
     }
 
-    fn cpu() -> CPU<FakeGPU> {
-        let mem = Memory::new();
-        let gpu = FakeGPU {};
-        CPU::new(mem, gpu)
+    // Running emulator::test_rom, which keeps the CPU busy without running into IO registers
+    fn looping() -> CPU<NullRenderer> {
+        let mut mem = Memory::new();
+        mem.insert_rom(test_rom());
+        CPU::new(mem, NullRenderer, Config::default())
     }
 
-    fn assert_flags(cpu: &CPU<FakeGPU>, z: bool, n: bool, h: bool, c: bool) {
+    fn assert_flags(cpu: &CPU<NullRenderer>, z: bool, n: bool, h: bool, c: bool) {
         assert_eq!(cpu.get_flag(Flag::Z), z as u8);
         assert_eq!(cpu.get_flag(Flag::N), n as u8);
         assert_eq!(cpu.get_flag(Flag::H), h as u8);
@@ -855,14 +3238,38 @@ mod tests {
 
     #[test]
     fn should_return_cb_opcode() {
-        assert_eq!(CPU::<FakeGPU>::get_leftmost_five_bits(0b00000_0000), 0b0000_0000);
-        assert_eq!(CPU::<FakeGPU>::get_leftmost_five_bits(0b0000_1000), 0b0000_0001);
-        assert_eq!(CPU::<FakeGPU>::get_leftmost_five_bits(0b0001_0000), 0b0000_0010);
-        assert_eq!(CPU::<FakeGPU>::get_leftmost_five_bits(0b0001_1000), 0b0000_0011);
-        assert_eq!(CPU::<FakeGPU>::get_leftmost_five_bits(0b0010_0000), 0b0000_0100);
-        assert_eq!(CPU::<FakeGPU>::get_leftmost_five_bits(0b0010_1000), 0b0000_0101);
-        assert_eq!(CPU::<FakeGPU>::get_leftmost_five_bits(0b0011_0000), 0b0000_0110);
-        assert_eq!(CPU::<FakeGPU>::get_leftmost_five_bits(0b0011_1000), 0b0000_0111);
+        assert_eq!(
+            CPU::<NullRenderer>::get_leftmost_five_bits(0b0000_0000),
+            0b0000_0000
+        );
+        assert_eq!(
+            CPU::<NullRenderer>::get_leftmost_five_bits(0b0000_1000),
+            0b0000_0001
+        );
+        assert_eq!(
+            CPU::<NullRenderer>::get_leftmost_five_bits(0b0001_0000),
+            0b0000_0010
+        );
+        assert_eq!(
+            CPU::<NullRenderer>::get_leftmost_five_bits(0b0001_1000),
+            0b0000_0011
+        );
+        assert_eq!(
+            CPU::<NullRenderer>::get_leftmost_five_bits(0b0010_0000),
+            0b0000_0100
+        );
+        assert_eq!(
+            CPU::<NullRenderer>::get_leftmost_five_bits(0b0010_1000),
+            0b0000_0101
+        );
+        assert_eq!(
+            CPU::<NullRenderer>::get_leftmost_five_bits(0b0011_0000),
+            0b0000_0110
+        );
+        assert_eq!(
+            CPU::<NullRenderer>::get_leftmost_five_bits(0b0011_1000),
+            0b0000_0111
+        );
     }
 
     #[test]
@@ -951,7 +3358,7 @@ mod tests {
 
     #[test]
     fn should_concatenate_bytes() {
-        assert_eq!(CPU::<FakeGPU>::concat_bytes(0x10, 0xAC), 0x10AC);
+        assert_eq!(CPU::<NullRenderer>::concat_bytes(0x10, 0xAC), 0x10AC);
     }
 
     #[test]
@@ -978,8 +3385,8 @@ mod tests {
     fn ld_h_hl() {
         let mut cpu = cpu();
         cpu.registers.pc = 0;
-        cpu.registers.hl = 0xFF02;
-        cpu.memory.memory[0xFF02] = 0xA;
+        cpu.registers.hl = 0xC002;
+        cpu.memory.memory[0xC002] = 0xA;
         assert_eq!(Instruction::LD_H_HL, cpu.decode(0x66));
         assert_eq!(cpu.registers.pc, 1);
         assert_eq!(cpu.registers.hl, 0x0A02);
@@ -1090,6 +3497,19 @@ mod tests {
         assert_eq!(cpu.registers.hl, 0xABCD);
     }
 
+    #[test]
+    // 0x11
+    fn ld_de_n16() {
+        let mut cpu = cpu();
+        cpu.registers.pc = 0;
+        cpu.registers.de = 0;
+        cpu.memory.memory[(cpu.registers.pc + 1) as usize] = 0xCD;
+        cpu.memory.memory[(cpu.registers.pc + 2) as usize] = 0xAB;
+        assert_eq!(Instruction::LD_DE_n16, cpu.decode(0x11));
+        assert_eq!(cpu.registers.de, 0xABCD);
+        assert_eq!(cpu.registers.pc, 3);
+    }
+
     #[test]
     // 0x31
     fn ld_sp_n16() {
@@ -1139,10 +3559,10 @@ mod tests {
     // 0xE2
     fn ldh_c_a() {
         let mut cpu = cpu();
-        cpu.registers.bc = 0xAB01;
+        cpu.registers.bc = 0xAB81;
         cpu.registers.af = 0xFF00;
         assert_eq!(Instruction::LDH_C_A, cpu.decode(0xE2));
-        assert_eq!(cpu.memory.memory[(cpu.memory.map.h_ram.start + 0x01) as usize], 0xFF);
+        assert_eq!(cpu.memory.memory[0xFF81], 0xFF);
     }
 
     #[test]
@@ -1162,6 +3582,7 @@ mod tests {
         cpu.memory.memory[(cpu.registers.pc + 1) as usize] = 0xAB;
         assert_eq!(Instruction::LDH_a8_A, cpu.decode(0xE0));
         assert_eq!(cpu.memory.memory[0xFFAB], 0xFF);
+        assert_eq!(cpu.registers.pc, 2);
     }
 
     #[test]
@@ -1248,4 +3669,333 @@ mod tests {
         assert_eq!(cpu.registers.de, 0xABFF);
         assert_eq!(cpu.registers.pc, 3);
     }
+
+    #[test]
+    fn should_call_frame_callbacks_every_vblank() {
+        let mut cpu = cpu();
+        cpu.config.frame_skip = 3;
+        let frames = Rc::new(RefCell::new(Vec::new()));
+        let recorded = frames.clone();
+        cpu.on_frame(move |frame, number| {
+            assert_eq!(frame.len(), FRAME_SIZE);
+            recorded.borrow_mut().push(number);
+        });
+        // Memory is all zeroes, so the CPU runs NOPs while the LCD is off
+        while cpu.frames < 3 {
+            cpu.cycle();
+        }
+        assert_eq!(*frames.borrow(), vec![0, 1, 2]);
+        assert!(!cpu.gpu.frame_ready());
+    }
+
+    #[test]
+    fn should_call_instruction_and_interrupt_request_hooks() {
+        let mut cpu = cpu();
+        let instructions = Rc::new(RefCell::new(Vec::new()));
+        let recorded = instructions.clone();
+        cpu.on_instruction(move |pc, opcode| recorded.borrow_mut().push((pc, opcode)));
+        let interrupts = Rc::new(RefCell::new(Vec::new()));
+        let recorded = interrupts.clone();
+        cpu.on_interrupt_request(move |interrupt| recorded.borrow_mut().push(interrupt));
+        // The timer at its fastest overflows every 1024 NOPs, requesting its interrupt each time
+        // even though IF is never cleared
+        cpu.memory.write(crate::timer::TAC, 0b101);
+        for _ in 0..2100 {
+            cpu.cycle();
+        }
+        assert_eq!(instructions.borrow().len(), 2100);
+        assert_eq!(instructions.borrow()[..2], [(0, 0x00), (1, 0x00)]);
+        assert_eq!(
+            *interrupts.borrow(),
+            vec![Interrupt::Timer, Interrupt::Timer]
+        );
+    }
+
+    #[test]
+    fn should_wake_from_halt_and_stop_on_a_button_press() {
+        let mut cpu = cpu();
+        cpu.memory.memory[..3].copy_from_slice(&[0x76, 0x10, 0x00]);
+        // Only enabled interrupts end HALT, IME doesn't matter
+        cpu.memory.write(memory::INTERRUPT_ENABLE, 1 << Interrupt::Joypad as u8);
+        for _ in 0..100 {
+            cpu.cycle();
+        }
+        assert_eq!(cpu.registers.pc, 0);
+        cpu.handle_event(Event::ButtonDown(Button::A));
+        assert_eq!(cpu.cycle(), Instruction::HALT);
+        assert_eq!(cpu.registers.pc, 1);
+        // IF stays set, STOP waits for the next press all the same
+        for _ in 0..100 {
+            cpu.cycle();
+        }
+        assert!(cpu.stopped);
+        assert_eq!(cpu.registers.pc, 1);
+        cpu.handle_event(Event::ButtonUp(Button::A));
+        cpu.handle_event(Event::ButtonDown(Button::Start));
+        assert_eq!(cpu.cycle(), Instruction::STOP);
+        assert!(!cpu.stopped);
+        assert_eq!(cpu.registers.pc, 3);
+    }
+
+    #[test]
+    fn should_produce_audio_for_callbacks_without_a_sound_device() {
+        let mut cpu = cpu();
+        assert_eq!(cpu.audio_sample_rate(), None);
+        let counts = Rc::new(RefCell::new(Vec::new()));
+        let recorded = counts.clone();
+        cpu.on_audio(move |samples| recorded.borrow_mut().push(samples.len()));
+        assert_eq!(cpu.audio_sample_rate(), Some(48_000));
+        while cpu.frames < 2 {
+            cpu.cycle();
+        }
+        // A frame lasts 70224 cycles, about 804 stereo samples at 48 kHz
+        let counts = counts.borrow();
+        assert_eq!(counts.len(), 2);
+        assert!((1600..=1610).contains(&counts[1]));
+    }
+
+    #[test]
+    fn should_replay_recorded_input() {
+        let path = std::env::temp_dir().join("gameboy-emulator-cpu-movie-test.gbm");
+        let mut recording = looping();
+        recording.record_movie(&path).unwrap();
+        let mut states = Vec::new();
+        for frame in 1..=4 {
+            match frame {
+                2 => recording.handle_event(Event::ButtonDown(Button::A)),
+                3 => recording.handle_event(Event::ButtonDown(Button::Left)),
+                4 => recording.handle_event(Event::ButtonUp(Button::A)),
+                _ => {}
+            }
+            while recording.frames < frame {
+                recording.cycle();
+            }
+            states.push(recording.memory.joypad.state());
+        }
+
+        let mut replay = looping();
+        replay.play_movie(&path).unwrap();
+        for (frame, state) in (1..=4).zip(states) {
+            // Live input doesn't get through while the movie plays
+            replay.handle_event(Event::ButtonDown(Button::Start));
+            while replay.frames < frame {
+                replay.cycle();
+            }
+            assert_eq!(replay.memory.joypad.state(), state);
+        }
+        while replay.frames < 5 {
+            replay.cycle();
+        }
+        assert!(replay.movie.is_none());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_log_serial_output() {
+        let path = std::env::temp_dir().join("gameboy-emulator-serial-log-test.txt");
+        let mut cpu = looping();
+        // Sends "Ok" with the internal clock, then loops
+        cpu.memory.memory[..16].copy_from_slice(&[
+            0x3E, b'O', 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02, 0x3E, b'k', 0xE0, 0x01, 0x3E, 0x81,
+            0xE0, 0x02,
+        ]);
+        cpu.log_serial(&path).unwrap();
+        while cpu.frames < 1 {
+            cpu.cycle();
+        }
+        assert_eq!(std::fs::read(&path).unwrap(), b"Ok");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    #[cfg(not(feature = "debug"))]
+    fn should_stop_running_and_finish_recordings_on_quit() {
+        let path = std::env::temp_dir().join("gameboy-emulator-quit-test.gbm");
+        let mut cpu = looping();
+        cpu.record_movie(&path).unwrap();
+        cpu.handle_event(Event::Action(Action::Quit));
+        cpu.run();
+        assert!(cpu.movie.is_none());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_toggle_pause() {
+        let mut cpu = cpu();
+        cpu.handle_event(Event::Action(Action::TogglePause));
+        assert!(cpu.paused);
+        cpu.handle_event(Event::Action(Action::TogglePause));
+        assert!(!cpu.paused);
+    }
+
+    #[test]
+    fn should_cycle_through_slow_motion_speeds() {
+        let mut cpu = cpu();
+        let mut speeds = Vec::new();
+        for _ in 0..3 {
+            cpu.handle_event(Event::Action(Action::CycleSlowMotion));
+            speeds.push(cpu.speed);
+        }
+        assert_eq!(speeds, vec![50, 25, 100]);
+    }
+
+    #[test]
+    fn should_only_advance_a_frame_while_paused() {
+        let mut cpu = looping();
+        cpu.handle_event(Event::Action(Action::FrameAdvance));
+        assert!(!cpu.advance_frame);
+        cpu.set_paused(true);
+        cpu.handle_event(Event::Action(Action::FrameAdvance));
+        assert!(cpu.advance_frame);
+        cpu.advance_frame();
+        assert!(!cpu.advance_frame);
+        assert_eq!(cpu.frames, 1);
+    }
+
+    #[test]
+    fn should_restore_a_saved_state() {
+        let mut cpu = looping();
+        cpu.run_frame();
+        let state = cpu.save_state();
+        let pc = cpu.registers.pc;
+        cpu.run_frame();
+        cpu.memory.write(0xC000, 0x42);
+        cpu.load_state(&state).unwrap();
+        assert_eq!(cpu.frames, 1);
+        assert_eq!(cpu.registers.pc, pc);
+        assert_eq!(cpu.memory.read(0xC000), 0);
+        assert_eq!(cpu.save_state(), state);
+    }
+
+    #[test]
+    fn should_leave_the_machine_alone_on_a_broken_state() {
+        let mut cpu = cpu();
+        let state = cpu.save_state();
+        cpu.memory.write(0xC000, 0x42);
+        assert!(cpu.load_state(&state[..state.len() - 1]).is_err());
+        assert!(cpu.load_state(b"GBSS").is_err());
+        // The ROM hash opens the INFO section, after the header and the section's own
+        let mut other_rom = state.clone();
+        other_rom[14] ^= 1;
+        assert!(cpu.load_state(&other_rom).is_err());
+        let mut newer = state.clone();
+        let ppu = newer.windows(4).position(|id| id == b"PPU ").unwrap();
+        newer[ppu + 4] = 2;
+        assert!(cpu.load_state(&newer).is_err());
+        assert_eq!(cpu.memory.read(0xC000), 0x42);
+    }
+
+    #[test]
+    fn should_step_back_to_earlier_snapshots() {
+        let mut cpu = looping();
+        for _ in 0..10 {
+            cpu.run_frame();
+        }
+        let mut limiter = FrameLimiter::new();
+        cpu.handle_event(Event::Action(Action::Rewind));
+        // Snapshots are taken after every 4th frame, stepping back past the oldest stays there
+        let mut frames = Vec::new();
+        for _ in 0..3 {
+            cpu.step_back(&mut limiter);
+            frames.push(cpu.frames);
+        }
+        assert_eq!(frames, vec![8, 4, 4]);
+        cpu.handle_event(Event::ActionReleased(Action::Rewind));
+        assert!(!cpu.rewinding);
+    }
+
+    #[test]
+    fn should_start_over_with_a_new_rom() {
+        let path = std::env::temp_dir().join("gameboy-emulator-load-test.gb");
+        let mut rom = test_rom();
+        // Color games use the title's last bytes for flags
+        rom[0x134..0x144].copy_from_slice(b"TETRIS\0\0\0\0\0\0\0\0\0\x80");
+        std::fs::write(&path, &rom).unwrap();
+        let mut cpu = looping();
+        assert_eq!(game_name(&cpu.memory), "GameBoy Emulator");
+        cpu.run_frame();
+        cpu.memory.write(0xC000, 0x42);
+        cpu.load_rom(&path).unwrap();
+        assert_eq!((cpu.frames, cpu.registers.pc), (0, 0));
+        assert_eq!(cpu.memory.read(0xC000), 0);
+        assert_eq!(cpu.memory.rom_path(), Some(path.as_path()));
+        assert_eq!(game_name(&cpu.memory), "TETRIS");
+        cpu.run_frame();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    #[cfg(not(feature = "debug"))]
+    fn should_pause_while_in_the_background() {
+        let mut cpu = cpu();
+        cpu.handle_event(Event::Focus(false));
+        assert!(cpu.paused);
+        cpu.handle_event(Event::Focus(true));
+        assert!(!cpu.paused);
+        // A pause of the player's own outlasts getting focus back
+        cpu.set_paused(true);
+        cpu.handle_event(Event::Focus(false));
+        cpu.handle_event(Event::Focus(true));
+        assert!(cpu.paused);
+        cpu.set_paused(false);
+        cpu.config.pause_in_background = false;
+        cpu.handle_event(Event::Focus(false));
+        assert!(!cpu.paused);
+    }
+
+    #[test]
+    fn should_only_clear_ram_on_a_hard_reset() {
+        let mut cpu = cpu();
+        // Larger than the ROM area, whatever's past it must not end up in RAM
+        cpu.memory.insert_rom(vec![0xAB; 0x10000]);
+        cpu.registers.pc = 0x150;
+        cpu.memory.write(0xC000, 0x42);
+        cpu.memory.write(0xFF42, 0x10);
+        cpu.handle_event(Event::Action(Action::Reset));
+        assert_eq!(cpu.registers.pc, 0);
+        assert_eq!(cpu.memory.read(0xC000), 0x42);
+        assert_eq!(cpu.memory.read(0xC001), 0);
+        assert_eq!(cpu.memory.read(0xFF42), 0);
+        assert_eq!(cpu.memory.read(memory::INTERRUPT_ENABLE), 0);
+        cpu.handle_event(Event::Action(Action::HardReset));
+        assert_eq!(cpu.memory.read(0xC000), 0);
+    }
+
+    #[test]
+    fn should_skip_sections_it_does_not_know() {
+        let mut cpu = cpu();
+        let mut state = StateWriter::new();
+        state.bytes(&cpu.save_state());
+        state.section(b"NEW ", 1, |state| state.u32(0xDEAD_BEEF));
+        let state = state.into_bytes();
+        cpu.memory.write(0xC000, 0x42);
+        assert_eq!(cpu.load_state(&state), Ok(()));
+        assert_eq!(cpu.memory.read(0xC000), 0);
+    }
+
+    #[test]
+    fn should_load_a_version_1_state() {
+        let mut cpu = cpu();
+        cpu.registers.pc = 0x1234;
+        cpu.memory.write(0xC000, 0x42);
+        let mut state = StateWriter::new();
+        state.bytes(savestate::MAGIC);
+        state.u8(1);
+        state.u64(cpu.memory.rom_hash());
+        cpu.registers.save(&mut state);
+        state.u64(77);
+        for part in cpu.memory.state_parts() {
+            part.save(&mut state);
+        }
+        cpu.gpu.save(&mut state);
+        let state = state.into_bytes();
+        let mut cpu = self::cpu();
+        assert_eq!(cpu.load_state(&state), Ok(()));
+        assert_eq!(cpu.registers.pc, 0x1234);
+        assert_eq!(cpu.frames, 77);
+        assert_eq!(cpu.memory.read(0xC000), 0x42);
+        cpu.registers.pc = 0;
+        assert!(cpu.load_state(&state[..state.len() - 1]).is_err());
+        assert_eq!(cpu.registers.pc, 0);
+    }
 }
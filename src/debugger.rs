@@ -0,0 +1,625 @@
+use crate::cpu::FREQUENCY;
+use crate::disassembler::Line;
+use crate::expression::{Expression, Machine};
+use crate::joypad::JOYP;
+use crate::memory::{self, Access, AccessKind, Interrupt};
+use crate::serial::{SB, SC};
+use crate::{apu, gpu, timer};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+// Addresses the debugger stops at, numbered like gdb does: numbers aren't reused after a delete,
+// so the ones the player has seen keep meaning the same breakpoint. Watchpoints share the numbers
+#[derive(Default)]
+pub struct Breakpoints {
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    // Interrupts to stop at, with their numbers
+    interrupts: Vec<(u32, Interrupt)>,
+    next_number: u32,
+}
+
+pub struct Breakpoint {
+    pub number: u32,
+    pub address: u16,
+    // Only stops when this is true, e.g. on the one iteration of a loop that matters
+    pub condition: Option<Condition>,
+}
+
+pub struct Condition {
+    // As typed, for listing
+    pub text: String,
+    expression: Expression,
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        Ok(Self {
+            text: text.trim().to_string(),
+            expression: Expression::parse(text)?,
+        })
+    }
+}
+
+// Stops when an instruction reads or writes anywhere from start to end, both included
+pub struct Watchpoint {
+    pub number: u32,
+    pub kind: AccessKind,
+    pub start: u16,
+    pub end: u16,
+}
+
+impl Watchpoint {
+    fn matches(&self, access: &Access) -> bool {
+        access.kind == self.kind && (self.start..=self.end).contains(&access.address)
+    }
+}
+
+impl Breakpoints {
+    // Returns the new breakpoint's number
+    pub fn add(&mut self, address: u16, condition: Option<Condition>) -> u32 {
+        self.next_number += 1;
+        self.breakpoints.push(Breakpoint {
+            number: self.next_number,
+            address,
+            condition,
+        });
+        self.next_number
+    }
+
+    // Returns the new watchpoint's number
+    pub fn watch(&mut self, kind: AccessKind, start: u16, end: u16) -> u32 {
+        self.next_number += 1;
+        self.watchpoints.push(Watchpoint {
+            number: self.next_number,
+            kind,
+            start,
+            end,
+        });
+        self.next_number
+    }
+
+    // Returns the new breakpoint's number
+    pub fn break_on_interrupt(&mut self, interrupt: Interrupt) -> u32 {
+        self.next_number += 1;
+        self.interrupts.push((self.next_number, interrupt));
+        self.next_number
+    }
+
+    // Deletes the breakpoint or watchpoint with that number, false if there's none
+    pub fn delete(&mut self, number: u32) -> bool {
+        let count = self.len();
+        self.breakpoints
+            .retain(|breakpoint| breakpoint.number != number);
+        self.watchpoints
+            .retain(|watchpoint| watchpoint.number != number);
+        self.interrupts
+            .retain(|(interrupt_number, _)| *interrupt_number != number);
+        self.len() < count
+    }
+
+    fn len(&self) -> usize {
+        self.breakpoints.len() + self.watchpoints.len() + self.interrupts.len()
+    }
+
+    // The breakpoint the machine is stopped at, if any. That's one at PC without a condition or
+    // with one that's true
+    pub fn hit(&self, machine: &impl Machine) -> Option<&Breakpoint> {
+        let pc = machine.registers()[5].1;
+        self.breakpoints.iter().find(|breakpoint| {
+            breakpoint.address == pc
+                && breakpoint
+                    .condition
+                    .as_ref()
+                    .is_none_or(|condition| condition.expression.is_true(machine))
+        })
+    }
+
+    // The first access a watchpoint is set on, with that watchpoint
+    pub fn watched<'a>(&self, accesses: &'a [Access]) -> Option<(&Watchpoint, &'a Access)> {
+        accesses.iter().find_map(|access| {
+            self.watchpoints
+                .iter()
+                .find(|watchpoint| watchpoint.matches(access))
+                .map(|watchpoint| (watchpoint, access))
+        })
+    }
+
+    // The first requested interrupt there's a breakpoint on, with that breakpoint's number
+    pub fn interrupted(&self, requested: &[Interrupt]) -> Option<(u32, Interrupt)> {
+        requested.iter().find_map(|requested| {
+            self.interrupts
+                .iter()
+                .find(|(_, interrupt)| interrupt == requested)
+                .copied()
+        })
+    }
+
+    // Whether memory accesses need logging for the watchpoints
+    pub fn is_watching(&self) -> bool {
+        !self.watchpoints.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Breakpoint> {
+        self.breakpoints.iter()
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = &Watchpoint> {
+        self.watchpoints.iter()
+    }
+
+    pub fn interrupts(&self) -> impl Iterator<Item = &(u32, Interrupt)> {
+        self.interrupts.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// Addresses are hex with a 0x prefix, decimal without
+pub fn parse_address(text: &str) -> Option<u16> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+// A log of every instruction run, one line each with the registers as they were before it and
+// the cycles since the trace started. Instructions outside range still count towards the cycles
+pub struct Trace<W: Write> {
+    writer: W,
+    range: Option<(u16, u16)>,
+    cycles: u64,
+    lines: u64,
+}
+
+impl<W: Write> Trace<W> {
+    pub fn new(writer: W, range: Option<(u16, u16)>) -> Self {
+        Self {
+            writer,
+            range,
+            cycles: 0,
+            lines: 0,
+        }
+    }
+
+    pub fn log(
+        &mut self,
+        line: &Line,
+        registers: &[(&'static str, u16); 6],
+        cycles: u32,
+    ) -> io::Result<()> {
+        let logged = self
+            .range
+            .is_none_or(|(start, end)| (start..=end).contains(&line.address));
+        if logged {
+            let registers: Vec<String> = registers
+                .iter()
+                .map(|(name, value)| format!("{name}={value:04X}"))
+                .collect();
+            writeln!(
+                self.writer,
+                "{:<40}{}  {}",
+                line.to_string(),
+                registers.join(" "),
+                self.cycles
+            )?;
+            self.lines += 1;
+        }
+        self.cycles += cycles as u64;
+        Ok(())
+    }
+
+    // Flushes what's left and gives back how many instructions were logged
+    pub fn finish(mut self) -> io::Result<u64> {
+        self.writer.flush()?;
+        Ok(self.lines)
+    }
+}
+
+// T-cycles and frames run since the counters were last cleared, for the time since a reset or
+// between two points of a routine
+#[derive(Default, Clone, Copy)]
+pub struct Counters {
+    pub cycles: u64,
+    pub frames: u64,
+}
+
+impl Counters {
+    pub fn count(&mut self, cycles: u32) {
+        self.cycles += cycles as u64;
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl fmt::Display for Counters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let milliseconds = self.cycles as f64 * 1000.0 / FREQUENCY as f64;
+        write!(
+            f,
+            "{} cycles ({} M-cycles), {} frames, {milliseconds:.3} ms",
+            self.cycles,
+            self.cycles / 4,
+            self.frames
+        )
+    }
+}
+
+// Instructions between checkpoints, and how many checkpoints are kept. That's about a quarter of a
+// million instructions to go back through, a few frames, for a few MB of states
+const CHECKPOINT_INTERVAL: u64 = 4096;
+const CHECKPOINTS: usize = 64;
+
+// The machine as it was before an instruction ran, with the counters that go back along with it
+pub struct Checkpoint {
+    pub instruction: u64,
+    pub state: Vec<u8>,
+    pub since_reset: Counters,
+    pub stopwatch: Counters,
+}
+
+// Checkpoints for stepping backwards. Going back loads the last checkpoint before where it's going
+// and runs forward from there, the machine being deterministic while the debugger has it stopped.
+// Changes made from the debugger since then aren't run again
+#[derive(Default)]
+pub struct History {
+    checkpoints: VecDeque<Checkpoint>,
+}
+
+impl History {
+    // Whether to take a checkpoint before instruction runs, checkpoints are never taken twice while
+    // running forward again
+    pub fn due(&self, instruction: u64) -> bool {
+        instruction.is_multiple_of(CHECKPOINT_INTERVAL)
+            && self
+                .checkpoints
+                .back()
+                .is_none_or(|last| last.instruction < instruction)
+    }
+
+    pub fn push(&mut self, checkpoint: Checkpoint) {
+        if self.checkpoints.len() == CHECKPOINTS {
+            self.checkpoints.pop_front();
+        }
+        self.checkpoints.push_back(checkpoint);
+    }
+
+    // The last checkpoint at or before instruction, the ones after it go as running forward from
+    // it takes them again
+    pub fn rewind_to(&mut self, instruction: u64) -> Option<&Checkpoint> {
+        let kept = self
+            .checkpoints
+            .partition_point(|checkpoint| checkpoint.instruction <= instruction);
+        self.checkpoints.truncate(kept);
+        self.checkpoints.back()
+    }
+
+    // The instruction as far back as going goes
+    pub fn oldest(&self) -> Option<u64> {
+        self.checkpoints
+            .front()
+            .map(|checkpoint| checkpoint.instruction)
+    }
+
+    pub fn clear(&mut self) {
+        self.checkpoints.clear();
+    }
+}
+
+// Where games keep their variables: cartridge RAM, WRAM and HRAM
+const RAM: [(u16, u16); 3] = [(0xA000, 0xBFFF), (0xC000, 0xDFFF), (0xFF80, 0xFFFE)];
+
+// RAM as it was at some point, to find a variable like the lives or the score by what changed
+// since then
+pub struct RamSnapshot {
+    bytes: Vec<u8>,
+}
+
+pub struct Change {
+    pub address: u16,
+    pub old: u8,
+    pub new: u8,
+}
+
+impl RamSnapshot {
+    pub fn take(read: impl Fn(u16) -> u8) -> Self {
+        Self {
+            bytes: ram_addresses().map(read).collect(),
+        }
+    }
+
+    // Every address that holds something else in now, lowest first
+    pub fn diff(&self, now: &RamSnapshot) -> Vec<Change> {
+        ram_addresses()
+            .zip(self.bytes.iter().zip(&now.bytes))
+            .filter(|(_, (old, new))| old != new)
+            .map(|(address, (&old, &new))| Change { address, old, new })
+            .collect()
+    }
+}
+
+fn ram_addresses() -> impl Iterator<Item = u16> {
+    RAM.into_iter().flat_map(|(start, end)| start..=end)
+}
+
+// count bytes from start in rows of 16, with the printable ones as ASCII on the right. Stops at
+// the end of memory rather than wrapping
+pub fn hexdump(start: u16, count: usize, read: impl Fn(u16) -> u8) -> Vec<String> {
+    let bytes: Vec<u8> = (start..=0xFFFF).take(count).map(read).collect();
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(row, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|byte| format!("{byte:02X}")).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&byte| match byte {
+                    0x20..=0x7E => byte as char,
+                    _ => '.',
+                })
+                .collect();
+            let address = start as usize + row * 16;
+            format!("{address:04X}  {:<47}  |{ascii}|", hex.join(" "))
+        })
+        .collect()
+}
+
+// Shows text through less, for output longer than the terminal
+pub fn page(text: &[u8]) -> io::Result<()> {
+    let mut pager = Command::new("less")
+        // Keep the formatting and scroll sideways instead of wrapping
+        .arg("-RS")
+        .stdin(Stdio::piped())
+        .spawn()?;
+    // stdin has to be closed for less to see the end of the text
+    if let Some(mut stdin) = pager.stdin.take() {
+        stdin.write_all(text)?;
+    }
+    pager.wait()?;
+    Ok(())
+}
+
+// Same as addresses, but up to 0xFF
+pub fn parse_byte(text: &str) -> Option<u8> {
+    parse_address(text).and_then(|byte| u8::try_from(byte).ok())
+}
+
+// By the names show irq lists them under, in any case
+pub fn parse_interrupt(text: &str) -> Option<Interrupt> {
+    Interrupt::ALL
+        .into_iter()
+        .find(|interrupt| format!("{interrupt:?}").eq_ignore_ascii_case(text))
+}
+
+// An IO register, by address or by name like LCDC. Also takes IE, which is past HRAM
+pub fn parse_io(text: &str) -> Option<u16> {
+    let named = [
+        ("JOYP", JOYP),
+        ("SB", SB),
+        ("SC", SC),
+        ("IF", memory::INTERRUPT_FLAG),
+        ("IE", memory::INTERRUPT_ENABLE),
+    ];
+    let address = named
+        .iter()
+        .chain(&timer::REGISTERS)
+        .chain(&apu::REGISTERS)
+        .chain(&gpu::REGISTERS)
+        .find(|(name, _)| name.eq_ignore_ascii_case(text))
+        .map(|(_, address)| *address)
+        .or_else(|| parse_address(text))?;
+    ((0xFF00..0xFF80).contains(&address) || address == memory::INTERRUPT_ENABLE).then_some(address)
+}
+
+// A single address or start-end, both included
+pub fn parse_range(text: &str) -> Option<(u16, u16)> {
+    match text.split_once('-') {
+        Some((start, end)) => {
+            let (start, end) = (parse_address(start)?, parse_address(end)?);
+            (start <= end).then_some((start, end))
+        }
+        None => parse_address(text).map(|address| (address, address)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler;
+
+    struct Stopped {
+        pc: u16,
+        a: u8,
+    }
+
+    impl Machine for Stopped {
+        fn registers(&self) -> [(&'static str, u16); 6] {
+            let mut registers = [("", 0); 6];
+            registers[0].1 = (self.a as u16) << 8;
+            registers[5].1 = self.pc;
+            registers
+        }
+
+        fn peek(&self, _: u16) -> u8 {
+            0
+        }
+    }
+
+    #[test]
+    fn should_keep_numbers_after_a_delete() {
+        let mut breakpoints = Breakpoints::default();
+        assert_eq!(breakpoints.add(0x0150, None), 1);
+        assert_eq!(breakpoints.add(parse_address("0x2345").unwrap(), None), 2);
+        assert!(breakpoints.delete(1));
+        assert!(!breakpoints.delete(1));
+        assert_eq!(breakpoints.add(0x0150, None), 3);
+        let stopped = Stopped { pc: 0x2345, a: 0 };
+        assert_eq!(breakpoints.hit(&stopped).unwrap().number, 2);
+        assert!(breakpoints.hit(&Stopped { pc: 0x0100, a: 0 }).is_none());
+        assert_eq!(parse_address("336"), Some(0x0150));
+        assert_eq!(parse_address("0xG"), None);
+        assert_eq!(parse_byte("0xFF"), Some(0xFF));
+        assert_eq!(parse_byte("256"), None);
+    }
+
+    #[test]
+    fn should_only_stop_when_the_condition_holds() {
+        let mut breakpoints = Breakpoints::default();
+        let condition = Condition::parse(" a == 0x3C ").unwrap();
+        assert_eq!(condition.text, "a == 0x3C");
+        breakpoints.add(0x2345, Some(condition));
+        assert!(breakpoints.hit(&Stopped { pc: 0x2345, a: 0 }).is_none());
+        let stopped = Stopped {
+            pc: 0x2345,
+            a: 0x3C,
+        };
+        assert!(breakpoints.hit(&stopped).is_some());
+    }
+
+    #[test]
+    fn should_dump_rows_of_16_and_stop_at_the_end_of_memory() {
+        let rows = hexdump(0xFFE8, 32, |address| (address as u8).wrapping_add(0x38));
+        assert_eq!(
+            rows,
+            [
+                "FFE8  20 21 22 23 24 25 26 27 28 29 2A 2B 2C 2D 2E 2F  | !\"#$%&'()*+,-./|",
+                "FFF8  30 31 32 33 34 35 36 37                          |01234567|",
+            ]
+        );
+        assert_eq!(
+            hexdump(0xC000, 2, |_| 0)[0],
+            format!("C000  00 00{}  |..|", " ".repeat(42))
+        );
+    }
+
+    #[test]
+    fn should_trace_instructions_in_range_and_count_every_cycle() {
+        let mut trace = Trace::new(Vec::new(), parse_range("0x0150-0x0151"));
+        // INC BC at 0x0150, NOPs around it
+        let read = |address| if address == 0x0150 { 0x03 } else { 0x00 };
+        for (address, bc) in [(0x014F, 0), (0x0150, 0), (0x0151, 1)] {
+            let registers = [
+                ("AF", 0x01B0),
+                ("BC", bc),
+                ("DE", 0),
+                ("HL", 0),
+                ("SP", 0xFFFE),
+                ("PC", address),
+            ];
+            let line = disassembler::disassemble(address, read);
+            trace.log(&line, &registers, 4).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(trace.writer.clone()).unwrap(),
+            format!(
+                "{:<40}AF=01B0 BC=0000 DE=0000 HL=0000 SP=FFFE PC=0150  4\n\
+                 {:<40}AF=01B0 BC=0001 DE=0000 HL=0000 SP=FFFE PC=0151  8\n",
+                "0150  03        INC BC", "0151  00        NOP"
+            )
+        );
+        assert_eq!(trace.finish().unwrap(), 2);
+    }
+
+    #[test]
+    fn should_stop_on_watched_accesses() {
+        let mut breakpoints = Breakpoints::default();
+        let (start, end) = parse_range("0xC000-0xC0FF").unwrap();
+        assert_eq!(breakpoints.watch(AccessKind::Write, start, end), 1);
+        let access = |kind, address| Access {
+            kind,
+            address,
+            old: 0,
+            new: 1,
+        };
+        let accesses = [
+            access(AccessKind::Read, 0xC010),
+            access(AccessKind::Write, 0xC100),
+            access(AccessKind::Write, 0xC0FF),
+        ];
+        let (watchpoint, access) = breakpoints.watched(&accesses).unwrap();
+        assert_eq!((watchpoint.number, access.address), (1, 0xC0FF));
+        assert_eq!(parse_range("0xC0FF-0xC000"), None);
+        assert_eq!(parse_range("0xFF40"), Some((0xFF40, 0xFF40)));
+    }
+
+    #[test]
+    fn should_stop_on_interrupts_and_name_io_registers() {
+        let mut breakpoints = Breakpoints::default();
+        let timer = parse_interrupt("timer").unwrap();
+        assert_eq!(breakpoints.break_on_interrupt(timer), 1);
+        let vblank = parse_interrupt("VBlank").unwrap();
+        assert_eq!(breakpoints.interrupted(&[vblank]), None);
+        assert_eq!(breakpoints.interrupted(&[vblank, timer]), Some((1, timer)));
+        assert!(breakpoints.delete(1));
+        assert!(breakpoints.is_empty());
+        assert_eq!(parse_interrupt("nmi"), None);
+        assert_eq!(parse_io("lcdc"), Some(0xFF40));
+        assert_eq!(parse_io("NR52"), Some(0xFF26));
+        assert_eq!(parse_io("IE"), Some(0xFFFF));
+        assert_eq!(parse_io("0xFF47"), Some(0xFF47));
+        assert_eq!(parse_io("0xC000"), None);
+    }
+
+    #[test]
+    fn should_time_cycles_in_m_cycles_and_milliseconds() {
+        let mut counters = Counters::default();
+        counters.count(70224 - 4);
+        counters.count(4);
+        counters.frames += 1;
+        assert_eq!(
+            counters.to_string(),
+            "70224 cycles (17556 M-cycles), 1 frames, 16.743 ms"
+        );
+        counters.clear();
+        assert_eq!(counters.cycles, 0);
+    }
+
+    #[test]
+    fn should_go_back_to_the_last_checkpoint_before() {
+        let checkpoint = |instruction| Checkpoint {
+            instruction,
+            state: Vec::new(),
+            since_reset: Counters::default(),
+            stopwatch: Counters::default(),
+        };
+        let mut history = History::default();
+        for number in 0..=CHECKPOINTS as u64 {
+            let instruction = number * CHECKPOINT_INTERVAL;
+            assert!(history.due(instruction) && !history.due(instruction + 1));
+            history.push(checkpoint(instruction));
+            assert!(!history.due(instruction));
+        }
+        assert_eq!(history.oldest(), Some(CHECKPOINT_INTERVAL));
+        assert!(history.rewind_to(CHECKPOINT_INTERVAL - 1).is_none());
+        let mut history = History::default();
+        history.push(checkpoint(0));
+        history.push(checkpoint(CHECKPOINT_INTERVAL));
+        history.push(checkpoint(2 * CHECKPOINT_INTERVAL));
+        let back = history.rewind_to(CHECKPOINT_INTERVAL + 10).unwrap();
+        assert_eq!(back.instruction, CHECKPOINT_INTERVAL);
+        assert!(history.due(2 * CHECKPOINT_INTERVAL));
+    }
+
+    #[test]
+    fn should_list_changed_ram_by_address() {
+        let mut memory = vec![0u8; 0x10000];
+        let before = RamSnapshot::take(|address| memory[address as usize]);
+        memory[0xC0A0] = 3;
+        memory[0xFF80] = 0xFF;
+        // Outside RAM, not compared
+        memory[0x8000] = 1;
+        let after = RamSnapshot::take(|address| memory[address as usize]);
+        let changes: Vec<(u16, u8, u8)> = before
+            .diff(&after)
+            .iter()
+            .map(|change| (change.address, change.old, change.new))
+            .collect();
+        assert_eq!(changes, [(0xC0A0, 0, 3), (0xFF80, 0, 0xFF)]);
+    }
+}
@@ -0,0 +1,432 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+// Every opcode's instruction, with its operand as a placeholder: n8 and n16 are immediates, a8 is
+// an address in the FF00 page, a16 a full address and e8 a signed offset. Illegal opcodes are empty
+#[rustfmt::skip]
+const OPCODES: [&str; 256] = [
+    // 0x00
+    "NOP", "LD BC, n16", "LD [BC], A", "INC BC", "INC B", "DEC B", "LD B, n8", "RLCA",
+    "LD [a16], SP", "ADD HL, BC", "LD A, [BC]", "DEC BC", "INC C", "DEC C", "LD C, n8", "RRCA",
+    // 0x10
+    "STOP", "LD DE, n16", "LD [DE], A", "INC DE", "INC D", "DEC D", "LD D, n8", "RLA",
+    "JR e8", "ADD HL, DE", "LD A, [DE]", "DEC DE", "INC E", "DEC E", "LD E, n8", "RRA",
+    // 0x20
+    "JR NZ, e8", "LD HL, n16", "LD [HL+], A", "INC HL", "INC H", "DEC H", "LD H, n8", "DAA",
+    "JR Z, e8", "ADD HL, HL", "LD A, [HL+]", "DEC HL", "INC L", "DEC L", "LD L, n8", "CPL",
+    // 0x30
+    "JR NC, e8", "LD SP, n16", "LD [HL-], A", "INC SP", "INC [HL]", "DEC [HL]", "LD [HL], n8", "SCF",
+    "JR C, e8", "ADD HL, SP", "LD A, [HL-]", "DEC SP", "INC A", "DEC A", "LD A, n8", "CCF",
+    // 0x40
+    "LD B, B", "LD B, C", "LD B, D", "LD B, E", "LD B, H", "LD B, L", "LD B, [HL]", "LD B, A",
+    "LD C, B", "LD C, C", "LD C, D", "LD C, E", "LD C, H", "LD C, L", "LD C, [HL]", "LD C, A",
+    // 0x50
+    "LD D, B", "LD D, C", "LD D, D", "LD D, E", "LD D, H", "LD D, L", "LD D, [HL]", "LD D, A",
+    "LD E, B", "LD E, C", "LD E, D", "LD E, E", "LD E, H", "LD E, L", "LD E, [HL]", "LD E, A",
+    // 0x60
+    "LD H, B", "LD H, C", "LD H, D", "LD H, E", "LD H, H", "LD H, L", "LD H, [HL]", "LD H, A",
+    "LD L, B", "LD L, C", "LD L, D", "LD L, E", "LD L, H", "LD L, L", "LD L, [HL]", "LD L, A",
+    // 0x70
+    "LD [HL], B", "LD [HL], C", "LD [HL], D", "LD [HL], E", "LD [HL], H", "LD [HL], L", "HALT",
+    "LD [HL], A", "LD A, B", "LD A, C", "LD A, D", "LD A, E", "LD A, H", "LD A, L", "LD A, [HL]",
+    "LD A, A",
+    // 0x80
+    "ADD A, B", "ADD A, C", "ADD A, D", "ADD A, E", "ADD A, H", "ADD A, L", "ADD A, [HL]",
+    "ADD A, A", "ADC A, B", "ADC A, C", "ADC A, D", "ADC A, E", "ADC A, H", "ADC A, L",
+    "ADC A, [HL]", "ADC A, A",
+    // 0x90
+    "SUB A, B", "SUB A, C", "SUB A, D", "SUB A, E", "SUB A, H", "SUB A, L", "SUB A, [HL]",
+    "SUB A, A", "SBC A, B", "SBC A, C", "SBC A, D", "SBC A, E", "SBC A, H", "SBC A, L",
+    "SBC A, [HL]", "SBC A, A",
+    // 0xA0
+    "AND A, B", "AND A, C", "AND A, D", "AND A, E", "AND A, H", "AND A, L", "AND A, [HL]",
+    "AND A, A", "XOR A, B", "XOR A, C", "XOR A, D", "XOR A, E", "XOR A, H", "XOR A, L",
+    "XOR A, [HL]", "XOR A, A",
+    // 0xB0
+    "OR A, B", "OR A, C", "OR A, D", "OR A, E", "OR A, H", "OR A, L", "OR A, [HL]", "OR A, A",
+    "CP A, B", "CP A, C", "CP A, D", "CP A, E", "CP A, H", "CP A, L", "CP A, [HL]", "CP A, A",
+    // 0xC0
+    "RET NZ", "POP BC", "JP NZ, a16", "JP a16", "CALL NZ, a16", "PUSH BC", "ADD A, n8", "RST $00",
+    "RET Z", "RET", "JP Z, a16", "", "CALL Z, a16", "CALL a16", "ADC A, n8", "RST $08",
+    // 0xD0
+    "RET NC", "POP DE", "JP NC, a16", "", "CALL NC, a16", "PUSH DE", "SUB A, n8", "RST $10",
+    "RET C", "RETI", "JP C, a16", "", "CALL C, a16", "", "SBC A, n8", "RST $18",
+    // 0xE0
+    "LDH [a8], A", "POP HL", "LDH [C], A", "", "", "PUSH HL", "AND A, n8", "RST $20",
+    "ADD SP, e8", "JP HL", "LD [a16], A", "", "", "", "XOR A, n8", "RST $28",
+    // 0xF0
+    "LDH A, [a8]", "POP AF", "LDH A, [C]", "DI", "", "PUSH AF", "OR A, n8", "RST $30",
+    "LD HL, SP + e8", "LD SP, HL", "LD A, [a16]", "EI", "", "", "CP A, n8", "RST $38",
+];
+
+// The CB-prefixed opcodes are regular: the operation in the top bits, the register in the low 3
+const CB_OPERATIONS: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL"];
+const REGISTERS: [&str; 8] = ["B", "C", "D", "E", "H", "L", "[HL]", "A"];
+const PREFIX: u8 = 0xCB;
+const BANK_SIZE: usize = 0x4000;
+// Where the CPU starts after the boot ROM, where the boot ROM itself starts, then the RST and
+// interrupt vectors
+const ENTRY_POINTS: [u16; 14] = [
+    0x0100, 0x00, 0x08, 0x10, 0x18, 0x20, 0x28, 0x30, 0x38, 0x40, 0x48, 0x50, 0x58, 0x60,
+];
+// Bytes per db line for data
+const DATA_ROW: usize = 8;
+
+// One decoded instruction
+pub struct Line {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+    // Where a jump, call or RST goes, JP HL's target isn't known without running it
+    pub target: Option<u16>,
+    pub illegal: bool,
+}
+
+impl Line {
+    // The address of the instruction after this one
+    pub fn next(&self) -> u16 {
+        self.address.wrapping_add(self.bytes.len() as u16)
+    }
+
+    // Whether execution can't carry on to the next instruction, like after JP or RET
+    pub fn ends_flow(&self) -> bool {
+        self.illegal
+            || matches!(self.text.as_str(), "RET" | "RETI" | "JP HL" | "STOP")
+            || self.text.starts_with("JP $")
+            || self.text.starts_with("JR $")
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<String> = self
+            .bytes
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect();
+        write!(
+            f,
+            "{:04X}  {:<8}  {}",
+            self.address,
+            bytes.join(" "),
+            self.text
+        )
+    }
+}
+
+// Decodes the instruction at address, reading its bytes with read
+pub fn disassemble(address: u16, read: impl Fn(u16) -> u8) -> Line {
+    let opcode = read(address);
+    if opcode == PREFIX {
+        let opcode = read(address.wrapping_add(1));
+        let register = REGISTERS[opcode as usize & 0b111];
+        let bit = opcode >> 3 & 0b111;
+        let text = match opcode >> 6 {
+            0 => format!("{} {register}", CB_OPERATIONS[bit as usize]),
+            1 => format!("BIT {bit}, {register}"),
+            2 => format!("RES {bit}, {register}"),
+            _ => format!("SET {bit}, {register}"),
+        };
+        return Line {
+            address,
+            bytes: vec![PREFIX, opcode],
+            text,
+            target: None,
+            illegal: false,
+        };
+    }
+    let template = OPCODES[opcode as usize];
+    if template.is_empty() {
+        return Line {
+            address,
+            bytes: vec![opcode],
+            text: format!("DB ${opcode:02X}"),
+            target: None,
+            illegal: true,
+        };
+    }
+    let operand = |offset: u16| read(address.wrapping_add(offset));
+    let word = u16::from_le_bytes([operand(1), operand(2)]);
+    let placeholder = ["n16", "a16", "n8", "a8", "e8"]
+        .into_iter()
+        .find(|placeholder| template.contains(placeholder));
+    let (length, value, target) = match placeholder {
+        Some("n16") => (3, format!("${word:04X}"), None),
+        Some("a16") => {
+            let target = template.starts_with(['J', 'C']).then_some(word);
+            (3, format!("${word:04X}"), target)
+        }
+        Some("n8") => (2, format!("${:02X}", operand(1)), None),
+        Some("a8") => (2, format!("${:04X}", 0xFF00 | operand(1) as u16), None),
+        Some(_) if template.starts_with("JR") => {
+            let offset = operand(1) as i8 as u16;
+            let target = address.wrapping_add(2).wrapping_add(offset);
+            (2, format!("${target:04X}"), Some(target))
+        }
+        Some(_) => (2, (operand(1) as i8).to_string(), None),
+        None => {
+            let vector = template.strip_prefix("RST $");
+            let target = vector.and_then(|vector| u16::from_str_radix(vector, 16).ok());
+            (1, String::new(), target)
+        }
+    };
+    let text = match placeholder {
+        // SP + -2 reads better as SP - 2
+        Some(placeholder) => template.replace(placeholder, &value).replace("+ -", "- "),
+        None => template.to_string(),
+    };
+    Line {
+        address,
+        bytes: (0..length).map(operand).collect(),
+        text,
+        target,
+        illegal: false,
+    }
+}
+
+// How many bytes the instruction starting with opcode takes, without decoding it
+pub fn length(opcode: u8) -> u16 {
+    let template = OPCODES[opcode as usize];
+    if opcode == PREFIX {
+        2
+    } else if template.contains("16") {
+        3
+    } else if template.contains('8') && !template.starts_with("RST") {
+        2
+    } else {
+        1
+    }
+}
+
+// CALL in any form, or RST
+pub fn is_call(opcode: u8) -> bool {
+    matches!(opcode, 0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC) || opcode & 0xC7 == 0xC7
+}
+
+// RET in any form, or RETI
+pub fn is_return(opcode: u8) -> bool {
+    matches!(opcode, 0xC0 | 0xC8 | 0xC9 | 0xD0 | 0xD8 | 0xD9)
+}
+
+// The count instructions in a row from address
+pub fn disassemble_from(address: u16, count: usize, read: impl Fn(u16) -> u8) -> Vec<Line> {
+    let mut lines = Vec::with_capacity(count);
+    let mut address = address;
+    for _ in 0..count {
+        let line = disassemble(address, &read);
+        address = line.next();
+        lines.push(line);
+    }
+    lines
+}
+
+// Where to start disassembling to show up to count instructions before address. Instructions
+// can't be decoded backwards, so this tries starting further and further back and takes the
+// furthest start whose instructions line up with address
+pub fn lead_in(address: u16, count: usize, read: impl Fn(u16) -> u8) -> u16 {
+    let mut start = address;
+    // Nothing comes before 0, going further back would wrap around to the end
+    for back in 1..=(count as u16 * 3).min(address) {
+        let candidate = address.wrapping_sub(back);
+        let mut at = candidate;
+        let mut instructions = 0;
+        while at != address && instructions < count && address.wrapping_sub(at) <= back {
+            at = disassemble(at, &read).next();
+            instructions += 1;
+        }
+        if at == address {
+            start = candidate;
+        }
+    }
+    start
+}
+
+// A listing of the whole ROM that RGBDS can assemble, a section per bank. Code is whatever can be
+// reached from the entry points by following jumps, calls and fall-through, everything else is
+// data. Jumps through HL can't be followed and bank switches aren't known, so jumps from bank 0
+// into 4000-7FFF are taken to land in bank 1: some code ends up as data, never the other way
+pub fn disassemble_rom(rom: &[u8], title: &str) -> String {
+    let banks = rom.len().div_ceil(BANK_SIZE).max(1);
+    // Offsets in the ROM where an instruction starts, and which bytes instructions cover
+    let mut starts = BTreeSet::new();
+    let mut covered = vec![false; rom.len()];
+    let mut labels = BTreeSet::new();
+    // Everything reachable from one entry point is traced before the next, so the game's own code
+    // claims its bytes before a vector it happens to run through does
+    let mut pending: Vec<(usize, u16)> =
+        ENTRY_POINTS.iter().rev().map(|entry| (0, *entry)).collect();
+    while let Some((bank, address)) = pending.pop() {
+        let Some(offset) = rom_offset(bank, address).filter(|offset| *offset < rom.len()) else {
+            continue;
+        };
+        if starts.contains(&offset) {
+            continue;
+        }
+        let line = disassemble(address, |address| read_banked(rom, bank, address));
+        let bytes = offset..(offset + line.bytes.len()).min(rom.len());
+        // Overlapping another instruction means this isn't really code
+        if covered[bytes.clone()].iter().any(|covered| *covered) {
+            continue;
+        }
+        covered[bytes].fill(true);
+        starts.insert(offset);
+        if let Some(target) = line.target
+            && let Some(target_bank) = bank_of(bank, target)
+        {
+            labels.insert(rom_offset(target_bank, target).unwrap());
+            pending.push((target_bank, target));
+        }
+        if !line.ends_flow() && bank_of(bank, line.next()) == Some(bank) {
+            pending.push((bank, line.next()));
+        }
+    }
+
+    let label = |offset: usize| {
+        let bank = offset / BANK_SIZE;
+        let address = if bank == 0 {
+            offset
+        } else {
+            BANK_SIZE + offset % BANK_SIZE
+        };
+        format!("label_{bank:03X}_{address:04X}")
+    };
+    let name = if title.is_empty() { "ROM" } else { title };
+    let mut listing = vec![format!("; {name}, disassembled by gameboy-emulator")];
+    for bank in 0..banks {
+        let (section, base) = match bank {
+            0 => ("ROM0[$0000]".to_string(), 0),
+            bank => (format!("ROMX[$4000], BANK[${bank:03X}]"), BANK_SIZE as u16),
+        };
+        listing.push(format!("\nSECTION \"ROM Bank ${bank:03X}\", {section}\n"));
+        let end = ((bank + 1) * BANK_SIZE).min(rom.len());
+        let mut offset = bank * BANK_SIZE;
+        while offset < end {
+            if labels.contains(&offset) {
+                listing.push(format!("{}:", label(offset)));
+            }
+            let address = base + (offset % BANK_SIZE) as u16;
+            if starts.contains(&offset) {
+                let mut line = disassemble(address, |address| read_banked(rom, bank, address));
+                if let Some(target) = line.target
+                    && let Some(target) =
+                        bank_of(bank, target).and_then(|bank| rom_offset(bank, target))
+                    && starts.contains(&target)
+                    && !line.text.starts_with("RST")
+                {
+                    let operand = format!("${:04X}", line.target.unwrap());
+                    line.text = line.text.replace(&operand, &label(target));
+                }
+                let bytes: Vec<String> = line
+                    .bytes
+                    .iter()
+                    .map(|byte| format!("{byte:02X}"))
+                    .collect();
+                listing.push(format!(
+                    "    {:<41}; ${address:04X}: {}",
+                    line.text,
+                    bytes.join(" ")
+                ));
+                offset += line.bytes.len();
+                continue;
+            }
+            // Data runs until the next instruction or label, a row at a time
+            let limit = end.min(offset + DATA_ROW);
+            let row_end = (offset + 1..limit)
+                .find(|next| starts.contains(next) || labels.contains(next))
+                .unwrap_or(limit);
+            let bytes: Vec<String> = rom[offset..row_end]
+                .iter()
+                .map(|byte| format!("${byte:02X}"))
+                .collect();
+            listing.push(format!("    db {:<38}; ${address:04X}", bytes.join(", ")));
+            offset = row_end;
+        }
+    }
+    listing.join("\n") + "\n"
+}
+
+// The bank code at address is in, running from bank. None outside the ROM
+fn bank_of(bank: usize, address: u16) -> Option<usize> {
+    match address {
+        0x0000..=0x3FFF => Some(0),
+        0x4000..=0x7FFF => Some(bank.max(1)),
+        _ => None,
+    }
+}
+
+fn rom_offset(bank: usize, address: u16) -> Option<usize> {
+    match bank_of(bank, address)? {
+        0 => Some(address as usize),
+        bank => Some(bank * BANK_SIZE + address as usize - BANK_SIZE),
+    }
+}
+
+// Past the end of the ROM reads like an open bus
+fn read_banked(rom: &[u8], bank: usize, address: u16) -> u8 {
+    rom_offset(bank, address)
+        .and_then(|offset| rom.get(offset).copied())
+        .unwrap_or(0xFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_decode_operands_and_branch_targets() {
+        let code = [
+            0x31, 0xFE, 0xFF, // LD SP, $FFFE
+            0x20, 0xFB, // JR NZ, back to the start
+            0xCB, 0x7C, // BIT 7, H
+            0xE0, 0x40, // LDH [$FF40], A
+            0xF8, 0xFE, // LD HL, SP - 2
+            0xCD, 0x50, 0x01, // CALL $0150
+            0xD3, // Illegal
+            0xC9, // RET
+        ];
+        let read = |address: u16| code.get(address as usize).copied().unwrap_or(0);
+        let lines = disassemble_from(0, 8, read);
+        let texts: Vec<&str> = lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            [
+                "LD SP, $FFFE",
+                "JR NZ, $0000",
+                "BIT 7, H",
+                "LDH [$FF40], A",
+                "LD HL, SP - 2",
+                "CALL $0150",
+                "DB $D3",
+                "RET",
+            ]
+        );
+        assert_eq!(lines[1].target, Some(0));
+        assert_eq!(lines[5].target, Some(0x0150));
+        assert!(lines[6].illegal && lines[7].ends_flow() && !lines[1].ends_flow());
+        assert_eq!(lines[5].to_string(), "000B  CD 50 01  CALL $0150");
+        assert_eq!(lead_in(7, 2, read), 3);
+        assert!(is_call(0xCD) && is_call(0xFF) && !is_call(0xC3));
+        assert!(is_return(0xD9) && !is_return(0xC1));
+        for line in &lines {
+            assert_eq!(length(line.bytes[0]) as usize, line.bytes.len());
+        }
+        assert_eq!(length(0xCF), 1);
+    }
+
+    #[test]
+    fn should_tell_code_from_data_across_banks() {
+        let mut rom = vec![0xFF; 0x8000];
+        rom[0x00..0x61].fill(0xD9); // RETI in every vector
+        rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]); // NOP, JP $0150
+        rom[0x104..0x150].fill(0xCE); // The header, ADC A, n8 if it were code
+        rom[0x150..0x155].copy_from_slice(&[0xCD, 0x00, 0x40, 0x18, 0xFB]); // CALL $4000, JR $0150
+        rom[0x4000] = 0xC9; // RET
+        let listing = disassemble_rom(&rom, "TEST");
+        assert!(listing.contains("SECTION \"ROM Bank $001\", ROMX[$4000], BANK[$001]"));
+        assert!(listing.contains("label_000_0150:\n    CALL label_001_4000"));
+        assert!(listing.contains("JR label_000_0150"));
+        assert!(listing.contains("label_001_4000:\n    RET "));
+        assert!(listing.contains("    db $CE, $CE"));
+        assert!(!listing.contains("ADC A"));
+    }
+}
@@ -0,0 +1,47 @@
+// How the picture fills a window whose size isn't a multiple of 160x144
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scaling {
+    // Largest whole multiple that fits, every Game Boy pixel stays the same size
+    Integer,
+    // Largest size that fits while keeping the aspect ratio, letterboxed
+    Fit,
+    // Fills the whole window, distorting the picture
+    Stretch,
+}
+
+impl Scaling {
+    pub const ALL: [Scaling; 3] = [Scaling::Integer, Scaling::Fit, Scaling::Stretch];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scaling::Integer => "integer",
+            Scaling::Fit => "fit",
+            Scaling::Stretch => "stretch",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Scaling> {
+        Self::ALL.into_iter().find(|scaling| scaling.name() == name)
+    }
+
+    pub fn next(&self) -> Scaling {
+        let index = Self::ALL
+            .iter()
+            .position(|scaling| scaling == self)
+            .unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_cycle_scaling_modes() {
+        assert_eq!(Scaling::Integer.next(), Scaling::Fit);
+        assert_eq!(Scaling::Stretch.next(), Scaling::Integer);
+        assert_eq!(Scaling::from_name("fit"), Some(Scaling::Fit));
+        assert_eq!(Scaling::from_name("zoom"), None);
+    }
+}
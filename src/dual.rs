@@ -0,0 +1,93 @@
+use crate::audio::AudioSink;
+use crate::config::Config;
+use crate::cpu::CPU;
+use crate::event::{Event, EventSource};
+use crate::frame_limiter::FrameLimiter;
+use crate::gpu::Renderer;
+use crate::link::ChannelLink;
+use crate::memory::Memory;
+use std::path::Path;
+use std::sync::mpsc::{Receiver, SyncSender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Frontend of the second emulator in --dual mode. Its frames go to whoever shows them, usually a
+// second window of the main frontend, and its input comes from there too. It plays no sound
+pub struct RemoteFrontend {
+    frames: SyncSender<Vec<u8>>,
+    events: Receiver<Event>,
+    // Whoever sent the input is gone, which ends the emulator
+    disconnected: bool,
+}
+
+impl Renderer for RemoteFrontend {
+    fn present(&mut self, frame: &[u8]) {
+        // A frame that isn't shown yet, or nobody showing them at all, means this one is dropped
+        let _ = self.frames.try_send(frame.to_vec());
+    }
+}
+
+impl EventSource for RemoteFrontend {
+    fn poll_events(&mut self) -> Vec<Event> {
+        let mut events = Vec::new();
+        loop {
+            match self.events.try_recv() {
+                Ok(event) => events.push(event),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.disconnected = true;
+                    break;
+                }
+            }
+        }
+        events
+    }
+}
+
+impl AudioSink for RemoteFrontend {
+    fn sample_rate(&self) -> Option<u32> {
+        None
+    }
+
+    fn queue_audio(&mut self, _samples: &[f32]) {}
+
+    fn queued_audio(&self) -> Option<Duration> {
+        None
+    }
+}
+
+// Runs a second emulator on the same ROM on its own thread, plugged into the other end of the
+// link cable. Each paces itself with its own frame limiter, transfers keep them in step where it
+// matters. It stops once the sender of its events is dropped
+pub fn spawn_second(
+    rom: &Path,
+    config: Config,
+    link: ChannelLink,
+    frames: SyncSender<Vec<u8>>,
+    events: Receiver<Event>,
+) -> JoinHandle<()> {
+    let rom = rom.to_path_buf();
+    thread::spawn(move || {
+        let mut memory = Memory::new();
+        if let Err(error) = memory.load_rom(&rom) {
+            eprintln!("Player 2 couldn't read {}: {error}", rom.display());
+            return;
+        }
+        memory.serial.set_link(Box::new(link));
+        let frontend = RemoteFrontend {
+            frames,
+            events,
+            disconnected: false,
+        };
+        let mut cpu = CPU::new(memory, frontend, config);
+        if let Err(error) = cpu.apply_palettes() {
+            eprintln!("Player 2: {error}");
+            return;
+        }
+        let mut limiter = FrameLimiter::new();
+        while !cpu.frontend_mut().disconnected {
+            cpu.run_frame();
+            limiter.wait();
+        }
+    })
+}
@@ -0,0 +1,290 @@
+use crate::emulator::Emulator;
+use crate::frame_limiter::FRAME_RATE;
+use crate::gpu::{FRAME_HEIGHT, FRAME_WIDTH};
+use crate::joypad::Button;
+use crate::memory;
+use crate::viewers::View;
+use eframe::egui::{self, ColorImage, Key, TextureHandle, TextureOptions};
+use std::path::{Path, PathBuf};
+use web_time::Instant;
+
+const KEYS: [(Key, Button); 8] = [
+    (Key::ArrowUp, Button::Up),
+    (Key::ArrowDown, Button::Down),
+    (Key::ArrowLeft, Button::Left),
+    (Key::ArrowRight, Button::Right),
+    (Key::Z, Button::A),
+    (Key::X, Button::B),
+    (Key::Enter, Button::Start),
+    (Key::Backspace, Button::Select),
+];
+
+// Frames run at once at most, after a stall the game carries on instead of catching up
+const MAX_OWED_FRAMES: f64 = 3.0;
+// Rows of 16 bytes in the memory panel
+const MEMORY_ROWS: u16 = 16;
+
+const VIEWS: [View; 3] = [View::Tiles, View::BackgroundMap, View::Oam];
+
+// The game next to panels showing what the machine is doing, which can be opened from the menu
+// and moved around. The terminal debugger's views, without having to know its commands
+pub struct EguiApp {
+    emulator: Emulator,
+    rom: Option<PathBuf>,
+    paused: bool,
+    // Frames due by the clock that haven't been run yet, and when that was last worked out
+    owed: f64,
+    last_update: Instant,
+    screen: Option<TextureHandle>,
+    show_registers: bool,
+    show_memory: bool,
+    memory_start: u16,
+    memory_address: String,
+    // Open views and their textures
+    views: Vec<(View, Option<TextureHandle>)>,
+    error: Option<String>,
+}
+
+impl EguiApp {
+    pub fn new(rom: Option<PathBuf>) -> Self {
+        let mut app = Self {
+            emulator: Emulator::new(),
+            rom: None,
+            paused: false,
+            owed: 0.0,
+            last_update: Instant::now(),
+            screen: None,
+            show_registers: true,
+            show_memory: false,
+            memory_start: 0xC000,
+            memory_address: "C000".to_string(),
+            views: Vec::new(),
+            error: None,
+        };
+        if let Some(rom) = rom {
+            app.load_rom(&rom);
+        }
+        app
+    }
+
+    fn load_rom(&mut self, path: &Path) {
+        match self.emulator.load_rom(path) {
+            Ok(()) => {
+                self.rom = Some(path.to_path_buf());
+                self.error = None;
+            }
+            Err(error) => self.error = Some(format!("Couldn't load {}: {error}", path.display())),
+        }
+    }
+
+    fn handle_input(&mut self, ctx: &egui::Context) {
+        // Keys only reach the game while no text field has them
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        ctx.input(|input| {
+            for event in &input.events {
+                if let egui::Event::Key {
+                    key,
+                    pressed,
+                    repeat: false,
+                    ..
+                } = event
+                    && let Some((_, button)) = KEYS.iter().find(|(bound, _)| bound == key)
+                {
+                    if *pressed {
+                        self.emulator.press(*button);
+                    } else {
+                        self.emulator.release(*button);
+                    }
+                }
+            }
+        });
+    }
+
+    // Runs the frames the clock says are due, egui repaints at the display's rate
+    fn run(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_update).as_secs_f64();
+        self.last_update = now;
+        if self.paused || self.rom.is_none() {
+            return;
+        }
+        self.owed = (self.owed + elapsed * FRAME_RATE).min(MAX_OWED_FRAMES);
+        while self.owed >= 1.0 {
+            self.emulator.run_until_vblank();
+            self.owed -= 1.0;
+        }
+    }
+
+    fn menu(&mut self, ui: &mut egui::Ui) {
+        egui::MenuBar::new().ui(ui, |ui| {
+            ui.menu_button("File", |ui| {
+                if ui.button("Open ROM...").clicked()
+                    && let Some(path) = rfd::FileDialog::new()
+                        .add_filter("Game Boy ROMs", &memory::ROM_EXTENSIONS)
+                        .pick_file()
+                {
+                    self.load_rom(&path);
+                }
+                if ui.button("Quit").clicked() {
+                    ui.ctx().send_viewport_cmd(egui::ViewportCommand::Close);
+                }
+            });
+            ui.menu_button("Emulation", |ui| {
+                ui.checkbox(&mut self.paused, "Paused");
+                if ui.button("Step frame").clicked() && self.rom.is_some() {
+                    self.emulator.run_until_vblank();
+                }
+                if ui.button("Reset").clicked() {
+                    self.emulator.cpu_mut().reset(false);
+                }
+            });
+            ui.menu_button("Panels", |ui| {
+                ui.checkbox(&mut self.show_registers, "Registers");
+                ui.checkbox(&mut self.show_memory, "Memory");
+                for view in VIEWS {
+                    let mut open = self.views.iter().any(|(open, _)| *open == view);
+                    if ui.checkbox(&mut open, view.title()).changed() {
+                        if open {
+                            self.views.push((view, None));
+                        } else {
+                            self.views.retain(|(open, _)| *open != view);
+                        }
+                    }
+                }
+            });
+        });
+    }
+
+    fn screen(&mut self, ui: &mut egui::Ui) {
+        let image = ColorImage::from_rgb([FRAME_WIDTH, FRAME_HEIGHT], self.emulator.framebuffer());
+        let texture = update_texture(ui.ctx(), &mut self.screen, "screen", image);
+        // The largest whole multiple that fits keeps the pixels square
+        let available = ui.available_size();
+        let scale = (available.x / FRAME_WIDTH as f32)
+            .min(available.y / FRAME_HEIGHT as f32)
+            .floor()
+            .max(1.0);
+        ui.centered_and_justified(|ui| {
+            ui.add(egui::Image::new(&texture).fit_to_exact_size(egui::vec2(
+                FRAME_WIDTH as f32 * scale,
+                FRAME_HEIGHT as f32 * scale,
+            )));
+        });
+    }
+
+    fn registers(&mut self, ctx: &egui::Context) {
+        let registers = self.emulator.cpu().registers();
+        egui::Window::new("Registers")
+            .open(&mut self.show_registers)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("registers").striped(true).show(ui, |ui| {
+                    for (name, value) in registers {
+                        ui.monospace(name);
+                        ui.monospace(format!("{value:04X}"));
+                        ui.end_row();
+                    }
+                });
+                // The low byte of AF
+                let flags = registers[0].1 as u8;
+                let set: Vec<&str> = [(0x80, "Z"), (0x40, "N"), (0x20, "H"), (0x10, "C")]
+                    .into_iter()
+                    .map(|(bit, name)| if flags & bit != 0 { name } else { "-" })
+                    .collect();
+                ui.monospace(format!("Flags {}", set.concat()));
+            });
+    }
+
+    fn memory(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_memory;
+        egui::Window::new("Memory").open(&mut open).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Address");
+                let field = ui.text_edit_singleline(&mut self.memory_address);
+                if field.changed()
+                    && let Ok(address) = u16::from_str_radix(self.memory_address.trim(), 16)
+                {
+                    self.memory_start = address & 0xFFF0;
+                }
+            });
+            egui::Grid::new("memory").show(ui, |ui| {
+                for row in 0..MEMORY_ROWS {
+                    let Some(address) = self.memory_start.checked_add(row * 16) else {
+                        break;
+                    };
+                    ui.monospace(format!("{address:04X}"));
+                    let bytes: Vec<String> = (0..16)
+                        .map(|offset| format!("{:02X}", self.emulator.read(address + offset)))
+                        .collect();
+                    ui.monospace(bytes.join(" "));
+                    ui.end_row();
+                }
+            });
+        });
+        self.show_memory = open;
+    }
+
+    fn views(&mut self, ctx: &egui::Context) {
+        let mut closed = Vec::new();
+        for (view, texture) in &mut self.views {
+            let rendered = self.emulator.cpu().render_view(*view);
+            let image = ColorImage::from_rgb([rendered.width, rendered.height], &rendered.pixels);
+            let texture = update_texture(ctx, texture, view.title(), image);
+            let mut open = true;
+            egui::Window::new(view.title())
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    let size = texture.size_vec2() * 2.0;
+                    ui.add(egui::Image::new(&texture).fit_to_exact_size(size));
+                });
+            if !open {
+                closed.push(*view);
+            }
+        }
+        self.views.retain(|(view, _)| !closed.contains(view));
+    }
+}
+
+impl eframe::App for EguiApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.handle_input(ctx);
+        self.run();
+        egui::TopBottomPanel::top("menu").show(ctx, |ui| self.menu(ui));
+        if let Some(error) = &self.error {
+            egui::TopBottomPanel::bottom("error").show(ctx, |ui| {
+                ui.colored_label(egui::Color32::LIGHT_RED, error);
+            });
+        }
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if self.rom.is_some() {
+                self.screen(ui);
+            } else {
+                ui.centered_and_justified(|ui| ui.label("Open a ROM from the File menu"));
+            }
+        });
+        self.registers(ctx);
+        self.memory(ctx);
+        self.views(ctx);
+        ctx.request_repaint();
+    }
+}
+
+// Uploads image to the texture, creating it the first time
+fn update_texture(
+    ctx: &egui::Context,
+    texture: &mut Option<TextureHandle>,
+    name: &str,
+    image: ColorImage,
+) -> TextureHandle {
+    match texture {
+        Some(texture) => {
+            texture.set(image, TextureOptions::NEAREST);
+            texture.clone()
+        }
+        None => texture
+            .insert(ctx.load_texture(name, image, TextureOptions::NEAREST))
+            .clone(),
+    }
+}
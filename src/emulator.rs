@@ -0,0 +1,169 @@
+use crate::config::Config;
+use crate::cpu::CPU;
+use crate::event::Event;
+use crate::headless::NullRenderer;
+use crate::joypad::Button;
+use crate::memory::{Interrupt, Memory};
+use std::error::Error;
+use std::path::Path;
+
+// The emulator for embedding in another program. It has no window, sound device or input of its
+// own: the host runs it a frame at a time, shows the framebuffer and feeds it the buttons
+pub struct Emulator {
+    cpu: CPU<NullRenderer>,
+}
+
+impl Emulator {
+    pub fn new() -> Self {
+        // Nothing can trigger a rewind, so there's no point keeping snapshots
+        Self::with_config(Config {
+            rewind_seconds: 0,
+            ..Config::default()
+        })
+    }
+
+    // Only the settings for emulation itself apply, like the speed or the low-pass. Window and
+    // input settings are the host's business
+    pub fn with_config(config: Config) -> Self {
+        Self {
+            cpu: CPU::new(Memory::new(), NullRenderer, config),
+        }
+    }
+
+    // Zipped ROMs work too
+    pub fn load_rom(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        self.cpu.load_rom(path)
+    }
+
+    pub fn load_rom_bytes(&mut self, rom: Vec<u8>) -> Result<(), Box<dyn Error>> {
+        self.cpu.insert_rom(rom)
+    }
+
+    // Runs one frame's worth of cycles, up to the PPU entering VBlank, and returns the finished
+    // frame. It never sleeps or starts threads, the host's own loop keeps to the Game Boy's 59.73
+    // frames a second, like from a display's vsync or a game engine's tick
+    pub fn run_until_vblank(&mut self) -> &[u8] {
+        self.cpu.run_frame();
+        self.framebuffer()
+    }
+
+    // Frames run since power-on
+    pub fn frame_count(&self) -> u64 {
+        self.cpu.frames()
+    }
+
+    // The last frame, 160x144 pixels of 3 bytes each, RGB, row by row
+    pub fn framebuffer(&self) -> &[u8] {
+        self.cpu.gpu().frame()
+    }
+
+    pub fn press(&mut self, button: Button) {
+        self.cpu.handle_event(Event::ButtonDown(button));
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.cpu.handle_event(Event::ButtonUp(button));
+    }
+
+    // Called with the framebuffer and the frame's number as each one completes
+    pub fn on_frame(&mut self, callback: impl FnMut(&[u8], u64) + 'static) {
+        self.cpu.on_frame(callback);
+    }
+
+    // Called with the PC and the opcode before every instruction, for tracing or profiling
+    pub fn on_instruction(&mut self, callback: impl FnMut(u16, u8) + 'static) {
+        self.cpu.on_instruction(callback);
+    }
+
+    // Called whenever the PPU, timer, serial port or joypad requests an interrupt, which only sets
+    // its bit in IF. Requests are all there is, interrupts aren't dispatched to handlers yet
+    pub fn on_interrupt_request(&mut self, callback: impl FnMut(Interrupt) + 'static) {
+        self.cpu.on_interrupt_request(callback);
+    }
+
+    // Stereo samples of every frame as it completes, interleaved left and right, at
+    // Config::sample_rate
+    pub fn on_audio(&mut self, callback: impl FnMut(&[f32]) + 'static) {
+        self.cpu.on_audio(callback);
+    }
+
+    // Memory as the running game sees it, like a score or the player's position. Reading never
+    // changes anything, writing has the effect the same write from the game would
+    pub fn read(&self, address: u16) -> u8 {
+        self.cpu.peek(address)
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        self.cpu.poke(address, value);
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        self.cpu.save_state()
+    }
+
+    // Only states of the same ROM load, the machine is left as it was otherwise
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        self.cpu.load_state(state)
+    }
+
+    pub fn cpu(&self) -> &CPU<NullRenderer> {
+        &self.cpu
+    }
+
+    // Everything else, like palettes, movies or the debug views
+    pub fn cpu_mut(&mut self) -> &mut CPU<NullRenderer> {
+        &mut self.cpu
+    }
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// NOPs up to a JR NZ, -2 at 0x10 that spins in place forever, for tests that just need a game
+// that keeps running without touching any IO register
+#[cfg(test)]
+pub(crate) fn test_rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x10..0x12].copy_from_slice(&[0x20, 0xFE]);
+    rom
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpu::{BYTES_PER_PIXEL, FRAME_HEIGHT, FRAME_WIDTH};
+    use crate::timer::DIV;
+
+    #[test]
+    fn should_run_a_rom_a_frame_at_a_time() {
+        let mut emulator = Emulator::new();
+        emulator.load_rom_bytes(test_rom()).unwrap();
+        let frame = emulator.run_until_vblank();
+        assert_eq!(frame.len(), FRAME_WIDTH * FRAME_HEIGHT * BYTES_PER_PIXEL);
+        assert_eq!(emulator.frame_count(), 1);
+        let state = emulator.save_state();
+        emulator.press(Button::Start);
+        emulator.run_until_vblank();
+        emulator.release(Button::Start);
+        assert!(emulator.load_state(&state).is_ok());
+        assert_eq!(emulator.save_state(), state);
+    }
+
+    #[test]
+    fn should_peek_and_poke_through_the_bus() {
+        let mut emulator = Emulator::new();
+        emulator.load_rom_bytes(test_rom()).unwrap();
+        emulator.write(0xC000, 0x42);
+        assert_eq!(emulator.read(0xC000), 0x42);
+        // The first frame ends just as DIV wraps around
+        emulator.run_until_vblank();
+        emulator.run_until_vblank();
+        assert_ne!(emulator.read(DIV), 0);
+        assert_eq!(emulator.read(DIV), emulator.read(DIV));
+        emulator.write(DIV, 0xAB);
+        assert_eq!(emulator.read(DIV), 0);
+    }
+}
@@ -0,0 +1,62 @@
+use crate::joypad::Button;
+use crate::viewers::View;
+use std::path::PathBuf;
+
+// Emulator-level actions a frontend can trigger, independent of which key or button is bound
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    CyclePalette,
+    CycleFilter,
+    ToggleGhosting,
+    Screenshot,
+    ToggleCapture,
+    ToggleAudioRecording,
+    ToggleView(View),
+    ToggleFullscreen,
+    CycleScaling,
+    // Resizes the window to this many times 160x144
+    SetScale(u32),
+    // APU channel, 0 to 3
+    ToggleMute(usize),
+    SoloChannel(usize),
+    TogglePause,
+    // Held down, runs until released
+    FastForward,
+    // Held down, steps back through the last few seconds until released
+    Rewind,
+    // 100%, 50%, 25% and back
+    CycleSlowMotion,
+    // Runs a single frame while paused
+    FrameAdvance,
+    // Save states go to and come from the selected slot
+    SaveState,
+    LoadState,
+    SelectSlot(u8),
+    // Starts the game over, the hard one with RAM cleared
+    Reset,
+    HardReset,
+    // Shows the recently played ROMs to pick one from
+    RecentRoms,
+    Quit,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Action(Action),
+    // The input bound to an action was let go, only matters for the held ones
+    ActionReleased(Action),
+    ButtonDown(Button),
+    ButtonUp(Button),
+    // A ROM to play instead of the current one, dropped on the window
+    OpenRom(PathBuf),
+    // Keyboard focus moved to or away from the emulator's windows
+    Focus(bool),
+    // A menu of the frontend's opened or closed, the game waits while one is open
+    Menu(bool),
+}
+
+pub trait EventSource {
+    fn poll_events(&mut self) -> Vec<Event>;
+    // Force feedback from the cartridge, 0 to 1. Frontends without a way to shake ignore it
+    fn set_rumble(&mut self, _strength: f32) {}
+}
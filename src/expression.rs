@@ -0,0 +1,324 @@
+// Conditions for the debugger, like `a == 0x3C && [0xC0A0] > 5`. Values are registers (a, f, b, c,
+// d, e, h, l and the pairs), flags (zf, nf, hf, cf), bytes in memory ([address]) and numbers, hex
+// with 0x. Operators are C's, except that & and | come before comparisons so `[hl] & 0x80 == 0x80`
+// means what it looks like. Comparisons and logic give 1 or 0, anything but 0 is true
+
+// What conditions are evaluated against
+pub trait Machine {
+    fn registers(&self) -> [(&'static str, u16); 6];
+    fn peek(&self, address: u16) -> u8;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Register(usize),
+    High(usize),
+    Low(usize),
+    Flag(u8),
+    Number(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    BitOr,
+    BitAnd,
+    Add,
+    Subtract,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Expression {
+    Value(Value),
+    Memory(Box<Expression>),
+    Not(Box<Expression>),
+    Binary(Operator, Box<Expression>, Box<Expression>),
+}
+
+// Lowest precedence first, operators of a level are tried longest first so <= isn't taken for <
+const LEVELS: [&[(&str, Operator)]; 6] = [
+    &[("||", Operator::Or)],
+    &[("&&", Operator::And)],
+    &[
+        ("==", Operator::Equal),
+        ("!=", Operator::NotEqual),
+        ("<=", Operator::LessOrEqual),
+        (">=", Operator::GreaterOrEqual),
+        ("<", Operator::Less),
+        (">", Operator::Greater),
+    ],
+    &[("|", Operator::BitOr)],
+    &[("&", Operator::BitAnd)],
+    &[("+", Operator::Add), ("-", Operator::Subtract)],
+];
+
+// The index of each pair in Machine::registers, and the bit of each flag in F
+const PAIRS: [&str; 6] = ["af", "bc", "de", "hl", "sp", "pc"];
+const HALVES: [(&str, &str, usize); 3] = [("b", "c", 1), ("d", "e", 2), ("h", "l", 3)];
+const FLAGS: [(&str, u8); 4] = [("zf", 7), ("nf", 6), ("hf", 5), ("cf", 4)];
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = Parser { text, position: 0 };
+        let expression = parser.level(0)?;
+        parser.skip_whitespace();
+        match parser.rest() {
+            "" => Ok(expression),
+            rest => Err(format!("Unexpected {rest}")),
+        }
+    }
+
+    pub fn evaluate(&self, machine: &impl Machine) -> u32 {
+        match self {
+            Expression::Value(value) => {
+                let registers = machine.registers();
+                match *value {
+                    Value::Register(index) => registers[index].1 as u32,
+                    Value::High(index) => (registers[index].1 >> 8) as u32,
+                    Value::Low(index) => (registers[index].1 & 0xFF) as u32,
+                    Value::Flag(bit) => (registers[0].1 as u32 >> bit) & 1,
+                    Value::Number(number) => number,
+                }
+            }
+            Expression::Memory(address) => machine.peek(address.evaluate(machine) as u16) as u32,
+            Expression::Not(operand) => (operand.evaluate(machine) == 0) as u32,
+            Expression::Binary(operator, left, right) => {
+                let left = left.evaluate(machine);
+                // Like C, the right side of || and && is only evaluated when it matters
+                match operator {
+                    Operator::Or => return (left != 0 || right.evaluate(machine) != 0) as u32,
+                    Operator::And => return (left != 0 && right.evaluate(machine) != 0) as u32,
+                    _ => {}
+                }
+                let right = right.evaluate(machine);
+                match operator {
+                    Operator::Equal => (left == right) as u32,
+                    Operator::NotEqual => (left != right) as u32,
+                    Operator::Less => (left < right) as u32,
+                    Operator::LessOrEqual => (left <= right) as u32,
+                    Operator::Greater => (left > right) as u32,
+                    Operator::GreaterOrEqual => (left >= right) as u32,
+                    Operator::BitOr => left | right,
+                    Operator::BitAnd => left & right,
+                    Operator::Add => left.wrapping_add(right),
+                    Operator::Subtract => left.wrapping_sub(right),
+                    Operator::Or | Operator::And => unreachable!(),
+                }
+            }
+        }
+    }
+
+    pub fn is_true(&self, machine: &impl Machine) -> bool {
+        self.evaluate(machine) != 0
+    }
+}
+
+impl Value {
+    // A register, half or flag by the name conditions use for it
+    pub fn register(name: &str) -> Option<Self> {
+        value(&name.to_ascii_lowercase()).filter(|value| !matches!(value, Value::Number(_)))
+    }
+
+    // The index of the pair this is in, and what the pair holds once number is written to it
+    pub fn written(
+        self,
+        registers: &[(&'static str, u16); 6],
+        number: u32,
+    ) -> Result<(usize, u16), String> {
+        let too_big = |bits| format!("{number} doesn't fit in {bits} bits");
+        match self {
+            Value::Register(index) => u16::try_from(number)
+                .map(|number| (index, number))
+                .map_err(|_| too_big(16)),
+            Value::High(index) => {
+                let number = u8::try_from(number).map_err(|_| too_big(8))?;
+                Ok((index, registers[index].1 & 0x00FF | (number as u16) << 8))
+            }
+            Value::Low(index) => {
+                let number = u8::try_from(number).map_err(|_| too_big(8))?;
+                Ok((index, registers[index].1 & 0xFF00 | number as u16))
+            }
+            Value::Flag(bit) if number <= 1 => {
+                Ok((0, registers[0].1 & !(1 << bit) | (number as u16) << bit))
+            }
+            Value::Flag(_) => Err("Flags are 0 or 1".to_string()),
+            Value::Number(_) => Err("Only registers and flags can be set".to_string()),
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.text[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        let found = self.rest().starts_with(token);
+        if found {
+            self.position += token.len();
+        }
+        found
+    }
+
+    // Binary operators of this precedence level, left to right
+    fn level(&mut self, level: usize) -> Result<Expression, String> {
+        let Some(operators) = LEVELS.get(level) else {
+            return self.unary();
+        };
+        let mut left = self.level(level + 1)?;
+        'operands: loop {
+            for (token, operator) in *operators {
+                // | and & aren't the start of || and &&
+                let doubled =
+                    token.len() == 1 && self.rest().trim_start().starts_with(&token.repeat(2));
+                if !doubled && self.eat(token) {
+                    let right = self.level(level + 1)?;
+                    left = Expression::Binary(*operator, Box::new(left), Box::new(right));
+                    continue 'operands;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expression, String> {
+        if self.eat("!") {
+            return Ok(Expression::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let expression = self.level(0)?;
+            return if self.eat(")") {
+                Ok(expression)
+            } else {
+                Err("Missing )".to_string())
+            };
+        }
+        if self.eat("[") {
+            let address = self.level(0)?;
+            return if self.eat("]") {
+                Ok(Expression::Memory(Box::new(address)))
+            } else {
+                Err("Missing ]".to_string())
+            };
+        }
+        self.skip_whitespace();
+        let rest = self.rest();
+        let length = rest
+            .find(|character: char| !character.is_ascii_alphanumeric())
+            .unwrap_or(rest.len());
+        if length == 0 {
+            return Err(match rest {
+                "" => "Expression ends too soon".to_string(),
+                rest => format!("Unexpected {rest}"),
+            });
+        }
+        let word = rest[..length].to_ascii_lowercase();
+        self.position += length;
+        value(&word)
+            .map(Expression::Value)
+            .ok_or_else(|| format!("Unknown value {word}"))
+    }
+}
+
+fn value(word: &str) -> Option<Value> {
+    if let Some(hex) = word.strip_prefix("0x") {
+        return u32::from_str_radix(hex, 16).ok().map(Value::Number);
+    }
+    if let Ok(number) = word.parse() {
+        return Some(Value::Number(number));
+    }
+    if let Some(index) = PAIRS.iter().position(|pair| *pair == word) {
+        return Some(Value::Register(index));
+    }
+    if let Some((_, bit)) = FLAGS.iter().find(|(flag, _)| *flag == word) {
+        return Some(Value::Flag(*bit));
+    }
+    match word {
+        "a" => return Some(Value::High(0)),
+        "f" => return Some(Value::Low(0)),
+        _ => {}
+    }
+    HALVES.iter().find_map(|(high, low, index)| {
+        if word == *high {
+            Some(Value::High(*index))
+        } else if word == *low {
+            Some(Value::Low(*index))
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestMachine;
+
+    impl Machine for TestMachine {
+        fn registers(&self) -> [(&'static str, u16); 6] {
+            [
+                ("AF", 0x3C80),
+                ("BC", 0x0102),
+                ("DE", 0),
+                ("HL", 0xC0A0),
+                ("SP", 0xFFFE),
+                ("PC", 0x2345),
+            ]
+        }
+
+        fn peek(&self, address: u16) -> u8 {
+            address as u8
+        }
+    }
+
+    fn evaluate(text: &str) -> u32 {
+        Expression::parse(text).unwrap().evaluate(&TestMachine)
+    }
+
+    #[test]
+    fn should_evaluate_against_the_machine() {
+        assert_eq!(evaluate("a == 0x3C && [0xC0A0] > 5"), 1);
+        assert_eq!(evaluate("a == 0x3C && [hl + 1] == 0xA0"), 0);
+        assert_eq!(evaluate("zf && !cf"), 1);
+        assert_eq!(evaluate("b | c == 3"), 1);
+        assert_eq!(evaluate("(pc - 0x45) & 0xFF00"), 0x2300);
+        assert_eq!(evaluate("SP >= 65534 || [0]"), 1);
+        assert!(Expression::parse("a ==").is_err());
+        assert!(Expression::parse("x == 1").is_err());
+        assert!(Expression::parse("(a == 1").is_err());
+        assert!(Expression::parse("a == 1 )").is_err());
+    }
+
+    #[test]
+    fn should_write_into_the_right_part_of_a_pair() {
+        let registers = TestMachine.registers();
+        let written = |name, number| Value::register(name).unwrap().written(&registers, number);
+        assert_eq!(written("A", 0), Ok((0, 0x0080)));
+        assert_eq!(written("c", 0xFF), Ok((1, 0x01FF)));
+        assert_eq!(written("zf", 0), Ok((0, 0x3C00)));
+        assert_eq!(written("cf", 1), Ok((0, 0x3C90)));
+        assert_eq!(written("hl", 0xD000), Ok((3, 0xD000)));
+        assert!(written("b", 0x100).is_err());
+        assert!(written("nf", 2).is_err());
+        assert!(Value::register("0x10").is_none());
+    }
+}
@@ -0,0 +1,185 @@
+// The C API, declared in include/gameboy_emulator.h. Functions only ever get added to it, existing
+// ones keep their signatures and meaning. Every emulator passed in has to come from gb_create and
+// not be destroyed yet, every buffer has to hold as many bytes as it's passed with
+#![allow(clippy::missing_safety_doc)]
+
+use crate::emulator::Emulator;
+use crate::gpu::{BYTES_PER_PIXEL, FRAME_HEIGHT, FRAME_WIDTH};
+use crate::joypad::Button;
+use std::slice;
+
+// Spelled out for the header, which only sees this file
+pub const GB_FRAME_WIDTH: usize = 160;
+pub const GB_FRAME_HEIGHT: usize = 144;
+// RGB
+pub const GB_BYTES_PER_PIXEL: usize = 3;
+
+const _: () = assert!(
+    GB_FRAME_WIDTH == FRAME_WIDTH
+        && GB_FRAME_HEIGHT == FRAME_HEIGHT
+        && GB_BYTES_PER_PIXEL == BYTES_PER_PIXEL
+);
+
+// Bits of gb_set_buttons' mask
+pub const GB_BUTTON_RIGHT: u8 = 1 << 0;
+pub const GB_BUTTON_LEFT: u8 = 1 << 1;
+pub const GB_BUTTON_UP: u8 = 1 << 2;
+pub const GB_BUTTON_DOWN: u8 = 1 << 3;
+pub const GB_BUTTON_A: u8 = 1 << 4;
+pub const GB_BUTTON_B: u8 = 1 << 5;
+pub const GB_BUTTON_SELECT: u8 = 1 << 6;
+pub const GB_BUTTON_START: u8 = 1 << 7;
+
+const BUTTONS: [(u8, Button); 8] = [
+    (GB_BUTTON_RIGHT, Button::Right),
+    (GB_BUTTON_LEFT, Button::Left),
+    (GB_BUTTON_UP, Button::Up),
+    (GB_BUTTON_DOWN, Button::Down),
+    (GB_BUTTON_A, Button::A),
+    (GB_BUTTON_B, Button::B),
+    (GB_BUTTON_SELECT, Button::Select),
+    (GB_BUTTON_START, Button::Start),
+];
+
+// Opaque to C, only handled through pointers from gb_create
+pub struct GbEmulator {
+    emulator: Emulator,
+    // The mask last set, so only the buttons that changed get pressed or released
+    buttons: u8,
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn gb_create() -> *mut GbEmulator {
+    Box::into_raw(Box::new(GbEmulator {
+        emulator: Emulator::new(),
+        buttons: 0,
+    }))
+}
+
+// Null is ignored
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_destroy(emulator: *mut GbEmulator) {
+    if !emulator.is_null() {
+        drop(unsafe { Box::from_raw(emulator) });
+    }
+}
+
+// Loads the ROM in the length bytes at rom, which can be freed afterwards. Returns false if it
+// isn't a ROM this emulator runs, or rom is null
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_load_rom(
+    emulator: *mut GbEmulator,
+    rom: *const u8,
+    length: usize,
+) -> bool {
+    if rom.is_null() {
+        return false;
+    }
+    let emulator = unsafe { &mut *emulator };
+    let rom = unsafe { slice::from_raw_parts(rom, length) };
+    emulator.emulator.load_rom_bytes(rom.to_vec()).is_ok()
+}
+
+// Runs until the next frame is complete, pacing is up to the caller
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_run_frame(emulator: *mut GbEmulator) {
+    unsafe { &mut *emulator }.emulator.run_until_vblank();
+}
+
+// GB_FRAME_WIDTH * GB_FRAME_HEIGHT pixels of GB_BYTES_PER_PIXEL bytes, row by row. Valid until the
+// next call that runs the emulator
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_get_framebuffer(emulator: *const GbEmulator) -> *const u8 {
+    unsafe { &*emulator }.emulator.framebuffer().as_ptr()
+}
+
+// Holds down the GB_BUTTON_* bits set in buttons and releases the rest
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_set_buttons(emulator: *mut GbEmulator, buttons: u8) {
+    let emulator = unsafe { &mut *emulator };
+    for (bit, button) in BUTTONS {
+        if (buttons ^ emulator.buttons) & bit == 0 {
+            continue;
+        }
+        if buttons & bit != 0 {
+            emulator.emulator.press(button);
+        } else {
+            emulator.emulator.release(button);
+        }
+    }
+    emulator.buttons = buttons;
+}
+
+// The byte the game would read at address, reading has no side effects
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_read(emulator: *const GbEmulator, address: u16) -> u8 {
+    unsafe { &*emulator }.emulator.read(address)
+}
+
+// Writes like the game would, IO registers react to it
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_write(emulator: *mut GbEmulator, address: u16, value: u8) {
+    unsafe { &mut *emulator }.emulator.write(address, value);
+}
+
+// Writes a save state to buffer if it fits in capacity bytes. Returns the state's size either way,
+// so calling with a null buffer or a capacity of 0 finds out how much to allocate
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_save_state(
+    emulator: *const GbEmulator,
+    buffer: *mut u8,
+    capacity: usize,
+) -> usize {
+    let state = unsafe { &*emulator }.emulator.save_state();
+    if !buffer.is_null() && state.len() <= capacity {
+        unsafe { slice::from_raw_parts_mut(buffer, state.len()) }.copy_from_slice(&state);
+    }
+    state.len()
+}
+
+// Returns false if the state is null, damaged or of another ROM, the emulator carries on as it was
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_load_state(
+    emulator: *mut GbEmulator,
+    state: *const u8,
+    length: usize,
+) -> bool {
+    if state.is_null() {
+        return false;
+    }
+    let emulator = unsafe { &mut *emulator };
+    let state = unsafe { slice::from_raw_parts(state, length) };
+    emulator.emulator.load_state(state).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::test_rom;
+    use std::ptr;
+
+    #[test]
+    fn should_run_through_the_c_api() {
+        let rom = test_rom();
+        unsafe {
+            let emulator = gb_create();
+            assert!(gb_load_rom(emulator, rom.as_ptr(), rom.len()));
+            gb_set_buttons(emulator, GB_BUTTON_START | GB_BUTTON_A);
+            gb_run_frame(emulator);
+            assert!(!gb_get_framebuffer(emulator).is_null());
+            gb_write(emulator, 0xC000, 0x42);
+            assert_eq!(gb_read(emulator, 0xC000), 0x42);
+            assert!(!gb_load_rom(emulator, ptr::null(), rom.len()));
+            let size = gb_save_state(emulator, ptr::null_mut(), 0);
+            assert_eq!(gb_save_state(emulator, ptr::null_mut(), size), size);
+            let mut state = vec![0; size];
+            assert_eq!(gb_save_state(emulator, state.as_mut_ptr(), size), size);
+            gb_set_buttons(emulator, GB_BUTTON_A);
+            gb_run_frame(emulator);
+            assert!(gb_load_state(emulator, state.as_ptr(), size));
+            assert!(!gb_load_state(emulator, state.as_ptr(), 3));
+            assert!(!gb_load_state(emulator, ptr::null(), size));
+            gb_destroy(emulator);
+        }
+    }
+}
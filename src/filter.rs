@@ -0,0 +1,254 @@
+use crate::gpu::{BYTES_PER_PIXEL, FRAME_HEIGHT, FRAME_WIDTH};
+
+// How much the grid lines and scanlines darken the pixels they cover, out of 256
+const LINE_BRIGHTNESS: u16 = 160;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Nearest,
+    // EPX/Scale2x, smooths diagonal edges while keeping the pixel art look
+    Scale2x,
+    // Every pixel becomes a 3x3 cell with darkened borders, like the gaps of the DMG's LCD
+    LcdGrid,
+    // Doubles the resolution and darkens every other line
+    Scanlines,
+}
+
+impl Filter {
+    pub const ALL: [Filter; 4] = [
+        Filter::Nearest,
+        Filter::Scale2x,
+        Filter::LcdGrid,
+        Filter::Scanlines,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Filter::Nearest => "nearest",
+            Filter::Scale2x => "scale2x",
+            Filter::LcdGrid => "lcd-grid",
+            Filter::Scanlines => "scanlines",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Filter> {
+        Self::ALL.into_iter().find(|filter| filter.name() == name)
+    }
+
+    pub fn next(&self) -> Filter {
+        let index = Self::ALL
+            .iter()
+            .position(|filter| filter == self)
+            .unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    pub fn scale(&self) -> usize {
+        match self {
+            Filter::Nearest => 1,
+            Filter::Scale2x | Filter::Scanlines => 2,
+            Filter::LcdGrid => 3,
+        }
+    }
+}
+
+// Sits between the GPU framebuffer and the screen: frames first go through the optional
+// ghosting stage, then through the selected spatial filter
+pub struct FilterPipeline {
+    pub filter: Filter,
+    // Blends every frame with the previous one, imitating the slow response time of the DMG LCD
+    pub ghosting: bool,
+    previous: Vec<u8>,
+    output: Vec<u8>,
+}
+
+impl FilterPipeline {
+    pub fn new(filter: Filter, ghosting: bool) -> Self {
+        Self {
+            filter,
+            ghosting,
+            previous: Vec::new(),
+            output: Vec::new(),
+        }
+    }
+
+    pub fn output_size(&self) -> (usize, usize) {
+        let scale = self.filter.scale();
+        (FRAME_WIDTH * scale, FRAME_HEIGHT * scale)
+    }
+
+    // Returns the filtered frame, output_size() pixels in the same format as the input
+    pub fn apply(&mut self, frame: &[u8]) -> &[u8] {
+        let source = if self.ghosting {
+            if self.previous.len() != frame.len() {
+                self.previous = frame.to_vec();
+            }
+            self.previous
+                .iter_mut()
+                .zip(frame)
+                .for_each(|(previous, current)| {
+                    *previous = ((*previous as u16 + *current as u16) / 2) as u8
+                });
+            &self.previous
+        } else {
+            self.previous.clear();
+            frame
+        };
+
+        let (width, height) = self.output_size();
+        self.output.resize(width * height * BYTES_PER_PIXEL, 0);
+        match self.filter {
+            Filter::Nearest => self.output.copy_from_slice(source),
+            Filter::Scale2x => Self::scale2x(source, &mut self.output),
+            Filter::LcdGrid => Self::lcd_grid(source, &mut self.output),
+            Filter::Scanlines => Self::scanlines(source, &mut self.output),
+        }
+        &self.output
+    }
+
+    fn pixel(frame: &[u8], x: usize, y: usize) -> [u8; BYTES_PER_PIXEL] {
+        let i = (y * FRAME_WIDTH + x) * BYTES_PER_PIXEL;
+        [frame[i], frame[i + 1], frame[i + 2]]
+    }
+
+    fn put(output: &mut [u8], width: usize, x: usize, y: usize, pixel: [u8; BYTES_PER_PIXEL]) {
+        let i = (y * width + x) * BYTES_PER_PIXEL;
+        output[i..i + BYTES_PER_PIXEL].copy_from_slice(&pixel);
+    }
+
+    fn darken(pixel: [u8; BYTES_PER_PIXEL]) -> [u8; BYTES_PER_PIXEL] {
+        pixel.map(|channel| (channel as u16 * LINE_BRIGHTNESS / 256) as u8)
+    }
+
+    fn scale2x(frame: &[u8], output: &mut [u8]) {
+        let width = FRAME_WIDTH * 2;
+        for y in 0..FRAME_HEIGHT {
+            for x in 0..FRAME_WIDTH {
+                let p = Self::pixel(frame, x, y);
+                let a = Self::pixel(frame, x, y.saturating_sub(1));
+                let b = Self::pixel(frame, (x + 1).min(FRAME_WIDTH - 1), y);
+                let c = Self::pixel(frame, x.saturating_sub(1), y);
+                let d = Self::pixel(frame, x, (y + 1).min(FRAME_HEIGHT - 1));
+                let (mut e0, mut e1, mut e2, mut e3) = (p, p, p, p);
+                if c == a && c != d && a != b {
+                    e0 = a;
+                }
+                if a == b && a != c && b != d {
+                    e1 = b;
+                }
+                if d == c && d != b && c != a {
+                    e2 = c;
+                }
+                if b == d && b != a && d != c {
+                    e3 = d;
+                }
+                Self::put(output, width, x * 2, y * 2, e0);
+                Self::put(output, width, x * 2 + 1, y * 2, e1);
+                Self::put(output, width, x * 2, y * 2 + 1, e2);
+                Self::put(output, width, x * 2 + 1, y * 2 + 1, e3);
+            }
+        }
+    }
+
+    fn lcd_grid(frame: &[u8], output: &mut [u8]) {
+        let width = FRAME_WIDTH * 3;
+        for y in 0..FRAME_HEIGHT * 3 {
+            for x in 0..width {
+                let pixel = Self::pixel(frame, x / 3, y / 3);
+                let on_grid = x % 3 == 2 || y % 3 == 2;
+                Self::put(
+                    output,
+                    width,
+                    x,
+                    y,
+                    if on_grid { Self::darken(pixel) } else { pixel },
+                );
+            }
+        }
+    }
+
+    fn scanlines(frame: &[u8], output: &mut [u8]) {
+        let width = FRAME_WIDTH * 2;
+        for y in 0..FRAME_HEIGHT * 2 {
+            for x in 0..width {
+                let pixel = Self::pixel(frame, x / 2, y / 2);
+                Self::put(
+                    output,
+                    width,
+                    x,
+                    y,
+                    if y % 2 == 1 {
+                        Self::darken(pixel)
+                    } else {
+                        pixel
+                    },
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpu::FRAME_SIZE;
+
+    fn frame_with_pixel(x: usize, y: usize, pixel: [u8; 3]) -> Vec<u8> {
+        let mut frame = vec![0; FRAME_SIZE];
+        let i = (y * FRAME_WIDTH + x) * BYTES_PER_PIXEL;
+        frame[i..i + 3].copy_from_slice(&pixel);
+        frame
+    }
+
+    #[test]
+    fn should_pass_frames_through_nearest() {
+        let frame = frame_with_pixel(5, 5, [0xFF; 3]);
+        let mut pipeline = FilterPipeline::new(Filter::Nearest, false);
+        assert_eq!(pipeline.output_size(), (FRAME_WIDTH, FRAME_HEIGHT));
+        assert_eq!(pipeline.apply(&frame), &frame[..]);
+    }
+
+    #[test]
+    fn should_blend_with_previous_frame_when_ghosting() {
+        let mut pipeline = FilterPipeline::new(Filter::Nearest, true);
+        pipeline.apply(&vec![0xFF; FRAME_SIZE]);
+        let output = pipeline.apply(&vec![0x00; FRAME_SIZE]);
+        assert_eq!(output[0], 0x7F);
+    }
+
+    #[test]
+    fn should_darken_lcd_grid_lines() {
+        let frame = frame_with_pixel(0, 0, [0xFF; 3]);
+        let mut pipeline = FilterPipeline::new(Filter::LcdGrid, false);
+        let output = pipeline.apply(&frame);
+        let width = FRAME_WIDTH * 3;
+        assert_eq!(output[0], 0xFF);
+        assert_eq!(output[2 * BYTES_PER_PIXEL], 0x9F);
+        assert_eq!(output[2 * width * BYTES_PER_PIXEL], 0x9F);
+        assert_eq!(output[3 * BYTES_PER_PIXEL], 0x00);
+    }
+
+    #[test]
+    fn should_smooth_diagonals_with_scale2x() {
+        // A lone pixel stays a 2x2 block, the corner of an L shape gets filled in
+        let mut frame = frame_with_pixel(1, 0, [0xFF; 3]);
+        let i = (FRAME_WIDTH + 2) * BYTES_PER_PIXEL;
+        frame[i..i + 3].copy_from_slice(&[0xFF; 3]);
+        let mut pipeline = FilterPipeline::new(Filter::Scale2x, false);
+        let output = pipeline.apply(&frame).to_vec();
+        let at = |x: usize, y: usize| output[(y * FRAME_WIDTH * 2 + x) * BYTES_PER_PIXEL];
+        assert_eq!(at(2, 0), 0xFF);
+        assert_eq!(at(3, 1), 0xFF);
+        // Pixel (2, 0) is black with white on its left and bottom: its bottom-left corner smooths
+        assert_eq!(at(4, 1), 0xFF);
+        assert_eq!(at(5, 0), 0x00);
+    }
+
+    #[test]
+    fn should_cycle_filters() {
+        assert_eq!(Filter::Nearest.next(), Filter::Scale2x);
+        assert_eq!(Filter::Scanlines.next(), Filter::Nearest);
+        assert_eq!(Filter::from_name("lcd-grid"), Some(Filter::LcdGrid));
+        assert_eq!(Filter::from_name("hq4x"), None);
+    }
+}
@@ -0,0 +1,58 @@
+use crate::cpu::FREQUENCY;
+use crate::gpu::CYCLES_PER_FRAME;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// 4_194_304 / 70_224 = ~59.7275 frames per second
+pub const FRAME_RATE: f64 = FREQUENCY as f64 / CYCLES_PER_FRAME as f64;
+// Sleeping is only accurate to about a millisecond, the rest of the wait is spent spinning
+const SPIN_THRESHOLD: Duration = Duration::from_millis(1);
+// Past this much lag (e.g. after the process was suspended) stop trying to catch up
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+pub struct FrameLimiter {
+    // Real time per frame at the current speed
+    frame_duration: Duration,
+    next_deadline: Instant,
+}
+
+impl FrameLimiter {
+    pub fn new() -> Self {
+        let frame_duration = Duration::from_secs_f64(1.0 / FRAME_RATE);
+        Self {
+            frame_duration,
+            next_deadline: Instant::now() + frame_duration,
+        }
+    }
+
+    // Multiple of the Game Boy's speed to run at
+    pub fn set_speed(&mut self, speed: f64) {
+        self.frame_duration = Duration::from_secs_f64(1.0 / (FRAME_RATE * speed));
+    }
+
+    // Blocks until the current frame's time slot is over
+    pub fn wait(&mut self) {
+        let now = Instant::now();
+        if now < self.next_deadline {
+            let remaining = self.next_deadline - now;
+            if remaining > SPIN_THRESHOLD {
+                thread::sleep(remaining - SPIN_THRESHOLD);
+            }
+            while Instant::now() < self.next_deadline {
+                std::hint::spin_loop();
+            }
+        }
+
+        self.next_deadline += self.frame_duration;
+        let now = Instant::now();
+        if now > self.next_deadline + MAX_LAG {
+            self.next_deadline = now + self.frame_duration;
+        }
+    }
+}
+
+impl Default for FrameLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,167 @@
+use crate::event::Event;
+use crate::joypad::Button;
+use sdl2::GameControllerSubsystem;
+use sdl2::controller::{Axis, Button as PadButton, GameController};
+use sdl2::event::Event as SdlEvent;
+
+// How far the left stick has to be pushed before it counts as a d-pad press
+const STICK_DEADZONE: i16 = 16_000;
+// Rumble is renewed every frame while it lasts, the motors stop on their own shortly after the
+// emulator stops sending it, like when it's paused or stuck
+const RUMBLE_DURATION_MS: u32 = 100;
+
+// Drives the joypad from one game controller at a time. SDL reports controllers that are already
+// connected as added at startup, so plugging in and starting with a pad go through the same path
+pub struct Gamepad {
+    subsystem: GameControllerSubsystem,
+    controller: Option<GameController>,
+    // Joystick index of the controller to use, the first one connected otherwise
+    choice: Option<u32>,
+    buttons: Vec<(PadButton, Button)>,
+    // Direction each stick axis currently holds down, horizontal then vertical
+    stick: [Option<Button>; 2],
+    rumbling: bool,
+}
+
+impl Gamepad {
+    pub fn new(
+        subsystem: GameControllerSubsystem,
+        choice: Option<u32>,
+        buttons: Vec<(PadButton, Button)>,
+    ) -> Self {
+        Self {
+            subsystem,
+            controller: None,
+            choice,
+            buttons,
+            stick: [None; 2],
+            rumbling: false,
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &SdlEvent) -> Vec<Event> {
+        match *event {
+            SdlEvent::ControllerDeviceAdded { which, .. } => {
+                self.connect(which);
+                Vec::new()
+            }
+            SdlEvent::ControllerDeviceRemoved { which, .. } if self.is_active(which) => {
+                if let Some(controller) = self.controller.take() {
+                    println!("Controller disconnected: {}", controller.name());
+                }
+                // Whatever the stick held is let go
+                self.stick
+                    .iter_mut()
+                    .filter_map(Option::take)
+                    .map(Event::ButtonUp)
+                    .collect()
+            }
+            SdlEvent::ControllerButtonDown { which, button, .. } if self.is_active(which) => self
+                .map_button(button)
+                .map(Event::ButtonDown)
+                .into_iter()
+                .collect(),
+            SdlEvent::ControllerButtonUp { which, button, .. } if self.is_active(which) => self
+                .map_button(button)
+                .map(Event::ButtonUp)
+                .into_iter()
+                .collect(),
+            SdlEvent::ControllerAxisMotion {
+                which, axis, value, ..
+            } if self.is_active(which) => match axis {
+                Axis::LeftX => {
+                    self.move_stick(0, stick_direction(value, Button::Left, Button::Right))
+                }
+                Axis::LeftY => self.move_stick(1, stick_direction(value, Button::Up, Button::Down)),
+                _ => Vec::new(),
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    // Both motors get the same strength, the cartridge only has one
+    pub fn set_rumble(&mut self, strength: f32) {
+        if strength == 0.0 && !self.rumbling {
+            return;
+        }
+        self.rumbling = strength > 0.0;
+        let level = (strength.clamp(0.0, 1.0) * u16::MAX as f32) as u16;
+        if let Some(controller) = &mut self.controller {
+            // Controllers without motors refuse, which is fine
+            let _ = controller.set_rumble(level, level, RUMBLE_DURATION_MS);
+        }
+    }
+
+    fn connect(&mut self, index: u32) {
+        if self.controller.is_some() || self.choice.is_some_and(|choice| choice != index) {
+            if let Ok(name) = self.subsystem.name_for_index(index) {
+                println!("Controller connected but not in use: {name} (index {index})");
+            }
+            return;
+        }
+        match self.subsystem.open(index) {
+            Ok(controller) => {
+                println!(
+                    "Controller connected: {} (index {index})",
+                    controller.name()
+                );
+                self.controller = Some(controller);
+            }
+            Err(error) => eprintln!("Couldn't open controller {index}: {error}"),
+        }
+    }
+
+    // Events carry the controller's instance id, which differs from the index it was opened with
+    fn is_active(&self, instance_id: u32) -> bool {
+        self.controller
+            .as_ref()
+            .is_some_and(|controller| controller.instance_id() == instance_id)
+    }
+
+    fn map_button(&self, pressed: PadButton) -> Option<Button> {
+        self.buttons
+            .iter()
+            .find(|(bound, _)| *bound == pressed)
+            .map(|(_, button)| *button)
+    }
+
+    fn move_stick(&mut self, axis: usize, direction: Option<Button>) -> Vec<Event> {
+        let previous = std::mem::replace(&mut self.stick[axis], direction);
+        if previous == direction {
+            return Vec::new();
+        }
+        previous
+            .map(Event::ButtonUp)
+            .into_iter()
+            .chain(direction.map(Event::ButtonDown))
+            .collect()
+    }
+}
+
+fn stick_direction(value: i16, negative: Button, positive: Button) -> Option<Button> {
+    if value < -STICK_DEADZONE {
+        Some(negative)
+    } else if value > STICK_DEADZONE {
+        Some(positive)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_map_the_stick_past_the_deadzone() {
+        assert_eq!(
+            stick_direction(-32_768, Button::Left, Button::Right),
+            Some(Button::Left)
+        );
+        assert_eq!(stick_direction(8_000, Button::Left, Button::Right), None);
+        assert_eq!(
+            stick_direction(32_767, Button::Up, Button::Down),
+            Some(Button::Down)
+        );
+    }
+}
@@ -1,77 +1,490 @@
-use sdl2::pixels::{Color, PixelFormatEnum};
-use sdl2::render::Canvas;
-use sdl2::render::{Texture, TextureCreator};
-use sdl2::video::{Window, WindowContext};
-
-const TILE_MAP_SIZE: u16 = 1024;
-const ORIGINAL_GB_DISPLAY_WIDTH: u32 = 160;
-const ORIGINAL_GB_DISPLAY_HEIGHT: u32 = 144;
-const SCALING_FACTOR: u32 = 7;
-// The display is 32x32 tiles. Each tile is 8x8 pixels, since we are using
-// PixelFormatEnum::RGB888, each pixel occupies 3 bytes, so the required memory for displaying all
-// tiles is: ((32 * 8) * (32 * 8)) * 3 = 196_608 bytes
-const DISPLAY_SIZE: u32 = 196_608;
-
-struct SdlUtils {
-    pub canvas: Canvas<Window>,
-    texture_creator: TextureCreator<WindowContext>,
+use crate::memory::{Interrupt, Memory};
+use crate::palette::{PALETTE_SIZE, Palette};
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+use crate::viewers::{Image, View};
+
+pub const FRAME_WIDTH: usize = 160;
+pub const FRAME_HEIGHT: usize = 144;
+pub const BYTES_PER_PIXEL: usize = 3;
+pub const FRAME_SIZE: usize = FRAME_WIDTH * FRAME_HEIGHT * BYTES_PER_PIXEL;
+pub const FRAME_PIXEL_FORMAT: PixelFormat = PixelFormat::Rgb24;
+pub const CYCLES_PER_FRAME: u32 = DOTS_PER_LINE * LINES_PER_FRAME as u32;
+
+const DOTS_PER_LINE: u32 = 456;
+const OAM_SCAN_DOTS: u32 = 80;
+const DRAWING_DOTS: u32 = 172;
+const LINES_PER_FRAME: u8 = 154;
+
+pub const LCDC: u16 = 0xFF40;
+const STAT: u16 = 0xFF41;
+pub const SCY: u16 = 0xFF42;
+pub const SCX: u16 = 0xFF43;
+pub const LY: u16 = 0xFF44;
+const LYC: u16 = 0xFF45;
+pub const BGP: u16 = 0xFF47;
+pub const OBP0: u16 = 0xFF48;
+pub const OBP1: u16 = 0xFF49;
+pub const WY: u16 = 0xFF4A;
+pub const WX: u16 = 0xFF4B;
+// The LCD registers in address order, for inspecting them
+pub const REGISTERS: [(&str, u16); 8] = [
+    ("LCDC", LCDC),
+    ("STAT", STAT),
+    ("SCY", SCY),
+    ("SCX", SCX),
+    ("LY", LY),
+    ("LYC", LYC),
+    ("WY", WY),
+    ("WX", WX),
+];
+pub const TILE_DATA_UNSIGNED: u16 = 0x8000;
+const TILE_DATA_SIGNED: u16 = 0x9000;
+pub const BG_MAP_LOW: u16 = 0x9800;
+pub const BG_MAP_HIGH: u16 = 0x9C00;
+pub const TILE_SIZE: u16 = 16;
+pub const OAM: u16 = 0xFE00;
+
+// Layout of the bytes returned by GPU::frame. Rgb24 stores one byte per channel in R, G, B
+// order, row by row, without any padding between rows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PixelFormat {
+    Rgb24,
 }
 
-impl SdlUtils {
-    pub fn new() -> Self {
-        let sdl_context = sdl2::init().unwrap();
-        let title = "GameBoy Emulator".to_string();
-        let video_subsystem = sdl_context.video().unwrap();
-        let window = video_subsystem
-            .window(
-                title.as_str(),
-                ORIGINAL_GB_DISPLAY_WIDTH * SCALING_FACTOR,
-                ORIGINAL_GB_DISPLAY_HEIGHT * SCALING_FACTOR,
-            )
-            .position_centered()
-            .build()
-            .unwrap();
-        let mut canvas = window.into_canvas().build().unwrap();
-        let _ = canvas.set_logical_size(ORIGINAL_GB_DISPLAY_WIDTH, ORIGINAL_GB_DISPLAY_HEIGHT);
-        let texture_creator = canvas.texture_creator();
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    HBlank = 0,
+    VBlank = 1,
+    OamScan = 2,
+    Drawing = 3,
+}
 
-        Self {
-            canvas,
-            texture_creator,
-        }
+// Presentation backend. The GPU only produces frames, a Renderer is what puts them on a screen
+pub trait Renderer {
+    fn present(&mut self, frame: &[u8]);
+
+    // Debug views currently on screen, the emulator renders them after every presented frame
+    fn open_views(&self) -> Vec<View> {
+        Vec::new()
     }
-}
 
-pub trait Drawable {
-    fn draw(&mut self);
+    fn present_view(&mut self, _view: View, _image: &Image) {}
+
+    // Lets the frontend show that emulation is paused, the last frame stays on screen
+    fn set_paused(&mut self, _paused: bool) {}
+
+    // Shows the game's name and how it's running, for frontends with a title bar
+    fn set_title(&mut self, _title: &str) {}
+
+    // Briefly shows a message over the picture, like a state being saved
+    fn show_message(&mut self, _message: &str) {}
 }
 
 pub struct GPU {
-    sdl_utils: SdlUtils,
-    tile_map: [u8; TILE_MAP_SIZE as usize],
-    display: [u8; DISPLAY_SIZE as usize],
+    framebuffer: [u8; FRAME_SIZE],
+    frame_ready: bool,
+    skip_rendering: bool,
+    palettes: Vec<Palette>,
+    palette: usize,
+    mode: Mode,
+    ly: u8,
+    dot: u32,
 }
 
 impl GPU {
     pub fn new() -> Self {
         Self {
-            sdl_utils: SdlUtils::new(),
-            tile_map: [0; TILE_MAP_SIZE as usize],
-            display: [0xFF; DISPLAY_SIZE as usize],
+            framebuffer: [0xFF; FRAME_SIZE],
+            frame_ready: false,
+            skip_rendering: false,
+            palettes: Palette::presets(),
+            palette: 0,
+            mode: Mode::OamScan,
+            ly: 0,
+            dot: 0,
+        }
+    }
+
+    // Back to power-on, palettes stay
+    pub fn reset(&mut self) {
+        *self = Self {
+            palettes: std::mem::take(&mut self.palettes),
+            palette: self.palette,
+            skip_rendering: self.skip_rendering,
+            ..Self::new()
+        };
+    }
+
+    // Last completed frame, FRAME_WIDTH * FRAME_HEIGHT pixels in FRAME_PIXEL_FORMAT
+    pub fn frame(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    pub fn frame_ready(&self) -> bool {
+        self.frame_ready
+    }
+
+    // Frontends call this once they consumed the frame so the flag only reports new frames
+    pub fn clear_frame_ready(&mut self) {
+        self.frame_ready = false;
+    }
+
+    // Skipped frames keep the PPU timing and interrupts but leave the framebuffer untouched
+    pub fn set_skip_rendering(&mut self, skip: bool) {
+        self.skip_rendering = skip;
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palettes[self.palette]
+    }
+
+    pub fn select_palette(&mut self, name: &str) -> Result<(), String> {
+        match self
+            .palettes
+            .iter()
+            .position(|palette| palette.name == name)
+        {
+            Some(index) => {
+                self.palette = index;
+                Ok(())
+            }
+            None => Err(format!("Unknown palette: {name}")),
+        }
+    }
+
+    // Custom palettes join the presets in the hotkey cycle
+    pub fn add_palette(&mut self, palette: Palette) {
+        self.palettes.push(palette);
+    }
+
+    pub fn cycle_palette(&mut self) -> &Palette {
+        self.palette = (self.palette + 1) % self.palettes.len();
+        self.palette()
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    // How far into the current scanline the PPU is, 0 to 455
+    pub fn dot(&self) -> u32 {
+        self.dot
+    }
+
+    // The STAT interrupt sources that are enabled and whose condition holds right now. A source
+    // only requests the interrupt as its condition starts holding, so these are what's keeping
+    // the next one from being requested
+    pub fn stat_sources(&self, memory: &Memory) -> Vec<&'static str> {
+        let lcdc = memory.memory[LCDC as usize];
+        let stat = memory.memory[STAT as usize];
+        if lcdc & 0b1000_0000 == 0 {
+            return Vec::new();
+        }
+        let sources = [
+            ("LYC=LY", 0b0100_0000, stat & 0b0000_0100 != 0),
+            ("OAM scan", 0b0010_0000, self.mode == Mode::OamScan),
+            ("VBlank", 0b0001_0000, self.mode == Mode::VBlank),
+            ("HBlank", 0b0000_1000, self.mode == Mode::HBlank),
+        ];
+        sources
+            .into_iter()
+            .filter(|(_, enabled, holds)| stat & enabled != 0 && *holds)
+            .map(|(name, _, _)| name)
+            .collect()
+    }
+
+    // Advances the PPU by the given number of dots (one dot per CPU clock cycle). A frame becomes
+    // ready when the PPU enters VBlank, with the LCD off it still completes blank frames so the
+    // frontends keep their cadence.
+    pub fn step(&mut self, memory: &mut Memory, cycles: u32) {
+        let lcd_enabled = memory.memory[LCDC as usize] & 0b1000_0000 != 0;
+        for _ in 0..cycles {
+            self.dot += 1;
+            if self.dot == DOTS_PER_LINE {
+                self.dot = 0;
+                self.ly = (self.ly + 1) % LINES_PER_FRAME;
+            }
+
+            let mode = if self.ly >= FRAME_HEIGHT as u8 {
+                Mode::VBlank
+            } else if self.dot < OAM_SCAN_DOTS {
+                Mode::OamScan
+            } else if self.dot < OAM_SCAN_DOTS + DRAWING_DOTS {
+                Mode::Drawing
+            } else {
+                Mode::HBlank
+            };
+            if mode != self.mode {
+                self.enter_mode(memory, mode, lcd_enabled);
+            }
+            if self.dot == 0 && lcd_enabled {
+                self.compare_ly(memory);
+            }
+        }
+
+        if lcd_enabled {
+            memory.memory[LY as usize] = self.ly;
+            let stat = memory.memory[STAT as usize] & 0b1111_1100;
+            memory.memory[STAT as usize] = stat | self.mode as u8;
+        } else {
+            memory.memory[LY as usize] = 0;
+            memory.memory[STAT as usize] &= 0b1111_1100;
+        }
+    }
+
+    fn enter_mode(&mut self, memory: &mut Memory, mode: Mode, lcd_enabled: bool) {
+        self.mode = mode;
+        if !lcd_enabled {
+            if mode == Mode::VBlank {
+                let blank = self.palette().colors[0];
+                self.framebuffer
+                    .chunks_mut(BYTES_PER_PIXEL)
+                    .for_each(|pixel| pixel.copy_from_slice(&blank));
+                self.frame_ready = true;
+            }
+            return;
+        }
+
+        let stat = memory.memory[STAT as usize];
+        let stat_source = match mode {
+            Mode::HBlank => {
+                if !self.skip_rendering {
+                    self.render_scanline(memory, self.ly);
+                }
+                stat & 0b0000_1000 != 0
+            }
+            Mode::VBlank => {
+                self.frame_ready = true;
+                memory.request_interrupt(Interrupt::VBlank);
+                stat & 0b0001_0000 != 0
+            }
+            Mode::OamScan => stat & 0b0010_0000 != 0,
+            Mode::Drawing => false,
+        };
+        if stat_source {
+            memory.request_interrupt(Interrupt::Stat);
+        }
+    }
+
+    fn compare_ly(&mut self, memory: &mut Memory) {
+        let stat = memory.memory[STAT as usize];
+        if self.ly == memory.memory[LYC as usize] {
+            memory.memory[STAT as usize] = stat | 0b0000_0100;
+            if stat & 0b0100_0000 != 0 {
+                memory.request_interrupt(Interrupt::Stat);
+            }
+        } else {
+            memory.memory[STAT as usize] = stat & 0b1111_1011;
         }
     }
+
+    fn render_scanline(&mut self, memory: &Memory, ly: u8) {
+        let lcdc = memory.memory[LCDC as usize];
+        let bgp = memory.memory[BGP as usize];
+        let colors = self.palettes[self.palette].colors;
+        let row_start = ly as usize * FRAME_WIDTH * BYTES_PER_PIXEL;
+        let row = &mut self.framebuffer[row_start..row_start + FRAME_WIDTH * BYTES_PER_PIXEL];
+
+        if lcdc & 0b0000_0001 == 0 {
+            let blank = Self::map_tile_pixels(0, 0, bgp, &colors)[0];
+            row.chunks_mut(BYTES_PER_PIXEL)
+                .for_each(|pixel| pixel.copy_from_slice(&blank));
+            return;
+        }
+
+        let scy = memory.memory[SCY as usize];
+        let scx = memory.memory[SCX as usize];
+        let y = ly.wrapping_add(scy);
+        let map = if lcdc & 0b0000_1000 != 0 {
+            BG_MAP_HIGH
+        } else {
+            BG_MAP_LOW
+        };
+
+        for x in 0..FRAME_WIDTH {
+            let bg_x = (x as u8).wrapping_add(scx);
+            let map_addr = map + (y as u16 / 8) * 32 + bg_x as u16 / 8;
+            let tile_index = memory.memory[map_addr as usize];
+            let line_addr = Self::tile_address(lcdc, tile_index) + (y as u16 % 8) * 2;
+            let low = memory.memory[line_addr as usize];
+            let high = memory.memory[(line_addr + 1) as usize];
+            let pixel = Self::map_tile_pixels(low, high, bgp, &colors)[(bg_x % 8) as usize];
+            row[x * BYTES_PER_PIXEL..(x + 1) * BYTES_PER_PIXEL].copy_from_slice(&pixel);
+        }
+    }
+
+    pub fn tile_address(lcdc: u8, tile_index: u8) -> u16 {
+        if lcdc & 0b0001_0000 != 0 {
+            TILE_DATA_UNSIGNED + tile_index as u16 * TILE_SIZE
+        } else {
+            TILE_DATA_SIGNED.wrapping_add_signed(tile_index as i8 as i16 * TILE_SIZE as i16)
+        }
+    }
+
+    // Decodes one 2bpp tile row into RGB pixels, leftmost pixel first
+    pub fn map_tile_pixels(
+        low: u8,
+        high: u8,
+        palette: u8,
+        colors: &[[u8; BYTES_PER_PIXEL]; PALETTE_SIZE],
+    ) -> [[u8; BYTES_PER_PIXEL]; 8] {
+        let mut pixels = [[0; BYTES_PER_PIXEL]; 8];
+        for (i, pixel) in pixels.iter_mut().enumerate() {
+            let bit = 7 - i;
+            let color_id = (((high >> bit) & 1) << 1) | ((low >> bit) & 1);
+            let shade = (palette >> (color_id * 2)) & 0b11;
+            *pixel = colors[shade as usize];
+        }
+        pixels
+    }
+}
+
+// The framebuffer is saved too so the screen shows the state's frame right away, even when paused
+impl Snapshot for GPU {
+    fn save(&self, state: &mut StateWriter) {
+        state.bytes(&self.framebuffer);
+        state.u8(self.mode as u8);
+        state.u8(self.ly);
+        state.u32(self.dot);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), String> {
+        state.bytes(&mut self.framebuffer)?;
+        self.mode = match state.u8()? {
+            0 => Mode::HBlank,
+            1 => Mode::VBlank,
+            2 => Mode::OamScan,
+            3 => Mode::Drawing,
+            mode => return Err(format!("Invalid PPU mode {mode} in save state")),
+        };
+        self.ly = state.u8()? % LINES_PER_FRAME;
+        self.dot = state.u32()? % DOTS_PER_LINE;
+        self.frame_ready = false;
+        Ok(())
+    }
+}
+
+impl Default for GPU {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl Drawable for GPU {
-    // TODO (luizf): Don't create texture in every call to this function
-    fn draw(&mut self) {
-        let mut texture = self
-            .sdl_utils
-            .texture_creator
-            .create_texture_streaming(PixelFormatEnum::RGB888, 256, 256)
-            .expect("Couldn't create texture");
-        let _ = texture.update(None, &self.display, ORIGINAL_GB_DISPLAY_WIDTH as usize);
-        let _ = self.sdl_utils.canvas.copy(&texture, None, None);
-        self.sdl_utils.canvas.present();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLORS: [[u8; 3]; 4] = [[0; 3], [1; 3], [2; 3], [3; 3]];
+
+    #[test]
+    fn should_decode_tile_row_through_palette() {
+        // Identity palette: color id n maps to shade n
+        let pixels = GPU::map_tile_pixels(0b1010_0000, 0b1100_0000, 0b1110_0100, &COLORS);
+        assert_eq!(pixels[0], [3; 3]);
+        assert_eq!(pixels[1], [2; 3]);
+        assert_eq!(pixels[2], [1; 3]);
+        assert_eq!(pixels[3], [0; 3]);
+
+        // Inverted palette
+        let pixels = GPU::map_tile_pixels(0b1010_0000, 0b1100_0000, 0b0001_1011, &COLORS);
+        assert_eq!(pixels[0], [0; 3]);
+        assert_eq!(pixels[3], [3; 3]);
+    }
+
+    #[test]
+    fn should_select_and_cycle_palettes() {
+        let mut gpu = GPU::new();
+        assert_eq!(gpu.palette().name, "green");
+        assert_eq!(gpu.cycle_palette().name, "pocket");
+        assert!(gpu.select_palette("high-contrast").is_ok());
+        assert_eq!(gpu.cycle_palette().name, "green");
+        assert!(gpu.select_palette("sepia").is_err());
+    }
+
+    #[test]
+    fn should_resolve_signed_and_unsigned_tile_addresses() {
+        assert_eq!(GPU::tile_address(0b0001_0000, 0x01), 0x8010);
+        assert_eq!(GPU::tile_address(0b0001_0000, 0xFF), 0x8FF0);
+        assert_eq!(GPU::tile_address(0b0000_0000, 0x01), 0x9010);
+        assert_eq!(GPU::tile_address(0b0000_0000, 0xFF), 0x8FF0);
+    }
+
+    #[test]
+    fn should_render_background_tile_into_frame() {
+        let mut memory = Memory::new();
+        let mut gpu = GPU::new();
+        memory.memory[LCDC as usize] = 0b1001_0001;
+        memory.memory[BGP as usize] = 0b1110_0100;
+        // Tile 1 has a fully dark first row, map entry (0, 0) points to it
+        memory.memory[0x8010] = 0xFF;
+        memory.memory[0x8011] = 0xFF;
+        memory.memory[BG_MAP_LOW as usize] = 0x01;
+
+        gpu.step(&mut memory, DOTS_PER_LINE * FRAME_HEIGHT as u32);
+
+        let dark = gpu.palette().colors[3];
+        let light = gpu.palette().colors[0];
+        assert!(gpu.frame_ready());
+        assert_eq!(gpu.frame()[0..3], dark);
+        assert_eq!(gpu.frame()[7 * 3..8 * 3], dark);
+        assert_eq!(gpu.frame()[8 * 3..9 * 3], light);
+        assert_eq!(gpu.frame()[FRAME_WIDTH * 3..FRAME_WIDTH * 3 + 3], light);
+    }
+
+    #[test]
+    fn should_enter_vblank_after_visible_lines() {
+        let mut memory = Memory::new();
+        let mut gpu = GPU::new();
+        memory.memory[LCDC as usize] = 0b1000_0000;
+
+        gpu.step(&mut memory, DOTS_PER_LINE * FRAME_HEIGHT as u32 - 1);
+        assert_eq!(gpu.mode(), Mode::HBlank);
+        assert!(!gpu.frame_ready());
+
+        gpu.step(&mut memory, 1);
+        assert_eq!(gpu.mode(), Mode::VBlank);
+        assert_eq!(memory.memory[LY as usize], 144);
+        assert_eq!(memory.memory[STAT as usize] & 0b11, Mode::VBlank as u8);
+        assert_eq!(memory.memory[0xFF0F] & 0b1, 1);
+        assert!(gpu.frame_ready());
+
+        gpu.step(&mut memory, DOTS_PER_LINE * 10);
+        assert_eq!(gpu.mode(), Mode::OamScan);
+        assert_eq!(memory.memory[LY as usize], 0);
+    }
+
+    #[test]
+    fn should_list_enabled_stat_sources_that_hold() {
+        let mut memory = Memory::new();
+        let mut gpu = GPU::new();
+        memory.memory[LCDC as usize] = 0b1000_0000;
+        memory.memory[STAT as usize] = 0b0100_1000;
+        memory.memory[LYC as usize] = 1;
+
+        gpu.step(&mut memory, OAM_SCAN_DOTS + DRAWING_DOTS + 4);
+        assert_eq!(gpu.dot(), OAM_SCAN_DOTS + DRAWING_DOTS + 4);
+        assert_eq!(gpu.stat_sources(&memory), ["HBlank"]);
+
+        gpu.step(&mut memory, DOTS_PER_LINE);
+        assert_eq!(gpu.stat_sources(&memory), ["LYC=LY", "HBlank"]);
+        memory.memory[LCDC as usize] = 0;
+        assert!(gpu.stat_sources(&memory).is_empty());
+    }
+
+    #[test]
+    fn should_set_coincidence_flag_when_ly_matches_lyc() {
+        let mut memory = Memory::new();
+        let mut gpu = GPU::new();
+        memory.memory[LCDC as usize] = 0b1000_0000;
+        memory.memory[LYC as usize] = 2;
+        memory.memory[STAT as usize] = 0b0100_0000;
+
+        gpu.step(&mut memory, DOTS_PER_LINE * 2);
+        assert_eq!(memory.memory[STAT as usize] & 0b100, 0b100);
+        assert_eq!(memory.memory[0xFF0F] & 0b10, 0b10);
+
+        gpu.step(&mut memory, DOTS_PER_LINE);
+        assert_eq!(memory.memory[STAT as usize] & 0b100, 0);
     }
 }
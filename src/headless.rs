@@ -0,0 +1,30 @@
+use crate::audio::AudioSink;
+use crate::event::{Event, EventSource};
+use crate::gpu::Renderer;
+use std::time::Duration;
+
+// Frontend that shows nothing, plays nothing and never produces input, for running without a display (CI,
+// servers, tests). The GPU still renders so screenshots and captures keep working
+pub struct NullRenderer;
+
+impl Renderer for NullRenderer {
+    fn present(&mut self, _frame: &[u8]) {}
+}
+
+impl EventSource for NullRenderer {
+    fn poll_events(&mut self) -> Vec<Event> {
+        Vec::new()
+    }
+}
+
+impl AudioSink for NullRenderer {
+    fn sample_rate(&self) -> Option<u32> {
+        None
+    }
+
+    fn queue_audio(&mut self, _samples: &[f32]) {}
+
+    fn queued_audio(&self) -> Option<Duration> {
+        None
+    }
+}
@@ -0,0 +1,74 @@
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+pub const RP: u16 = 0xFF56;
+
+const LED: u8 = 0b0000_0001;
+const READ_ENABLE: u8 = 0b1100_0000;
+
+// RP, the Game Boy Color's infrared port. Bit 0 drives the LED, bit 1 reads the sensor (0 when it
+// sees light) once both read enable bits are set. With no other device around the sensor stays
+// dark, which is what games probing for one expect to find
+pub struct Infrared {
+    led: bool,
+    read_enable: u8,
+}
+
+impl Infrared {
+    pub fn new() -> Self {
+        Self {
+            led: false,
+            read_enable: 0,
+        }
+    }
+
+    // Light is whether something shines on the sensor, like the LED of a linked emulator
+    pub fn read(&self, light: bool) -> u8 {
+        let reading = self.read_enable == READ_ENABLE;
+        let dark = !(reading && light);
+        0b0011_1100 | self.read_enable | (dark as u8) << 1 | self.led as u8
+    }
+
+    // Returns the LED's new state when it changed
+    pub fn write(&mut self, value: u8) -> Option<bool> {
+        self.read_enable = value & READ_ENABLE;
+        let led = value & LED != 0;
+        let changed = led != self.led;
+        self.led = led;
+        changed.then_some(led)
+    }
+}
+
+impl Snapshot for Infrared {
+    fn save(&self, state: &mut StateWriter) {
+        state.bool(self.led);
+        state.u8(self.read_enable);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.led = state.bool()?;
+        self.read_enable = state.u8()? & READ_ENABLE;
+        Ok(())
+    }
+}
+
+impl Default for Infrared {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_only_see_light_with_reading_enabled() {
+        let mut infrared = Infrared::new();
+        assert_eq!(infrared.read(true), 0x3E);
+        assert_eq!(infrared.write(0xC1), Some(true));
+        assert_eq!(infrared.read(false), 0xFF);
+        assert_eq!(infrared.read(true), 0xFD);
+        assert_eq!(infrared.write(0xC1), None);
+        assert_eq!(infrared.write(0x00), Some(false));
+    }
+}
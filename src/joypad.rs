@@ -0,0 +1,189 @@
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+pub const JOYP: u16 = 0xFF00;
+
+const SELECT_DIRECTIONS: u8 = 0b0001_0000;
+const SELECT_BUTTONS: u8 = 0b0010_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    // Line in the P1 matrix, the low nibble of JOYP when its row is selected
+    fn bit(&self) -> u8 {
+        match self {
+            Button::Right | Button::A => 0b0001,
+            Button::Left | Button::B => 0b0010,
+            Button::Up | Button::Select => 0b0100,
+            Button::Down | Button::Start => 0b1000,
+        }
+    }
+
+    // Bit in Joypad::state
+    pub fn state_bit(&self) -> u8 {
+        if self.is_direction() {
+            self.bit()
+        } else {
+            self.bit() << 4
+        }
+    }
+
+    fn is_direction(&self) -> bool {
+        matches!(
+            self,
+            Button::Right | Button::Left | Button::Up | Button::Down
+        )
+    }
+}
+
+// P1, the 2x4 button matrix. The game selects a row with bits 4 and 5 and reads its lines in the
+// low nibble. Everything is active low, pressed buttons read as 0
+pub struct Joypad {
+    select: u8,
+    // Pressed lines of each row, active high
+    directions: u8,
+    buttons: u8,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        // Both rows start selected, JOYP reads 0xCF
+        Self {
+            select: 0,
+            directions: 0,
+            buttons: 0,
+        }
+    }
+
+    // With both rows selected their lines are ANDed together, with none every line reads high
+    pub fn read(&self) -> u8 {
+        let mut pressed = 0;
+        if self.select & SELECT_DIRECTIONS == 0 {
+            pressed |= self.directions;
+        }
+        if self.select & SELECT_BUTTONS == 0 {
+            pressed |= self.buttons;
+        }
+        0b1100_0000 | self.select | (!pressed & 0x0F)
+    }
+
+    // Only the row selection bits are writable. Selecting a row with a button held down pulls
+    // its line low just like pressing it, returns whether a line fell
+    pub fn write(&mut self, value: u8) -> bool {
+        let before = self.read();
+        self.select = value & (SELECT_DIRECTIONS | SELECT_BUTTONS);
+        Self::fell(before, self.read())
+    }
+
+    // Returns whether a line fell, which is what requests the joypad interrupt
+    pub fn press(&mut self, button: Button) -> bool {
+        let before = self.read();
+        *self.row(button) |= button.bit();
+        Self::fell(before, self.read())
+    }
+
+    pub fn release(&mut self, button: Button) {
+        *self.row(button) &= !button.bit();
+    }
+
+    // Every pressed button, directions in the low nibble and the others in the high one, active
+    // high
+    pub fn state(&self) -> u8 {
+        self.directions | self.buttons << 4
+    }
+
+    // Returns whether a line fell, like press
+    pub fn set_state(&mut self, state: u8) -> bool {
+        let before = self.read();
+        self.directions = state & 0x0F;
+        self.buttons = state >> 4;
+        Self::fell(before, self.read())
+    }
+
+    fn fell(before: u8, after: u8) -> bool {
+        before & !after & 0x0F != 0
+    }
+
+    fn row(&mut self, button: Button) -> &mut u8 {
+        if button.is_direction() {
+            &mut self.directions
+        } else {
+            &mut self.buttons
+        }
+    }
+}
+
+// Which buttons are held is live input, loading a state leaves them alone
+impl Snapshot for Joypad {
+    fn save(&self, state: &mut StateWriter) {
+        state.u8(self.select);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.select = state.u8()? & (SELECT_DIRECTIONS | SELECT_BUTTONS);
+        Ok(())
+    }
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_read_the_selected_row_active_low() {
+        let mut joypad = Joypad::new();
+        assert_eq!(joypad.read(), 0xCF);
+        joypad.press(Button::Down);
+        joypad.press(Button::A);
+        joypad.write(0x30);
+        assert_eq!(joypad.read(), 0xFF);
+        joypad.write(0x20);
+        assert_eq!(joypad.read(), 0b1110_0111);
+        joypad.write(0x10);
+        assert_eq!(joypad.read(), 0b1101_1110);
+        joypad.write(0x00);
+        assert_eq!(joypad.read(), 0b1100_0110);
+        joypad.release(Button::Down);
+        assert_eq!(joypad.read(), 0b1100_1110);
+    }
+
+    #[test]
+    fn should_report_falling_lines_of_selected_rows_only() {
+        let mut joypad = Joypad::new();
+        joypad.write(0x20);
+        assert!(!joypad.press(Button::Start));
+        assert!(joypad.press(Button::Left));
+        // Already low through Left's row, B shares the line
+        joypad.write(0x00);
+        assert!(!joypad.press(Button::B));
+        joypad.write(0x30);
+        assert!(joypad.write(0x10));
+    }
+
+    #[test]
+    fn should_round_trip_state() {
+        let mut joypad = Joypad::new();
+        joypad.press(Button::Up);
+        joypad.press(Button::Start);
+        assert_eq!(joypad.state(), 0b1000_0100);
+        let mut copy = Joypad::new();
+        assert!(copy.set_state(joypad.state()));
+        assert_eq!(copy.read(), joypad.read());
+        assert!(!copy.set_state(0));
+    }
+}
@@ -0,0 +1,75 @@
+use crate::paths;
+use std::path::{Path, PathBuf};
+
+// Where a game's save states, screenshots and captures are written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    // Next to the ROM, so games and what was made with them can be carried around together
+    Portable,
+    // In the user's data directory, one directory per game named after its title and ROM hash
+    Managed,
+}
+
+impl Layout {
+    pub const ALL: [Layout; 2] = [Layout::Portable, Layout::Managed];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Layout::Portable => "portable",
+            Layout::Managed => "managed",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Layout> {
+        Self::ALL.into_iter().find(|layout| layout.name() == name)
+    }
+
+    // None when there's no data directory to manage games in. The title tells games apart at a
+    // glance and the hash keeps revisions and hacks of the same game apart
+    pub fn game_dir(&self, rom: &Path, title: Option<&str>, hash: u64) -> Option<PathBuf> {
+        match self {
+            Layout::Portable => Some(rom.parent().unwrap_or(Path::new("")).to_path_buf()),
+            Layout::Managed => {
+                let name = match title {
+                    Some(title) => title.to_string(),
+                    None => rom.file_stem()?.to_string_lossy().into_owned(),
+                };
+                let name: String = name
+                    .chars()
+                    .map(|character| match character {
+                        'a'..='z' | 'A'..='Z' | '0'..='9' | ' ' | '-' | '_' => character,
+                        _ => '_',
+                    })
+                    .collect();
+                let dir = format!("{name}-{:08x}", hash as u32);
+                paths::data_dir().map(|data| data.join("games").join(dir))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_keep_portable_files_next_to_the_rom() {
+        let dir = Layout::Portable.game_dir(Path::new("roms/game.gb"), Some("GAME"), 1);
+        assert_eq!(dir, Some(PathBuf::from("roms")));
+        assert_eq!(
+            Layout::Portable.game_dir(Path::new("game.gb"), None, 1),
+            Some(PathBuf::new())
+        );
+    }
+
+    #[test]
+    fn should_name_managed_directories_after_the_game() {
+        let Some(dir) = Layout::Managed.game_dir(Path::new("roms/game.gb"), Some("A/B"), 0xAB)
+        else {
+            return;
+        };
+        assert_eq!(dir.file_name().unwrap(), "A_B-000000ab");
+        let dir = Layout::Managed.game_dir(Path::new("roms/game.gb"), None, 0xAB);
+        assert_eq!(dir.unwrap().file_name().unwrap(), "game-000000ab");
+    }
+}
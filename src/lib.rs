@@ -0,0 +1,67 @@
+#[cfg(feature = "debug")]
+#[macro_use]
+extern crate prettytable;
+
+pub mod apu;
+pub mod audio;
+pub mod batch;
+pub mod bindings;
+pub mod capture;
+pub mod config;
+pub mod coverage;
+pub mod cpu;
+pub mod debugger;
+pub mod disassembler;
+pub mod display;
+pub mod dual;
+#[cfg(feature = "frontend-egui")]
+pub mod egui_frontend;
+pub mod emulator;
+pub mod event;
+pub mod expression;
+pub mod ffi;
+pub mod filter;
+pub mod frame_limiter;
+#[cfg(feature = "frontend-sdl")]
+pub mod gamepad;
+pub mod gpu;
+pub mod headless;
+pub mod infrared;
+pub mod joypad;
+pub mod layout;
+pub mod link;
+pub mod memory;
+pub mod movie;
+pub mod osd;
+pub mod palette;
+pub mod paths;
+pub mod profiler;
+#[cfg(feature = "debug")]
+pub mod prompt;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod recent;
+#[cfg(all(feature = "debug", unix))]
+pub mod remote;
+pub mod rewind;
+pub mod rumble;
+pub mod savestate;
+pub mod screenshot;
+#[cfg(feature = "debug")]
+pub mod script;
+#[cfg(feature = "frontend-sdl")]
+pub mod sdl;
+pub mod serial;
+pub mod stats;
+pub mod symbols;
+pub mod time_stretch;
+pub mod timer;
+#[cfg(feature = "debug")]
+pub mod tui;
+pub mod viewers;
+pub mod wav;
+#[cfg(feature = "web")]
+pub mod web;
+
+pub use emulator::Emulator;
+pub use joypad::Button;
@@ -0,0 +1,183 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+
+// What goes over a link cable between two serial ports. The side on the internal clock drives
+// the transfer and sends its byte, the other side answers with its own
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Packet {
+    Transfer(u8),
+    Reply(u8),
+    // The infrared LED turned on or off, see --infrared-link
+    Infrared(bool),
+}
+
+impl Packet {
+    fn encode(&self) -> [u8; 2] {
+        match *self {
+            Packet::Transfer(byte) => [0, byte],
+            Packet::Reply(byte) => [1, byte],
+            Packet::Infrared(on) => [2, on as u8],
+        }
+    }
+
+    fn decode(bytes: [u8; 2]) -> io::Result<Packet> {
+        match bytes[0] {
+            0 => Ok(Packet::Transfer(bytes[1])),
+            1 => Ok(Packet::Reply(bytes[1])),
+            2 => Ok(Packet::Infrared(bytes[1] != 0)),
+            kind => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Unknown link packet {kind}"),
+            )),
+        }
+    }
+}
+
+// The other end of the cable. Receiving never blocks, so neither emulator waits on the other
+// except for a transfer's reply
+pub trait Link {
+    fn send(&mut self, packet: Packet) -> io::Result<()>;
+    fn receive(&mut self) -> io::Result<Option<Packet>>;
+}
+
+// Link cable over TCP, for playing against another machine
+pub struct TcpLink {
+    stream: TcpStream,
+    // A packet split across reads
+    partial: Vec<u8>,
+}
+
+impl TcpLink {
+    // Waits for the other emulator to connect
+    pub fn listen(address: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        println!("Waiting for the other player on {}", listener.local_addr()?);
+        let (stream, peer) = listener.accept()?;
+        println!("Link cable connected to {peer}");
+        Self::new(stream)
+    }
+
+    pub fn connect(address: &str) -> io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        println!("Link cable connected to {address}");
+        Self::new(stream)
+    }
+
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        // Transfers are a couple of bytes each and the sender waits on the reply
+        stream.set_nodelay(true)?;
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            stream,
+            partial: Vec::new(),
+        })
+    }
+}
+
+impl Link for TcpLink {
+    fn send(&mut self, packet: Packet) -> io::Result<()> {
+        let bytes = packet.encode();
+        let mut written = 0;
+        while written < bytes.len() {
+            match self.stream.write(&bytes[written..]) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(count) => written += count,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => thread::yield_now(),
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> io::Result<Option<Packet>> {
+        let mut buffer = [0; 2];
+        let wanted = 2 - self.partial.len();
+        match self.stream.read(&mut buffer[..wanted]) {
+            Ok(0) => Err(ErrorKind::UnexpectedEof.into()),
+            Ok(count) => {
+                self.partial.extend(&buffer[..count]);
+                if self.partial.len() < 2 {
+                    return Ok(None);
+                }
+                let bytes = [self.partial[0], self.partial[1]];
+                self.partial.clear();
+                Packet::decode(bytes).map(Some)
+            }
+            Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+// Link cable between two emulators in the same process
+pub struct ChannelLink {
+    sender: Sender<Packet>,
+    receiver: Receiver<Packet>,
+}
+
+impl ChannelLink {
+    // Both ends of one cable
+    pub fn pair() -> (ChannelLink, ChannelLink) {
+        let (a_sender, b_receiver) = mpsc::channel();
+        let (b_sender, a_receiver) = mpsc::channel();
+        (
+            ChannelLink {
+                sender: a_sender,
+                receiver: a_receiver,
+            },
+            ChannelLink {
+                sender: b_sender,
+                receiver: b_receiver,
+            },
+        )
+    }
+}
+
+impl Link for ChannelLink {
+    fn send(&mut self, packet: Packet) -> io::Result<()> {
+        self.sender
+            .send(packet)
+            .map_err(|_| ErrorKind::BrokenPipe.into())
+    }
+
+    fn receive(&mut self) -> io::Result<Option<Packet>> {
+        match self.receiver.try_recv() {
+            Ok(packet) => Ok(Some(packet)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(ErrorKind::BrokenPipe.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_exchange_packets_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let client = thread::spawn(move || TcpLink::connect(&address).unwrap());
+        let mut server = TcpLink::new(listener.accept().unwrap().0).unwrap();
+        let mut client = client.join().unwrap();
+
+        assert_eq!(server.receive().unwrap(), None);
+        client.send(Packet::Transfer(0x42)).unwrap();
+        let received = loop {
+            if let Some(packet) = server.receive().unwrap() {
+                break packet;
+            }
+        };
+        assert_eq!(received, Packet::Transfer(0x42));
+        drop(client);
+        let closed = loop {
+            match server.receive() {
+                Ok(None) => {}
+                other => break other,
+            }
+        };
+        assert!(closed.is_err());
+    }
+}
@@ -1,23 +1,221 @@
-#[cfg(feature = "debug")]
-#[macro_use]
-extern crate prettytable;
-extern crate sdl2;
+use gameboy_emulator::audio::AudioSink;
+use gameboy_emulator::bindings::Bindings;
+use gameboy_emulator::config::Config;
+use gameboy_emulator::cpu::CPU;
+use gameboy_emulator::event::Event;
+use gameboy_emulator::event::EventSource;
+use gameboy_emulator::gpu::Renderer;
+use gameboy_emulator::headless::NullRenderer;
+use gameboy_emulator::link::{ChannelLink, TcpLink};
+use gameboy_emulator::memory::Memory;
+use gameboy_emulator::movie::Movie;
+use gameboy_emulator::recent::RecentRoms;
+use gameboy_emulator::sdl::SdlFrontend;
+use gameboy_emulator::{batch, dual, memory, recent};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::JoinHandle;
 
-use crate::cpu::CPU;
-use crate::gpu::GPU;
-use crate::memory::Memory;
-pub mod cpu;
-pub mod gpu;
-pub mod memory;
+const ROM: &str = "roms/dmg_rom.bin";
 
 fn main() {
+    let config = match Config::load(env::args().skip(1).collect()) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("{error}");
+            process::exit(2);
+        }
+    };
+    #[cfg(not(feature = "debug"))]
+    if config.debug_socket.is_some() {
+        eprintln!("--debug-socket needs a build with the debug feature");
+        process::exit(2);
+    }
+    if config.list_audio_devices {
+        match SdlFrontend::audio_devices() {
+            Ok(devices) => devices.iter().for_each(|device| println!("{device}")),
+            Err(error) => {
+                eprintln!("Couldn't list audio devices: {error}");
+                process::exit(1);
+            }
+        }
+        return;
+    }
+    let mut bindings = Bindings::default();
+    if let Some(value) = &config.key_bindings
+        && let Err(error) = bindings.apply_value(value)
+    {
+        eprintln!("Problem reading bindings from the config file: {error}");
+        process::exit(2);
+    }
+    if let Some(path) = &config.bindings_file
+        && let Err(error) = bindings.apply_file(path)
+    {
+        eprintln!("Problem reading bindings {path}: {error}");
+        process::exit(2);
+    }
+    if config.print_bindings {
+        bindings
+            .describe()
+            .iter()
+            .for_each(|line| println!("{line}"));
+        return;
+    }
+    if let Some(path) = &config.convert_movie {
+        let converted = path.with_extension("gbm");
+        match Movie::load(path).and_then(|movie| Ok(movie.save(&converted)?)) {
+            Ok(()) => println!("Movie converted to {}", converted.display()),
+            Err(error) => {
+                eprintln!("Problem converting movie {}: {error}", path.display());
+                process::exit(1);
+            }
+        }
+        return;
+    }
+    let rom = match &config.rom {
+        Some(rom) => rom.clone(),
+        None if config.last => match RecentRoms::load().roms().first() {
+            Some(rom) => rom.clone(),
+            None => {
+                eprintln!("No ROM has been played yet");
+                process::exit(2);
+            }
+        },
+        None if config.headless => PathBuf::from(ROM),
+        None => match choose_rom() {
+            Some(rom) => rom,
+            None => {
+                eprintln!("No ROM chosen, pass one on the command line or choose one to play");
+                process::exit(2);
+            }
+        },
+    };
     let mut mem = Memory::new();
-    match mem.load_rom("roms/dmg_rom.bin") {
+    match mem.load_rom(&rom) {
         Ok(_) => {
-            let mut gpu = GPU::new();
-            let mut cpu = CPU::new(mem, gpu);
-            cpu.run();
+            // The pre-allocated ROM wasn't picked by anyone, and batch runs are for scripts
+            if rom != Path::new(ROM) && config.frames.is_none() {
+                recent::remember(&rom);
+            }
+            connect_link(&config, &mut mem);
+            let second = config.dual.then(|| start_second(&rom, &config, &mut mem));
+            if config.headless {
+                start(mem, NullRenderer, config);
+            } else {
+                let mut frontend = match SdlFrontend::new(&config, &bindings) {
+                    Ok(frontend) => frontend,
+                    Err(error) => {
+                        eprintln!("{error}");
+                        process::exit(2);
+                    }
+                };
+                let thread = second.map(|(frames, events, thread)| {
+                    if let Err(error) = frontend.attach_second_screen(frames, events) {
+                        eprintln!("Couldn't open the second player's window: {error}");
+                        process::exit(2);
+                    }
+                    thread
+                });
+                // The frontend goes with the first player, which tells the second one to stop
+                start(mem, frontend, config);
+                if let Some(thread) = thread {
+                    let _ = thread.join();
+                }
+            }
+        }
+        Err(error) => {
+            eprintln!("Problem reading {}: {error}", rom.display());
+            process::exit(1);
         }
-        Err(error) => panic!("Problem reading file: {error:?}"),
     };
 }
+
+// Asks for a ROM with the system's file chooser when none was given. None when it was closed, or
+// there's no chooser to show
+fn choose_rom() -> Option<PathBuf> {
+    rfd::FileDialog::new()
+        .set_title("Choose a Game Boy ROM")
+        .add_filter("Game Boy ROMs", &memory::ROM_EXTENSIONS)
+        .pick_file()
+}
+
+// Waits for or reaches the other emulator before any window opens
+fn connect_link(config: &Config, mem: &mut Memory) {
+    let link = if let Some(address) = &config.link_listen {
+        TcpLink::listen(address)
+    } else if let Some(address) = &config.link_connect {
+        TcpLink::connect(address)
+    } else {
+        return;
+    };
+    match link {
+        Ok(link) => mem.serial.set_link(Box::new(link)),
+        Err(error) => {
+            eprintln!("Couldn't set up the link cable: {error}");
+            process::exit(2);
+        }
+    }
+}
+
+// Runs the second emulator of --dual, linked to this one. Returns where its frames come out, where
+// its input goes in and its thread. At most one frame waits to be shown, later ones are dropped
+fn start_second(
+    rom: &Path,
+    config: &Config,
+    mem: &mut Memory,
+) -> (Receiver<Vec<u8>>, Sender<Event>, JoinHandle<()>) {
+    let (link, other_end) = ChannelLink::pair();
+    mem.serial.set_link(Box::new(link));
+    let (frames_sender, frames) = mpsc::sync_channel(1);
+    let (events, events_receiver) = mpsc::channel();
+    let thread = dual::spawn_second(
+        rom,
+        config.clone(),
+        other_end,
+        frames_sender,
+        events_receiver,
+    );
+    (frames, events, thread)
+}
+
+fn start<T: Renderer + EventSource + AudioSink>(mem: Memory, frontend: T, config: Config) {
+    let mut cpu = CPU::new(mem, frontend, config.clone());
+    if let Err(error) = cpu.apply_palettes() {
+        eprintln!("{error}");
+        process::exit(2);
+    }
+    if let Some(path) = &config.record_movie
+        && let Err(error) = cpu.record_movie(path)
+    {
+        eprintln!("Problem creating movie {}: {error}", path.display());
+        process::exit(2);
+    }
+    if let Some(path) = &config.serial_log
+        && let Err(error) = cpu.log_serial(path)
+    {
+        eprintln!("Problem creating serial log {}: {error}", path.display());
+        process::exit(2);
+    }
+    if let Some(path) = &config.play_movie
+        && let Err(error) = cpu.play_movie(path)
+    {
+        eprintln!("Problem reading movie {}: {error}", path.display());
+        process::exit(2);
+    }
+    match config.frames {
+        Some(frames) => {
+            let report = batch::run(&mut cpu, frames);
+            println!("{report}");
+            if let Some(path) = &config.final_state
+                && let Err(error) = fs::write(path, &report.state)
+            {
+                eprintln!("Problem writing state {}: {error}", path.display());
+                process::exit(1);
+            }
+        }
+        None => cpu.run(),
+    }
+}
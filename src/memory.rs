@@ -1,16 +1,76 @@
 #[cfg(feature = "debug")]
-use prettytable::{Cell, Row, Table, format};
-#[cfg(feature = "debug")]
-use std::fmt::Write as _;
-#[cfg(feature = "debug")]
-use std::io::Write;
+use crate::debugger;
 #[cfg(feature = "debug")]
-use std::process::{Command, Stdio};
+use prettytable::{Cell, Row, Table, format};
 
+use crate::apu::{self, Apu};
+use crate::infrared::{Infrared, RP};
+use crate::joypad::{Button, JOYP, Joypad};
+use crate::rumble::Rumble;
+use crate::savestate::Snapshot;
+use crate::serial::{self, Serial};
+use crate::timer::{self, Timer};
+use std::cell::RefCell;
 use std::error::Error;
 use std::fs;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
 
 const MEMORY_SIZE: usize = 2_usize.pow(16);
+pub const INTERRUPT_FLAG: u16 = 0xFF0F;
+pub const INTERRUPT_ENABLE: u16 = 0xFFFF;
+const TITLE_START: usize = 0x134;
+const TITLE_END: usize = 0x143;
+// What ROMs can be loaded from, zips holding one included
+pub const ROM_EXTENSIONS: [&str; 3] = ["gb", "gbc", "zip"];
+
+// Save state sections of everything on the bus and the version of each one's layout, in the order
+// of Memory::state_parts. There's no cartridge RAM or bank switching yet, RAM is the whole address
+// space
+pub const STATE_SECTIONS: [(&[u8; 4], u8); 7] = [
+    (b"RAM ", 1),
+    (b"APU ", 1),
+    (b"JOYP", 1),
+    (b"RMBL", 1),
+    (b"TIMR", 1),
+    (b"SERL", 1),
+    (b"IR  ", 1),
+];
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interrupt {
+    VBlank = 0,
+    Stat = 1,
+    Timer = 2,
+    Serial = 3,
+    Joypad = 4,
+}
+
+impl Interrupt {
+    pub const ALL: [Interrupt; 5] = [
+        Interrupt::VBlank,
+        Interrupt::Stat,
+        Interrupt::Timer,
+        Interrupt::Serial,
+        Interrupt::Joypad,
+    ];
+}
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+// A read or write an instruction made. Reads have the same old and new value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Access {
+    pub kind: AccessKind,
+    pub address: u16,
+    pub old: u8,
+    pub new: u8,
+}
+
 pub struct Range {
     pub start: u16,
     pub end: u16,
@@ -20,6 +80,7 @@ impl Range {
         Self { start, end }
     }
 }
+#[allow(dead_code)]
 pub struct MemoryMap {
     rom: Range,
     v_ram: Range,
@@ -29,10 +90,25 @@ pub struct MemoryMap {
     io: Range,
     pub h_ram: Range,
 }
+// The bus: plain memory plus the IO devices behind their registers. The PPU still works on its
+// registers in the memory array directly
 pub struct Memory {
     pub memory: [u8; MEMORY_SIZE],
     pub map: MemoryMap,
-    rom_size: usize,
+    pub apu: Apu,
+    pub joypad: Joypad,
+    pub rumble: Rumble,
+    pub timer: Timer,
+    pub serial: Serial,
+    pub infrared: Infrared,
+    rom: Vec<u8>,
+    rom_path: Option<PathBuf>,
+    // Interrupts requested since the CPU last took them, one bit each like IF. IF itself stays set
+    // until the game clears it, so it doesn't tell when one is requested again
+    requested: u8,
+    // Every access since they were last taken, only kept while something like a watchpoint needs
+    // them. Reads only borrow the bus, hence the RefCell
+    accesses: Option<RefCell<Vec<Access>>>,
 }
 impl Memory {
     pub fn new() -> Self {
@@ -47,18 +123,216 @@ impl Memory {
                 io: Range::new(0xFF00, 0xFF7F),
                 h_ram: Range::new(0xFF80, 0xFFFE),
             },
-            rom_size: 0,
+            apu: Apu::new(),
+            joypad: Joypad::new(),
+            rumble: Rumble::new(),
+            timer: Timer::new(),
+            serial: Serial::new(),
+            infrared: Infrared::new(),
+            rom: Vec::new(),
+            rom_path: None,
+            requested: 0,
+            accesses: None,
         }
     }
-    pub fn load_rom(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
-        let file = fs::read(path)?;
-        file.iter()
-            .enumerate()
-            .for_each(|(i, byte)| self.memory[i] = *byte);
-        self.rom_size = file.len();
+    // Puts the cartridge in and powers the machine on
+    pub fn load_rom(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        self.insert_rom(read_rom(path)?);
+        self.rom_path = Some(path.to_path_buf());
         Ok(())
     }
 
+    // A ROM that isn't in a file, like one an embedding program downloaded. Without a path there
+    // are no save state slots
+    pub fn insert_rom(&mut self, rom: Vec<u8>) {
+        self.rom = rom;
+        self.rom_path = None;
+        self.rumble.detect(&self.rom);
+        self.reset(true);
+    }
+
+    // Back to how the machine starts up with the cartridge in. Without clearing RAM only the IO
+    // registers start over and RAM keeps what was in it, like pressing reset rather than power
+    // cycling. Settings and the link cable stay
+    pub fn reset(&mut self, clear_ram: bool) {
+        if clear_ram {
+            self.memory = [0; MEMORY_SIZE];
+        } else {
+            let io = self.map.io.start as usize..=self.map.io.end as usize;
+            self.memory[io].fill(0);
+            self.memory[INTERRUPT_ENABLE as usize] = 0;
+        }
+        // Writes to the ROM area land in the array while there's no bank switching. Only the first
+        // 32 KiB fit there, the rest of a larger ROM would land on RAM and the IO registers
+        let size = self.rom.len().min(self.map.rom.end as usize + 1);
+        self.memory[..size].copy_from_slice(&self.rom[..size]);
+        self.apu.reset();
+        self.timer = Timer::new();
+        self.serial.reset();
+        self.infrared = Infrared::new();
+        self.rumble.write(0);
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        let value = self.peek(address);
+        if let Some(accesses) = &self.accesses {
+            accesses.borrow_mut().push(Access {
+                kind: AccessKind::Read,
+                address,
+                old: value,
+                new: value,
+            });
+        }
+        value
+    }
+
+    // Reads without it counting as an access, for looking at memory from outside the machine
+    pub fn peek(&self, address: u16) -> u8 {
+        match address {
+            JOYP => self.joypad.read(),
+            serial::SB | serial::SC => self.serial.read(address),
+            RP => self.infrared.read(self.serial.infrared_light()),
+            timer::DIV..=timer::TAC => self.timer.read(address),
+            apu::START..=apu::END => self.apu.read(address),
+            _ => self.memory[address as usize],
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        if let Some(accesses) = &self.accesses {
+            accesses.borrow_mut().push(Access {
+                kind: AccessKind::Write,
+                address,
+                old: self.peek(address),
+                new: value,
+            });
+        }
+        match address {
+            JOYP => {
+                if self.joypad.write(value) {
+                    self.request_interrupt(Interrupt::Joypad);
+                }
+            }
+            serial::SB | serial::SC => self.serial.write(address, value),
+            RP => {
+                if let Some(on) = self.infrared.write(value) {
+                    self.serial.send_infrared(on);
+                }
+            }
+            timer::DIV..=timer::TAC => self.timer.write(address, value),
+            apu::START..=apu::END => self.apu.write(address, value),
+            // RAM bank register. There's no bank switching yet, but the rumble bit lives here too
+            0x4000..=0x5FFF if self.rumble.is_present() => self.rumble.write(value),
+            _ => self.memory[address as usize] = value,
+        }
+    }
+
+    // Clocks the devices on the bus, in CPU cycles
+    pub fn step(&mut self, cycles: u32) {
+        if self.timer.step(cycles) {
+            self.request_interrupt(Interrupt::Timer);
+        }
+        if self.serial.step(cycles) {
+            self.request_interrupt(Interrupt::Serial);
+        }
+        self.apu.step(cycles);
+        self.rumble.step(cycles);
+    }
+
+    pub fn press_button(&mut self, button: Button) {
+        if self.joypad.press(button) {
+            self.request_interrupt(Interrupt::Joypad);
+        }
+    }
+
+    pub fn set_joypad_state(&mut self, state: u8) {
+        if self.joypad.set_state(state) {
+            self.request_interrupt(Interrupt::Joypad);
+        }
+    }
+
+    // FNV-1a of the loaded ROM, identifies what a movie was recorded on
+    pub fn rom_hash(&self) -> u64 {
+        self.rom.iter().fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01B3)
+        })
+    }
+
+    pub fn state_parts(&self) -> [&dyn Snapshot; 7] {
+        [
+            &self.memory,
+            &self.apu,
+            &self.joypad,
+            &self.rumble,
+            &self.timer,
+            &self.serial,
+            &self.infrared,
+        ]
+    }
+
+    pub fn state_parts_mut(&mut self) -> [&mut dyn Snapshot; 7] {
+        [
+            &mut self.memory,
+            &mut self.apu,
+            &mut self.joypad,
+            &mut self.rumble,
+            &mut self.timer,
+            &mut self.serial,
+            &mut self.infrared,
+        ]
+    }
+
+    // The game's name from the cartridge header, padded with zeros. Later cartridges took over its
+    // last bytes for other things, which aren't printable
+    pub fn title(&self) -> Option<String> {
+        let header = self.rom.get(TITLE_START..=TITLE_END)?;
+        let title: String = header
+            .iter()
+            .take_while(|byte| (0x20..0x7F).contains(*byte))
+            .map(|byte| *byte as char)
+            .collect();
+        Some(title.trim().to_string()).filter(|title| !title.is_empty())
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    // Where the ROM was loaded from, save states go next to it
+    pub fn rom_path(&self) -> Option<&Path> {
+        self.rom_path.as_deref()
+    }
+
+    pub fn request_interrupt(&mut self, interrupt: Interrupt) {
+        self.memory[INTERRUPT_FLAG as usize] |= 1 << interrupt as u8;
+        self.requested |= 1 << interrupt as u8;
+    }
+
+    // Whether an interrupt is both requested and enabled, which ends HALT even with IME off
+    pub fn interrupt_pending(&self) -> bool {
+        self.memory[INTERRUPT_ENABLE as usize] & self.memory[INTERRUPT_FLAG as usize] & 0x1F != 0
+    }
+
+    // The interrupts requested since the last call
+    pub fn take_requested(&mut self) -> impl Iterator<Item = Interrupt> {
+        let requested = std::mem::take(&mut self.requested);
+        Interrupt::ALL
+            .into_iter()
+            .filter(move |interrupt| requested & 1 << *interrupt as u8 != 0)
+    }
+
+    pub fn set_logging_accesses(&mut self, logging: bool) {
+        self.accesses = logging.then(RefCell::default);
+    }
+
+    // The accesses since the last call, none unless they're being logged
+    pub fn take_accesses(&self) -> Vec<Access> {
+        self.accesses
+            .as_ref()
+            .map(|accesses| accesses.take())
+            .unwrap_or_default()
+    }
+
     #[cfg(feature = "debug")]
     pub fn display_rom(&self) -> Result<(), std::io::Error> {
         let mut table = Table::new();
@@ -69,10 +343,7 @@ impl Memory {
         headers.extend((0..cols_per_row).map(|i| Cell::new(&format!("{:X}", i))));
         table.set_titles(Row::new(headers));
 
-        for (i, chunk) in self.memory[..self.rom_size]
-            .chunks(cols_per_row)
-            .enumerate()
-        {
+        for (i, chunk) in self.rom.chunks(cols_per_row).enumerate() {
             let mut row = vec![Cell::new(&format!("{:04X}", i * cols_per_row))];
 
             for &byte in chunk {
@@ -90,14 +361,94 @@ impl Memory {
         let mut output = Vec::new();
         table.print(&mut output).unwrap(); // `print` works with a writer like Vec<u8>
 
-        // Pipe output to `less`
-        let mut pager = Command::new("less")
-            .arg("-RS") // Preserve formatting & allow horizontal scrolling
-            .stdin(Stdio::piped())
-            .spawn()
-            .expect("Failed to launch less");
+        debugger::page(&output)
+    }
+}
 
-        pager.stdin.unwrap().write_all(&output)?;
-        Ok(())
+// A zip is read for the first .gb or .gbc file in it
+fn read_rom(path: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    if path.extension().is_none_or(|extension| extension != "zip") {
+        return Ok(bytes);
+    }
+    extract_rom(bytes)
+}
+
+// The first .gb or .gbc file in a zip
+pub fn extract_rom(zip: Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(zip))?;
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index)?;
+        let name = Path::new(file.name());
+        if name
+            .extension()
+            .is_some_and(|extension| extension == "gb" || extension == "gbc")
+        {
+            let mut rom = Vec::new();
+            file.read_to_end(&mut rom)?;
+            return Ok(rom);
+        }
+    }
+    Err("No .gb or .gbc file in the zip".into())
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn should_read_a_rom_out_of_a_zip() {
+        let path = std::env::temp_dir().join("gameboy-emulator-zip-test.zip");
+        let mut bytes = Vec::new();
+        let mut writer = zip::ZipWriter::new(Cursor::new(&mut bytes));
+        let options = zip::write::SimpleFileOptions::default();
+        writer.start_file("readme.txt", options).unwrap();
+        writer.write_all(b"Have fun").unwrap();
+        writer.start_file("game.gb", options).unwrap();
+        writer.write_all(&[1, 2, 3]).unwrap();
+        writer.finish().unwrap();
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(read_rom(&path).unwrap(), [1, 2, 3]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_only_map_the_first_32_kib_of_a_rom() {
+        let mut memory = Memory::new();
+        memory.insert_rom(vec![0xAB; 0x10000]);
+        assert_eq!(memory.read(0x7FFF), 0xAB);
+        assert_eq!(memory.read(0xC000), 0);
+        assert_eq!(memory.read(crate::gpu::LCDC), 0);
+        assert_eq!(memory.read(INTERRUPT_ENABLE), 0);
+    }
+
+    #[test]
+    fn should_log_accesses_but_not_peeks() {
+        let mut memory = Memory::new();
+        memory.write(0xC000, 1);
+        memory.set_logging_accesses(true);
+        memory.write(0xC000, 2);
+        memory.read(0xC000);
+        memory.peek(0xC001);
+        let write = Access {
+            kind: AccessKind::Write,
+            address: 0xC000,
+            old: 1,
+            new: 2,
+        };
+        let read = Access {
+            kind: AccessKind::Read,
+            old: 2,
+            ..write
+        };
+        assert_eq!(memory.take_accesses(), [write, read]);
+        assert!(memory.take_accesses().is_empty());
     }
 }
@@ -0,0 +1,132 @@
+mod import;
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 4] = b"GBMV";
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 13;
+
+// Input movie: the joypad state of every frame since power-on, one Joypad::state byte each.
+// The header is the magic, a version byte and the hash of the ROM it was recorded on. The
+// emulator has no other source of randomness, so replaying the same bytes from power-on on the
+// same ROM plays out the same way
+#[derive(Debug, PartialEq)]
+pub struct Movie {
+    // Unknown for movies imported from other emulators, stored as 0
+    pub rom_hash: Option<u64>,
+    pub frames: Vec<u8>,
+}
+
+impl Movie {
+    // Also reads VisualBoyAdvance (.vbm) and BizHawk (.bk2) movies, going by their contents
+    pub fn load(path: &Path) -> Result<Movie, Box<dyn Error>> {
+        let bytes = fs::read(path)?;
+        match bytes.get(..4) {
+            Some(magic) if magic == import::VBM_MAGIC => {
+                Ok(Self::imported(import::parse_vbm(&bytes)?))
+            }
+            Some(magic) if magic == import::ZIP_MAGIC => {
+                Ok(Self::imported(import::parse_bk2(&bytes)?))
+            }
+            _ => Self::parse(&bytes),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut bytes = Self::header(self.rom_hash.unwrap_or(0));
+        bytes.extend(&self.frames);
+        fs::write(path, bytes)
+    }
+
+    fn imported(frames: Vec<u8>) -> Movie {
+        Movie {
+            rom_hash: None,
+            frames,
+        }
+    }
+
+    fn header(rom_hash: u64) -> Vec<u8> {
+        let mut header = MAGIC.to_vec();
+        header.push(VERSION);
+        header.extend(rom_hash.to_le_bytes());
+        header
+    }
+
+    fn parse(bytes: &[u8]) -> Result<Movie, Box<dyn Error>> {
+        if bytes.len() < HEADER_SIZE || &bytes[..4] != MAGIC {
+            return Err("Not a movie file".into());
+        }
+        if bytes[4] != VERSION {
+            return Err(format!("Unsupported movie version {}", bytes[4]).into());
+        }
+        let rom_hash = u64::from_le_bytes(bytes[5..HEADER_SIZE].try_into()?);
+        Ok(Movie {
+            rom_hash: (rom_hash != 0).then_some(rom_hash),
+            frames: bytes[HEADER_SIZE..].to_vec(),
+        })
+    }
+}
+
+// Writes frames as they come and flushes every one, so a crash or a killed process still leaves
+// a playable movie behind
+pub struct MovieRecorder {
+    file: BufWriter<File>,
+    path: PathBuf,
+}
+
+impl MovieRecorder {
+    pub fn create(path: &Path, rom_hash: u64) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&Movie::header(rom_hash))?;
+        file.flush()?;
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn push(&mut self, state: u8) -> io::Result<()> {
+        self.file.write_all(&[state])?;
+        self.file.flush()
+    }
+}
+
+pub enum MovieMode {
+    Recording(MovieRecorder),
+    // Index of the next frame to apply
+    Playing(Movie, usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_load_what_was_recorded() {
+        let path = std::env::temp_dir().join("gameboy-emulator-movie-test.gbm");
+        let mut recorder = MovieRecorder::create(&path, 0x1234).unwrap();
+        for state in [0x00, 0x10, 0x11] {
+            recorder.push(state).unwrap();
+        }
+        let movie = Movie::load(&path).unwrap();
+        assert_eq!(
+            movie,
+            Movie {
+                rom_hash: Some(0x1234),
+                frames: vec![0x00, 0x10, 0x11]
+            }
+        );
+        Movie::imported(vec![0x01]).save(&path).unwrap();
+        assert_eq!(Movie::load(&path).unwrap(), Movie::imported(vec![0x01]));
+        fs::remove_file(path).unwrap();
+        assert!(Movie::parse(b"GBMV").is_err());
+        assert!(Movie::parse(b"GBMV\x02\0\0\0\0\0\0\0\0").is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use crate::joypad::Button;
+use std::error::Error;
+use std::io::{Cursor, Read};
+
+pub const VBM_MAGIC: &[u8; 4] = b"VBM\x1A";
+pub const ZIP_MAGIC: &[u8; 4] = b"PK\x03\x04";
+
+const VBM_HEADER_SIZE: usize = 0x40;
+// Low byte of a VisualBoyAdvance controller word, from bit 0 up
+const VBM_BUTTONS: [Button; 8] = [
+    Button::A,
+    Button::B,
+    Button::Select,
+    Button::Start,
+    Button::Right,
+    Button::Left,
+    Button::Up,
+    Button::Down,
+];
+
+// VisualBoyAdvance movie: a 64-byte header pointing at 16-bit controller words, one per frame
+// and enabled controller. Only the first controller is used
+pub fn parse_vbm(bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    if bytes.len() < VBM_HEADER_SIZE {
+        return Err("VBM header is truncated".into());
+    }
+    let read_u32 =
+        |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+    if bytes[0x14] & 0b11 != 0 {
+        return Err("VBM movies starting from a savestate or SRAM aren't supported".into());
+    }
+    let frames = read_u32(0x0C) as usize;
+    let controllers = (bytes[0x15] & 0x0F).count_ones().max(1) as usize;
+    let start = read_u32(0x3C) as usize;
+    let stride = controllers * 2;
+    // Past the end of the address space on 32-bit targets is as truncated as past the end of bytes
+    let data = frames
+        .checked_mul(stride)
+        .and_then(|length| start.checked_add(length))
+        .and_then(|end| bytes.get(start..end))
+        .ok_or("VBM input data is truncated")?;
+    Ok(data
+        .chunks(stride)
+        .map(|frame| {
+            VBM_BUTTONS
+                .iter()
+                .enumerate()
+                .filter(|(bit, _)| frame[0] & (1 << bit) != 0)
+                .fold(0, |state, (_, button)| state | button.state_bit())
+        })
+        .collect())
+}
+
+// BizHawk movie: a zip whose "Input Log.txt" has a LogKey line naming the buttons, followed by
+// one |...| line per frame with a character per button, '.' when it's released
+pub fn parse_bk2(bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+    let mut log = String::new();
+    archive.by_name("Input Log.txt")?.read_to_string(&mut log)?;
+    parse_bk2_log(&log)
+}
+
+fn parse_bk2_log(log: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let key = log
+        .lines()
+        .find_map(|line| line.strip_prefix("LogKey:"))
+        .ok_or("BK2 input log has no LogKey")?;
+    let buttons: Vec<Option<Button>> = key
+        .split(['#', '|'])
+        .filter(|name| !name.is_empty())
+        .map(|name| match name.trim_start_matches("P1 ") {
+            "Up" => Some(Button::Up),
+            "Down" => Some(Button::Down),
+            "Left" => Some(Button::Left),
+            "Right" => Some(Button::Right),
+            "Start" => Some(Button::Start),
+            "Select" => Some(Button::Select),
+            "B" => Some(Button::B),
+            "A" => Some(Button::A),
+            // Power, reset and whatever else the core logs
+            _ => None,
+        })
+        .collect();
+    Ok(log
+        .lines()
+        .filter(|line| line.starts_with('|'))
+        .map(|line| {
+            line.chars()
+                .filter(|c| *c != '|')
+                .zip(&buttons)
+                .filter(|(c, _)| *c != '.' && *c != ' ')
+                .filter_map(|(_, button)| *button)
+                .fold(0, |state, button| state | button.state_bit())
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn should_parse_vbm_input() {
+        let mut bytes = vec![0; VBM_HEADER_SIZE];
+        bytes[..4].copy_from_slice(VBM_MAGIC);
+        bytes[0x0C] = 3;
+        // Controllers 1 and 2
+        bytes[0x15] = 0b0011;
+        bytes[0x3C] = VBM_HEADER_SIZE as u8;
+        // A, then Down + Start, then nothing. Controller 2 presses everything and is ignored
+        bytes.extend([0x01, 0, 0xFF, 0, 0x88, 0, 0xFF, 0, 0x00, 0, 0xFF, 0]);
+        let frames = parse_vbm(&bytes).unwrap();
+        assert_eq!(
+            frames,
+            vec![
+                Button::A.state_bit(),
+                Button::Down.state_bit() | Button::Start.state_bit(),
+                0
+            ]
+        );
+        let mut huge = bytes.clone();
+        huge[0x0C..0x10].fill(0xFF);
+        huge[0x3C..0x40].fill(0xFF);
+        assert!(parse_vbm(&huge).is_err());
+        bytes[0x14] = 1;
+        assert!(parse_vbm(&bytes).is_err());
+        assert!(parse_vbm(&bytes[..0x20]).is_err());
+    }
+
+    #[test]
+    fn should_parse_bk2_input() {
+        let log = "[Input]\nLogKey:#Up|Down|Left|Right|Start|Select|B|A|Power|\n\
+                   |.........|\n|U......A.|\n|...RS...P|\n[/Input]\n";
+        let mut bytes = Vec::new();
+        let mut writer = zip::ZipWriter::new(Cursor::new(&mut bytes));
+        writer
+            .start_file("Input Log.txt", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(log.as_bytes()).unwrap();
+        writer.finish().unwrap();
+
+        let frames = parse_bk2(&bytes).unwrap();
+        assert_eq!(
+            frames,
+            vec![
+                0,
+                Button::Up.state_bit() | Button::A.state_bit(),
+                Button::Right.state_bit() | Button::Start.state_bit()
+            ]
+        );
+        assert!(parse_bk2_log("|....|").is_err());
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use web_time::Instant;
+
+const GLYPH_WIDTH: u32 = 3;
+pub const GLYPH_HEIGHT: u32 = 5;
+// A column between letters
+const ADVANCE: u32 = GLYPH_WIDTH + 1;
+
+const DURATION: Duration = Duration::from_secs(2);
+const MAX_MESSAGES: usize = 3;
+
+// 3x5 pixel letters, one row per byte with the leftmost pixel in bit 2. Small enough that a whole
+// message fits across the Game Boy's 160 pixels, lowercase is shown as uppercase
+fn glyph(character: char) -> [u8; 5] {
+    match character.to_ascii_uppercase() {
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b110, 0b001, 0b010, 0b100, 0b111],
+        '3' => [0b110, 0b001, 0b010, 0b001, 0b110],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b110, 0b001, 0b110],
+        '6' => [0b011, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b110],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+        '\'' => [0b010, 0b010, 0b000, 0b000, 0b000],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '<' => [0b001, 0b010, 0b100, 0b010, 0b001],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        _ => [0b111, 0b001, 0b011, 0b000, 0b010],
+    }
+}
+
+// Pixels of text set in the font, relative to its top left corner
+pub fn text_pixels(text: &str) -> impl Iterator<Item = (u32, u32)> + '_ {
+    text.chars().enumerate().flat_map(|(index, character)| {
+        let rows = glyph(character);
+        (0..GLYPH_HEIGHT).flat_map(move |y| {
+            (0..GLYPH_WIDTH)
+                .filter(move |x| rows[y as usize] & (0b100 >> x) != 0)
+                .map(move |x| (index as u32 * ADVANCE + x, y))
+        })
+    })
+}
+
+pub fn text_width(text: &str) -> u32 {
+    (text.chars().count() as u32 * ADVANCE).saturating_sub(1)
+}
+
+// As much of the start of text as fits in width pixels
+pub fn truncate(text: &str, width: u32) -> &str {
+    let fitting = ((width + 1) / ADVANCE) as usize;
+    match text.char_indices().nth(fitting) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+// Short messages shown over the picture for a couple of seconds, like a state being saved. The
+// frontend draws them, this only keeps track of which are still up
+pub struct Osd {
+    messages: VecDeque<(String, Instant)>,
+}
+
+impl Osd {
+    pub fn new() -> Self {
+        Self {
+            messages: VecDeque::new(),
+        }
+    }
+
+    // The oldest message makes room when too many are up
+    pub fn show(&mut self, message: &str) {
+        if self.messages.len() == MAX_MESSAGES {
+            self.messages.pop_front();
+        }
+        self.messages
+            .push_back((message.to_string(), Instant::now() + DURATION));
+    }
+
+    // Drops messages that have been up long enough, returns whether any were
+    pub fn expire(&mut self) -> bool {
+        let count = self.messages.len();
+        let now = Instant::now();
+        self.messages.retain(|(_, until)| *until > now);
+        self.messages.len() != count
+    }
+
+    // Oldest first
+    pub fn messages(&self) -> impl Iterator<Item = &str> {
+        self.messages.iter().map(|(message, _)| message.as_str())
+    }
+}
+
+impl Default for Osd {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_set_text_one_glyph_after_another() {
+        let pixels: Vec<_> = text_pixels("-1").collect();
+        assert_eq!(
+            pixels,
+            [
+                (0, 2),
+                (1, 2),
+                (2, 2),
+                (5, 0),
+                (4, 1),
+                (5, 1),
+                (5, 2),
+                (5, 3),
+                (4, 4),
+                (5, 4),
+                (6, 4)
+            ]
+        );
+        assert_eq!(text_width("-1"), 7);
+        assert_eq!(text_width(""), 0);
+        assert_eq!(glyph('a'), glyph('A'));
+        assert_eq!(truncate("ROMS", 11), "ROM");
+        assert_eq!(truncate("ROMS", 15), "ROMS");
+    }
+
+    #[test]
+    fn should_keep_only_the_latest_messages() {
+        let mut osd = Osd::new();
+        for message in ["one", "two", "three", "four"] {
+            osd.show(message);
+        }
+        assert_eq!(osd.messages().collect::<Vec<_>>(), ["two", "three", "four"]);
+        assert!(!osd.expire());
+    }
+}
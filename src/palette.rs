@@ -0,0 +1,180 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+pub const PALETTE_SIZE: usize = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    pub name: String,
+    // Shades for DMG color values 0 (lightest) to 3 (darkest), as RGB
+    pub colors: [[u8; 3]; PALETTE_SIZE],
+}
+
+impl Palette {
+    pub fn new(name: &str, colors: [[u8; 3]; PALETTE_SIZE]) -> Self {
+        Self {
+            name: name.to_string(),
+            colors,
+        }
+    }
+
+    pub fn presets() -> Vec<Palette> {
+        vec![
+            Palette::new(
+                "green",
+                [
+                    [0x9B, 0xBC, 0x0F],
+                    [0x8B, 0xAC, 0x0F],
+                    [0x30, 0x62, 0x30],
+                    [0x0F, 0x38, 0x0F],
+                ],
+            ),
+            Palette::new(
+                "pocket",
+                [
+                    [0xE0, 0xE0, 0xE0],
+                    [0xA8, 0xA8, 0xA8],
+                    [0x60, 0x60, 0x60],
+                    [0x20, 0x20, 0x20],
+                ],
+            ),
+            Palette::new(
+                "high-contrast",
+                [
+                    [0xFF, 0xFF, 0xFF],
+                    [0xAA, 0xAA, 0xAA],
+                    [0x55, 0x55, 0x55],
+                    [0x00, 0x00, 0x00],
+                ],
+            ),
+        ]
+    }
+
+    // Loads a 4-color ramp, lightest first. Supported formats are:
+    // - JSON, either ["#9BBC0F", ...] or {"colors": ["#9BBC0F", ...]}
+    // - JASC-PAL (.pal written by most image editors) or 12 raw RGB bytes
+    // - plain text with one hex color per line, blank lines and `;` comments are ignored
+    pub fn from_file(path: &str) -> Result<Palette, Box<dyn Error>> {
+        let bytes = fs::read(path)?;
+        let name = Path::new(path)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or(path);
+        let colors = match std::str::from_utf8(&bytes) {
+            Ok(text) if text.trim_start().starts_with(['[', '{']) => Self::parse_json(text)?,
+            Ok(text) if text.starts_with("JASC-PAL") => Self::parse_jasc(text)?,
+            Ok(text) if bytes.len() != PALETTE_SIZE * 3 => Self::parse_hex_lines(text)?,
+            _ => Self::parse_raw(&bytes)?,
+        };
+        Ok(Palette::new(name, colors))
+    }
+
+    fn parse_json(text: &str) -> Result<[[u8; 3]; PALETTE_SIZE], Box<dyn Error>> {
+        let value: serde_json::Value = serde_json::from_str(text)?;
+        let colors = value
+            .get("colors")
+            .unwrap_or(&value)
+            .as_array()
+            .ok_or("Expected an array of colors")?;
+        let colors = colors
+            .iter()
+            .map(|color| color.as_str().ok_or("Colors must be hex strings"))
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::collect_colors(colors.into_iter().map(Self::parse_hex))
+    }
+
+    fn parse_jasc(text: &str) -> Result<[[u8; 3]; PALETTE_SIZE], Box<dyn Error>> {
+        // Header is the JASC-PAL magic, the version and the color count
+        let colors = text.lines().skip(3).filter(|line| !line.trim().is_empty());
+        Self::collect_colors(colors.map(|line| {
+            let channels = line
+                .split_whitespace()
+                .map(|channel| channel.parse::<u8>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| format!("Invalid color: {line}"))?;
+            match channels[..] {
+                [r, g, b] => Ok([r, g, b]),
+                _ => Err(format!("Invalid color: {line}")),
+            }
+        }))
+    }
+
+    fn parse_hex_lines(text: &str) -> Result<[[u8; 3]; PALETTE_SIZE], Box<dyn Error>> {
+        let colors = text
+            .lines()
+            .map(|line| line.split(';').next().unwrap_or("").trim())
+            .filter(|line| !line.is_empty());
+        Self::collect_colors(colors.map(Self::parse_hex))
+    }
+
+    fn parse_raw(bytes: &[u8]) -> Result<[[u8; 3]; PALETTE_SIZE], Box<dyn Error>> {
+        if bytes.len() != PALETTE_SIZE * 3 {
+            return Err("Binary palettes must contain exactly 4 RGB colors".into());
+        }
+        Self::collect_colors(bytes.chunks(3).map(|rgb| Ok([rgb[0], rgb[1], rgb[2]])))
+    }
+
+    fn parse_hex(color: &str) -> Result<[u8; 3], String> {
+        let hex = color.trim_start_matches('#').trim_start_matches("0x");
+        // from_str_radix would also take a sign
+        if hex.len() != 6 || !hex.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+            return Err(format!("Invalid color: {color}"));
+        }
+        let rgb = u32::from_str_radix(hex, 16).map_err(|error| error.to_string())?;
+        Ok([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+    }
+
+    fn collect_colors(
+        colors: impl Iterator<Item = Result<[u8; 3], String>>,
+    ) -> Result<[[u8; 3]; PALETTE_SIZE], Box<dyn Error>> {
+        let colors = colors.collect::<Result<Vec<_>, _>>()?;
+        colors
+            .try_into()
+            .map_err(|colors: Vec<_>| format!("Expected 4 colors, found {}", colors.len()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAMP: [[u8; 3]; PALETTE_SIZE] = [
+        [0xFF, 0xF6, 0xD3],
+        [0xF9, 0xA8, 0x75],
+        [0xEB, 0x6B, 0x6F],
+        [0x7C, 0x3F, 0x58],
+    ];
+
+    #[test]
+    fn should_parse_hex_lines() {
+        let text = "; warm ramp\n#FFF6D3\nF9A875\n\n0xEB6B6F ; shadow\n#7c3f58\n";
+        assert_eq!(Palette::parse_hex_lines(text).unwrap(), RAMP);
+        assert!(Palette::parse_hex_lines("#FFF6D3\n#F9A875").is_err());
+        assert!(Palette::parse_hex_lines("#FFF6D3\n#F9A875\n#EB6B6F\n#7C3F5").is_err());
+        assert!(Palette::parse_hex_lines("#FFF6D3\n#F9A875\n#EB6B6F\n+7C3F5").is_err());
+    }
+
+    #[test]
+    fn should_parse_json() {
+        let array = r##"["#FFF6D3", "#F9A875", "#EB6B6F", "#7C3F58"]"##;
+        let object = r##"{"colors": ["#FFF6D3", "#F9A875", "#EB6B6F", "#7C3F58"]}"##;
+        assert_eq!(Palette::parse_json(array).unwrap(), RAMP);
+        assert_eq!(Palette::parse_json(object).unwrap(), RAMP);
+        assert!(Palette::parse_json(r#"{"colors": [1, 2, 3, 4]}"#).is_err());
+    }
+
+    #[test]
+    fn should_parse_jasc_pal() {
+        let text =
+            "JASC-PAL\r\n0100\r\n4\r\n255 246 211\r\n249 168 117\r\n235 107 111\r\n124 63 88\r\n";
+        assert_eq!(Palette::parse_jasc(text).unwrap(), RAMP);
+        assert!(Palette::parse_jasc("JASC-PAL\n0100\n4\n255 246\n").is_err());
+    }
+
+    #[test]
+    fn should_parse_raw_rgb_bytes() {
+        assert_eq!(Palette::parse_raw(RAMP.as_flattened()).unwrap(), RAMP);
+        assert!(Palette::parse_raw(&[0; 9]).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Creates `dir` if needed and returns a path like `dir/<prefix>-2025-01-31_18-04-59-123.<extension>`
+pub fn timestamped_file(dir: &Path, prefix: &str, extension: &str) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Ok(dir.join(format!(
+        "{prefix}-{}.{extension}",
+        format_timestamp(now.as_secs(), now.subsec_millis())
+    )))
+}
+
+// Where the emulator's per-user settings live: %APPDATA% on Windows, Application Support on macOS,
+// $XDG_CONFIG_HOME or ~/.config elsewhere
+pub fn config_dir() -> Option<PathBuf> {
+    let home = || env::var_os("HOME").map(PathBuf::from);
+    let dir = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| home().map(|home| home.join(".config")))
+    };
+    dir.map(|dir| dir.join("gameboy-emulator"))
+}
+
+// Where the emulator keeps files it manages itself: %APPDATA% on Windows, Application Support on
+// macOS, $XDG_DATA_HOME or ~/.local/share elsewhere
+pub fn data_dir() -> Option<PathBuf> {
+    if cfg!(windows) || cfg!(target_os = "macos") {
+        return config_dir();
+    }
+    env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .map(|dir| dir.join("gameboy-emulator"))
+}
+
+fn format_timestamp(secs: u64, millis: u32) -> String {
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let time = secs % 86_400;
+    format!(
+        "{year:04}-{month:02}-{day:02}_{:02}-{:02}-{:02}-{millis:03}",
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+// Days since 1970-01-01 to a (year, month, day) UTC date, see
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_format_utc_timestamps() {
+        assert_eq!(format_timestamp(0, 0), "1970-01-01_00-00-00-000");
+        assert_eq!(
+            format_timestamp(1_700_000_000, 42),
+            "2023-11-14_22-13-20-042"
+        );
+        assert_eq!(
+            format_timestamp(951_825_600, 999),
+            "2000-02-29_12-00-00-999"
+        );
+    }
+}
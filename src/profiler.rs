@@ -0,0 +1,115 @@
+use crate::symbols::Symbols;
+use std::collections::HashMap;
+
+// Cycles spent at every PC while profiling. Every instruction run is counted, so it's exact
+// rather than sampled, and a loop's cost shows up on the instructions it's made of
+#[derive(Default)]
+pub struct Profile {
+    cycles: HashMap<u16, u64>,
+    total: u64,
+}
+
+// Where the cycles went, with its share of all of them in percent
+pub struct HotSpot {
+    pub address: u16,
+    // The symbol the cycles were added up under, None for a single address
+    pub symbol: Option<String>,
+    pub cycles: u64,
+    pub share: f64,
+}
+
+impl Profile {
+    pub fn record(&mut self, pc: u16, cycles: u32) {
+        *self.cycles.entry(pc).or_default() += cycles as u64;
+        self.total += cycles as u64;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    // The count addresses that took the most cycles, most first
+    pub fn by_address(&self, count: usize) -> Vec<HotSpot> {
+        let spots = self
+            .cycles
+            .iter()
+            .map(|(&address, &cycles)| (address, None, cycles));
+        self.hottest(spots, count)
+    }
+
+    // Cycles added up under the symbol each address is part of, addresses before the first
+    // symbol in their area of memory stay on their own
+    pub fn by_symbol(&self, symbols: &Symbols, count: usize) -> Vec<HotSpot> {
+        let mut totals: HashMap<u16, (Option<&str>, u64)> = HashMap::new();
+        for (&address, &cycles) in &self.cycles {
+            let (start, name) = match symbols.enclosing(address) {
+                Some((start, name)) => (start, Some(name)),
+                None => (address, None),
+            };
+            totals.entry(start).or_insert((name, 0)).1 += cycles;
+        }
+        let spots = totals
+            .into_iter()
+            .map(|(address, (name, cycles))| (address, name.map(str::to_string), cycles));
+        self.hottest(spots, count)
+    }
+
+    fn hottest(
+        &self,
+        spots: impl Iterator<Item = (u16, Option<String>, u64)>,
+        count: usize,
+    ) -> Vec<HotSpot> {
+        let mut spots: Vec<HotSpot> = spots
+            .map(|(address, symbol, cycles)| HotSpot {
+                address,
+                symbol,
+                cycles,
+                share: cycles as f64 * 100.0 / self.total as f64,
+            })
+            .collect();
+        // Ties go to the lower address, so the same profile always reports the same way
+        spots.sort_by_key(|spot| (std::cmp::Reverse(spot.cycles), spot.address));
+        spots.truncate(count);
+        spots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_rank_addresses_and_add_up_symbols() {
+        let symbols = Symbols::parse("00:0150 Main\n00:0200 Copy\n").unwrap();
+        let mut profile = Profile::default();
+        for _ in 0..10 {
+            profile.record(0x0203, 8);
+            profile.record(0x0205, 12);
+        }
+        profile.record(0x0150, 4);
+        profile.record(0x0100, 16);
+        assert_eq!(profile.total(), 220);
+
+        let spots = profile.by_address(2);
+        let ranked: Vec<(u16, u64)> = spots
+            .iter()
+            .map(|spot| (spot.address, spot.cycles))
+            .collect();
+        assert_eq!(ranked, [(0x0205, 120), (0x0203, 80)]);
+
+        let spots = profile.by_symbol(&symbols, 10);
+        let ranked: Vec<(u16, Option<&str>, u64)> = spots
+            .iter()
+            .map(|spot| (spot.address, spot.symbol.as_deref(), spot.cycles))
+            .collect();
+        assert_eq!(
+            ranked,
+            [
+                (0x0200, Some("Copy"), 200),
+                (0x0100, None, 16),
+                (0x0150, Some("Main"), 4)
+            ]
+        );
+        assert!((spots[0].share - 90.909).abs() < 0.001);
+    }
+}
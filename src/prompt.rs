@@ -0,0 +1,203 @@
+use crate::paths;
+#[cfg(unix)]
+use crate::remote::Remote;
+use colored::Colorize;
+use rustyline::completion::{Completer, Pair};
+use rustyline::config::Configurer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const PROMPT: &str = "(gb-debugger) ";
+// Commands kept between sessions, the oldest go first
+const HISTORY_SIZE: usize = 1000;
+
+// What a command starts with, and the words that can follow
+const COMMANDS: [&str; 39] = [
+    "help",
+    "run",
+    "quit",
+    "pause",
+    "resume",
+    "frame",
+    "step",
+    "rstep",
+    "next",
+    "finish",
+    "until",
+    "stopwatch",
+    "break",
+    "watch",
+    "delete",
+    "breakpoints",
+    "disasm",
+    "trace",
+    "profile",
+    "coverage",
+    "sym",
+    "script",
+    "tui",
+    "x",
+    "reset",
+    "display",
+    "screenshot",
+    "save",
+    "load",
+    "slot",
+    "capture",
+    "record",
+    "mute",
+    "solo",
+    "oam",
+    "show",
+    "set",
+    "fill",
+    "snapshot",
+];
+const ARGUMENTS: [&str; 39] = [
+    "ret", "vblank", "if", "read", "write", "rom", "start", "stop", "take", "diff", "report",
+    "export", "clear", "hard", "state", "audio", "apu", "ppu", "irq", "timer", "io", "stat",
+    "serial", "joypad", "lcdc", "register", "memory", "less", "all", "af", "bc", "de", "hl", "sp",
+    "pc", "zf", "nf", "hf", "cf",
+];
+
+// The debugger's prompt, with history on the arrow keys and Ctrl-R, and tab completion of
+// command and register names. Or the same commands from a socket
+pub struct Prompt {
+    input: Input,
+    history: Option<PathBuf>,
+}
+
+enum Input {
+    Terminal(Box<Editor<Completions, DefaultHistory>>),
+    #[cfg(unix)]
+    Remote(Remote),
+}
+
+impl Prompt {
+    pub fn new() -> rustyline::Result<Self> {
+        let mut editor = Editor::new()?;
+        editor.set_helper(Some(Completions));
+        editor.set_max_history_size(HISTORY_SIZE)?;
+        let history = paths::data_dir().map(|dir| dir.join("debugger-history"));
+        if let Some(history) = &history {
+            // There's none the first time
+            let _ = editor.load_history(history);
+        }
+        Ok(Self {
+            input: Input::Terminal(Box::new(editor)),
+            history,
+        })
+    }
+
+    // Takes commands from connections to address instead of the terminal, see Remote
+    pub fn serve(address: &str) -> io::Result<Self> {
+        // Scripts would have to strip the escape codes
+        colored::control::set_override(false);
+        #[cfg(unix)]
+        return Ok(Self {
+            input: Input::Remote(Remote::listen(address)?),
+            history: None,
+        });
+        #[cfg(not(unix))]
+        Err(io::Error::other(format!(
+            "Serving the debugger on {address} only works on Unix"
+        )))
+    }
+
+    // The next command, None once input has ended. status goes before the prompt. A socket is
+    // waited on without blocking, calling wait meanwhile until it returns false, see Remote::read
+    pub fn read(&mut self, status: &str, wait: &mut dyn FnMut() -> bool) -> Option<String> {
+        let editor = match &mut self.input {
+            Input::Terminal(editor) => editor,
+            #[cfg(unix)]
+            Input::Remote(remote) => return remote.read(&format!("{status}{PROMPT}\n"), wait),
+        };
+        loop {
+            match editor.readline(&format!("{status}{PROMPT}")) {
+                Ok(line) => {
+                    if !line.trim().is_empty() {
+                        let _ = editor.add_history_entry(line.as_str());
+                    }
+                    return Some(line);
+                }
+                // Ctrl-C drops what was typed, like in a shell
+                Err(ReadlineError::Interrupted) => continue,
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+impl Drop for Prompt {
+    fn drop(&mut self) {
+        if let Some(history) = &self.history
+            && let Input::Terminal(editor) = &mut self.input
+        {
+            let saved = history
+                .parent()
+                .is_none_or(|dir| fs::create_dir_all(dir).is_ok())
+                && editor.save_history(history).is_ok();
+            if !saved {
+                eprintln!(
+                    "Couldn't save the debugger history to {}",
+                    history.display()
+                );
+            }
+        }
+    }
+}
+
+struct Completions;
+
+impl Completer for Completions {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let start = before
+            .rfind(char::is_whitespace)
+            .map_or(0, |space| space + 1);
+        let word = &before[start..];
+        let words: &[&str] = if before[..start].trim().is_empty() {
+            &COMMANDS
+        } else {
+            &ARGUMENTS
+        };
+        let candidates = words
+            .iter()
+            .filter(|candidate| candidate.starts_with(word))
+            .map(|candidate| Pair {
+                display: candidate.to_string(),
+                replacement: format!("{candidate} "),
+            })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Highlighter for Completions {
+    fn highlight_prompt<'b, 's: 'b, 'p: 'b>(&'s self, prompt: &'p str, _: bool) -> Cow<'b, str> {
+        Cow::Owned(prompt.bright_green().to_string())
+    }
+}
+
+impl Hinter for Completions {
+    type Hint = String;
+}
+
+impl Validator for Completions {}
+
+impl Helper for Completions {}
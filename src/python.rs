@@ -0,0 +1,120 @@
+use crate::bindings::Target;
+use crate::emulator::Emulator;
+use crate::gpu::{BYTES_PER_PIXEL, FRAME_HEIGHT, FRAME_WIDTH};
+use crate::joypad::Button;
+use numpy::{PyArray1, PyArray3, PyArrayMethods};
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::path::PathBuf;
+
+// The emulator as a Python class, for scripts and reinforcement learning agents that step it a
+// frame at a time and look at the screen or RAM in between. Like PyBoy, but on this core
+#[pyclass(name = "Emulator", unsendable)]
+pub struct PythonEmulator {
+    emulator: Emulator,
+}
+
+#[pymethods]
+impl PythonEmulator {
+    #[new]
+    #[pyo3(signature = (rom=None))]
+    fn new(rom: Option<PathBuf>) -> PyResult<Self> {
+        let mut emulator = Self {
+            emulator: Emulator::new(),
+        };
+        if let Some(rom) = rom {
+            emulator.load_rom(rom)?;
+        }
+        Ok(emulator)
+    }
+
+    fn load_rom(&mut self, path: PathBuf) -> PyResult<()> {
+        self.emulator.load_rom(&path).map_err(|error| {
+            PyOSError::new_err(format!("Couldn't load {}: {error}", path.display()))
+        })
+    }
+
+    // Runs frames as fast as the machine allows, an agent usually acts every few of them
+    #[pyo3(signature = (frames=1))]
+    fn tick(&mut self, frames: u32) {
+        for _ in 0..frames {
+            self.emulator.run_until_vblank();
+        }
+    }
+
+    // Buttons go by their names in bindings files: up, down, left, right, a, b, select, start
+    fn button_press(&mut self, button: &str) -> PyResult<()> {
+        self.emulator.press(button_named(button)?);
+        Ok(())
+    }
+
+    fn button_release(&mut self, button: &str) -> PyResult<()> {
+        self.emulator.release(button_named(button)?);
+        Ok(())
+    }
+
+    // The last frame as a 144x160x3 array of RGB bytes, a copy the emulator won't change
+    fn screen<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyArray3<u8>>> {
+        PyArray1::from_slice(py, self.emulator.framebuffer()).reshape([
+            FRAME_HEIGHT,
+            FRAME_WIDTH,
+            BYTES_PER_PIXEL,
+        ])
+    }
+
+    // The byte the game would read at address, like a score or a player's position in RAM
+    fn peek(&self, address: u16) -> u8 {
+        self.emulator.read(address)
+    }
+
+    fn poke(&mut self, address: u16, value: u8) {
+        self.emulator.write(address, value);
+    }
+
+    // Starts the game over, for the next episode
+    #[pyo3(signature = (hard=false))]
+    fn reset(&mut self, hard: bool) {
+        self.emulator.cpu_mut().reset(hard);
+    }
+
+    fn save_state<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.emulator.save_state())
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> PyResult<()> {
+        self.emulator
+            .load_state(state)
+            .map_err(PyValueError::new_err)
+    }
+}
+
+fn button_named(name: &str) -> PyResult<Button> {
+    match Target::from_name(name) {
+        Some(Target::Button(button)) => Ok(button),
+        _ => Err(PyValueError::new_err(format!("No button called {name}"))),
+    }
+}
+
+// Named after the library, which is what Python looks for
+#[pymodule]
+fn gameboy_emulator(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PythonEmulator>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::test_rom;
+
+    #[test]
+    fn should_step_and_poke_without_python() {
+        let mut emulator = PythonEmulator::new(None).unwrap();
+        emulator.emulator.load_rom_bytes(test_rom()).unwrap();
+        emulator.tick(2);
+        emulator.poke(0xC000, 0x42);
+        assert_eq!(emulator.peek(0xC000), 0x42);
+        assert!(emulator.button_press("start").is_ok());
+        assert!(emulator.button_release("start").is_ok());
+    }
+}
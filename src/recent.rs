@@ -0,0 +1,88 @@
+use crate::paths;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const RECENT_FILE: &str = "recent.txt";
+const MAX_RECENT: usize = 10;
+
+// The ROMs played last, most recent first, kept in the config directory one path per line
+pub struct RecentRoms {
+    roms: Vec<PathBuf>,
+}
+
+impl RecentRoms {
+    // Starts out empty when there's no list yet or it can't be read
+    pub fn load() -> Self {
+        let text = paths::config_dir()
+            .and_then(|dir| fs::read_to_string(dir.join(RECENT_FILE)).ok())
+            .unwrap_or_default();
+        Self::parse(&text)
+    }
+
+    fn parse(text: &str) -> Self {
+        Self {
+            roms: text
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(PathBuf::from)
+                .take(MAX_RECENT)
+                .collect(),
+        }
+    }
+
+    pub fn roms(&self) -> &[PathBuf] {
+        &self.roms
+    }
+
+    // Moves the ROM to the top, the oldest one drops off once the list is full. Paths are stored
+    // absolute so they still work from another directory
+    pub fn add(&mut self, rom: &Path) {
+        let rom = fs::canonicalize(rom).unwrap_or_else(|_| rom.to_path_buf());
+        self.roms.retain(|known| *known != rom);
+        self.roms.insert(0, rom);
+        self.roms.truncate(MAX_RECENT);
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let dir = paths::config_dir()
+            .ok_or_else(|| io::Error::other("No config directory to save recent ROMs in"))?;
+        fs::create_dir_all(&dir)?;
+        let mut text = String::new();
+        for rom in &self.roms {
+            text.push_str(&rom.to_string_lossy());
+            text.push('\n');
+        }
+        fs::write(dir.join(RECENT_FILE), text)
+    }
+}
+
+// Adds a ROM that was just loaded to the list, failing to only costs it a place there
+pub fn remember(rom: &Path) {
+    let mut recent = RecentRoms::load();
+    recent.add(rom);
+    if let Err(error) = recent.save() {
+        eprintln!("Couldn't update the recent ROMs: {error}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_put_the_latest_rom_first() {
+        let mut recent = RecentRoms::parse("/roms/a.gb\n/roms/b.gb\n\n/roms/c.gb\n");
+        assert_eq!(recent.roms().len(), 3);
+        recent.add(Path::new("/roms/c.gb"));
+        assert_eq!(
+            recent.roms(),
+            ["/roms/c.gb", "/roms/a.gb", "/roms/b.gb"].map(PathBuf::from)
+        );
+        for index in 0..MAX_RECENT {
+            recent.add(Path::new(&format!("/roms/{index}.gb")));
+        }
+        assert_eq!(recent.roms().len(), MAX_RECENT);
+        assert_eq!(recent.roms()[0], Path::new("/roms/9.gb"));
+    }
+}
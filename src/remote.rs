@@ -0,0 +1,197 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, PipeReader, PipeWriter, Read, Write};
+use std::net::TcpListener;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+// What the thread serving the socket hands over to the prompt
+enum Message {
+    // A connection was accepted, and where its output goes
+    Connected(OwnedFd),
+    Line(String),
+    Closed,
+}
+
+// How often the window is kept going while waiting for a command
+const WAIT: Duration = Duration::from_millis(16);
+
+// The debugger's prompt served on a socket, for scripts, editors and CI. A connection sends
+// commands a line at a time and gets back what they print, followed by the prompt once the
+// command is done. One connection at a time, the next is accepted when it closes:
+//
+//     $ printf 'step\nshow register pc\n' | nc localhost 6502
+//
+// Connections are accepted and read on a thread of their own, so the emulator's thread can keep
+// its window going while it waits for the next command
+pub struct Remote {
+    messages: Receiver<Message>,
+    connected: bool,
+    // stdout goes into this pipe while connected, and a thread copies it on to the connection
+    pipe: PipeWriter,
+    // The connection the copies go to, None once it stopped taking them
+    output: Arc<Mutex<Option<File>>>,
+    // Where a Unix socket is bound, removed when done
+    path: Option<PathBuf>,
+    // stdout as it was before it was pointed at a connection, to go back to when it closes
+    terminal: OwnedFd,
+}
+
+impl Remote {
+    // A TCP address like 127.0.0.1:6502, or unix: and the path of a Unix socket
+    pub fn listen(address: &str) -> io::Result<Self> {
+        let (listener, path) = match address.strip_prefix("unix:") {
+            Some(path) => {
+                // Left behind by a run that didn't end cleanly, anything else is kept
+                if fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+                    fs::remove_file(path)?;
+                }
+                let listener = Listener::Unix(UnixListener::bind(path)?);
+                (listener, Some(PathBuf::from(path)))
+            }
+            None => (Listener::Tcp(TcpListener::bind(address)?), None),
+        };
+        let (sender, messages) = mpsc::channel();
+        let address = address.to_string();
+        thread::spawn(move || serve(listener, &address, sender));
+        let (reader, pipe) = io::pipe()?;
+        let output = Arc::new(Mutex::new(None));
+        let forwarded = output.clone();
+        thread::spawn(move || forward(reader, &forwarded));
+        Ok(Self {
+            messages,
+            connected: false,
+            pipe,
+            output,
+            path,
+            terminal: io::stdout().as_fd().try_clone_to_owned()?,
+        })
+    }
+
+    // The next command, waiting for a connection first if there's none. wait is called every few
+    // milliseconds meanwhile and stops waiting by returning false. None then, or if the socket
+    // stopped working
+    pub fn read(&mut self, prompt: &str, wait: &mut dyn FnMut() -> bool) -> Option<String> {
+        if self.connected {
+            show_prompt(prompt);
+        }
+        loop {
+            match self.messages.recv_timeout(WAIT) {
+                Ok(Message::Connected(output)) => {
+                    *self.output.lock().unwrap() = Some(File::from(output));
+                    if let Err(error) = redirect_stdout(self.pipe.as_fd()) {
+                        eprintln!("Couldn't send the debugger's output to the connection: {error}");
+                    }
+                    self.connected = true;
+                    show_prompt(prompt);
+                }
+                Ok(Message::Line(line)) => return Some(line),
+                Ok(Message::Closed) => {
+                    self.connected = false;
+                    // Nothing printed meanwhile can reach a closed connection
+                    let _ = redirect_stdout(self.terminal.as_fd());
+                    *self.output.lock().unwrap() = None;
+                    eprintln!("Debugger connection closed");
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if !wait() {
+                        return None;
+                    }
+                }
+                // The thread stopped after saying why
+                Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+    }
+}
+
+impl Drop for Remote {
+    fn drop(&mut self) {
+        let _ = redirect_stdout(self.terminal.as_fd());
+        if let Some(path) = &self.path {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+// Accepts connections one after the other and passes on the lines read from them, until the
+// prompt is gone or the socket stops working
+fn serve(listener: Listener, address: &str, messages: Sender<Message>) {
+    loop {
+        eprintln!("Waiting for a debugger connection on {address}");
+        let connection = match &listener {
+            Listener::Tcp(listener) => listener.accept().and_then(|(stream, _)| {
+                let output = stream.as_fd().try_clone_to_owned()?;
+                Ok((Box::new(stream) as Box<dyn Read + Send>, output))
+            }),
+            Listener::Unix(listener) => listener.accept().and_then(|(stream, _)| {
+                let output = stream.as_fd().try_clone_to_owned()?;
+                Ok((Box::new(stream) as Box<dyn Read + Send>, output))
+            }),
+        };
+        let (reader, output) = match connection {
+            Ok(connection) => connection,
+            Err(error) => {
+                eprintln!("Couldn't accept a debugger connection: {error}");
+                return;
+            }
+        };
+        if messages.send(Message::Connected(output)).is_err() {
+            return;
+        }
+        for line in BufReader::new(reader).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if messages.send(Message::Line(line)).is_err() {
+                return;
+            }
+        }
+        if messages.send(Message::Closed).is_err() {
+            return;
+        }
+    }
+}
+
+// Copies what's printed on to the connection. Writing to a connection that's gone fails, which
+// here only means the rest is dropped, where printing it straight to the socket would panic
+fn forward(mut pipe: PipeReader, output: &Mutex<Option<File>>) {
+    let mut buffer = [0; 4096];
+    // Ends once the pipe is closed, with the prompt
+    while let Ok(read) = pipe.read(&mut buffer)
+        && read > 0
+    {
+        let mut output = output.lock().unwrap();
+        if let Some(connection) = output.as_mut()
+            && connection.write_all(&buffer[..read]).is_err()
+        {
+            *output = None;
+        }
+    }
+}
+
+fn show_prompt(prompt: &str) {
+    print!("{prompt}");
+    let _ = io::stdout().flush();
+}
+
+// Everything the debugger prints goes to stdout, so pointing stdout at the pipe to the connection
+// sends it all there without the commands knowing
+fn redirect_stdout(to: BorrowedFd) -> io::Result<()> {
+    io::stdout().flush()?;
+    // Both descriptors are open, and dup2 only changes what stdout's one refers to
+    if unsafe { libc::dup2(to.as_raw_fd(), libc::STDOUT_FILENO) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+
+// Frames between snapshots, rewinding steps back this many frames at a time
+pub const INTERVAL: u64 = 4;
+const FRAMES_PER_SECOND: u64 = 60;
+
+// The last few seconds of save states, each compressed on its own since most of the address space
+// in one is unused and shrinks well. The oldest snapshot makes room for the newest once the buffer
+// is full
+pub struct Rewind {
+    snapshots: VecDeque<Vec<u8>>,
+    capacity: usize,
+}
+
+impl Rewind {
+    // Keeps enough snapshots to go back this many seconds, none with 0
+    pub fn new(seconds: u32) -> Self {
+        Self {
+            snapshots: VecDeque::new(),
+            capacity: (seconds as u64 * FRAMES_PER_SECOND / INTERVAL) as usize,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub fn push(&mut self, state: &[u8]) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+        let compressed = encoder
+            .write_all(state)
+            .and_then(|_| encoder.finish())
+            .expect("Compressing into memory can't fail");
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(compressed);
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+
+    // The newest snapshot, which is dropped so the next one goes further back
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let compressed = self.snapshots.pop_back()?;
+        let mut state = Vec::new();
+        DeflateDecoder::new(&compressed[..])
+            .read_to_end(&mut state)
+            .ok()?;
+        Some(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_give_back_the_newest_snapshots_first() {
+        // Room for 15 snapshots
+        let mut rewind = Rewind::new(1);
+        for i in 0..20u8 {
+            rewind.push(&[i; 1000]);
+        }
+        for i in (5..20u8).rev() {
+            assert_eq!(rewind.pop(), Some(vec![i; 1000]));
+        }
+        assert_eq!(rewind.pop(), None);
+    }
+
+    #[test]
+    fn should_keep_nothing_when_disabled() {
+        let mut rewind = Rewind::new(0);
+        assert!(!rewind.is_enabled());
+        rewind.push(&[1, 2, 3]);
+        assert_eq!(rewind.pop(), None);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+// Cartridge types with a rumble motor, MBC5 + RUMBLE with and without RAM and battery
+const RUMBLE_CARTRIDGES: [u8; 3] = [0x1C, 0x1D, 0x1E];
+const CARTRIDGE_TYPE: usize = 0x147;
+const MOTOR_BIT: u8 = 0b0000_1000;
+
+// The motor of a rumble cartridge, switched by bit 3 of the RAM bank register. Games vary its
+// strength by toggling it many times a frame, so what reaches the controller is how long it was on
+// over the frame, averaged over the last few frames to smooth out the pulses
+pub struct Rumble {
+    present: bool,
+    motor: bool,
+    cycles: u32,
+    cycles_on: u32,
+    // Weight of the newest frame in the average, 1 / smoothing frames
+    weight: f32,
+    strength: f32,
+}
+
+impl Rumble {
+    pub fn new() -> Self {
+        Self {
+            present: false,
+            motor: false,
+            cycles: 0,
+            cycles_on: 0,
+            weight: 1.0,
+            strength: 0.0,
+        }
+    }
+
+    pub fn detect(&mut self, rom: &[u8]) {
+        self.present = rom
+            .get(CARTRIDGE_TYPE)
+            .is_some_and(|kind| RUMBLE_CARTRIDGES.contains(kind));
+    }
+
+    pub fn is_present(&self) -> bool {
+        self.present
+    }
+
+    // Number of frames the strength is averaged over, 1 follows the motor as it is
+    pub fn set_smoothing(&mut self, frames: u32) {
+        self.weight = 1.0 / frames.max(1) as f32;
+    }
+
+    // The rest of the value selects a RAM bank
+    pub fn write(&mut self, value: u8) {
+        self.motor = value & MOTOR_BIT != 0;
+    }
+
+    pub fn step(&mut self, cycles: u32) {
+        self.cycles += cycles;
+        if self.motor {
+            self.cycles_on += cycles;
+        }
+    }
+
+    // Strength from 0 to 1 including the frame that just ended
+    pub fn end_frame(&mut self) -> f32 {
+        let duty = if self.cycles == 0 {
+            0.0
+        } else {
+            self.cycles_on as f32 / self.cycles as f32
+        };
+        self.strength += (duty - self.strength) * self.weight;
+        // Let the tail of the average die out instead of buzzing faintly forever
+        if self.strength < 0.01 {
+            self.strength = 0.0;
+        }
+        self.cycles = 0;
+        self.cycles_on = 0;
+        self.strength
+    }
+}
+
+impl Snapshot for Rumble {
+    fn save(&self, state: &mut StateWriter) {
+        state.bool(self.motor);
+        state.u32(self.cycles);
+        state.u32(self.cycles_on);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.motor = state.bool()?;
+        self.cycles = state.u32()?;
+        self.cycles_on = state.u32()?.min(self.cycles);
+        Ok(())
+    }
+}
+
+impl Default for Rumble {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_report_the_motor_duty_cycle() {
+        let mut rumble = Rumble::new();
+        rumble.write(0x08);
+        rumble.step(100);
+        rumble.write(0x02);
+        rumble.step(300);
+        assert_eq!(rumble.end_frame(), 0.25);
+        assert_eq!(rumble.end_frame(), 0.0);
+    }
+
+    #[test]
+    fn should_smooth_over_frames() {
+        let mut rumble = Rumble::new();
+        rumble.set_smoothing(4);
+        rumble.write(0x08);
+        rumble.step(100);
+        assert_eq!(rumble.end_frame(), 0.25);
+        rumble.step(100);
+        assert_eq!(rumble.end_frame(), 0.4375);
+        rumble.write(0x00);
+        rumble.step(100);
+        assert!(rumble.end_frame() < 0.4375);
+    }
+
+    #[test]
+    fn should_detect_rumble_cartridges() {
+        let mut rom = vec![0; 0x150];
+        let mut rumble = Rumble::new();
+        rumble.detect(&rom);
+        assert!(!rumble.is_present());
+        rom[CARTRIDGE_TYPE] = 0x1E;
+        rumble.detect(&rom);
+        assert!(rumble.is_present());
+        rumble.detect(&[0; 0x100]);
+        assert!(!rumble.is_present());
+    }
+}
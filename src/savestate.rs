@@ -0,0 +1,258 @@
+use std::path::{Path, PathBuf};
+
+pub const MAGIC: &[u8; 4] = b"GBSS";
+pub const VERSION: u8 = 2;
+pub const SLOTS: u8 = 10;
+
+// Something whose state goes into a save state. Only what the emulated machine would remember is
+// saved, settings like palettes or muted channels stay as they are when a state is loaded
+pub trait Snapshot {
+    fn save(&self, state: &mut StateWriter);
+    fn load(&mut self, state: &mut StateReader) -> Result<(), String>;
+}
+
+// Memory and other plain buffers
+impl<const N: usize> Snapshot for [u8; N] {
+    fn save(&self, state: &mut StateWriter) {
+        state.bytes(self);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), String> {
+        state.bytes(self)
+    }
+}
+
+// Little endian fields one after the other, read back in the same order
+#[derive(Default)]
+pub struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend(bytes);
+    }
+
+    // Writes what write produces as a section: its id, the version of its layout and its length,
+    // so readers can skip what they don't know
+    pub fn section(&mut self, id: &[u8; 4], version: u8, write: impl FnOnce(&mut StateWriter)) {
+        let mut section = StateWriter::new();
+        write(&mut section);
+        self.bytes(id);
+        self.u8(version);
+        self.u32(section.bytes.len() as u32);
+        self.bytes(&section.bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+pub struct StateReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, String> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    // Fills the whole of bytes
+    pub fn bytes(&mut self, bytes: &mut [u8]) -> Result<(), String> {
+        bytes.copy_from_slice(self.take(bytes.len())?);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let mut array = [0; N];
+        self.bytes(&mut array)?;
+        Ok(array)
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < count {
+            return Err("Save state is truncated".to_string());
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+}
+
+pub struct Section<'a> {
+    pub id: [u8; 4],
+    pub version: u8,
+    pub data: &'a [u8],
+}
+
+impl Section<'_> {
+    pub fn name(&self) -> String {
+        section_name(&self.id)
+    }
+
+    // Loads part from the section, which has to hold exactly its state
+    pub fn load(&self, part: &mut dyn Snapshot) -> Result<(), String> {
+        let mut state = StateReader::new(self.data);
+        part.load(&mut state)?;
+        if !state.is_empty() {
+            return Err(format!(
+                "The {} section is longer than expected",
+                self.name()
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub fn section_name(id: &[u8; 4]) -> String {
+    String::from_utf8_lossy(id).trim_end().to_string()
+}
+
+// The magic and format version, then sections until the end. A section's id says what it holds,
+// its version how that's laid out. New sections can be added without breaking older emulators,
+// which skip them, and a section changes version whenever its layout changes
+pub fn parse(bytes: &[u8]) -> Result<Vec<Section<'_>>, String> {
+    if bytes.get(..4) != Some(MAGIC) {
+        return Err("Not a save state".to_string());
+    }
+    let mut state = StateReader::new(&bytes[4..]);
+    let version = state.u8()?;
+    if version != VERSION {
+        return Err(format!("Unsupported save state format {version}"));
+    }
+    let mut sections = Vec::new();
+    while !state.is_empty() {
+        let id = state.array()?;
+        let version = state.u8()?;
+        let length = state.u32()? as usize;
+        let data = state.take(length)?;
+        sections.push(Section { id, version, data });
+    }
+    Ok(sections)
+}
+
+// The first format, from before sections: the parts of the machine one after the other with nothing
+// to tell them apart, so only the CPU can read them. What follows the header if bytes is one
+pub fn parse_v1(bytes: &[u8]) -> Option<StateReader<'_>> {
+    match bytes.strip_prefix(MAGIC)?.split_first()? {
+        (1, rest) => Some(StateReader::new(rest)),
+        _ => None,
+    }
+}
+
+// States sit next to the ROM, roms/game.gb saves slot 3 to roms/game.ss3
+pub fn slot_path(rom: &Path, slot: u8) -> PathBuf {
+    rom.with_extension(format!("ss{slot}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_read_back_what_was_written() {
+        let mut writer = StateWriter::new();
+        writer.u8(0x12);
+        writer.bool(true);
+        writer.u16(0x3456);
+        writer.u32(0x789A_BCDE);
+        writer.u64(u64::MAX);
+        writer.bytes(&[1, 2, 3]);
+        let bytes = writer.into_bytes();
+        let mut reader = StateReader::new(&bytes);
+        assert_eq!(reader.u8(), Ok(0x12));
+        assert_eq!(reader.bool(), Ok(true));
+        assert_eq!(reader.u16(), Ok(0x3456));
+        assert_eq!(reader.u32(), Ok(0x789A_BCDE));
+        assert_eq!(reader.u64(), Ok(u64::MAX));
+        let mut three = [0; 3];
+        assert_eq!(reader.bytes(&mut three), Ok(()));
+        assert_eq!(three, [1, 2, 3]);
+        assert!(reader.is_empty());
+        assert!(reader.u8().is_err());
+    }
+
+    #[test]
+    fn should_split_a_state_into_sections() {
+        let mut writer = StateWriter::new();
+        writer.bytes(MAGIC);
+        writer.u8(VERSION);
+        writer.section(b"ONE ", 1, |state| state.u16(0x1234));
+        writer.section(b"TWO ", 3, |_| {});
+        let bytes = writer.into_bytes();
+        let sections = parse(&bytes).unwrap();
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].name(), "ONE");
+        assert_eq!(sections[0].data, &[0x34, 0x12]);
+        assert_eq!((sections[1].id, sections[1].version), (*b"TWO ", 3));
+        assert!(sections[1].data.is_empty());
+        assert!(parse(&bytes[..bytes.len() - 3]).is_err());
+        assert!(parse(b"GBSS\x01").is_err());
+        assert!(parse(b"GBMV\x02").is_err());
+    }
+
+    #[test]
+    fn should_tell_version_1_states_apart() {
+        assert!(parse_v1(b"GBSS\x01\x12").is_some_and(|mut state| state.u8() == Ok(0x12)));
+        assert!(parse_v1(b"GBSS\x02").is_none());
+        assert!(parse_v1(b"GBMV\x01").is_none());
+        assert!(parse_v1(b"GBSS").is_none());
+    }
+
+    #[test]
+    fn should_put_slots_next_to_the_rom() {
+        assert_eq!(
+            slot_path(Path::new("roms/game.gb"), 3),
+            PathBuf::from("roms/game.ss3")
+        );
+    }
+}
@@ -0,0 +1,23 @@
+use crate::gpu::{FRAME_HEIGHT, FRAME_WIDTH};
+use crate::paths;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+// Writes an RGB24 frame as a timestamped PNG inside `dir`, returning the file's path
+pub fn save(frame: &[u8], dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let path = paths::timestamped_file(dir, "screenshot", "png")?;
+    write_png(frame, &path)?;
+    Ok(path)
+}
+
+pub fn write_png(frame: &[u8], path: &Path) -> Result<(), Box<dyn Error>> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, FRAME_WIDTH as u32, FRAME_HEIGHT as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(frame)?;
+    Ok(())
+}
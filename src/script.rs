@@ -0,0 +1,200 @@
+use crate::debugger;
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, NativeCallContext};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+// Debugger scripts, in Rhai. A script runs on a thread of its own and asks the debugger for
+// everything it does, so the machine never leaves the debugger. For example:
+//
+//     on_break(0x1234, || print(hexdump(0xC000, 256)));
+//     loop { run(); }
+pub enum Request {
+    Register(String),
+    SetRegister(String, i64),
+    Peek(u16),
+    Poke(u16, u8),
+    Step(u32),
+    // Until something stops the machine, answered with PC
+    Run,
+    // Answered with the new breakpoint's number
+    Break(u16),
+}
+
+// Values for requests that have one, 0 for the others
+pub type Reply = Result<i64, String>;
+
+pub struct Script {
+    requests: Receiver<Request>,
+    replies: Sender<Reply>,
+    thread: JoinHandle<Result<(), String>>,
+}
+
+impl Script {
+    pub fn start(path: PathBuf) -> Self {
+        let (request_sender, requests) = mpsc::channel();
+        let (replies, reply_receiver) = mpsc::channel();
+        let thread = thread::spawn(move || run(path, request_sender, reply_receiver));
+        Self {
+            requests,
+            replies,
+            thread,
+        }
+    }
+
+    // What the script wants next, None once it's over
+    pub fn next(&self) -> Option<Request> {
+        self.requests.recv().ok()
+    }
+
+    pub fn reply(&self, reply: Reply) {
+        // The script can only be gone if it's ended, which finish reports
+        let _ = self.replies.send(reply);
+    }
+
+    // Waits for the script to end, a script still waiting on a reply fails on the next one
+    pub fn finish(self) -> Result<(), String> {
+        drop(self.replies);
+        self.thread
+            .join()
+            .unwrap_or_else(|_| Err("The script crashed".to_string()))
+    }
+}
+
+// The script's end of the channels, and the functions to call at breakpoints
+struct Debugger {
+    requests: Sender<Request>,
+    replies: Receiver<Reply>,
+    callbacks: RefCell<HashMap<u16, FnPtr>>,
+}
+
+impl Debugger {
+    fn ask(&self, request: Request) -> Result<i64, Box<EvalAltResult>> {
+        let gone = || "The debugger stopped the script".to_string();
+        self.requests.send(request).map_err(|_| gone())?;
+        let reply = self.replies.recv().map_err(|_| gone())?;
+        Ok(reply?)
+    }
+}
+
+fn address(value: i64) -> Result<u16, Box<EvalAltResult>> {
+    u16::try_from(value).map_err(|_| format!("{value} isn't an address").into())
+}
+
+fn run(path: PathBuf, requests: Sender<Request>, replies: Receiver<Reply>) -> Result<(), String> {
+    let debugger = Rc::new(Debugger {
+        requests,
+        replies,
+        callbacks: RefCell::default(),
+    });
+    let mut engine = Engine::new();
+    let d = debugger.clone();
+    engine.register_fn("reg", move |name: &str| {
+        d.ask(Request::Register(name.to_string()))
+    });
+    let d = debugger.clone();
+    engine.register_fn("set_reg", move |name: &str, value: i64| {
+        d.ask(Request::SetRegister(name.to_string(), value))
+            .map(|_| ())
+    });
+    let d = debugger.clone();
+    engine.register_fn("peek", move |at: i64| d.ask(Request::Peek(address(at)?)));
+    let d = debugger.clone();
+    engine.register_fn("poke", move |at: i64, value: i64| {
+        let value = u8::try_from(value).map_err(|_| format!("{value} isn't a byte"))?;
+        d.ask(Request::Poke(address(at)?, value)).map(|_| ())
+    });
+    let d = debugger.clone();
+    engine.register_fn("step", move || d.ask(Request::Step(1)).map(|_| ()));
+    let d = debugger.clone();
+    engine.register_fn("step", move |count: i64| {
+        let count = u32::try_from(count).map_err(|_| format!("Can't step {count} times"))?;
+        d.ask(Request::Step(count)).map(|_| ())
+    });
+    let d = debugger.clone();
+    engine.register_fn("break_at", move |at: i64| {
+        d.ask(Request::Break(address(at)?))
+    });
+    let d = debugger.clone();
+    engine.register_fn("on_break", move |at: i64, callback: FnPtr| {
+        let at = address(at)?;
+        let number = d.ask(Request::Break(at))?;
+        d.callbacks.borrow_mut().insert(at, callback);
+        Ok::<_, Box<EvalAltResult>>(number)
+    });
+    let d = debugger.clone();
+    engine.register_fn("run", move |context: NativeCallContext| {
+        let pc = d.ask(Request::Run)?;
+        let callback = d.callbacks.borrow().get(&(pc as u16)).cloned();
+        // What the callback returns is of no use, run gives back PC
+        if let Some(callback) = callback {
+            let _: Dynamic = callback.call_within_context(&context, ())?;
+        }
+        Ok::<_, Box<EvalAltResult>>(pc)
+    });
+    let d = debugger.clone();
+    engine.register_fn("hexdump", move |start: i64, count: i64| {
+        let start = address(start)?;
+        let bytes = (start..=0xFFFF)
+            .take(count.max(0) as usize)
+            .map(|at| d.ask(Request::Peek(at)).map(|byte| byte as u8))
+            .collect::<Result<Vec<u8>, _>>()?;
+        let rows = debugger::hexdump(start, bytes.len(), |at| bytes[(at - start) as usize]);
+        Ok::<_, Box<EvalAltResult>>(rows.join("\n"))
+    });
+    engine.run_file(path).map_err(|error| error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn should_call_back_at_breakpoints() {
+        let path = std::env::temp_dir().join("gameboy-emulator-script-test.rhai");
+        fs::write(
+            &path,
+            "on_break(0x0150, || poke(0xC000, reg(\"a\") + 1));\n\
+             step(3);\n\
+             if run() != 0x0150 { throw \"wrong stop\"; }\n\
+             set_reg(\"hl\", peek(0xC000));\n\
+             print(hexdump(0xC000, 2));",
+        )
+        .unwrap();
+        let script = Script::start(path.clone());
+        let mut memory = [0u8; 2];
+        let mut log = Vec::new();
+        while let Some(request) = script.next() {
+            let reply = match request {
+                Request::Register(name) => Ok(if name == "a" { 0x3C } else { -1 }),
+                Request::Peek(address) => Ok(memory[address as usize - 0xC000] as i64),
+                Request::Poke(address, value) => {
+                    memory[address as usize - 0xC000] = value;
+                    Ok(0)
+                }
+                Request::SetRegister(name, value) => {
+                    log.push(format!("{name}={value:X}"));
+                    Ok(0)
+                }
+                Request::Step(count) => {
+                    log.push(format!("step {count}"));
+                    Ok(0)
+                }
+                Request::Run => Ok(0x0150),
+                Request::Break(address) => {
+                    log.push(format!("break {address:04X}"));
+                    Ok(1)
+                }
+            };
+            script.reply(reply);
+        }
+        fs::remove_file(&path).unwrap();
+        assert_eq!(script.finish(), Ok(()));
+        assert_eq!(memory[0], 0x3D);
+        assert_eq!(log, ["break 0150", "step 3", "hl=3D"]);
+    }
+}
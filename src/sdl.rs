@@ -0,0 +1,745 @@
+use crate::audio::AudioSink;
+use crate::bindings::{Bindings, Chord, Target};
+use crate::config::Config;
+use crate::display::Scaling;
+use crate::event::{Action, Event, EventSource};
+use crate::filter::FilterPipeline;
+use crate::gamepad::Gamepad;
+use crate::gpu::{BYTES_PER_PIXEL, FRAME_HEIGHT, FRAME_WIDTH, Renderer};
+use crate::joypad::Button;
+use crate::osd::{self, GLYPH_HEIGHT, Osd};
+use crate::recent::RecentRoms;
+use crate::viewers::{Image, View};
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+use sdl2::controller::Button as PadButton;
+use sdl2::event::{Event as SdlEvent, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, Texture};
+use sdl2::video::{FullscreenType, Window};
+use sdl2::{EventPump, Sdl, VideoSubsystem};
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, Sender};
+use std::time::Duration;
+
+const VIEW_SCALING_FACTOR: u32 = 3;
+// Audio that would be queued beyond this many device buffers is dropped rather than left to grow
+// into latency
+const MAX_QUEUED_BUFFERS: u32 = 4;
+
+// A debug view in its own window, next to the game
+struct ViewWindow {
+    view: View,
+    canvas: Canvas<Window>,
+    texture: Texture,
+}
+
+// The second emulator of --dual, which runs on its own thread. Keys pressed while its window has
+// focus go to it instead
+struct SecondScreen {
+    canvas: Canvas<Window>,
+    texture: Texture,
+    frames: Receiver<Vec<u8>>,
+    events: Sender<Event>,
+}
+
+struct RomPicker {
+    roms: Vec<PathBuf>,
+    selected: usize,
+}
+
+pub struct SdlFrontend {
+    canvas: Canvas<Window>,
+    // Textures have no lifetime thanks to the unsafe_textures feature, SDL frees this one
+    // together with the canvas
+    texture: Texture,
+    texture_size: (usize, usize),
+    filters: FilterPipeline,
+    scaling: Scaling,
+    paused: bool,
+    // Open over the picture while choosing one of the recent ROMs
+    picker: Option<RomPicker>,
+    // Whether one of our windows had keyboard focus when events were last polled
+    focused: bool,
+    osd: Osd,
+    views: Vec<ViewWindow>,
+    second_screen: Option<SecondScreen>,
+    video_subsystem: VideoSubsystem,
+    audio: Option<AudioQueue<f32>>,
+    keys: Vec<(Keycode, Chord, Target)>,
+    gamepad: Option<Gamepad>,
+    event_pump: EventPump,
+}
+
+impl SdlFrontend {
+    // Fails on bindings naming keys or controller buttons SDL doesn't know
+    pub fn new(config: &Config, bindings: &Bindings) -> Result<Self, String> {
+        let keys = Self::resolve_keys(bindings)?;
+        let buttons = Self::resolve_controller(bindings)?;
+        let sdl_context = sdl2::init().unwrap();
+        let title = "GameBoy Emulator".to_string();
+        let video_subsystem = sdl_context.video().unwrap();
+        let window = video_subsystem
+            .window(
+                title.as_str(),
+                FRAME_WIDTH as u32 * config.scale,
+                FRAME_HEIGHT as u32 * config.scale,
+            )
+            .position_centered()
+            .resizable()
+            .build()
+            .unwrap();
+        let canvas_builder = window.into_canvas();
+        let canvas_builder = if config.vsync {
+            canvas_builder.present_vsync()
+        } else {
+            canvas_builder
+        };
+        let mut canvas = canvas_builder.build().unwrap();
+        Self::apply_scaling(&mut canvas, config.scaling);
+        let filters = FilterPipeline::new(config.filter, config.ghosting);
+        let texture_size = filters.output_size();
+        let texture = Self::create_texture(&canvas, texture_size);
+        let audio = match Self::open_audio(&sdl_context, config) {
+            Ok(audio) => Some(audio),
+            Err(error) => {
+                eprintln!("Couldn't open the audio device, continuing without sound: {error}");
+                None
+            }
+        };
+        let gamepad = match sdl_context.game_controller() {
+            Ok(subsystem) => Some(Gamepad::new(subsystem, config.controller, buttons)),
+            Err(error) => {
+                eprintln!("Couldn't initialize game controllers: {error}");
+                None
+            }
+        };
+        let event_pump = sdl_context.event_pump().unwrap();
+
+        Ok(Self {
+            canvas,
+            texture,
+            texture_size,
+            filters,
+            scaling: config.scaling,
+            paused: false,
+            picker: None,
+            focused: true,
+            osd: Osd::new(),
+            views: Vec::new(),
+            second_screen: None,
+            video_subsystem,
+            audio,
+            keys,
+            gamepad,
+            event_pump,
+        })
+    }
+
+    fn resolve_keys(bindings: &Bindings) -> Result<Vec<(Keycode, Chord, Target)>, String> {
+        let mut keys = Vec::new();
+        for (target, chords) in &bindings.keys {
+            for chord in chords {
+                let chord = Chord::parse(chord);
+                let keycode =
+                    Keycode::from_name(&chord.key).ok_or(format!("Unknown key: {}", chord.key))?;
+                keys.push((keycode, chord, *target));
+            }
+        }
+        Ok(keys)
+    }
+
+    fn resolve_controller(bindings: &Bindings) -> Result<Vec<(PadButton, Button)>, String> {
+        let mut buttons = Vec::new();
+        for (button, names) in &bindings.controller {
+            for name in names {
+                let pad_button = PadButton::from_string(name)
+                    .ok_or(format!("Unknown controller button: {name}"))?;
+                buttons.push((pad_button, *button));
+            }
+        }
+        Ok(buttons)
+    }
+
+    fn open_audio(sdl_context: &Sdl, config: &Config) -> Result<AudioQueue<f32>, String> {
+        let desired = AudioSpecDesired {
+            freq: Some(config.sample_rate as i32),
+            channels: Some(2),
+            samples: Some(config.audio_buffer),
+        };
+        let queue = sdl_context
+            .audio()?
+            .open_queue(config.audio_device.as_deref(), &desired)?;
+        queue.resume();
+        // The device may round the buffer to a size it supports, this is the latency actually
+        // achieved
+        let spec = queue.spec();
+        println!(
+            "Audio: {}, {} Hz, {} sample buffer ({:.1} ms)",
+            config.audio_device.as_deref().unwrap_or("default device"),
+            spec.freq,
+            spec.samples,
+            spec.samples as f64 * 1000.0 / spec.freq as f64
+        );
+        Ok(queue)
+    }
+
+    pub fn audio_devices() -> Result<Vec<String>, String> {
+        let audio = sdl2::init()?.audio()?;
+        let count = audio.num_audio_playback_devices().unwrap_or(0);
+        (0..count)
+            .map(|index| audio.audio_playback_device_name(index))
+            .collect()
+    }
+
+    fn create_texture(canvas: &Canvas<Window>, (width, height): (usize, usize)) -> Texture {
+        canvas
+            .texture_creator()
+            .create_texture_streaming(PixelFormatEnum::RGB24, width as u32, height as u32)
+            .expect("Couldn't create texture")
+    }
+
+    // The renderer's logical size does the letterboxing, dropping it makes copies fill the window
+    fn apply_scaling(canvas: &mut Canvas<Window>, scaling: Scaling) {
+        let (width, height) = match scaling {
+            Scaling::Integer | Scaling::Fit => (FRAME_WIDTH as u32, FRAME_HEIGHT as u32),
+            Scaling::Stretch => (0, 0),
+        };
+        let _ = canvas.set_logical_size(width, height);
+        let _ = canvas.set_integer_scale(scaling == Scaling::Integer);
+    }
+
+    fn open_view(&self, view: View) -> Result<ViewWindow, String> {
+        let (width, height) = view.size();
+        let window = self
+            .video_subsystem
+            .window(
+                view.title(),
+                width as u32 * VIEW_SCALING_FACTOR,
+                height as u32 * VIEW_SCALING_FACTOR,
+            )
+            .build()
+            .map_err(|error| error.to_string())?;
+        let mut canvas = window
+            .into_canvas()
+            .build()
+            .map_err(|error| error.to_string())?;
+        let _ = canvas.set_logical_size(width as u32, height as u32);
+        let texture = Self::create_texture(&canvas, (width, height));
+        Ok(ViewWindow {
+            view,
+            canvas,
+            texture,
+        })
+    }
+
+    pub fn attach_second_screen(
+        &mut self,
+        frames: Receiver<Vec<u8>>,
+        events: Sender<Event>,
+    ) -> Result<(), String> {
+        let (width, height) = self.canvas.window().size();
+        let window = self
+            .video_subsystem
+            .window("GameBoy Emulator - Player 2", width, height)
+            .resizable()
+            .build()
+            .map_err(|error| error.to_string())?;
+        let mut canvas = window
+            .into_canvas()
+            .build()
+            .map_err(|error| error.to_string())?;
+        let _ = canvas.set_logical_size(FRAME_WIDTH as u32, FRAME_HEIGHT as u32);
+        let texture = Self::create_texture(&canvas, (FRAME_WIDTH, FRAME_HEIGHT));
+        self.second_screen = Some(SecondScreen {
+            canvas,
+            texture,
+            frames,
+            events,
+        });
+        Ok(())
+    }
+
+    // Shows the latest frame the second emulator finished, if there's a new one
+    fn present_second_screen(&mut self) {
+        if let Some(screen) = &mut self.second_screen
+            && let Some(frame) = screen.frames.try_iter().last()
+        {
+            let _ = screen
+                .texture
+                .update(None, &frame, FRAME_WIDTH * BYTES_PER_PIXEL);
+            screen.canvas.clear();
+            let _ = screen.canvas.copy(&screen.texture, None, None);
+            screen.canvas.present();
+        }
+    }
+
+    fn has_focus(&self) -> bool {
+        self.canvas.window().has_input_focus()
+            || self
+                .views
+                .iter()
+                .any(|window| window.canvas.window().has_input_focus())
+            || self
+                .second_screen
+                .as_ref()
+                .is_some_and(|screen| screen.canvas.window().has_input_focus())
+    }
+
+    // Joypad events from keys pressed in the second screen's window go to the second emulator,
+    // returns whether the event was sent there
+    fn send_to_second_screen(&self, window_id: u32, event: &Event) -> bool {
+        match &self.second_screen {
+            Some(screen)
+                if screen.canvas.window().id() == window_id
+                    && matches!(event, Event::ButtonDown(_) | Event::ButtonUp(_)) =>
+            {
+                let _ = screen.events.send(event.clone());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn toggle_view(&mut self, view: View) {
+        if let Some(index) = self.views.iter().position(|window| window.view == view) {
+            self.views.remove(index);
+            return;
+        }
+        match self.open_view(view) {
+            Ok(window) => self.views.push(window),
+            Err(error) => eprintln!("Couldn't open the {} view: {error}", view.title()),
+        }
+    }
+
+    // Filters are a presentation concern, the emulator never sees these actions. Returns whether
+    // the event was consumed here
+    fn handle_frontend_event(&mut self, event: &Event) -> bool {
+        match event {
+            Event::Action(Action::CycleFilter) => {
+                self.filters.filter = self.filters.filter.next();
+                true
+            }
+            Event::Action(Action::ToggleGhosting) => {
+                self.filters.ghosting = !self.filters.ghosting;
+                true
+            }
+            Event::Action(Action::ToggleView(view)) => {
+                self.toggle_view(*view);
+                true
+            }
+            Event::Action(Action::RecentRoms) => {
+                self.open_picker();
+                true
+            }
+            Event::Action(Action::CycleScaling) => {
+                self.scaling = self.scaling.next();
+                Self::apply_scaling(&mut self.canvas, self.scaling);
+                self.redraw();
+                true
+            }
+            Event::Action(Action::SetScale(scale)) => {
+                self.set_scale(*scale);
+                true
+            }
+            Event::Action(Action::ToggleFullscreen) => {
+                let window = self.canvas.window_mut();
+                let fullscreen = match window.fullscreen_state() {
+                    FullscreenType::Off => FullscreenType::Desktop,
+                    _ => FullscreenType::Off,
+                };
+                if let Err(error) = window.set_fullscreen(fullscreen) {
+                    eprintln!("Couldn't toggle fullscreen: {error}");
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Sizes the window to a whole multiple of the picture, leaving fullscreen for it
+    fn set_scale(&mut self, scale: u32) {
+        let window = self.canvas.window_mut();
+        if window.fullscreen_state() != FullscreenType::Off {
+            let _ = window.set_fullscreen(FullscreenType::Off);
+        }
+        let size = (FRAME_WIDTH as u32 * scale, FRAME_HEIGHT as u32 * scale);
+        match window.set_size(size.0, size.1) {
+            Ok(()) => self.show_message(&format!("Scale {scale}x")),
+            Err(error) => eprintln!("Couldn't resize the window: {error}"),
+        }
+    }
+
+    // Draws the last frame again, the texture keeps it around between frames
+    fn redraw(&mut self) {
+        self.canvas.clear();
+        let _ = self.canvas.copy(&self.texture, None, None);
+        if self.paused {
+            self.draw_pause_overlay();
+        }
+        self.draw_picker();
+        self.draw_messages();
+        self.canvas.present();
+    }
+
+    // What drawing is done in, the frame's own pixels unless the picture is stretched
+    fn drawing_size(&self) -> (u32, u32) {
+        match self.canvas.logical_size() {
+            (0, 0) => self.canvas.output_size().unwrap_or((0, 0)),
+            size => size,
+        }
+    }
+
+    // Dims the picture and puts a pause symbol in the middle
+    fn draw_pause_overlay(&mut self) {
+        let (width, height) = self.drawing_size();
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 128));
+        let _ = self.canvas.fill_rect(None);
+        self.canvas.set_draw_color(Color::RGB(255, 255, 255));
+        let (bar_width, bar_height) = (width / 16, height / 4);
+        let top = ((height - bar_height) / 2) as i32;
+        for left in [width / 2 - bar_width * 3 / 2, width / 2 + bar_width / 2] {
+            let _ = self
+                .canvas
+                .fill_rect(Rect::new(left as i32, top, bar_width, bar_height));
+        }
+        // Clearing uses the draw color
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+    }
+
+    // Messages stack up from the bottom left corner, newest at the bottom, each on a dark strip
+    // so it reads on any picture. Sized in Game Boy pixels
+    fn draw_messages(&mut self) {
+        let (width, height) = self.drawing_size();
+        let pixel = (width / FRAME_WIDTH as u32).max(1);
+        let pitch = (GLYPH_HEIGHT + 3) * pixel;
+        let messages: Vec<String> = self.osd.messages().map(str::to_string).collect();
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        for (line, message) in messages.iter().rev().enumerate() {
+            let top = height as i32 - (line as i32 + 1) * pitch as i32;
+            let strip = Rect::new(
+                pixel as i32,
+                top,
+                (osd::text_width(message) + 2) * pixel,
+                (GLYPH_HEIGHT + 2) * pixel,
+            );
+            self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+            let _ = self.canvas.fill_rect(strip);
+            self.draw_text(message, (strip.x(), strip.y()), pixel);
+        }
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+    }
+
+    // The recent ROMs over the darkened picture, an arrow on the selected one
+    fn draw_picker(&mut self) {
+        let Some(picker) = &self.picker else {
+            return;
+        };
+        let (width, _) = self.drawing_size();
+        let pixel = (width / FRAME_WIDTH as u32).max(1);
+        let mut lines = vec!["Recent ROMs".to_string()];
+        lines.extend(picker.roms.iter().enumerate().map(|(index, rom)| {
+            let name = rom.file_name().unwrap_or(rom.as_os_str()).to_string_lossy();
+            let arrow = if index == picker.selected { '>' } else { ' ' };
+            format!("{arrow} {name}")
+        }));
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 192));
+        let _ = self.canvas.fill_rect(None);
+        let columns = width / pixel - 2;
+        for (line, text) in lines.iter().enumerate() {
+            let top = (1 + line as u32 * (GLYPH_HEIGHT + 2)) * pixel;
+            let text = osd::truncate(text, columns);
+            self.draw_text(text, (0, top as i32), pixel);
+        }
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+    }
+
+    // White text a pixel in from the given corner, in Game Boy pixels of the given size
+    fn draw_text(&mut self, text: &str, (left, top): (i32, i32), pixel: u32) {
+        let pixels: Vec<Rect> = osd::text_pixels(text)
+            .map(|(x, y)| {
+                Rect::new(
+                    left + ((x + 1) * pixel) as i32,
+                    top + ((y + 1) * pixel) as i32,
+                    pixel,
+                    pixel,
+                )
+            })
+            .collect();
+        self.canvas.set_draw_color(Color::RGB(255, 255, 255));
+        let _ = self.canvas.fill_rects(&pixels);
+    }
+
+    fn open_picker(&mut self) {
+        let recent = RecentRoms::load();
+        if recent.roms().is_empty() {
+            self.show_message("No recent ROMs");
+            return;
+        }
+        self.picker = Some(RomPicker {
+            roms: recent.roms().to_vec(),
+            selected: 0,
+        });
+        self.redraw();
+    }
+
+    // Up and down choose, Return loads the choice and Escape closes the picker. It takes every
+    // other key too, so nothing reaches the game meanwhile
+    fn pick(&mut self, keycode: Keycode) -> Option<Event> {
+        let picker = self.picker.as_mut()?;
+        let count = picker.roms.len();
+        let event = match keycode {
+            Keycode::Up => {
+                picker.selected = (picker.selected + count - 1) % count;
+                None
+            }
+            Keycode::Down => {
+                picker.selected = (picker.selected + 1) % count;
+                None
+            }
+            Keycode::Return | Keycode::KpEnter => {
+                let rom = picker.roms[picker.selected].clone();
+                self.picker = None;
+                Some(Event::OpenRom(rom))
+            }
+            Keycode::Escape => {
+                self.picker = None;
+                None
+            }
+            _ => None,
+        };
+        self.redraw();
+        event
+    }
+
+    // The binding whose modifiers are all held wins, the one asking for the most of them if
+    // several are, so Shift+F9 beats F9 while Shift+X still reaches X
+    fn map_key(&self, keycode: Keycode, keymod: Mod) -> Option<Target> {
+        let held = |modifiers: Mod| keymod.intersects(modifiers);
+        self.keys
+            .iter()
+            .filter(|(key, chord, _)| {
+                *key == keycode
+                    && (!chord.ctrl || held(Mod::LCTRLMOD | Mod::RCTRLMOD))
+                    && (!chord.shift || held(Mod::LSHIFTMOD | Mod::RSHIFTMOD))
+                    && (!chord.alt || held(Mod::LALTMOD | Mod::RALTMOD))
+            })
+            .max_by_key(|(_, chord, _)| chord.modifiers())
+            .map(|(_, _, target)| *target)
+    }
+
+    // Releases go by key alone, modifiers may have changed while it was down. A Game Boy button
+    // on the key comes first
+    fn map_release(&self, keycode: Keycode) -> Option<Event> {
+        let targets = self
+            .keys
+            .iter()
+            .filter(|(key, _, _)| *key == keycode)
+            .map(|(_, _, target)| *target);
+        targets
+            .clone()
+            .find_map(|target| match target {
+                Target::Button(button) => Some(Event::ButtonUp(button)),
+                Target::Action(_) => None,
+            })
+            .or_else(|| {
+                targets
+                    .map(|target| match target {
+                        Target::Button(button) => Event::ButtonUp(button),
+                        Target::Action(action) => Event::ActionReleased(action),
+                    })
+                    .next()
+            })
+    }
+}
+
+impl Renderer for SdlFrontend {
+    fn present(&mut self, frame: &[u8]) {
+        let size = self.filters.output_size();
+        if size != self.texture_size {
+            // SDL frees the old texture together with the canvas, so drop it explicitly here
+            let texture = Self::create_texture(&self.canvas, size);
+            unsafe { std::mem::replace(&mut self.texture, texture).destroy() };
+            self.texture_size = size;
+        }
+        let frame = self.filters.apply(frame);
+        let _ = self.texture.update(None, frame, size.0 * BYTES_PER_PIXEL);
+        self.redraw();
+        self.present_second_screen();
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        // Drops what's still queued so the sound stops right away
+        if paused && let Some(audio) = &self.audio {
+            audio.clear();
+        }
+        self.redraw();
+    }
+
+    fn set_title(&mut self, title: &str) {
+        // Only fails on titles with a nul byte
+        let _ = self.canvas.window_mut().set_title(title);
+    }
+
+    // Up on the next frame, or right away when nothing is being presented
+    fn show_message(&mut self, message: &str) {
+        self.osd.show(message);
+        if self.paused {
+            self.redraw();
+        }
+    }
+
+    fn open_views(&self) -> Vec<View> {
+        self.views.iter().map(|window| window.view).collect()
+    }
+
+    fn present_view(&mut self, view: View, image: &Image) {
+        if let Some(window) = self.views.iter_mut().find(|window| window.view == view) {
+            let _ = window
+                .texture
+                .update(None, &image.pixels, image.width * BYTES_PER_PIXEL);
+            let _ = window.canvas.copy(&window.texture, None, None);
+            window.canvas.present();
+        }
+    }
+}
+
+impl AudioSink for SdlFrontend {
+    // The device may not support the requested rate, the one it opened with is what counts
+    fn sample_rate(&self) -> Option<u32> {
+        self.audio.as_ref().map(|audio| audio.spec().freq as u32)
+    }
+
+    fn queue_audio(&mut self, samples: &[f32]) {
+        // Only the newest samples go, what's already queued plays out without a gap
+        if let Some(audio) = &self.audio
+            && audio.size() <= audio.spec().size * MAX_QUEUED_BUFFERS
+        {
+            let _ = audio.queue_audio(samples);
+        }
+    }
+
+    fn queued_audio(&self) -> Option<Duration> {
+        self.audio.as_ref().map(|audio| {
+            let spec = audio.spec();
+            let bytes_per_second = spec.freq as u32 * spec.channels as u32 * 4;
+            Duration::from_secs_f64(audio.size() as f64 / bytes_per_second as f64)
+        })
+    }
+}
+
+impl EventSource for SdlFrontend {
+    fn poll_events(&mut self) -> Vec<Event> {
+        // Nothing else redraws while paused
+        if self.osd.expire() && self.paused {
+            self.redraw();
+        }
+        let picking = self.picker.is_some();
+        let main_window = self.canvas.window().id();
+        let second_window = self
+            .second_screen
+            .as_ref()
+            .map(|screen| screen.canvas.window().id());
+        let mut resized = false;
+        let sdl_events: Vec<SdlEvent> = self.event_pump.poll_iter().collect();
+        let events: Vec<Event> = sdl_events
+            .into_iter()
+            .flat_map(|event| {
+                let mut events = match &mut self.gamepad {
+                    Some(gamepad) => gamepad.handle_event(&event),
+                    None => Vec::new(),
+                };
+                events.extend(match event {
+                    // Held keys repeat, for scrolling through the picker
+                    SdlEvent::KeyDown {
+                        keycode: Some(keycode),
+                        ..
+                    } if self.picker.is_some() => self.pick(keycode),
+                    SdlEvent::KeyDown {
+                        window_id,
+                        keycode: Some(keycode),
+                        keymod,
+                        repeat: false,
+                        ..
+                    } => self
+                        .map_key(keycode, keymod)
+                        .map(|target| match target {
+                            Target::Button(button) => Event::ButtonDown(button),
+                            Target::Action(action) => Event::Action(action),
+                        })
+                        .filter(|event| !self.send_to_second_screen(window_id, event)),
+                    SdlEvent::KeyUp {
+                        window_id,
+                        keycode: Some(keycode),
+                        ..
+                    } => self
+                        .map_release(keycode)
+                        .filter(|event| !self.send_to_second_screen(window_id, event)),
+                    // The logical size keeps the 160x144 picture letterboxed, only the bars around it
+                    // need clearing before the next frame arrives
+                    SdlEvent::Window {
+                        window_id,
+                        win_event: WindowEvent::SizeChanged(..),
+                        ..
+                    } if window_id == main_window => {
+                        resized = true;
+                        None
+                    }
+                    // Also what SDL sends on Ctrl+C
+                    SdlEvent::Quit { .. } => Some(Event::Action(Action::Quit)),
+                    // Either player's window, the other one has no one to link with without it
+                    SdlEvent::Window {
+                        window_id,
+                        win_event: WindowEvent::Close,
+                        ..
+                    } if window_id == main_window || second_window == Some(window_id) => {
+                        Some(Event::Action(Action::Quit))
+                    }
+                    SdlEvent::DropFile { filename, .. } => {
+                        Some(Event::OpenRom(PathBuf::from(filename)))
+                    }
+                    // Closing a view window only hides that view
+                    SdlEvent::Window {
+                        window_id,
+                        win_event: WindowEvent::Close,
+                        ..
+                    } => self
+                        .views
+                        .iter()
+                        .find(|window| window.canvas.window().id() == window_id)
+                        .map(|window| Event::Action(Action::ToggleView(window.view))),
+                    _ => None,
+                });
+                events
+            })
+            .collect();
+        if resized {
+            self.redraw();
+        }
+        let mut events: Vec<Event> = events
+            .into_iter()
+            .filter(|event| !self.handle_frontend_event(event))
+            .collect();
+        // Going by the windows' state rather than their events, moving between our own windows
+        // loses focus in one and gains it in another
+        let focused = self.has_focus();
+        if focused != self.focused {
+            self.focused = focused;
+            events.push(Event::Focus(focused));
+        }
+        if self.picker.is_some() != picking {
+            events.push(Event::Menu(!picking));
+        }
+        events
+    }
+
+    fn set_rumble(&mut self, strength: f32) {
+        if let Some(gamepad) = &mut self.gamepad {
+            gamepad.set_rumble(strength);
+        }
+    }
+}
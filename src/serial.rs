@@ -0,0 +1,291 @@
+use crate::link::{Link, Packet};
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+pub const SB: u16 = 0xFF01;
+pub const SC: u16 = 0xFF02;
+
+const TRANSFER_START: u8 = 0b1000_0000;
+const INTERNAL_CLOCK: u8 = 0b0000_0001;
+// The internal clock shifts at 8192 Hz
+const CYCLES_PER_BIT: u32 = 512;
+
+// The link port. Setting bit 7 of SC starts shifting SB out MSB first while the other side's bits
+// come in at the bottom, after eight bits SC's bit 7 clears and the serial interrupt is requested.
+// With nothing connected every incoming bit is 1, so a game reads 0xFF back. On an external clock
+// nothing ever drives the transfer and it waits forever, like on hardware with no cable.
+// With a link cable the side on the internal clock sends its byte when the transfer starts and
+// holds the transfer at the last bit until the other side's byte comes back. The other side only
+// takes part if it started a transfer on the external clock
+pub struct Serial {
+    sb: u8,
+    sc: u8,
+    // Cycles into the current bit, and bits shifted so far
+    cycles: u32,
+    bits: u8,
+    // Every byte a transfer was started with, until taken
+    sent: Vec<u8>,
+    link: Option<Box<dyn Link>>,
+    // The other side's byte for the transfer we're driving, once it arrived
+    reply: Option<u8>,
+    // Cycles since the link was last checked for packets
+    since_poll: u32,
+    // Whether the infrared LED is mirrored over the link, and whether the other side's is on
+    share_infrared: bool,
+    infrared_light: bool,
+}
+
+impl Serial {
+    pub fn new() -> Self {
+        Self {
+            sb: 0,
+            sc: 0,
+            cycles: 0,
+            bits: 0,
+            sent: Vec::new(),
+            link: None,
+            reply: None,
+            since_poll: 0,
+            share_infrared: false,
+            infrared_light: false,
+        }
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            SB => self.sb,
+            SC => 0b0111_1110 | self.sc,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            SB => self.sb = value,
+            SC => {
+                self.sc = value & (TRANSFER_START | INTERNAL_CLOCK);
+                if self.transferring() {
+                    self.cycles = 0;
+                    self.bits = 0;
+                    self.sent.push(self.sb);
+                    self.reply = None;
+                    if self.sc & INTERNAL_CLOCK != 0 {
+                        self.send(Packet::Transfer(self.sb));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Clocks the port in CPU cycles, returns whether a transfer completed and requested the
+    // interrupt
+    pub fn step(&mut self, cycles: u32) -> bool {
+        let mut interrupt = false;
+        if self.link.is_some() {
+            // Checking every bit's worth of cycles is plenty and keeps syscalls down
+            self.since_poll += cycles;
+            if self.since_poll >= CYCLES_PER_BIT {
+                self.since_poll = 0;
+                interrupt = self.poll_link();
+            }
+        }
+        if !self.transferring() || self.sc & INTERNAL_CLOCK == 0 {
+            return interrupt;
+        }
+        if self.bits < 8 {
+            self.cycles += cycles;
+        }
+        while self.cycles >= CYCLES_PER_BIT && self.bits < 8 {
+            self.cycles -= CYCLES_PER_BIT;
+            self.sb = self.sb << 1 | 1;
+            self.bits += 1;
+        }
+        if self.bits == 8 {
+            if self.link.is_some() {
+                match self.reply.take() {
+                    Some(byte) => self.sb = byte,
+                    None => return interrupt,
+                }
+            }
+            self.sc &= !TRANSFER_START;
+            return true;
+        }
+        interrupt
+    }
+
+    // Power-on state, the link cable stays plugged in
+    pub fn reset(&mut self) {
+        self.sb = 0;
+        self.sc = 0;
+        self.cycles = 0;
+        self.bits = 0;
+        self.reply = None;
+    }
+
+    pub fn set_link(&mut self, link: Box<dyn Link>) {
+        self.link = Some(link);
+    }
+
+    // Lets a linked emulator see this one's infrared LED, the cable stands in for the line of
+    // sight between the two
+    pub fn set_share_infrared(&mut self, share: bool) {
+        self.share_infrared = share;
+    }
+
+    pub fn send_infrared(&mut self, on: bool) {
+        if self.share_infrared {
+            self.send(Packet::Infrared(on));
+        }
+    }
+
+    // Whether the other side's LED shines on our sensor
+    pub fn infrared_light(&self) -> bool {
+        self.infrared_light
+    }
+
+    // Handles whatever the other side sent, returns whether it completed a transfer
+    fn poll_link(&mut self) -> bool {
+        let mut completed = false;
+        while let Some(link) = &mut self.link {
+            match link.receive() {
+                Ok(Some(Packet::Transfer(byte))) => {
+                    self.send(Packet::Reply(self.sb));
+                    if self.transferring() && self.sc & INTERNAL_CLOCK == 0 {
+                        self.sb = byte;
+                        self.sc &= !TRANSFER_START;
+                        completed = true;
+                    }
+                }
+                Ok(Some(Packet::Reply(byte))) => {
+                    if self.transferring() && self.sc & INTERNAL_CLOCK != 0 {
+                        self.reply = Some(byte);
+                    }
+                }
+                Ok(Some(Packet::Infrared(on))) => self.infrared_light = on,
+                Ok(None) => break,
+                Err(error) => self.disconnect(error),
+            }
+        }
+        completed
+    }
+
+    fn send(&mut self, packet: Packet) {
+        if let Some(link) = &mut self.link
+            && let Err(error) = link.send(packet)
+        {
+            self.disconnect(error);
+        }
+    }
+
+    // Carries on as if the cable was pulled, a transfer waiting on a reply reads 0xFF
+    fn disconnect(&mut self, error: std::io::Error) {
+        eprintln!("Link cable disconnected: {error}");
+        self.link = None;
+        self.infrared_light = false;
+    }
+
+    // Test ROMs print their results by sending them one character per transfer
+    pub fn take_sent(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.sent)
+    }
+
+    fn transferring(&self) -> bool {
+        self.sc & TRANSFER_START != 0
+    }
+}
+
+// A transfer in flight over a link cable can't be saved, the other side has moved on by the time
+// the state is loaded
+impl Snapshot for Serial {
+    fn save(&self, state: &mut StateWriter) {
+        state.u8(self.sb);
+        state.u8(self.sc);
+        state.u32(self.cycles);
+        state.u8(self.bits);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.sb = state.u8()?;
+        self.sc = state.u8()? & (TRANSFER_START | INTERNAL_CLOCK);
+        self.cycles = state.u32()?;
+        self.bits = state.u8()?.min(8);
+        self.reply = None;
+        Ok(())
+    }
+}
+
+impl Default for Serial {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::ChannelLink;
+
+    #[test]
+    fn should_receive_0xff_with_nothing_connected() {
+        let mut serial = Serial::new();
+        serial.write(SB, 0x42);
+        serial.write(SC, 0x81);
+        assert_eq!(serial.read(SC), 0xFF);
+        assert!(!serial.step(CYCLES_PER_BIT * 8 - 1));
+        assert_eq!(serial.read(SB), 0x7F);
+        assert!(serial.step(1));
+        assert_eq!(serial.read(SB), 0xFF);
+        assert_eq!(serial.read(SC), 0x7F);
+        assert!(!serial.step(CYCLES_PER_BIT * 8));
+        assert_eq!(serial.take_sent(), vec![0x42]);
+        assert!(serial.take_sent().is_empty());
+    }
+
+    #[test]
+    fn should_wait_for_an_external_clock() {
+        let mut serial = Serial::new();
+        serial.write(SB, 0x42);
+        serial.write(SC, 0x80);
+        assert!(!serial.step(CYCLES_PER_BIT * 16));
+        assert_eq!(serial.read(SB), 0x42);
+        assert_eq!(serial.read(SC), 0xFE);
+    }
+
+    fn cable() -> (Serial, Serial) {
+        let (a, b) = ChannelLink::pair();
+        let mut master = Serial::new();
+        master.set_link(Box::new(a));
+        let mut slave = Serial::new();
+        slave.set_link(Box::new(b));
+        (master, slave)
+    }
+
+    #[test]
+    fn should_swap_bytes_over_a_link() {
+        let (mut master, mut slave) = cable();
+        slave.write(SB, 0x22);
+        slave.write(SC, 0x80);
+        master.write(SB, 0x11);
+        master.write(SC, 0x81);
+        // Held at the last bit until the reply arrives
+        assert!(!master.step(CYCLES_PER_BIT * 8));
+        assert!(slave.step(CYCLES_PER_BIT));
+        assert_eq!(slave.read(SB), 0x11);
+        assert_eq!(slave.read(SC), 0x7E);
+        assert!(master.step(CYCLES_PER_BIT));
+        assert_eq!(master.read(SB), 0x22);
+        assert_eq!(master.read(SC), 0x7F);
+    }
+
+    #[test]
+    fn should_mirror_the_infrared_led_when_shared() {
+        let (mut a, mut b) = cable();
+        a.send_infrared(true);
+        b.step(CYCLES_PER_BIT);
+        assert!(!b.infrared_light());
+        a.set_share_infrared(true);
+        a.send_infrared(true);
+        b.step(CYCLES_PER_BIT);
+        assert!(b.infrared_light());
+    }
+}
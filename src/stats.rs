@@ -0,0 +1,51 @@
+use crate::cpu::FREQUENCY;
+use crate::gpu::CYCLES_PER_FRAME;
+use std::time::Duration;
+use web_time::Instant;
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+// Counts frames to report how fast emulation runs and how many frames reach the screen
+pub struct FrameStats {
+    since: Instant,
+    emulated: u32,
+    presented: u32,
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        Self {
+            since: Instant::now(),
+            emulated: 0,
+            presented: 0,
+        }
+    }
+
+    // Starts counting over, so time spent paused doesn't count against the speed
+    pub fn restart(&mut self) {
+        *self = Self::new();
+    }
+
+    // Called after every emulated frame. About once a second returns the speed in percent of the
+    // Game Boy's and the number of frames presented per second
+    pub fn frame(&mut self, presented: bool) -> Option<(u32, u32)> {
+        self.emulated += 1;
+        self.presented += presented as u32;
+        let elapsed = self.since.elapsed();
+        if elapsed < REPORT_INTERVAL {
+            return None;
+        }
+        let seconds = elapsed.as_secs_f64();
+        let frame_rate = FREQUENCY as f64 / CYCLES_PER_FRAME as f64;
+        let speed = self.emulated as f64 / seconds / frame_rate * 100.0;
+        let fps = self.presented as f64 / seconds;
+        self.restart();
+        Some((speed.round() as u32, fps.round() as u32))
+    }
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+// Where each area of the memory map starts, an offset from a symbol doesn't run into the next one
+const REGIONS: [u16; 8] = [
+    0x0000, 0x4000, 0x8000, 0xA000, 0xC000, 0xFE00, 0xFF00, 0xFF80,
+];
+
+// Names from an RGBDS .sym file (rgblink -n), lines like `00:0150 Main` with ; comments. There's
+// no bank switching, so only bank 1 of the switchable ROM is mapped and the other banks' symbols
+// can't be at any address the debugger shows
+#[derive(Default)]
+pub struct Symbols {
+    names: BTreeMap<u16, String>,
+}
+
+impl Symbols {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut names = BTreeMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let bad_line = || format!("Line {} isn't BB:AAAA Name: {line}", number + 1);
+            let (location, name) = line.split_once(char::is_whitespace).ok_or_else(bad_line)?;
+            let (bank, address) = location.split_once(':').ok_or_else(bad_line)?;
+            let bank = u16::from_str_radix(bank, 16).map_err(|_| bad_line())?;
+            let address = u16::from_str_radix(address, 16).map_err(|_| bad_line())?;
+            if (0x4000..0x8000).contains(&address) && bank != 1 {
+                continue;
+            }
+            // The first of several names for an address is usually the one that was meant, later
+            // ones tend to be local labels
+            names
+                .entry(address)
+                .or_insert_with(|| name.trim().to_string());
+        }
+        Ok(Self { names })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::parse(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn name(&self, address: u16) -> Option<&str> {
+        self.names.get(&address).map(String::as_str)
+    }
+
+    // The symbol at or before address in the same area of memory, as Name or Name+$N
+    pub fn locate(&self, address: u16) -> Option<String> {
+        let (start, name) = self.enclosing(address)?;
+        Some(match address - start {
+            0 => name.to_string(),
+            offset => format!("{name}+${offset:X}"),
+        })
+    }
+
+    // The symbol whose code or data address is part of, with the address it's at
+    pub fn enclosing(&self, address: u16) -> Option<(u16, &str)> {
+        let region = REGIONS.iter().rev().find(|&&start| start <= address)?;
+        let (&start, name) = self.names.range(region..=&address).next_back()?;
+        Some((start, name))
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_name_addresses_in_the_mapped_banks() {
+        let symbols = Symbols::parse(
+            "; File generated by rgblink\n\
+             00:0150 Main\n\
+             00:0150 Main.loop\n\
+             01:4000 LoadTiles ; bank 1\n\
+             02:4000 Music\n\
+             00:C0A0 wScore\n",
+        )
+        .unwrap();
+        assert_eq!(symbols.len(), 3);
+        assert_eq!(symbols.name(0x0150), Some("Main"));
+        assert_eq!(symbols.name(0x4000), Some("LoadTiles"));
+        assert_eq!(symbols.locate(0x4012), Some("LoadTiles+$12".to_string()));
+        assert_eq!(symbols.locate(0xC0A0), Some("wScore".to_string()));
+        assert_eq!(symbols.locate(0x0100), None);
+        assert_eq!(symbols.locate(0xFF40), None);
+        assert!(Symbols::parse("0150 Main").is_err());
+    }
+}
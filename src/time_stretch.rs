@@ -0,0 +1,91 @@
+use std::f32::consts::PI;
+
+// Stereo frames per grain, about 20 ms at 48 kHz
+const GRAIN: usize = 1024;
+const HOP: usize = GRAIN / 2;
+
+// Slows sound down without lowering its pitch, for slow motion. Grains of the input are windowed
+// and overlapped half a grain apart on the output, but taken from closer together on the input,
+// so the same waveform is stretched over more time instead of being played slower. Crude next to
+// proper time stretching, but cheap and good enough to follow the music
+pub struct TimeStretch {
+    // Interleaved stereo input not consumed yet
+    input: Vec<f32>,
+    // Frame of the input the next grain starts at
+    position: f64,
+    // Second half of the previous grain, added to the first half of the next
+    tail: Vec<f32>,
+    window: Vec<f32>,
+}
+
+impl TimeStretch {
+    pub fn new() -> Self {
+        Self {
+            input: Vec::new(),
+            position: 0.0,
+            tail: vec![0.0; HOP * 2],
+            // Periodic Hann, which sums to exactly 1 at half a grain of overlap
+            window: (0..GRAIN)
+                .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / GRAIN as f32).cos())
+                .collect(),
+        }
+    }
+
+    // Returns about samples.len() / speed samples, with a grain of latency
+    pub fn process(&mut self, samples: &[f32], speed: f64) -> Vec<f32> {
+        self.input.extend_from_slice(samples);
+        let mut output = Vec::new();
+        while (self.position as usize + GRAIN) * 2 <= self.input.len() {
+            let start = self.position as usize * 2;
+            let grain: Vec<f32> = self.input[start..start + GRAIN * 2]
+                .iter()
+                .enumerate()
+                .map(|(i, sample)| sample * self.window[i / 2])
+                .collect();
+            output.extend(self.tail.iter().zip(&grain).map(|(tail, head)| tail + head));
+            self.tail.copy_from_slice(&grain[HOP * 2..]);
+            self.position += HOP as f64 * speed;
+        }
+        let consumed = self.position as usize;
+        self.input.drain(..consumed * 2);
+        self.position -= consumed as f64;
+        output
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl Default for TimeStretch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_stretch_to_the_inverse_of_the_speed() {
+        let mut stretch = TimeStretch::new();
+        let input = vec![0.5; GRAIN * 2 * 8];
+        let output = stretch.process(&input, 0.5);
+        let expected = input.len() * 2;
+        assert!(output.len().abs_diff(expected) <= GRAIN * 2 * 2);
+        // Past the first half grain the windows add back up to the input level
+        assert!(
+            output[HOP * 2..]
+                .iter()
+                .all(|sample| (sample - 0.5).abs() < 1e-4)
+        );
+    }
+
+    #[test]
+    fn should_keep_leftover_input_for_the_next_call() {
+        let mut stretch = TimeStretch::new();
+        assert!(stretch.process(&[0.0; GRAIN], 0.25).is_empty());
+        assert_eq!(stretch.process(&[0.0; GRAIN], 0.25).len(), HOP * 2);
+    }
+}
@@ -0,0 +1,244 @@
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+pub const DIV: u16 = 0xFF04;
+pub const TIMA: u16 = 0xFF05;
+pub const TMA: u16 = 0xFF06;
+pub const TAC: u16 = 0xFF07;
+
+// The timer's registers in address order, for inspecting them
+pub const REGISTERS: [(&str, u16); 4] = [("DIV", DIV), ("TIMA", TIMA), ("TMA", TMA), ("TAC", TAC)];
+
+const TAC_ENABLE: u8 = 0b100;
+// Cycles between TIMA overflowing and it being reloaded from TMA, it reads 0 in between
+const RELOAD_DELAY: u8 = 4;
+
+// DIV, TIMA, TMA and TAC, all driven by one 16-bit counter that goes up every cycle. DIV is its
+// upper byte, TIMA goes up whenever the counter bit TAC selects falls while the timer is enabled.
+// Since it's the fall that counts, resetting DIV or changing TAC can bump TIMA too
+pub struct Timer {
+    counter: u16,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+    // Cycles left until an overflowed TIMA is reloaded
+    reload: Option<u8>,
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Self {
+            counter: 0,
+            tima: 0,
+            tma: 0,
+            tac: 0,
+            reload: None,
+        }
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            DIV => (self.counter >> 8) as u8,
+            TIMA => self.tima,
+            TMA => self.tma,
+            TAC => 0b1111_1000 | self.tac,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            DIV => self.set_counter(0),
+            // Writing during the reload delay cancels the reload
+            TIMA => {
+                self.tima = value;
+                self.reload = None;
+            }
+            TMA => self.tma = value,
+            TAC => {
+                let before = self.signal();
+                self.tac = value & 0b111;
+                if before && !self.signal() {
+                    self.increment();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Clocks the timer in CPU cycles, returns whether it requested its interrupt
+    pub fn step(&mut self, cycles: u32) -> bool {
+        let mut interrupt = false;
+        for _ in 0..cycles {
+            if let Some(delay) = self.reload {
+                if delay == 1 {
+                    self.tima = self.tma;
+                    self.reload = None;
+                    interrupt = true;
+                } else {
+                    self.reload = Some(delay - 1);
+                }
+            }
+            self.set_counter(self.counter.wrapping_add(1));
+        }
+        interrupt
+    }
+
+    // The 16-bit counter DIV is the upper byte of
+    pub fn counter(&self) -> u16 {
+        self.counter
+    }
+
+    // Cycles until TIMA next overflows, None while the timer is stopped. An overflow that's
+    // waiting to be reloaded has already happened, the next one counts up from TMA. The counter
+    // keeps going during the reload delay, which is shorter than any period
+    pub fn cycles_to_overflow(&self) -> Option<u32> {
+        if self.tac & TAC_ENABLE == 0 {
+            return None;
+        }
+        // TIMA goes up each time the counter passes a multiple of the period
+        let period = 2u32 << self.selected_bit();
+        let to_increment = period - self.counter as u32 % period;
+        let tima = if self.reload.is_some() {
+            self.tma
+        } else {
+            self.tima
+        };
+        Some(to_increment + (0xFF - tima as u32) * period)
+    }
+
+    fn set_counter(&mut self, counter: u16) {
+        let before = self.signal();
+        self.counter = counter;
+        if before && !self.signal() {
+            self.increment();
+        }
+    }
+
+    // The selected counter bit ANDed with the enable bit, TIMA counts its falling edges
+    fn signal(&self) -> bool {
+        self.tac & TAC_ENABLE != 0 && self.counter & (1 << self.selected_bit()) != 0
+    }
+
+    fn selected_bit(&self) -> u32 {
+        match self.tac & 0b11 {
+            // 4096 Hz
+            0b00 => 9,
+            // 262144 Hz
+            0b01 => 3,
+            // 65536 Hz
+            0b10 => 5,
+            // 16384 Hz
+            _ => 7,
+        }
+    }
+
+    fn increment(&mut self) {
+        let (tima, overflow) = self.tima.overflowing_add(1);
+        self.tima = tima;
+        if overflow {
+            self.reload = Some(RELOAD_DELAY);
+        }
+    }
+}
+
+impl Snapshot for Timer {
+    fn save(&self, state: &mut StateWriter) {
+        state.u16(self.counter);
+        state.u8(self.tima);
+        state.u8(self.tma);
+        state.u8(self.tac);
+        state.bool(self.reload.is_some());
+        state.u8(self.reload.unwrap_or(0));
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.counter = state.u16()?;
+        self.tima = state.u8()?;
+        self.tma = state.u8()?;
+        self.tac = state.u8()?;
+        let reloading = state.bool()?;
+        let reload = state.u8()?;
+        self.reload = reloading.then_some(reload);
+        Ok(())
+    }
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_count_at_the_selected_frequency() {
+        let mut timer = Timer::new();
+        timer.step(256);
+        assert_eq!(timer.read(DIV), 1);
+        assert_eq!(timer.read(TIMA), 0);
+        timer.write(TAC, 0b101);
+        timer.step(16 * 3);
+        assert_eq!(timer.read(TIMA), 3);
+        timer.write(TAC, 0b100);
+        timer.write(TIMA, 0);
+        timer.step(1024);
+        assert_eq!(timer.read(TIMA), 1);
+        assert_eq!(timer.read(TAC), 0xFC);
+    }
+
+    #[test]
+    fn should_know_when_tima_overflows() {
+        let mut timer = Timer::new();
+        assert_eq!(timer.cycles_to_overflow(), None);
+        timer.write(TIMA, 0xFE);
+        timer.write(TAC, 0b101);
+        timer.step(5);
+        assert_eq!(timer.cycles_to_overflow(), Some(27));
+        timer.step(26);
+        assert_eq!(timer.read(TIMA), 0xFF);
+        timer.step(1);
+        assert_eq!(timer.read(TIMA), 0);
+        // From the reload of TMA, which is 0
+        assert_eq!(timer.cycles_to_overflow(), Some(256 * 16));
+        assert_eq!(timer.counter(), 32);
+    }
+
+    #[test]
+    fn should_reload_from_tma_and_interrupt_after_overflow() {
+        let mut timer = Timer::new();
+        timer.write(TMA, 0xAB);
+        timer.write(TIMA, 0xFF);
+        timer.write(TAC, 0b101);
+        assert!(!timer.step(16));
+        assert_eq!(timer.read(TIMA), 0);
+        assert!(timer.step(RELOAD_DELAY as u32));
+        assert_eq!(timer.read(TIMA), 0xAB);
+
+        // Writing TIMA in between cancels the reload
+        timer.write(TIMA, 0xFF);
+        timer.step(16);
+        timer.write(TIMA, 0x10);
+        assert!(!timer.step(RELOAD_DELAY as u32));
+        assert_eq!(timer.read(TIMA), 0x10);
+    }
+
+    #[test]
+    fn should_count_falling_edges_from_div_and_tac_writes() {
+        let mut timer = Timer::new();
+        timer.write(TAC, 0b101);
+        timer.step(8);
+        // Bit 3 is set, resetting DIV makes it fall
+        timer.write(DIV, 0);
+        assert_eq!(timer.read(TIMA), 1);
+        assert_eq!(timer.read(DIV), 0);
+        timer.step(8);
+        // So does disabling the timer
+        timer.write(TAC, 0b001);
+        assert_eq!(timer.read(TIMA), 2);
+        timer.write(DIV, 0);
+        assert_eq!(timer.read(TIMA), 2);
+    }
+}
@@ -0,0 +1,221 @@
+use ratatui::Terminal;
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{
+    EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode,
+};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph};
+use std::io::{self, Stdout};
+
+const KEYS: &str = "s step  n next  f finish  r run  b break at PC  ↑↓ PgUp PgDn g memory  q back";
+
+// What the panes show, taken from the machine before every draw
+pub struct Panes {
+    // Instructions around PC, true for PC's
+    pub disassembly: Vec<(bool, String)>,
+    pub registers: [(&'static str, u16); 6],
+    // Words from SP up, with their addresses
+    pub stack: Vec<(u16, u16)>,
+    pub memory: Vec<String>,
+    pub breakpoints: Vec<String>,
+}
+
+pub enum Command {
+    Step,
+    Next,
+    Finish,
+    Run,
+    ToggleBreakpoint,
+    // Something only the panes need redrawing for, like scrolling the memory
+    Refresh,
+    Quit,
+}
+
+// The debugger as a full screen of panes, driven by single keys instead of commands
+pub struct Tui {
+    terminal: Terminal<CrosstermBackend<Stdout>>,
+    // Where the memory pane starts
+    pub memory_start: u16,
+    // The address being typed after g
+    input: Option<String>,
+    // What the last command did, shown under the panes
+    pub status: String,
+}
+
+impl Tui {
+    pub fn start() -> io::Result<Self> {
+        enter()?;
+        Ok(Self {
+            terminal: Terminal::new(CrosstermBackend::new(io::stdout()))?,
+            memory_start: 0xC000,
+            input: None,
+            status: String::new(),
+        })
+    }
+
+    // Hands the terminal back for the output of running, until resume
+    pub fn suspend(&mut self) -> io::Result<()> {
+        leave()
+    }
+
+    pub fn resume(&mut self) -> io::Result<()> {
+        enter()?;
+        // Whatever was printed meanwhile means nothing on screen is as it was drawn
+        self.terminal.clear()
+    }
+
+    pub fn draw(&mut self, panes: &Panes) -> io::Result<()> {
+        let status = match &self.input {
+            Some(input) => format!("Memory address: {input}"),
+            None => format!("{}    {}", self.status, KEYS),
+        };
+        self.terminal.draw(|frame| {
+            let [main, status_area] =
+                Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+            let [left, right] =
+                Layout::horizontal([Constraint::Min(0), Constraint::Length(30)]).areas(main);
+            let [code, memory] =
+                Layout::vertical([Constraint::Min(0), Constraint::Length(18)]).areas(left);
+            let [registers, stack, breakpoints] = Layout::vertical([
+                Constraint::Length(9),
+                Constraint::Length(10),
+                Constraint::Min(0),
+            ])
+            .areas(right);
+
+            let code_lines: Vec<Line> = panes
+                .disassembly
+                .iter()
+                .map(|(pc, text)| {
+                    let style = if *pc {
+                        Style::new().fg(Color::Yellow)
+                    } else {
+                        Style::new()
+                    };
+                    Line::styled(text.as_str(), style)
+                })
+                .collect();
+            frame.render_widget(
+                Paragraph::new(code_lines).block(Block::bordered().title("Disassembly")),
+                code,
+            );
+
+            let mut register_lines: Vec<Line> = panes
+                .registers
+                .iter()
+                .map(|(name, value)| Line::raw(format!("{name}  {value:04X}")))
+                .collect();
+            register_lines.push(Line::raw(format!("Flags {}", flags(panes.registers[0].1))));
+            frame.render_widget(
+                Paragraph::new(register_lines).block(Block::bordered().title("Registers")),
+                registers,
+            );
+
+            let stack_lines: Vec<Line> = panes
+                .stack
+                .iter()
+                .map(|(address, word)| Line::raw(format!("{address:04X}  {word:04X}")))
+                .collect();
+            frame.render_widget(
+                Paragraph::new(stack_lines).block(Block::bordered().title("Stack")),
+                stack,
+            );
+
+            let memory_lines: Vec<Line> = panes.memory.iter().map(Line::raw).collect();
+            frame.render_widget(
+                Paragraph::new(memory_lines).block(Block::bordered().title("Memory")),
+                memory,
+            );
+
+            let breakpoint_lines: Vec<Line> = panes.breakpoints.iter().map(Line::raw).collect();
+            frame.render_widget(
+                Paragraph::new(breakpoint_lines).block(Block::bordered().title("Breakpoints")),
+                breakpoints,
+            );
+
+            frame.render_widget(Paragraph::new(status), status_area);
+        })?;
+        Ok(())
+    }
+
+    // Waits for a key that means something, scrolling and typing an address are done here
+    pub fn next_command(&mut self) -> io::Result<Command> {
+        loop {
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if let Some(input) = &mut self.input {
+                match key.code {
+                    KeyCode::Char(digit) if digit.is_ascii_hexdigit() && input.len() < 4 => {
+                        input.push(digit)
+                    }
+                    KeyCode::Backspace => {
+                        input.pop();
+                    }
+                    KeyCode::Enter => {
+                        if let Ok(address) = u16::from_str_radix(input, 16) {
+                            self.memory_start = address;
+                        }
+                        self.input = None;
+                    }
+                    KeyCode::Esc => self.input = None,
+                    _ => continue,
+                }
+                return Ok(Command::Refresh);
+            }
+            return Ok(match key.code {
+                KeyCode::Char('s') => Command::Step,
+                KeyCode::Char('n') => Command::Next,
+                KeyCode::Char('f') => Command::Finish,
+                KeyCode::Char('r') => Command::Run,
+                KeyCode::Char('b') => Command::ToggleBreakpoint,
+                KeyCode::Char('q') | KeyCode::Esc => Command::Quit,
+                KeyCode::Char('g') => {
+                    self.input = Some(String::new());
+                    Command::Refresh
+                }
+                KeyCode::Up => self.scroll(-0x10),
+                KeyCode::Down => self.scroll(0x10),
+                KeyCode::PageUp => self.scroll(-0x100),
+                KeyCode::PageDown => self.scroll(0x100),
+                _ => continue,
+            });
+        }
+    }
+
+    fn scroll(&mut self, by: i32) -> Command {
+        self.memory_start = (self.memory_start as i32 + by).clamp(0, 0xFFF0) as u16;
+        Command::Refresh
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        let _ = leave();
+    }
+}
+
+fn enter() -> io::Result<()> {
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)
+}
+
+fn leave() -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen)
+}
+
+// Z, N, H and C for the set flags in AF, - for the clear ones
+fn flags(af: u16) -> String {
+    [(0x80, 'Z'), (0x40, 'N'), (0x20, 'H'), (0x10, 'C')]
+        .into_iter()
+        .map(|(bit, name)| if af & bit != 0 { name } else { '-' })
+        .collect()
+}
@@ -0,0 +1,374 @@
+use crate::gpu::{
+    BG_MAP_HIGH, BG_MAP_LOW, BGP, BYTES_PER_PIXEL, FRAME_HEIGHT, FRAME_WIDTH, GPU, LCDC, LY, OAM,
+    OBP0, OBP1, SCX, SCY, TILE_DATA_UNSIGNED, TILE_SIZE, WX, WY,
+};
+use crate::memory::Memory;
+use crate::palette::PALETTE_SIZE;
+
+// The DMG's 6KiB of tile data holds 384 tiles, shown 16 per row like most debuggers do
+const TILE_COUNT: usize = 384;
+const TILES_PER_ROW: usize = 16;
+// Background maps are 32x32 tiles, the viewport wraps around their edges
+const MAP_SIZE: usize = 256;
+const VIEWPORT_COLOR: [u8; BYTES_PER_PIXEL] = [0xFF, 0x00, 0x00];
+const WINDOW_COLOR: [u8; BYTES_PER_PIXEL] = [0x00, 0x60, 0xFF];
+const SPRITE_COUNT: usize = 40;
+// Sprites are laid out 8 per row, each in a cell big enough for 8x16 sprites plus a border
+const SPRITES_PER_ROW: usize = 8;
+const SPRITE_CELL: (usize, usize) = (12, 20);
+const CELL_COLOR: [u8; BYTES_PER_PIXEL] = [0x40, 0x40, 0x40];
+const SCANLINE_COLOR: [u8; BYTES_PER_PIXEL] = [0xFF, 0xD0, 0x00];
+
+// Debug views of the PPU state, rendered by the emulator and shown by frontends that support them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum View {
+    Tiles,
+    BackgroundMap,
+    Oam,
+}
+
+impl View {
+    pub fn title(&self) -> &'static str {
+        match self {
+            View::Tiles => "Tiles",
+            View::BackgroundMap => "Background map",
+            View::Oam => "OAM",
+        }
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        match self {
+            View::Tiles => (TILES_PER_ROW * 8, TILE_COUNT / TILES_PER_ROW * 8),
+            View::BackgroundMap => (MAP_SIZE, MAP_SIZE),
+            View::Oam => (
+                SPRITES_PER_ROW * SPRITE_CELL.0,
+                SPRITE_COUNT / SPRITES_PER_ROW * SPRITE_CELL.1,
+            ),
+        }
+    }
+}
+
+// One OAM entry, Y and X hold the raw values which are offset by 16 and 8 from the screen
+pub struct Sprite {
+    pub index: usize,
+    pub y: u8,
+    pub x: u8,
+    pub tile: u8,
+    pub attributes: u8,
+}
+
+impl Sprite {
+    pub fn priority(&self) -> bool {
+        self.attributes & 0b1000_0000 != 0
+    }
+
+    pub fn y_flip(&self) -> bool {
+        self.attributes & 0b0100_0000 != 0
+    }
+
+    pub fn x_flip(&self) -> bool {
+        self.attributes & 0b0010_0000 != 0
+    }
+
+    pub fn palette(&self) -> u16 {
+        if self.attributes & 0b0001_0000 != 0 {
+            OBP1
+        } else {
+            OBP0
+        }
+    }
+
+    pub fn on_line(&self, ly: u8, height: u8) -> bool {
+        let top = self.y as i16 - 16;
+        (top..top + height as i16).contains(&(ly as i16))
+    }
+}
+
+pub fn sprites(memory: &Memory) -> Vec<Sprite> {
+    (0..SPRITE_COUNT)
+        .map(|index| {
+            let address = OAM as usize + index * 4;
+            Sprite {
+                index,
+                y: memory.memory[address],
+                x: memory.memory[address + 1],
+                tile: memory.memory[address + 2],
+                attributes: memory.memory[address + 3],
+            }
+        })
+        .collect()
+}
+
+// 8x8 or 8x16 depending on LCDC
+pub fn sprite_height(memory: &Memory) -> u8 {
+    if memory.memory[LCDC as usize] & 0b0000_0100 != 0 {
+        16
+    } else {
+        8
+    }
+}
+
+// RGB24 pixels, like GPU frames but with a size that depends on the view
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    fn new((width, height): (usize, usize)) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height * BYTES_PER_PIXEL],
+        }
+    }
+
+    fn put(&mut self, x: usize, y: usize, pixel: [u8; BYTES_PER_PIXEL]) {
+        let i = (y * self.width + x) * BYTES_PER_PIXEL;
+        self.pixels[i..i + BYTES_PER_PIXEL].copy_from_slice(&pixel);
+    }
+
+    // Outlines a rectangle, wrapping around the image edges like the PPU does with the map
+    fn outline(&mut self, x: usize, y: usize, width: usize, height: usize, color: [u8; 3]) {
+        for dx in 0..width {
+            self.put((x + dx) % self.width, y % self.height, color);
+            self.put((x + dx) % self.width, (y + height - 1) % self.height, color);
+        }
+        for dy in 0..height {
+            self.put(x % self.width, (y + dy) % self.height, color);
+            self.put((x + width - 1) % self.width, (y + dy) % self.height, color);
+        }
+    }
+}
+
+pub fn render(view: View, gpu: &GPU, memory: &Memory) -> Image {
+    match view {
+        View::Tiles => tiles(gpu, memory),
+        View::BackgroundMap => background_map(gpu, memory),
+        View::Oam => oam(gpu, memory),
+    }
+}
+
+fn draw_tile(
+    image: &mut Image,
+    memory: &Memory,
+    address: u16,
+    x: usize,
+    y: usize,
+    palette: u8,
+    colors: &[[u8; BYTES_PER_PIXEL]; PALETTE_SIZE],
+) {
+    for row in 0..8 {
+        let low = memory.memory[address as usize + row * 2];
+        let high = memory.memory[address as usize + row * 2 + 1];
+        let pixels = GPU::map_tile_pixels(low, high, palette, colors);
+        for (dx, pixel) in pixels.into_iter().enumerate() {
+            image.put(x + dx, y + row, pixel);
+        }
+    }
+}
+
+// Every tile in VRAM through BGP and the active palette, in address order
+fn tiles(gpu: &GPU, memory: &Memory) -> Image {
+    let mut image = Image::new(View::Tiles.size());
+    let bgp = memory.memory[BGP as usize];
+    let colors = gpu.palette().colors;
+    for tile in 0..TILE_COUNT {
+        let address = TILE_DATA_UNSIGNED + (tile as u16) * TILE_SIZE;
+        let (x, y) = (tile % TILES_PER_ROW * 8, tile / TILES_PER_ROW * 8);
+        draw_tile(&mut image, memory, address, x, y, bgp, &colors);
+    }
+    image
+}
+
+// The background map selected by LCDC, with the visible screen outlined. When the window is on,
+// the part of the screen it covers is outlined as well
+fn background_map(gpu: &GPU, memory: &Memory) -> Image {
+    let mut image = Image::new(View::BackgroundMap.size());
+    let lcdc = memory.memory[LCDC as usize];
+    let bgp = memory.memory[BGP as usize];
+    let colors = gpu.palette().colors;
+    let map = if lcdc & 0b0000_1000 != 0 {
+        BG_MAP_HIGH
+    } else {
+        BG_MAP_LOW
+    };
+    for tile_y in 0..MAP_SIZE / 8 {
+        for tile_x in 0..MAP_SIZE / 8 {
+            let tile_index = memory.memory[map as usize + tile_y * 32 + tile_x];
+            let address = GPU::tile_address(lcdc, tile_index);
+            draw_tile(
+                &mut image,
+                memory,
+                address,
+                tile_x * 8,
+                tile_y * 8,
+                bgp,
+                &colors,
+            );
+        }
+    }
+
+    let scx = memory.memory[SCX as usize] as usize;
+    let scy = memory.memory[SCY as usize] as usize;
+    image.outline(scx, scy, FRAME_WIDTH, FRAME_HEIGHT, VIEWPORT_COLOR);
+    // WX is offset by 7, values below that still start the window at the left edge
+    let wx = (memory.memory[WX as usize] as usize).saturating_sub(7);
+    let wy = memory.memory[WY as usize] as usize;
+    if lcdc & 0b0010_0000 != 0 && wx < FRAME_WIDTH && wy < FRAME_HEIGHT {
+        image.outline(
+            scx + wx,
+            scy + wy,
+            FRAME_WIDTH - wx,
+            FRAME_HEIGHT - wy,
+            WINDOW_COLOR,
+        );
+    }
+    image
+}
+
+// Thumbnails of the 40 sprites as stored in VRAM, unflipped. Sprites overlapping the current
+// scanline get a highlighted cell, useful when stepping through a frame in the debugger
+fn oam(gpu: &GPU, memory: &Memory) -> Image {
+    let mut image = Image::new(View::Oam.size());
+    let (cell_width, cell_height) = SPRITE_CELL;
+    let height = sprite_height(memory);
+    let ly = memory.memory[LY as usize];
+    let colors = gpu.palette().colors;
+    for sprite in sprites(memory) {
+        let x = sprite.index % SPRITES_PER_ROW * cell_width;
+        let y = sprite.index / SPRITES_PER_ROW * cell_height;
+        for dy in 0..cell_height {
+            for dx in 0..cell_width {
+                image.put(x + dx, y + dy, CELL_COLOR);
+            }
+        }
+        if sprite.on_line(ly, height) {
+            image.outline(x, y, cell_width, cell_height, SCANLINE_COLOR);
+        }
+
+        let palette = memory.memory[sprite.palette() as usize];
+        let (left, top) = (x + (cell_width - 8) / 2, y + (cell_height - 16) / 2);
+        // Bit 0 of the tile index is ignored for 8x16 sprites
+        let tiles = if height == 16 {
+            vec![sprite.tile & 0xFE, sprite.tile | 0x01]
+        } else {
+            vec![sprite.tile]
+        };
+        for (i, tile) in tiles.into_iter().enumerate() {
+            let address = TILE_DATA_UNSIGNED + tile as u16 * TILE_SIZE;
+            draw_tile(
+                &mut image,
+                memory,
+                address,
+                left,
+                top + i * 8,
+                palette,
+                &colors,
+            );
+        }
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(image: &Image, x: usize, y: usize) -> [u8; 3] {
+        let i = (y * image.width + x) * BYTES_PER_PIXEL;
+        [image.pixels[i], image.pixels[i + 1], image.pixels[i + 2]]
+    }
+
+    #[test]
+    fn should_lay_out_tiles_in_address_order() {
+        let mut memory = Memory::new();
+        memory.memory[BGP as usize] = 0b1110_0100;
+        // First row of tile 17, the second tile of the second row, all color 3
+        let address = TILE_DATA_UNSIGNED as usize + 17 * TILE_SIZE as usize;
+        memory.memory[address] = 0xFF;
+        memory.memory[address + 1] = 0xFF;
+        let gpu = GPU::new();
+
+        let image = render(View::Tiles, &gpu, &memory);
+        assert_eq!((image.width, image.height), (128, 192));
+        let pixel = |x, y| pixel(&image, x, y);
+        assert_eq!(pixel(8, 8), gpu.palette().colors[3]);
+        assert_eq!(pixel(15, 8), gpu.palette().colors[3]);
+        assert_eq!(pixel(8, 9), gpu.palette().colors[0]);
+        assert_eq!(pixel(7, 8), gpu.palette().colors[0]);
+    }
+
+    #[test]
+    fn should_outline_wrapping_viewport_on_background_map() {
+        let mut memory = Memory::new();
+        memory.memory[LCDC as usize] = 0b1001_0001;
+        memory.memory[SCX as usize] = 200;
+        memory.memory[SCY as usize] = 10;
+        let gpu = GPU::new();
+
+        let image = render(View::BackgroundMap, &gpu, &memory);
+        assert_eq!((image.width, image.height), (256, 256));
+        assert_eq!(pixel(&image, 200, 10), VIEWPORT_COLOR);
+        // The right edge wraps around to x = (200 + 159) % 256
+        assert_eq!(pixel(&image, 103, 50), VIEWPORT_COLOR);
+        assert_eq!(pixel(&image, 104, 50), gpu.palette().colors[0]);
+        assert_eq!(pixel(&image, 10, 153), VIEWPORT_COLOR);
+        assert_ne!(pixel(&image, 10, 154), VIEWPORT_COLOR);
+    }
+
+    #[test]
+    fn should_outline_window_only_when_enabled() {
+        let mut memory = Memory::new();
+        memory.memory[LCDC as usize] = 0b1001_0001;
+        memory.memory[WX as usize] = 87;
+        memory.memory[WY as usize] = 100;
+        let gpu = GPU::new();
+        assert_ne!(
+            pixel(&render(View::BackgroundMap, &gpu, &memory), 80, 120),
+            WINDOW_COLOR
+        );
+
+        memory.memory[LCDC as usize] |= 0b0010_0000;
+        let image = render(View::BackgroundMap, &gpu, &memory);
+        assert_eq!(pixel(&image, 80, 120), WINDOW_COLOR);
+        assert_eq!(pixel(&image, 100, 100), WINDOW_COLOR);
+    }
+
+    #[test]
+    fn should_read_oam_entries() {
+        let mut memory = Memory::new();
+        memory.memory[OAM as usize + 4..OAM as usize + 8].copy_from_slice(&[
+            20,
+            8,
+            0x42,
+            0b0011_0000,
+        ]);
+        let sprites = sprites(&memory);
+        assert_eq!(sprites.len(), 40);
+        let sprite = &sprites[1];
+        assert_eq!((sprite.y, sprite.x, sprite.tile), (20, 8, 0x42));
+        assert!(sprite.x_flip() && !sprite.y_flip() && !sprite.priority());
+        assert_eq!(sprite.palette(), OBP1);
+        // Y is offset by 16, so this sprite covers lines 4 to 11 in 8x8 mode
+        assert!(!sprite.on_line(3, 8));
+        assert!(sprite.on_line(4, 8));
+        assert!(sprite.on_line(11, 8));
+        assert!(!sprite.on_line(12, 8));
+        assert!(sprite.on_line(19, 16));
+    }
+
+    #[test]
+    fn should_highlight_sprites_on_current_scanline() {
+        let mut memory = Memory::new();
+        memory.memory[OAM as usize + 4] = 20;
+        memory.memory[LY as usize] = 6;
+        let gpu = GPU::new();
+
+        let image = render(View::Oam, &gpu, &memory);
+        assert_eq!((image.width, image.height), (96, 100));
+        assert_eq!(pixel(&image, 0, 0), CELL_COLOR);
+        assert_eq!(pixel(&image, SPRITE_CELL.0, 0), SCANLINE_COLOR);
+    }
+}
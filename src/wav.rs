@@ -0,0 +1,162 @@
+use crate::paths;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const CHANNELS: u16 = 2;
+const BITS_PER_SAMPLE: u16 = 16;
+const HEADER_SIZE: u32 = 44;
+
+// 16-bit stereo PCM. The sizes in the header aren't known until the recording stops, they're
+// written as 0 and patched by finish
+pub struct WavWriter {
+    file: BufWriter<File>,
+    path: PathBuf,
+    data_size: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: PathBuf, sample_rate: u32) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(&path)?);
+        let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+        file.write_all(b"RIFF")?;
+        file.write_all(&0u32.to_le_bytes())?;
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        // PCM
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&CHANNELS.to_le_bytes())?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        file.write_all(&block_align.to_le_bytes())?;
+        file.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&0u32.to_le_bytes())?;
+        Ok(Self {
+            file,
+            path,
+            data_size: 0,
+        })
+    }
+
+    // Takes interleaved stereo samples between -1.0 and 1.0
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        for sample in samples {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.file.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_size += samples.len() as u32 * BITS_PER_SAMPLE as u32 / 8;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<PathBuf> {
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file
+            .write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(HEADER_SIZE as u64 - 4))?;
+        self.file.write_all(&self.data_size.to_le_bytes())?;
+        self.file.flush()?;
+        Ok(self.path)
+    }
+}
+
+// The mixed output, plus one file per sound channel when stems are requested
+pub struct WavRecording {
+    mix: WavWriter,
+    stems: Option<[WavWriter; 4]>,
+}
+
+impl WavRecording {
+    pub fn start(dir: &Path, sample_rate: u32, stems: bool) -> Result<Self, Box<dyn Error>> {
+        let path = paths::timestamped_file(dir, "audio", "wav")?;
+        let stems = if stems {
+            let stem = |channel: usize| {
+                WavWriter::create(
+                    path.with_extension(format!("ch{}.wav", channel + 1)),
+                    sample_rate,
+                )
+            };
+            Some([stem(0)?, stem(1)?, stem(2)?, stem(3)?])
+        } else {
+            None
+        };
+        Ok(Self {
+            mix: WavWriter::create(path, sample_rate)?,
+            stems,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.mix.path
+    }
+
+    pub fn has_stems(&self) -> bool {
+        self.stems.is_some()
+    }
+
+    pub fn push(&mut self, mix: &[f32], stems: Option<&[Vec<f32>; 4]>) -> io::Result<()> {
+        self.mix.write_samples(mix)?;
+        if let (Some(writers), Some(stems)) = (&mut self.stems, stems) {
+            for (writer, stem) in writers.iter_mut().zip(stems) {
+                writer.write_samples(stem)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the path of the mix, the stems sit next to it
+    pub fn finish(self) -> io::Result<PathBuf> {
+        if let Some(stems) = self.stems {
+            for stem in stems {
+                stem.finish()?;
+            }
+        }
+        self.mix.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn should_patch_sizes_into_the_header() {
+        let dir = std::env::temp_dir().join("gameboy-emulator-wav-test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("mix.wav");
+        let mut writer = WavWriter::create(path.clone(), 48_000).unwrap();
+        writer.write_samples(&[0.0, 1.0, -1.0, 2.0]).unwrap();
+        writer.finish().unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(bytes[4..8], 44u32.to_le_bytes());
+        assert_eq!(bytes[24..28], 48_000u32.to_le_bytes());
+        assert_eq!(bytes[40..44], 8u32.to_le_bytes());
+        assert_eq!(bytes[46..48], i16::MAX.to_le_bytes());
+        assert_eq!(bytes[48..50], (-i16::MAX).to_le_bytes());
+        // Out of range samples are clamped
+        assert_eq!(bytes[50..52], i16::MAX.to_le_bytes());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_write_a_file_per_stem() {
+        let dir = std::env::temp_dir().join("gameboy-emulator-wav-stems-test");
+        let mut recording = WavRecording::start(&dir, 48_000, true).unwrap();
+        let stems: [Vec<f32>; 4] = Default::default();
+        recording.push(&[0.5, 0.5], Some(&stems)).unwrap();
+        let path = recording.finish().unwrap();
+
+        assert_eq!(fs::read(&path).unwrap().len(), 48);
+        for channel in 1..=4 {
+            let stem = path.with_extension(format!("ch{channel}.wav"));
+            assert_eq!(fs::read(&stem).unwrap().len(), 44);
+            fs::remove_file(stem).unwrap();
+        }
+        fs::remove_file(path).unwrap();
+    }
+}
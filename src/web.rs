@@ -0,0 +1,120 @@
+use crate::bindings::Target;
+use crate::config::Config;
+use crate::emulator::Emulator;
+use crate::joypad::Button;
+use crate::memory;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+
+// The emulator as the browser sees it, web/main.js drives it. The page keeps time with
+// requestAnimationFrame, so nothing in here sleeps or looks at a clock
+#[wasm_bindgen]
+pub struct WebEmulator {
+    emulator: Emulator,
+    // What the APU produced since the page last took it
+    samples: Rc<RefCell<Vec<f32>>>,
+}
+
+#[wasm_bindgen]
+impl WebEmulator {
+    // Sample rate is the AudioContext's, so the page plays samples as they come
+    #[wasm_bindgen(constructor)]
+    pub fn new(sample_rate: u32) -> Self {
+        let mut emulator = Emulator::with_config(Config {
+            rewind_seconds: 0,
+            sample_rate,
+            ..Config::default()
+        });
+        let samples = Rc::new(RefCell::new(Vec::new()));
+        let queue = Rc::clone(&samples);
+        emulator.on_audio(move |frame| queue.borrow_mut().extend_from_slice(frame));
+        Self { emulator, samples }
+    }
+
+    // The name and bytes of the .gb, .gbc or .zip file the player picked
+    pub fn load_rom(&mut self, name: &str, mut rom: Vec<u8>) -> Result<(), String> {
+        if name.ends_with(".zip") {
+            rom = memory::extract_rom(rom).map_err(|error| error.to_string())?;
+        }
+        self.emulator
+            .load_rom_bytes(rom)
+            .map_err(|error| error.to_string())
+    }
+
+    pub fn run_frame(&mut self) {
+        self.emulator.run_until_vblank();
+    }
+
+    // The last frame as RGBA, ready for an ImageData
+    pub fn frame(&self) -> Vec<u8> {
+        self.emulator
+            .framebuffer()
+            .chunks_exact(3)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 0xFF])
+            .collect()
+    }
+
+    // Interleaved stereo samples, emptying the queue
+    pub fn take_audio(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples.borrow_mut())
+    }
+
+    // Buttons go by their names in bindings files, like "start" or "a". Returns false for
+    // anything else
+    pub fn press(&mut self, button: &str) -> bool {
+        match button_named(button) {
+            Some(button) => {
+                self.emulator.press(button);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn release(&mut self, button: &str) -> bool {
+        match button_named(button) {
+            Some(button) => {
+                self.emulator.release(button);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        self.emulator.save_state()
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        self.emulator.load_state(state)
+    }
+}
+
+fn button_named(name: &str) -> Option<Button> {
+    match Target::from_name(name) {
+        Some(Target::Button(button)) => Some(button),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::test_rom;
+    use crate::gpu::{FRAME_HEIGHT, FRAME_WIDTH};
+
+    #[test]
+    fn should_hand_the_page_rgba_frames_and_audio() {
+        let mut emulator = WebEmulator::new(44_100);
+        emulator.load_rom("loop.gb", test_rom()).unwrap();
+        emulator.run_frame();
+        let frame = emulator.frame();
+        assert_eq!(frame.len(), FRAME_WIDTH * FRAME_HEIGHT * 4);
+        assert!(frame.chunks_exact(4).all(|pixel| pixel[3] == 0xFF));
+        assert!(!emulator.take_audio().is_empty());
+        assert!(emulator.take_audio().is_empty());
+        assert!(emulator.press("start"));
+        assert!(!emulator.press("pause"));
+    }
+}
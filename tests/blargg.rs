@@ -0,0 +1,63 @@
+use gameboy_emulator::Emulator;
+use std::cell::RefCell;
+use std::env;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+// Blargg's test ROMs, which can't be in the repository. BLARGG_ROMS is a checkout of
+// https://github.com/retrio/gb-test-roms or anything laid out the same way:
+//
+//     BLARGG_ROMS=~/gb-test-roms cargo test --no-default-features --features test-roms --test blargg
+//
+// Each ROM prints what it tests over the link port and ends with Passed or Failed. Without
+// BLARGG_ROMS the tests pass without running anything
+
+// cpu_instrs takes about a minute on hardware, the others a few seconds
+const MAX_FRAMES: u64 = 60 * 90;
+
+// Everything the ROM printed, up to its verdict or MAX_FRAMES
+fn run(rom: &str) -> Option<String> {
+    let Some(dir) = env::var_os("BLARGG_ROMS") else {
+        eprintln!("BLARGG_ROMS isn't set, skipping {rom}");
+        return None;
+    };
+    let mut emulator = Emulator::new();
+    let path = PathBuf::from(dir).join(rom);
+    if let Err(error) = emulator.load_rom(&path) {
+        panic!("Couldn't load {}: {error}", path.display());
+    }
+    let serial = Rc::new(RefCell::new(Vec::new()));
+    let sent = Rc::clone(&serial);
+    emulator
+        .cpu_mut()
+        .on_serial(move |bytes| sent.borrow_mut().extend_from_slice(bytes));
+    while emulator.frame_count() < MAX_FRAMES {
+        emulator.run_until_vblank();
+        let output = String::from_utf8_lossy(&serial.borrow()).into_owned();
+        if output.contains("Passed") || output.contains("Failed") {
+            return Some(output);
+        }
+    }
+    Some(String::from_utf8_lossy(&serial.borrow()).into_owned())
+}
+
+fn assert_passes(rom: &str) {
+    if let Some(output) = run(rom) {
+        assert!(output.contains("Passed"), "{rom} didn't pass:\n{output}");
+    }
+}
+
+#[test]
+fn cpu_instrs() {
+    assert_passes("cpu_instrs/cpu_instrs.gb");
+}
+
+#[test]
+fn instr_timing() {
+    assert_passes("instr_timing/instr_timing.gb");
+}
+
+#[test]
+fn mem_timing() {
+    assert_passes("mem_timing/mem_timing.gb");
+}
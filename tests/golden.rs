@@ -0,0 +1,201 @@
+use gameboy_emulator::Emulator;
+use gameboy_emulator::gpu::{FRAME_HEIGHT, FRAME_WIDTH};
+use gameboy_emulator::palette::Palette;
+use gameboy_emulator::screenshot;
+use std::env;
+use std::fs::{self, File};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+// Golden images: ROMs run headlessly for a number of frames, with the last frame compared pixel
+// by pixel to a reference PNG in tests/golden. The ROMs come from the directory GOLDEN_ROMS points
+// to:
+//
+//     GOLDEN_ROMS=~/test-roms cargo test --no-default-features --features test-roms --test golden
+//
+// A frame that doesn't match is written next to the test binary's temporary files, see the
+// failure. GOLDEN_UPDATE=1 writes the frames as the new references instead, for a new case or
+// after checking by eye that a change is right
+
+// Like the shades reference images are usually drawn in: white, light and dark gray, black
+const PALETTE: &str = "high-contrast";
+const REFERENCES: &str = "tests/golden";
+
+struct Golden {
+    // The reference is <name>.png
+    name: &'static str,
+    rom: &'static str,
+    // Where the reference comes from, for when it's missing
+    source: &'static str,
+    frames: u64,
+    // Pixels that may differ, 0 for an exact match
+    tolerance: usize,
+}
+
+const GOLDEN: [Golden; 1] = [
+    // The face is drawn within a couple of frames and stays
+    Golden {
+        name: "dmg-acid2",
+        rom: "dmg-acid2.gb",
+        source: "img/reference-dmg.png from https://github.com/mattcurrie/dmg-acid2",
+        frames: 60,
+        tolerance: 0,
+    },
+];
+
+fn last_frame(rom: &Path, frames: u64) -> Result<Vec<u8>, String> {
+    let mut emulator = Emulator::new();
+    emulator
+        .load_rom(rom)
+        .map_err(|error| format!("Couldn't load {}: {error}", rom.display()))?;
+    emulator.cpu_mut().gpu_mut().select_palette(PALETTE)?;
+    while emulator.frame_count() < frames {
+        emulator.run_until_vblank();
+    }
+    Ok(emulator.framebuffer().to_vec())
+}
+
+// As RGB, whatever the PNG's color type, as long as it's the size of the screen
+fn read_png(path: &Path) -> Result<Vec<u8>, String> {
+    let file = File::open(path).map_err(|error| format!("{}: {error}", path.display()))?;
+    let mut decoder = png::Decoder::new(std::io::BufReader::new(file));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(|error| error.to_string())?;
+    let mut pixels = vec![0; reader.output_buffer_size().ok_or("PNG too large")?];
+    let info = reader
+        .next_frame(&mut pixels)
+        .map_err(|error| error.to_string())?;
+    if (info.width as usize, info.height as usize) != (FRAME_WIDTH, FRAME_HEIGHT) {
+        return Err(format!(
+            "{} isn't {FRAME_WIDTH}x{FRAME_HEIGHT}",
+            path.display()
+        ));
+    }
+    let channels = info.color_type.samples();
+    Ok(pixels[..info.buffer_size()]
+        .chunks(channels)
+        .flat_map(|pixel| match channels {
+            // Grayscale, with or without alpha
+            1 | 2 => [pixel[0]; 3],
+            _ => [pixel[0], pixel[1], pixel[2]],
+        })
+        .collect())
+}
+
+fn differing_pixels(frame: &[u8], reference: &[u8]) -> usize {
+    frame
+        .chunks(3)
+        .zip(reference.chunks(3))
+        .filter(|(pixel, expected)| pixel != expected)
+        .count()
+}
+
+// Pixels of a reference that aren't one of PALETTE's shades. Any means it was drawn in other
+// shades, and no frame could ever match it
+fn foreign_shades(reference: &[u8]) -> usize {
+    let palette = Palette::presets()
+        .into_iter()
+        .find(|palette| palette.name == PALETTE)
+        .unwrap();
+    reference
+        .chunks(3)
+        .filter(|pixel| !palette.colors.iter().any(|shade| shade == pixel))
+        .count()
+}
+
+fn reference(golden: &Golden) -> PathBuf {
+    Path::new(REFERENCES).join(format!("{}.png", golden.name))
+}
+
+// Why the case failed, None if it passed
+fn check(golden: &Golden, roms: &Path, update: bool) -> Option<String> {
+    let name = golden.name;
+    let reference = reference(golden);
+    let frame = match panic::catch_unwind(AssertUnwindSafe(|| {
+        last_frame(&roms.join(golden.rom), golden.frames)
+    })) {
+        Ok(Ok(frame)) => frame,
+        Ok(Err(error)) => return Some(format!("{name}: {error}")),
+        Err(_) => return Some(format!("{name}: crashed")),
+    };
+    if update {
+        return screenshot::write_png(&frame, &reference)
+            .err()
+            .map(|error| format!("{name}: couldn't write {}: {error}", reference.display()));
+    }
+    let expected = match read_png(&reference) {
+        Ok(expected) => expected,
+        Err(error) => return Some(format!("{name}: unreadable reference, {error}")),
+    };
+    let foreign = foreign_shades(&expected);
+    if foreign > 0 {
+        return Some(format!(
+            "{name}: {foreign} pixels of {} aren't in the {PALETTE} palette",
+            reference.display()
+        ));
+    }
+    let differing = differing_pixels(&frame, &expected);
+    if differing <= golden.tolerance {
+        return None;
+    }
+    let actual = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{name}.png"));
+    let _ = screenshot::write_png(&frame, &actual);
+    Some(format!(
+        "{name}: {differing} pixels differ from {}, the frame is in {}",
+        reference.display(),
+        actual.display()
+    ))
+}
+
+#[test]
+fn golden_images() {
+    let Some(roms) = env::var_os("GOLDEN_ROMS").map(PathBuf::from) else {
+        eprintln!("GOLDEN_ROMS isn't set, skipping the golden images");
+        return;
+    };
+    let update = env::var_os("GOLDEN_UPDATE").is_some();
+    if update {
+        fs::create_dir_all(REFERENCES).unwrap();
+    } else {
+        // A reference that comes with its test ROM isn't made with GOLDEN_UPDATE, that would only
+        // check the emulator against itself
+        let missing: Vec<String> = GOLDEN
+            .iter()
+            .filter(|golden| !reference(golden).exists())
+            .map(|golden| {
+                format!(
+                    "{} is missing, it's {}",
+                    reference(golden).display(),
+                    golden.source
+                )
+            })
+            .collect();
+        assert!(missing.is_empty(), "Setup error:\n{}", missing.join("\n"));
+    }
+    // Unimplemented opcodes panic, which is reported like any other failure
+    panic::set_hook(Box::new(|_| {}));
+    let failures: Vec<String> = GOLDEN
+        .iter()
+        .filter_map(|golden| check(golden, &roms, update))
+        .collect();
+    let _ = panic::take_hook();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn should_count_the_pixels_that_differ() {
+    let reference = vec![0xAA; FRAME_WIDTH * FRAME_HEIGHT * 3];
+    let mut frame = reference.clone();
+    frame[3] = 0x55;
+    frame[5] = 0x55;
+    frame[9] = 0;
+    assert_eq!(differing_pixels(&frame, &reference), 2);
+}
+
+#[test]
+fn should_find_shades_outside_the_palette() {
+    let mut reference = [[0xFF; 3], [0xAA; 3], [0x55; 3], [0x00; 3]].concat();
+    assert_eq!(foreign_shades(&reference), 0);
+    reference[4] = 0xAB;
+    assert_eq!(foreign_shades(&reference), 1);
+}
@@ -0,0 +1,27 @@
+use std::fs;
+use std::process::Command;
+
+// include/gameboy_emulator.h has to be what cbindgen makes of src/ffi.rs as it is now. Needs
+// cbindgen on the PATH, without it the test passes without checking anything
+#[test]
+fn header_should_match_the_c_api() {
+    let root = env!("CARGO_MANIFEST_DIR");
+    let Ok(output) = Command::new("cbindgen")
+        .current_dir(root)
+        .args(["--config", "cbindgen.toml", "src/ffi.rs"])
+        .output()
+    else {
+        eprintln!("cbindgen isn't installed, skipping the header check");
+        return;
+    };
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let header = fs::read_to_string(format!("{root}/include/gameboy_emulator.h")).unwrap();
+    assert!(
+        output.stdout == header.as_bytes(),
+        "include/gameboy_emulator.h is out of date, regenerate it with cbindgen as the README shows"
+    );
+}
@@ -0,0 +1,134 @@
+use gameboy_emulator::Emulator;
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+// The acceptance ROMs of the Mooneye Test Suite, built from
+// https://github.com/Gekkio/mooneye-test-suite, in the directory MOONEYE_ROMS points to. Every
+// .gb under it is run and reported, the summary shows with --nocapture:
+//
+//     MOONEYE_ROMS=~/mooneye-test-suite/build/acceptance cargo test --no-default-features \
+//         --features test-roms --test mooneye -- --nocapture
+//
+// Most don't pass yet, so only the ROMs listed in mooneye-passing.txt have to. One that stops
+// passing fails the test, one that starts passing is pointed out to be added to the list
+
+// A ROM ends with LD B, B, a breakpoint to debuggers, with the Fibonacci numbers in the
+// registers if it passed, or 0x42 in all of them if it failed
+const BREAKPOINT: u8 = 0x40;
+const PASSED: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const FAILED: [u8; 6] = [0x42; 6];
+// The longest ones take a few seconds
+const MAX_FRAMES: u64 = 60 * 20;
+const PASSING_LIST: &str = "tests/mooneye-passing.txt";
+
+enum Verdict {
+    Passed,
+    Failed,
+    // Reached the breakpoint without either signature
+    Unknown([u8; 6]),
+    TimedOut,
+    // Panicked, at an instruction that isn't emulated yet
+    Crashed(String),
+}
+
+fn run(path: &Path) -> Verdict {
+    let mut emulator = Emulator::new();
+    if let Err(error) = emulator.load_rom(path) {
+        return Verdict::Crashed(error.to_string());
+    }
+    let cpu = emulator.cpu_mut();
+    while cpu.frames() < MAX_FRAMES {
+        let [_, bc, de, hl, _, pc] = cpu.registers().map(|(_, value)| value);
+        if cpu.peek(pc) == BREAKPOINT {
+            let registers = [bc, de, hl].map(u16::to_be_bytes).concat();
+            return match <[u8; 6]>::try_from(registers).unwrap() {
+                PASSED => Verdict::Passed,
+                FAILED => Verdict::Failed,
+                registers => Verdict::Unknown(registers),
+            };
+        }
+        cpu.step_instruction();
+    }
+    Verdict::TimedOut
+}
+
+// Every .gb under dir, as paths relative to it
+fn roms(dir: &Path, relative: &Path, found: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir.join(relative)) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = relative.join(entry.file_name());
+        if entry.path().is_dir() {
+            roms(dir, &path, found);
+        } else if path.extension().is_some_and(|extension| extension == "gb") {
+            found.push(path);
+        }
+    }
+}
+
+#[test]
+fn acceptance() {
+    let Some(dir) = env::var_os("MOONEYE_ROMS").map(PathBuf::from) else {
+        eprintln!("MOONEYE_ROMS isn't set, skipping the Mooneye Test Suite");
+        return;
+    };
+    let mut found = Vec::new();
+    roms(&dir, Path::new(""), &mut found);
+    found.sort();
+    assert!(!found.is_empty(), "No .gb files in {}", dir.display());
+    let list = fs::read_to_string(PASSING_LIST).unwrap();
+    let expected: BTreeSet<&str> = list
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+
+    // Unimplemented opcodes panic, which is reported like any other failure
+    panic::set_hook(Box::new(|_| {}));
+    let mut passed = BTreeSet::new();
+    for rom in &found {
+        let name = rom.to_string_lossy().replace('\\', "/");
+        let verdict = panic::catch_unwind(AssertUnwindSafe(|| run(&dir.join(rom)))).unwrap_or_else(
+            |payload| {
+                let message = payload
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .or_else(|| payload.downcast_ref::<&str>().map(|text| text.to_string()))
+                    .unwrap_or_default();
+                Verdict::Crashed(message)
+            },
+        );
+        let result = match verdict {
+            Verdict::Passed => "passed".to_string(),
+            Verdict::Failed => "failed".to_string(),
+            Verdict::Unknown(registers) => format!("stopped with registers {registers:02X?}"),
+            Verdict::TimedOut => format!("didn't finish in {MAX_FRAMES} frames"),
+            Verdict::Crashed(message) => format!("crashed: {message}"),
+        };
+        println!("{name}: {result}");
+        if result == "passed" {
+            passed.insert(name);
+        }
+    }
+    let _ = panic::take_hook();
+
+    println!("{} of {} passed", passed.len(), found.len());
+    for name in passed
+        .iter()
+        .filter(|name| !expected.contains(name.as_str()))
+    {
+        println!("{name} passes now, add it to {PASSING_LIST}");
+    }
+    let regressed: Vec<&&str> = expected
+        .iter()
+        .filter(|name| !passed.contains(**name))
+        .collect();
+    assert!(
+        regressed.is_empty(),
+        "Passed before but not now: {regressed:?}"
+    );
+}
@@ -0,0 +1,104 @@
+// Runs the emulator in the page: frames go to the canvas, sound to WebAudio and the keyboard to
+// the joypad. pkg/ is what wasm-pack builds, see the README
+import init, { WebEmulator } from "./pkg/gameboy_emulator.js";
+
+const FRAME_RATE = 4194304 / 70224;
+// Sound is scheduled this far ahead so a late frame doesn't make it crackle
+const AUDIO_LATENCY = 0.05;
+
+const KEYS = {
+  ArrowUp: "up",
+  ArrowDown: "down",
+  ArrowLeft: "left",
+  ArrowRight: "right",
+  KeyZ: "a",
+  KeyX: "b",
+  Enter: "start",
+  Backspace: "select",
+};
+
+await init();
+
+const canvas = document.getElementById("screen");
+const context = canvas.getContext("2d");
+let audio = null;
+let emulator = null;
+let nextSound = 0;
+let lastTime = null;
+let owed = 0;
+
+document.getElementById("rom").addEventListener("change", async (event) => {
+  const file = event.target.files[0];
+  if (!file) {
+    return;
+  }
+  // Browsers only allow sound to start after the player did something
+  audio ??= new AudioContext();
+  await audio.resume();
+  emulator = new WebEmulator(audio.sampleRate);
+  try {
+    emulator.load_rom(file.name, new Uint8Array(await file.arrayBuffer()));
+  } catch (error) {
+    alert(`Couldn't load ${file.name}: ${error}`);
+    emulator = null;
+  }
+});
+
+for (const [type, down] of [["keydown", true], ["keyup", false]]) {
+  window.addEventListener(type, (event) => {
+    const button = KEYS[event.code];
+    if (!emulator || !button) {
+      return;
+    }
+    if (down) {
+      emulator.press(button);
+    } else {
+      emulator.release(button);
+    }
+    event.preventDefault();
+  });
+}
+
+function play(samples) {
+  if (samples.length === 0) {
+    return;
+  }
+  const frames = samples.length / 2;
+  const buffer = audio.createBuffer(2, frames, audio.sampleRate);
+  const left = buffer.getChannelData(0);
+  const right = buffer.getChannelData(1);
+  for (let index = 0; index < frames; index++) {
+    left[index] = samples[index * 2];
+    right[index] = samples[index * 2 + 1];
+  }
+  const source = audio.createBufferSource();
+  source.buffer = buffer;
+  source.connect(audio.destination);
+  // Starts over from the current time whenever playback ran dry
+  nextSound = Math.max(nextSound, audio.currentTime + AUDIO_LATENCY);
+  source.start(nextSound);
+  nextSound += buffer.duration;
+}
+
+// The display refreshes at its own rate, frames are run as time calls for them. After a long
+// stall, like a hidden tab, the emulator carries on instead of catching up
+function tick(time) {
+  if (emulator) {
+    owed = Math.min(owed + (lastTime === null ? 0 : time - lastTime) / 1000 * FRAME_RATE, 3);
+    let ran = false;
+    while (owed >= 1) {
+      emulator.run_frame();
+      owed -= 1;
+      ran = true;
+    }
+    if (ran) {
+      const pixels = new Uint8ClampedArray(emulator.frame());
+      context.putImageData(new ImageData(pixels, 160, 144), 0, 0);
+      play(emulator.take_audio());
+    }
+  }
+  lastTime = time;
+  requestAnimationFrame(tick);
+}
+
+requestAnimationFrame(tick);